@@ -1,21 +1,36 @@
 use crate::{
-    lidar::generate_dem_and_vegetation_density_tiff_images_from_laz_file,
+    buildings::detect_buildings,
+    cliffs::vectorize_cliffs,
+    config::{get_config, Config},
+    contours::generate_contours_with_pullautin_algorithme,
+    crs::resolve_crs_for_laz_file,
+    error::CassiniError,
+    land_cover::detect_water_and_paved_areas,
+    lidar::{generate_dem_and_vegetation_density_tiff_images_from_laz_file, LidarBackend},
     merge::merge_maps,
-    render::{
-        cleanup_render_step_files, generate_png_from_dem_vegetation_density_tiff_images_and_vector_file,
-    },
+    micro_relief::detect_micro_relief,
+    render::{generate_shapefiles_from_osm_data, get_image_size, render_full_map},
+    scheduler::{Job, TileScheduler},
+    state::{hash_file_metadata, hash_inputs, Stage, StageStatus, StateManifest},
+    streams::extract_streams,
+    symbol_rules::{get_symbol_rules, SymbolRule},
+    symbol_styles::{get_symbol_styles, SymbolStyles},
     tile::{Tile, TileWithNeighbors},
+    vector_map::VectorMapFormat,
+    vegetation::render_vegetation,
     UndergrowthMode,
 };
 use las::raw::Header;
-use log::info;
+use log::{error, info, warn};
+use serde::Serialize;
 use std::{
+    any::Any,
     collections::HashMap,
     fs::{read_dir, File},
+    panic::{catch_unwind, AssertUnwindSafe},
     path::{Path, PathBuf},
     sync::Arc,
-    thread::{sleep, spawn, JoinHandle},
-    time::Duration,
+    thread::{spawn, JoinHandle},
 };
 
 pub fn batch(
@@ -25,94 +40,464 @@ pub fn batch(
     skip_lidar: bool,
     skip_vector: bool,
     skip_520: bool,
+    symbol_rules_path: Option<PathBuf>,
+    symbol_styles_path: Option<PathBuf>,
     undergrowth_mode: &UndergrowthMode,
-) {
-    let tiles = get_tiles_with_neighbors(input_dir, output_dir);
+    lidar_backend: &LidarBackend,
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
+    let tiles = get_tiles_with_neighbors(input_dir, output_dir)?;
     let tiles_arc = Arc::new(tiles.clone());
-    let chunk_size = (tiles.len() + number_of_threads - 1) / number_of_threads;
 
-    if skip_lidar {
-        cleanup_render_step_files(&tiles, output_dir);
-    } else {
-        let tiles_chunks: Vec<Vec<TileWithNeighbors>> =
-            tiles_arc.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
+    let state = Arc::new(StateManifest::load(output_dir));
+    let config = Arc::new(get_config()?);
+    let config_hash = hash_serialized_inputs(config.as_ref(), "config")?;
+
+    let symbol_rules = Arc::new(get_symbol_rules(&symbol_rules_path)?);
+    let symbol_rules_hash = hash_serialized_inputs(symbol_rules.as_ref(), "symbol rules")?;
 
-        let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(number_of_threads);
+    let symbol_styles = Arc::new(get_symbol_styles(
+        &symbol_styles_path,
+        config.symbol_set,
+        config.get_map_scale(),
+    )?);
+    let symbol_styles_hash = hash_serialized_inputs(symbol_styles.as_ref(), "symbol styles")?;
 
-        for chunk in tiles_chunks {
-            let chunk = Arc::new(chunk);
+    // The LiDAR step only depends on the LAZ file, the CRS and the backend
+    let lidar_backend_string = format!("{:?}", lidar_backend);
+    let lidar_hashes: Arc<HashMap<PathBuf, String>> = Arc::new(
+        tiles
+            .iter()
+            .map(|tile| {
+                (
+                    tile.tile.lidar_dir_path.clone(),
+                    hash_inputs(&[
+                        &hash_file_metadata(&tile.laz_path),
+                        &tile.tile.crs,
+                        &lidar_backend_string,
+                    ]),
+                )
+            })
+            .collect(),
+    );
 
-            let spawned_thread = spawn(move || {
-                for tile in chunk.iter() {
-                    info!(
-                        "Tile min_x={} min_y={} max_x={} max_y={}. Generating raw rasters",
-                        tile.tile.min_x, tile.tile.min_y, tile.tile.max_x, tile.tile.max_y
-                    );
+    let scheduler = Arc::new(TileScheduler::new(&tiles, skip_lidar));
+    let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(number_of_threads);
+
+    for _ in 0..number_of_threads {
+        let tiles = Arc::clone(&tiles_arc);
+        let scheduler = Arc::clone(&scheduler);
+        let state = Arc::clone(&state);
+        let config = Arc::clone(&config);
+        let config_hash = config_hash.clone();
+        let symbol_rules = Arc::clone(&symbol_rules);
+        let symbol_rules_hash = symbol_rules_hash.clone();
+        let symbol_styles = Arc::clone(&symbol_styles);
+        let symbol_styles_hash = symbol_styles_hash.clone();
+        let lidar_hashes = Arc::clone(&lidar_hashes);
+        let cloned_undergrowth_mode = undergrowth_mode.clone();
+        let cloned_lidar_backend = lidar_backend.clone();
+        let cloned_vector_map_format = vector_map_format.clone();
 
-                    generate_dem_and_vegetation_density_tiff_images_from_laz_file(
-                        &tile.laz_path,
-                        &tile.tile.lidar_dir_path,
-                    );
+        let spawned_thread = spawn(move || {
+            while let Some(job) = scheduler.next_job() {
+                match job {
+                    Job::Lidar(tile_index) => {
+                        let tile = &tiles[tile_index];
+
+                        run_stage(
+                            &state,
+                            &tile.tile,
+                            &tile.tile.lidar_dir_path,
+                            Stage::Lidar,
+                            &lidar_hashes[&tile.tile.lidar_dir_path],
+                            || {
+                                info!(
+                                    "Tile min_x={} min_y={} max_x={} max_y={}. Generating raw rasters",
+                                    tile.tile.min_x, tile.tile.min_y, tile.tile.max_x, tile.tile.max_y
+                                );
+
+                                generate_dem_and_vegetation_density_tiff_images_from_laz_file(
+                                    &tile.laz_path,
+                                    &tile.tile.lidar_dir_path,
+                                    &cloned_lidar_backend,
+                                )
+                            },
+                        );
+
+                        scheduler.finish_lidar_job(tile_index);
+                    }
+                    Job::Render(tile_index) => {
+                        render_tile_stages(
+                            &tiles[tile_index],
+                            &state,
+                            &config,
+                            &config_hash,
+                            &lidar_hashes,
+                            skip_lidar,
+                            skip_vector,
+                            skip_520,
+                            &symbol_rules,
+                            &symbol_rules_hash,
+                            &symbol_styles,
+                            &symbol_styles_hash,
+                            &cloned_undergrowth_mode,
+                            &cloned_vector_map_format,
+                            omap,
+                        );
+                    }
                 }
+            }
+        });
 
-                sleep(Duration::from_millis(1));
-            });
+        handles.push(spawned_thread);
+    }
 
-            handles.push(spawned_thread);
-        }
+    for handle in handles {
+        handle.join().unwrap();
+    }
 
-        for handle in handles {
-            handle.join().unwrap();
-        }
+    let number_of_tiles = tiles.len();
+
+    let rendered_tiles: Vec<TileWithNeighbors> = tiles
+        .into_iter()
+        .filter(|tile| state.is_done(&tile.tile.render_dir_path, Stage::Render))
+        .collect();
+
+    let number_of_rendered_tiles = rendered_tiles.len();
+    let merge_result = merge_maps(output_dir, rendered_tiles, &symbol_styles);
+
+    log_batch_summary(&state, number_of_tiles, number_of_rendered_tiles);
+
+    merge_result
+}
+
+/// Logs which tiles failed at which stage, so that failures are not lost in the logs of the other tiles.
+fn log_batch_summary(state: &StateManifest, number_of_tiles: usize, number_of_rendered_tiles: usize) {
+    let failed_stages = state.get_failed_stages();
+
+    if failed_stages.is_empty() {
+        info!(
+            "Batch finished. {}/{} tiles rendered",
+            number_of_rendered_tiles, number_of_tiles
+        );
+
+        return;
     }
 
-    let tiles_chunks: Vec<Vec<TileWithNeighbors>> =
-        tiles_arc.chunks(chunk_size).map(|chunk| chunk.to_vec()).collect();
+    error!(
+        "Batch finished. {}/{} tiles rendered, {} stage(s) failed:",
+        number_of_rendered_tiles,
+        number_of_tiles,
+        failed_stages.len()
+    );
 
-    let mut handles: Vec<JoinHandle<()>> = Vec::with_capacity(number_of_threads);
+    for (tile_key, stage, error) in failed_stages {
+        error!("  Tile {} | stage {:?} | {}", tile_key, stage, error);
+    }
+}
 
-    for chunk in tiles_chunks {
-        let chunk = Arc::new(chunk);
+/// Runs the render stages of a tile, skipping the ones that are up to date in the state manifest.
+/// A stage only runs if the stages it depends on succeeded.
+fn render_tile_stages(
+    tile: &TileWithNeighbors,
+    state: &StateManifest,
+    config: &Config,
+    config_hash: &str,
+    lidar_hashes: &HashMap<PathBuf, String>,
+    skip_lidar: bool,
+    skip_vector: bool,
+    skip_520: bool,
+    symbol_rules: &Vec<SymbolRule>,
+    symbol_rules_hash: &str,
+    symbol_styles: &SymbolStyles,
+    symbol_styles_hash: &str,
+    undergrowth_mode: &UndergrowthMode,
+    vector_map_format: &Option<VectorMapFormat>,
+    omap: bool,
+) {
+    let is_lidar_done = |lidar_dir_path: &PathBuf| skip_lidar || state.is_done(lidar_dir_path, Stage::Lidar);
 
-        let cloned_undergrowth_mode = undergrowth_mode.clone();
+    if !is_lidar_done(&tile.tile.lidar_dir_path) {
+        warn!(
+            "Tile min_x={} min_y={} max_x={} max_y={}. LiDAR step failed, skipping map rendering",
+            tile.tile.min_x, tile.tile.min_y, tile.tile.max_x, tile.tile.max_y
+        );
 
-        let spawned_thread = spawn(move || {
-            for tile in chunk.iter() {
-                info!(
-                    "Tile min_x={} min_y={} max_x={} max_y={}. Rendering map",
-                    tile.tile.min_x, tile.tile.min_y, tile.tile.max_x, tile.tile.max_y
-                );
+        return;
+    }
 
-                generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
-                    tile.tile.clone(),
-                    tile.neighbors.clone(),
-                    skip_vector,
-                    skip_520,
-                    &cloned_undergrowth_mode,
-                    None,
-                );
-            }
+    // Neighbors whose LiDAR step failed would break the buffer computation
+    let neighbors: Vec<PathBuf> = tile
+        .neighbors
+        .iter()
+        .filter(|neighbor| is_lidar_done(neighbor))
+        .cloned()
+        .collect();
 
-            sleep(Duration::from_millis(1));
-        });
+    let mut rasters_inputs: Vec<&str> = vec![config_hash, &lidar_hashes[&tile.tile.lidar_dir_path]];
 
-        handles.push(spawned_thread);
+    for neighbor in &neighbors {
+        rasters_inputs.push(lidar_hashes.get(neighbor).map(|hash| hash.as_str()).unwrap_or(""));
     }
 
-    for handle in handles {
-        handle.join().unwrap();
+    let rasters_hash = hash_inputs(&rasters_inputs);
+    let undergrowth_mode_string = format!("{:?}", undergrowth_mode);
+    let vegetation_hash = hash_inputs(&[
+        "vegetation",
+        &rasters_hash,
+        &undergrowth_mode_string,
+        symbol_styles_hash,
+    ]);
+    let contours_hash = hash_inputs(&["contours", &rasters_hash, symbol_styles_hash]);
+    let cliffs_hash = hash_inputs(&["cliffs", &rasters_hash]);
+    let micro_relief_hash = hash_inputs(&["micro-relief", &rasters_hash]);
+    let streams_hash = hash_inputs(&["streams", &rasters_hash]);
+    let land_cover_hash = hash_inputs(&["land-cover", &rasters_hash]);
+    let buildings_hash = hash_inputs(&["buildings", &rasters_hash]);
+    let vectors_hash = hash_inputs(&["vectors", &tile.tile.crs]);
+
+    let render_hash = hash_inputs(&[
+        "render",
+        &vegetation_hash,
+        &contours_hash,
+        &cliffs_hash,
+        &micro_relief_hash,
+        &streams_hash,
+        &land_cover_hash,
+        &buildings_hash,
+        if skip_vector { "" } else { &vectors_hash },
+        &skip_520.to_string(),
+        symbol_rules_hash,
+        &format!("{:?}", vector_map_format),
+        &omap.to_string(),
+    ]);
+
+    let render_dir_path = &tile.tile.render_dir_path;
+    let (image_width, image_height) = get_image_size(&tile.tile, config);
+
+    let is_vegetation_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Vegetation,
+        &vegetation_hash,
+        || {
+            render_vegetation(
+                &tile.tile,
+                &neighbors,
+                image_width,
+                image_height,
+                config,
+                symbol_styles,
+                undergrowth_mode,
+            )
+        },
+    );
+
+    let is_contours_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Contours,
+        &contours_hash,
+        || {
+            generate_contours_with_pullautin_algorithme(
+                &tile.tile,
+                &neighbors,
+                image_width,
+                image_height,
+                config,
+                symbol_styles,
+            )
+        },
+    );
+
+    let is_cliffs_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Cliffs,
+        &cliffs_hash,
+        || vectorize_cliffs(&tile.tile, &neighbors, config),
+    );
+
+    let is_micro_relief_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::MicroRelief,
+        &micro_relief_hash,
+        || detect_micro_relief(&tile.tile, &neighbors, config),
+    );
+
+    let is_streams_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Streams,
+        &streams_hash,
+        || extract_streams(&tile.tile, &neighbors, config),
+    );
+
+    let is_land_cover_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::LandCover,
+        &land_cover_hash,
+        || detect_water_and_paved_areas(&tile.tile, &neighbors, config),
+    );
+
+    let is_buildings_ok = run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Buildings,
+        &buildings_hash,
+        || detect_buildings(&tile.tile, &neighbors, config),
+    );
+
+    let is_vectors_ok = skip_vector
+        || run_stage(
+            state,
+            &tile.tile,
+            render_dir_path,
+            Stage::Vectors,
+            &vectors_hash,
+            || generate_shapefiles_from_osm_data(&tile.tile).map(|_| ()),
+        );
+
+    if !(is_vegetation_ok
+        && is_contours_ok
+        && is_cliffs_ok
+        && is_micro_relief_ok
+        && is_streams_ok
+        && is_land_cover_ok
+        && is_buildings_ok
+        && is_vectors_ok)
+    {
+        warn!(
+            "Tile min_x={} min_y={} max_x={} max_y={}. A previous stage failed, skipping map rendering",
+            tile.tile.min_x, tile.tile.min_y, tile.tile.max_x, tile.tile.max_y
+        );
+
+        return;
+    }
+
+    let shapes_path = if skip_vector {
+        None
+    } else {
+        Some(render_dir_path.join("shapes"))
+    };
+
+    run_stage(
+        state,
+        &tile.tile,
+        render_dir_path,
+        Stage::Render,
+        &render_hash,
+        || {
+            render_full_map(
+                &tile.tile,
+                image_width,
+                image_height,
+                config,
+                skip_520,
+                symbol_rules,
+                symbol_styles,
+                shapes_path,
+                vector_map_format.clone(),
+                omap,
+            )
+        },
+    );
+}
+
+/// Runs a stage if it is not up to date in the state manifest, and records its outcome.
+/// A stage fails if it returns an error, panics or does not produce its output files.
+/// Returns true if the stage was up to date or succeeded.
+fn hash_serialized_inputs<T: Serialize>(inputs: &T, name: &str) -> Result<String, CassiniError> {
+    let json_string = serde_json::to_string(inputs)
+        .map_err(|error| CassiniError::InvalidData(format!("Could not serialize the {}: {}", name, error)))?;
+
+    return Ok(hash_inputs(&[&json_string]));
+}
+
+fn run_stage<F: FnOnce() -> Result<(), CassiniError>>(
+    state: &StateManifest,
+    tile: &Tile,
+    dir_path: &PathBuf,
+    stage: Stage,
+    inputs_hash: &str,
+    stage_function: F,
+) -> bool {
+    if state.is_up_to_date(dir_path, stage, inputs_hash) {
+        info!(
+            "Tile min_x={} min_y={} max_x={} max_y={}. Stage {:?} is up to date, skipping it",
+            tile.min_x, tile.min_y, tile.max_x, tile.max_y, stage
+        );
+
+        return true;
     }
 
-    merge_maps(output_dir, tiles);
+    let stage_error: Option<String> = match catch_unwind(AssertUnwindSafe(stage_function)) {
+        Ok(Ok(())) => stage
+            .output_files()
+            .iter()
+            .find(|output_file| !dir_path.join(output_file).exists())
+            .map(|output_file| format!("The {} output file is missing", output_file)),
+        Ok(Err(error)) => Some(error.to_string()),
+        Err(panic_payload) => Some(get_panic_message(panic_payload)),
+    };
+
+    match stage_error {
+        None => {
+            state.record(dir_path, stage, StageStatus::Done, inputs_hash, None);
+            return true;
+        }
+        Some(stage_error) => {
+            error!(
+                "Tile min_x={} min_y={} max_x={} max_y={}. Stage {:?} failed: {}",
+                tile.min_x, tile.min_y, tile.max_x, tile.max_y, stage, stage_error
+            );
+
+            state.record(
+                dir_path,
+                stage,
+                StageStatus::Failed,
+                inputs_hash,
+                Some(stage_error),
+            );
+            return false;
+        }
+    }
+}
+
+fn get_panic_message(panic_payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = panic_payload.downcast_ref::<&str>() {
+        return format!("Panicked: {}", message);
+    }
+
+    if let Some(message) = panic_payload.downcast_ref::<String>() {
+        return format!("Panicked: {}", message);
+    }
+
+    return "Panicked".to_owned();
 }
 
-pub fn get_tiles_with_neighbors(input_dir: &str, output_dir: &str) -> Vec<TileWithNeighbors> {
-    let paths = read_dir(input_dir).expect(&format!("There is no {} directory.", input_dir));
+pub fn get_tiles_with_neighbors(
+    input_dir: &str,
+    output_dir: &str,
+) -> Result<Vec<TileWithNeighbors>, CassiniError> {
+    let paths = read_dir(input_dir)?;
     let mut tiles: Vec<TileWithNeighbors> = vec![];
     let mut tiles_map = HashMap::<(i64, i64, i64, i64), PathBuf>::new();
+    let config = get_config()?;
 
     for dir_entry in paths {
-        let path = dir_entry.expect("Problem reading directory entry").path();
+        let path = dir_entry?.path();
 
         match path.extension() {
             Some(extension) => {
@@ -120,8 +505,16 @@ pub fn get_tiles_with_neighbors(input_dir: &str, output_dir: &str) -> Vec<TileWi
                     continue;
                 }
 
-                let mut file = File::open(&path).expect("Cound not open laz file");
-                let header = Header::read_from(&mut file).unwrap();
+                let header = match File::open(&path)
+                    .map_err(CassiniError::from)
+                    .and_then(|mut file| Ok(Header::read_from(&mut file)?))
+                {
+                    Ok(header) => header,
+                    Err(error) => {
+                        error!("Skipping {:?}, its header could not be read: {}", path, error);
+                        continue;
+                    }
+                };
 
                 tiles_map.insert(
                     (
@@ -150,6 +543,7 @@ pub fn get_tiles_with_neighbors(input_dir: &str, output_dir: &str) -> Vec<TileWi
             min_y,
             max_x,
             max_y,
+            crs: resolve_crs_for_laz_file(&laz_path, &config),
         };
 
         let neighbors: Vec<PathBuf> = vec![
@@ -201,7 +595,7 @@ pub fn get_tiles_with_neighbors(input_dir: &str, output_dir: &str) -> Vec<TileWi
         })
     }
 
-    return tiles;
+    return Ok(tiles);
 }
 
 fn get_neighbor_tile_from_hash_map(
@@ -0,0 +1,292 @@
+use log::info;
+use shapefile::{Point, Polygon, PolygonRing};
+use std::{fs::create_dir_all, path::PathBuf, time::Instant};
+
+use crate::{
+    config::Config,
+    constants::BUFFER,
+    error::CassiniError,
+    helpers::simplify_polyline,
+    land_cover::{
+        apply_majority_filter, get_signed_area, to_shapefile_points, vectorize_mask, write_areas,
+        COVER_RESOLUTION,
+    },
+    mosaic::create_raster_with_buffer,
+    tile::Tile,
+};
+
+/// Finds the buildings of the tile from the LiDAR points classified as buildings, and writes their
+/// simplified and orthogonalized footprints to a polygon shapefile in the buildings directory.
+pub fn detect_buildings(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    config: &Config,
+) -> Result<(), CassiniError> {
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. Detecting buildings",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y
+    );
+
+    let start = Instant::now();
+    let buildings_config = &config.buildings;
+
+    let buildings = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "buildings",
+        COVER_RESOLUTION,
+        0u8,
+    )?;
+
+    let mask: Vec<bool> = buildings.data.iter().map(|count| *count > 0).collect();
+    let mask = apply_majority_filter(&mask, buildings.width, buildings.height);
+
+    let footprints: Vec<(Polygon, f64)> = vectorize_mask(&mask, &buildings, tile, buildings_config.min_area)
+        .iter()
+        .map(|(polygon, _)| regularize_footprint(polygon, buildings_config.simplification_tolerance))
+        .collect();
+
+    let buildings_dir = tile.render_dir_path.join("buildings");
+    create_dir_all(&buildings_dir)?;
+
+    write_areas(&buildings_dir.join("buildings.shp"), &footprints)?;
+
+    let duration = start.elapsed();
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. {} buildings detected in {:.1?}",
+        tile.min_x,
+        tile.min_y,
+        tile.max_x,
+        tile.max_y,
+        footprints.len(),
+        duration
+    );
+
+    Ok(())
+}
+
+/// Simplifies the rings of a building footprint, then squares their corners along the main direction
+/// of the outer ring. Returns the footprint with its area.
+fn regularize_footprint(polygon: &Polygon, tolerance: f64) -> (Polygon, f64) {
+    let mut angle: Option<f64> = None;
+    let mut rings: Vec<PolygonRing<Point>> = vec![];
+    let mut signed_area = 0.;
+
+    for ring in polygon.rings() {
+        let points: Vec<(f64, f64)> = ring.points().iter().map(|point| (point.x, point.y)).collect();
+        let simplified_points = simplify_polyline(&points, tolerance);
+
+        let points = if simplified_points.len() < 4 {
+            points
+        } else {
+            simplified_points
+        };
+
+        // The holes are squared along the outer ring, which comes first
+        let ring_angle = *angle.get_or_insert_with(|| get_main_direction(&points));
+        let points = orthogonalize_ring(&points, ring_angle).unwrap_or(points);
+        signed_area += get_signed_area(&points);
+
+        rings.push(match ring {
+            PolygonRing::Outer(_) => PolygonRing::Outer(to_shapefile_points(&points)),
+            PolygonRing::Inner(_) => PolygonRing::Inner(to_shapefile_points(&points)),
+        });
+    }
+
+    // Outer rings are clockwise, so the area of the polygon is the opposite of the summed signed areas
+    return (Polygon::with_rings(rings), -signed_area);
+}
+
+/// Returns the angle, between -45° and 45°, of the direction that best fits the edges of a ring or of
+/// their perpendiculars. Edge angles are multiplied by 4 so that perpendicular edges add up.
+fn get_main_direction(points: &Vec<(f64, f64)>) -> f64 {
+    let (mut sin_sum, mut cos_sum) = (0., 0.);
+
+    for segment in points.windows(2) {
+        let (dx, dy) = (segment[1].0 - segment[0].0, segment[1].1 - segment[0].1);
+        let length = (dx * dx + dy * dy).sqrt();
+        let edge_angle = dy.atan2(dx);
+
+        sin_sum += length * (4. * edge_angle).sin();
+        cos_sum += length * (4. * edge_angle).cos();
+    }
+
+    return sin_sum.atan2(cos_sum) / 4.;
+}
+
+/// Squares the corners of a closed ring along the given direction. Every edge is classified as parallel
+/// or perpendicular to the direction, consecutive edges of the same class are merged into one side at
+/// their length-weighted mean position, and the new corners are the intersections of consecutive sides.
+/// Returns None when the ring is too irregular for this: less than 4 sides, or an area flipped or changed
+/// by more than half.
+fn orthogonalize_ring(points: &Vec<(f64, f64)>, angle: f64) -> Option<Vec<(f64, f64)>> {
+    let (sin, cos) = angle.sin_cos();
+
+    let rotated_points: Vec<(f64, f64)> = points
+        .iter()
+        .map(|(x, y)| (x * cos + y * sin, -x * sin + y * cos))
+        .collect();
+
+    // (is_horizontal, length, position)
+    let edges: Vec<(bool, f64, f64)> = rotated_points
+        .windows(2)
+        .map(|segment| {
+            let (dx, dy) = (segment[1].0 - segment[0].0, segment[1].1 - segment[0].1);
+            let length = (dx * dx + dy * dy).sqrt();
+
+            if dx.abs() >= dy.abs() {
+                (true, length, (segment[0].1 + segment[1].1) / 2.)
+            } else {
+                (false, length, (segment[0].0 + segment[1].0) / 2.)
+            }
+        })
+        .collect();
+
+    // Starting on a change of class so that no side wraps around the end of the ring
+    let start_index = (0..edges.len()).find(|index| edges[*index].0 != edges[(index + 1) % edges.len()].0)?;
+
+    // (is_horizontal, summed length, summed length-weighted position)
+    let mut sides: Vec<(bool, f64, f64)> = vec![];
+
+    for offset in 1..=edges.len() {
+        let (is_horizontal, length, position) = edges[(start_index + offset) % edges.len()];
+
+        match sides.last_mut() {
+            Some(side) if side.0 == is_horizontal => {
+                side.1 += length;
+                side.2 += length * position;
+            }
+            _ => sides.push((is_horizontal, length, length * position)),
+        }
+    }
+
+    if sides.len() < 4 {
+        return None;
+    }
+
+    let mut orthogonal_points: Vec<(f64, f64)> = (0..sides.len())
+        .map(|index| {
+            let (is_horizontal, length, weighted_position) = sides[index];
+            let (_, next_length, next_weighted_position) = sides[(index + 1) % sides.len()];
+            let position = weighted_position / length;
+            let next_position = next_weighted_position / next_length;
+
+            let (x, y) = if is_horizontal {
+                (next_position, position)
+            } else {
+                (position, next_position)
+            };
+
+            (x * cos - y * sin, x * sin + y * cos)
+        })
+        .collect();
+
+    orthogonal_points.push(orthogonal_points[0]);
+
+    let area = get_signed_area(&orthogonal_points);
+    let original_area = get_signed_area(points);
+
+    if area * original_area <= 0. || (area / original_area - 1.).abs() > 0.5 {
+        return None;
+    }
+
+    return Some(orthogonal_points);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rotates points around the origin and closes the ring.
+    fn get_rotated_ring(points: &[(f64, f64)], angle: f64) -> Vec<(f64, f64)> {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut ring: Vec<(f64, f64)> = points
+            .iter()
+            .map(|(x, y)| (x * cos - y * sin, x * sin + y * cos))
+            .collect();
+        ring.push(ring[0]);
+
+        return ring;
+    }
+
+    fn get_corner_cosines(points: &[(f64, f64)]) -> Vec<f64> {
+        let closed_points: Vec<(f64, f64)> = points
+            .iter()
+            .chain(points.iter().skip(1).take(1))
+            .copied()
+            .collect();
+
+        closed_points
+            .windows(3)
+            .map(|corner| {
+                let (dx1, dy1) = (corner[1].0 - corner[0].0, corner[1].1 - corner[0].1);
+                let (dx2, dy2) = (corner[2].0 - corner[1].0, corner[2].1 - corner[1].1);
+
+                (dx1 * dx2 + dy1 * dy2) / ((dx1 * dx1 + dy1 * dy1).sqrt() * (dx2 * dx2 + dy2 * dy2).sqrt())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn noisy_rectangle_is_squared() {
+        // A clockwise 20 by 10 meters rectangle with noisy corners and extra points along its sides
+        let ring = get_rotated_ring(
+            &[
+                (0.1, -0.1),
+                (-0.2, 5.1),
+                (0.1, 10.),
+                (10., 10.2),
+                (19.9, 9.8),
+                (20.2, 0.1),
+                (9.9, -0.2),
+            ],
+            20.,
+        );
+
+        let orthogonal_ring = orthogonalize_ring(&ring, get_main_direction(&ring)).unwrap();
+        assert_eq!(orthogonal_ring.len(), 5);
+
+        for cosine in get_corner_cosines(&orthogonal_ring) {
+            assert!(cosine.abs() < 1e-9, "cosine={}", cosine);
+        }
+
+        let polygon = Polygon::with_rings(vec![PolygonRing::Outer(to_shapefile_points(&ring))]);
+        let (footprint, area) = regularize_footprint(&polygon, 0.5);
+
+        assert_eq!(footprint.rings()[0].points().len(), 5);
+        assert!((area - 200.).abs() < 10., "area={}", area);
+    }
+
+    #[test]
+    fn l_shape_keeps_its_area() {
+        // A clockwise L-shape of 300 square meters with noisy corners
+        let ring = get_rotated_ring(
+            &[
+                (0.2, -0.1),
+                (-0.1, 20.2),
+                (10.1, 19.9),
+                (9.8, 10.2),
+                (20.1, 9.9),
+                (19.9, 0.2),
+            ],
+            -30.,
+        );
+
+        let polygon = Polygon::with_rings(vec![PolygonRing::Outer(to_shapefile_points(&ring))]);
+        let (footprint, area) = regularize_footprint(&polygon, 0.5);
+        let points: Vec<(f64, f64)> = footprint.rings()[0]
+            .points()
+            .iter()
+            .map(|point| (point.x, point.y))
+            .collect();
+
+        assert_eq!(points.len(), 7);
+        assert!((area - 300.).abs() < 300. * 0.03, "area={}", area);
+
+        for cosine in get_corner_cosines(&points) {
+            assert!(cosine.abs() < 1e-9, "cosine={}", cosine);
+        }
+    }
+}
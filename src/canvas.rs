@@ -6,10 +6,17 @@ use std::fs::File;
 use std::io::Write;
 use std::mem;
 
+use crate::error::CassiniError;
+use crate::vector_map::VectorShape;
+
 pub struct Canvas {
     surface: Surface,
     path: Path,
     paint: Paint,
+    dash: Option<(f32, f32)>,
+    is_transparent: bool,
+    /// The shapes drawn on the canvas, kept for the vector map outputs once recording is started
+    recorded_shapes: Option<Vec<VectorShape>>,
 }
 
 impl Canvas {
@@ -25,9 +32,50 @@ impl Canvas {
             surface,
             path,
             paint,
+            dash: None,
+            is_transparent: false,
+            recorded_shapes: None,
         }
     }
 
+    #[inline]
+    pub fn start_recording(&mut self) {
+        self.recorded_shapes = Some(vec![]);
+    }
+
+    #[inline]
+    pub fn take_recorded_shapes(&mut self) -> Vec<VectorShape> {
+        self.recorded_shapes.take().unwrap_or_default()
+    }
+
+    #[inline]
+    fn record_area(&mut self, outer: &[(f32, f32)], holes: &Vec<Vec<(f32, f32)>>) {
+        let color = self.get_rgb_color();
+
+        if let Some(recorded_shapes) = &mut self.recorded_shapes {
+            let shape = if self.is_transparent {
+                VectorShape::Erased {
+                    outer: outer.to_vec(),
+                    holes: holes.clone(),
+                }
+            } else {
+                VectorShape::Area {
+                    outer: outer.to_vec(),
+                    holes: holes.clone(),
+                    color,
+                }
+            };
+
+            recorded_shapes.push(shape);
+        }
+    }
+
+    #[inline]
+    fn get_rgb_color(&self) -> (u8, u8, u8) {
+        let color = self.paint.color();
+        (color.r(), color.g(), color.b())
+    }
+
     #[inline]
     pub fn set_line_width(&mut self, width: f32) {
         self.paint.set_stroke_width(width);
@@ -35,11 +83,13 @@ impl Canvas {
 
     #[inline]
     pub fn set_color(&mut self, rgb: (u8, u8, u8)) {
+        self.is_transparent = false;
         self.paint.set_color(Color::from_rgb(rgb.0, rgb.1, rgb.2));
     }
 
     #[inline]
     pub fn set_transparent_color(&mut self) {
+        self.is_transparent = true;
         self.paint.set_blend_mode(skia_safe::BlendMode::SrcIn);
         self.paint.set_color(Color::TRANSPARENT);
     }
@@ -56,12 +106,14 @@ impl Canvas {
 
     #[inline]
     pub fn set_dash(&mut self, interval_on: f32, interval_off: f32) {
+        self.dash = Some((interval_on, interval_off));
         self.paint
             .set_path_effect(PathEffect::dash(&[interval_on, interval_off], 0.0));
     }
 
     #[inline]
     pub fn unset_dash(&mut self) {
+        self.dash = None;
         self.paint.set_path_effect(None);
     }
 
@@ -81,6 +133,22 @@ impl Canvas {
         }
         self.surface.canvas().draw_path(&self.path, &self.paint);
         self.save();
+
+        // Transparent lines are only used by unused symbols, they are not recorded
+        if !self.is_transparent {
+            let color = self.get_rgb_color();
+            let width = self.paint.stroke_width();
+            let dash = self.dash;
+
+            if let Some(recorded_shapes) = &mut self.recorded_shapes {
+                recorded_shapes.push(VectorShape::Line {
+                    points: pts.to_vec(),
+                    color,
+                    width,
+                    dash,
+                });
+            }
+        }
     }
 
     #[inline]
@@ -96,6 +164,7 @@ impl Canvas {
 
         self.surface.canvas().draw_path(&self.path, &self.paint);
         self.save();
+        self.record_area(pts, &vec![]);
     }
 
     #[inline]
@@ -130,6 +199,28 @@ impl Canvas {
 
         self.surface.canvas().draw_path(&self.path, &self.paint);
         self.save();
+        self.record_area(outer_geometry, holes);
+    }
+
+    #[inline]
+    pub fn draw_filled_circle(&mut self, center: (f32, f32), radius: f32) {
+        self.paint.set_style(PaintStyle::Fill);
+        self.surface
+            .canvas()
+            .draw_circle((center.0, center.1), radius, &self.paint);
+        self.save();
+
+        if !self.is_transparent {
+            let color = self.get_rgb_color();
+
+            if let Some(recorded_shapes) = &mut self.recorded_shapes {
+                recorded_shapes.push(VectorShape::Dot {
+                    center,
+                    radius,
+                    color,
+                });
+            }
+        }
     }
 
     #[inline]
@@ -152,20 +243,23 @@ impl Canvas {
     }
 
     #[inline]
-    pub fn save_as(&mut self, filename: &str) {
+    pub fn save_as(&mut self, filename: &str) -> Result<(), CassiniError> {
         let d = self.data();
-        let mut file = File::create(filename).unwrap();
+        let mut file = File::create(filename)?;
         let bytes = d.as_bytes();
-        file.write_all(bytes).unwrap();
+        file.write_all(bytes)?;
+        Ok(())
     }
 
     #[inline]
-    pub fn load_from(filename: &str) -> Canvas {
-        let data = Data::from_filename(filename).unwrap();
-        let image = Image::from_encoded(data).unwrap();
+    pub fn load_from(filename: &str) -> Result<Canvas, CassiniError> {
+        let data = Data::from_filename(filename)
+            .ok_or_else(|| CassiniError::InvalidData(format!("Could not read {}", filename)))?;
+        let image = Image::from_encoded(data)
+            .ok_or_else(|| CassiniError::InvalidData(format!("Could not decode {}", filename)))?;
         let mut c = Canvas::new(image.width(), image.height());
         c.draw_image(image);
-        c
+        Ok(c)
     }
 
     #[inline]
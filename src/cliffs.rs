@@ -1,85 +1,270 @@
-use image::RgbaImage;
-use imageproc::drawing::draw_filled_ellipse_mut;
 use log::info;
-use std::{fs::File, time::Instant};
-use tiff::decoder::{Decoder, DecodingResult};
+use shapefile::{
+    dbase::{FieldIOError, FieldName, FieldWriter, TableWriterBuilder, WritableRecord},
+    Point, Polyline, Writer,
+};
+use std::{
+    fs::{create_dir_all, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
 
 use crate::{
     config::Config,
-    constants::{BLACK, BUFFER, CLIFF_THICKNESS_1, CLIFF_THICKNESS_2, DEM_BLOCK_SIZE, INCH, TRANSPARENT},
+    constants::{BUFFER, DEM_BLOCK_SIZE, DEM_NODATA_VALUE},
+    error::CassiniError,
+    helpers::{clip_polyline, get_polyline_length, simplify_polyline},
+    mosaic::create_raster_with_buffer,
+    raster::{
+        compute_slopes, fill_nodata, get_horn_gradient, is_nodata, Raster, FILL_NODATA_MAX_SEARCH_DISTANCE,
+    },
+    skeleton::{thin_mask, trace_skeleton},
     tile::Tile,
 };
 
-pub fn render_cliffs(tile: &Tile, image_width: u32, image_height: u32, config: &Config) {
+/// Cliffs shorter than this length in meters are dropped
+const MIN_CLIFF_LENGTH: f64 = 2.;
+
+/// Distance, in DEM cells, between a cliff and the elevations sampled on both sides to measure its height
+const CLIFF_HEIGHT_SAMPLING_DISTANCE: f64 = 2.;
+
+/// Vectorizes the cliffs of the tile: the slopes steeper than cliff_threshold_1 are thinned to one pixel
+/// wide lines, chained into polylines and simplified. The polylines are oriented with the downhill side on
+/// their right, where the tags are drawn, clipped to the tile and written to cliffs/cliffs.shp with their
+/// height and slope.
+pub fn vectorize_cliffs(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    config: &Config,
+) -> Result<(), CassiniError> {
     info!(
-        "Tile min_x={} min_y={} max_x={} max_y={}. Rendering cliffs",
+        "Tile min_x={} min_y={} max_x={} max_y={}. Vectorizing cliffs",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y
     );
 
     let start = Instant::now();
 
-    let dem_block_size_pixel = DEM_BLOCK_SIZE * config.dpi_resolution / INCH;
+    let mut dem = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "dem",
+        DEM_BLOCK_SIZE as f64,
+        DEM_NODATA_VALUE,
+    )?;
+    fill_nodata(&mut dem, FILL_NODATA_MAX_SEARCH_DISTANCE);
+    let slopes = compute_slopes(&dem);
 
-    let slopes_path = tile.render_dir_path.join("slopes.tif");
-    let slopes_tif_file = File::open(slopes_path).expect("Cannot find slopes tif image!");
+    let mut cliffs_mask: Vec<bool> = slopes
+        .data
+        .iter()
+        .map(|slope| !is_nodata(*slope) && *slope > config.cliff_threshold_1 as f64)
+        .collect();
 
-    let mut slopes_img_decoder = Decoder::new(slopes_tif_file).expect("Cannot create decoder");
-    slopes_img_decoder = slopes_img_decoder.with_limits(tiff::decoder::Limits::unlimited());
+    thin_mask(&mut cliffs_mask, slopes.width, slopes.height);
 
-    let (slopes_width, _) = slopes_img_decoder.dimensions().unwrap();
-    let mut cliffs_layer_canvas = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
+    let cliffs_dir = tile.render_dir_path.join("cliffs");
+    create_dir_all(&cliffs_dir)?;
+    let mut writer = create_cliffs_shapefile_writer(&cliffs_dir.join("cliffs.shp"))?;
+    let mut id: i32 = 0;
 
-    let DecodingResult::F32(image_data) = slopes_img_decoder.read_image().unwrap() else {
-        panic!("Cannot read band data")
-    };
+    for chain in trace_skeleton(&cliffs_mask, slopes.width, slopes.height) {
+        let points: Vec<(f64, f64)> = chain
+            .iter()
+            .map(|(x, y)| slopes.get_pixel_center(*x, *y))
+            .collect();
 
-    for index in 0..image_data.len() {
-        let x = index % slopes_width as usize;
-        let y = index / slopes_width as usize;
+        let mut points = simplify_polyline(&points, slopes.cell_size / 2.);
 
-        let x_pixel =
-            ((x as i64 - (BUFFER as f32 / DEM_BLOCK_SIZE) as i64) as f32 * dem_block_size_pixel) as i32;
-        let y_pixel =
-            ((y as i64 - (BUFFER as f32 / DEM_BLOCK_SIZE) as i64) as f32 * dem_block_size_pixel) as i32;
-
-        if x_pixel < 0 || x_pixel > image_width as i32 || y_pixel < 0 || y_pixel > image_height as i32 {
+        if get_polyline_length(&points) < MIN_CLIFF_LENGTH {
             continue;
         }
 
-        let slope = image_data[index];
+        if !is_downhill_on_the_right(&dem, &points) {
+            points.reverse();
+        }
+
+        let slope = chain
+            .iter()
+            .map(|(x, y)| slopes.get(*x, *y))
+            .filter(|slope| !is_nodata(*slope))
+            .fold(0., f64::max);
+
+        let height = get_cliff_height(&dem, &points);
+
+        // The neighbor tiles vectorize the same cliffs in their buffer, so only the parts inside the tile are
+        // kept, measured on the whole cliff so that they match across the tile edges
+        for part in clip_polyline(
+            &points,
+            (
+                tile.min_x as f64,
+                tile.min_y as f64,
+                tile.max_x as f64,
+                tile.max_y as f64,
+            ),
+        ) {
+            let record = CliffRecord { id, height, slope };
+
+            let polyline = Polyline::new(part.iter().map(|(x, y)| Point { x: *x, y: *y }).collect());
+            writer.write_shape_and_record(&polyline, &record)?;
+            id += 1;
+        }
+    }
+
+    let duration = start.elapsed();
 
-        let mut cliff_thickness: Option<f32> = None;
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. {} cliffs vectorized in {:.1?}",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y, id, duration
+    );
 
-        if slope > config.cliff_threshold_2 {
-            cliff_thickness = Some(CLIFF_THICKNESS_2);
-        } else if slope > config.cliff_threshold_1 {
-            cliff_thickness = Some(CLIFF_THICKNESS_1);
+    Ok(())
+}
+
+/// Returns true if the downhill side of the cliff is mostly on the right of the polyline, weighting the
+/// DEM aspect at the middle of every segment by the segment length.
+fn is_downhill_on_the_right(dem: &Raster, points: &Vec<(f64, f64)>) -> bool {
+    let mut score = 0.;
+
+    for segment in points.windows(2) {
+        let ((start_x, start_y), (end_x, end_y)) = (segment[0], segment[1]);
+
+        let Some((dz_dx, dz_dy)) = get_gradient_at(dem, (start_x + end_x) / 2., (start_y + end_y) / 2.)
+        else {
+            continue;
+        };
+
+        // The downhill direction is (-dz_dx, dz_dy) with the y axis going north, and the right of the
+        // segment is (end_y - start_y, start_x - end_x)
+        score += (end_y - start_y) * -dz_dx + (start_x - end_x) * dz_dy;
+    }
+
+    return score >= 0.;
+}
+
+/// Estimates the height of a cliff from the elevations sampled on both sides of its vertices, along the
+/// DEM aspect. The highest difference is kept.
+fn get_cliff_height(dem: &Raster, points: &Vec<(f64, f64)>) -> f64 {
+    let mut height: f64 = 0.;
+
+    for (x, y) in points {
+        let Some((dz_dx, dz_dy)) = get_gradient_at(dem, *x, *y) else {
+            continue;
+        };
+
+        let norm = (dz_dx * dz_dx + dz_dy * dz_dy).sqrt();
+
+        if norm == 0. {
+            continue;
         }
 
-        match cliff_thickness {
-            Some(thickness) => {
-                draw_filled_ellipse_mut(
-                    &mut cliffs_layer_canvas,
-                    (x_pixel, y_pixel),
-                    (thickness * config.dpi_resolution * 10.0 / INCH / 2.0) as i32,
-                    (thickness * config.dpi_resolution * 10.0 / INCH / 2.0) as i32,
-                    BLACK,
-                );
+        let distance = CLIFF_HEIGHT_SAMPLING_DISTANCE * dem.cell_size;
+        let downhill_x = -dz_dx / norm * distance;
+        let downhill_y = dz_dy / norm * distance;
+
+        let (Some(upper_elevation), Some(lower_elevation)) = (
+            get_elevation_at(dem, x - downhill_x, y - downhill_y),
+            get_elevation_at(dem, x + downhill_x, y + downhill_y),
+        ) else {
+            continue;
+        };
+
+        height = height.max(upper_elevation - lower_elevation);
+    }
+
+    return height;
+}
+
+fn get_gradient_at(dem: &Raster, x: f64, y: f64) -> Option<(f64, f64)> {
+    let (pixel_x, pixel_y) = dem.get_pixel_at(x, y)?;
+    get_horn_gradient(dem, pixel_x, pixel_y)
+}
+
+fn get_elevation_at(dem: &Raster, x: f64, y: f64) -> Option<f64> {
+    let (pixel_x, pixel_y) = dem.get_pixel_at(x, y)?;
+    let elevation = dem.get(pixel_x, pixel_y);
+
+    if is_nodata(elevation) {
+        return None;
+    }
+
+    return Some(elevation);
+}
+
+/// The attributes of the cliffs shapefile
+struct CliffRecord {
+    id: i32,
+    /// Elevation difference between the top and the bottom of the cliff, in meters
+    height: f64,
+    /// Steepest slope along the cliff, in degrees
+    slope: f64,
+}
+
+impl WritableRecord for CliffRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.height)?;
+        field_writer.write_next_field_value(&self.slope)?;
+        Ok(())
+    }
+}
+
+fn create_cliffs_shapefile_writer(path: &PathBuf) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("height").expect("Invalid field name"), 8, 2)
+        .add_numeric_field(FieldName::try_from("slope").expect("Invalid field name"), 8, 2);
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10 meters high step facing east, along the x = 1005 line.
+    fn get_step_dem() -> Raster {
+        let mut dem = Raster {
+            width: 10,
+            height: 10,
+            left: 1000.,
+            top: 2000.,
+            cell_size: 1.,
+            data: vec![],
+        };
+
+        for y in 0..dem.height {
+            for x in 0..dem.width {
+                let (easting, _) = dem.get_pixel_center(x, y);
+                dem.data.push(if easting < 1005. { 110. } else { 100. });
             }
-            _ => (),
         }
+
+        return dem;
     }
 
-    let cliffs_path = tile.render_dir_path.join("cliffs.png");
+    #[test]
+    fn cliff_orientation_keeps_downhill_side_on_the_right() {
+        let dem = get_step_dem();
+        let northward = vec![(1005., 1992.), (1005., 1995.), (1005., 1998.)];
+        let southward: Vec<(f64, f64)> = northward.iter().rev().cloned().collect();
 
-    cliffs_layer_canvas
-        .save(cliffs_path)
-        .expect("could not save cliffs png");
+        assert!(is_downhill_on_the_right(&dem, &northward));
+        assert!(!is_downhill_on_the_right(&dem, &southward));
+    }
 
-    let duration = start.elapsed();
+    #[test]
+    fn cliff_height_is_step_height() {
+        let dem = get_step_dem();
+        let points = vec![(1005., 1992.), (1005., 1998.)];
 
-    info!(
-        "Tile min_x={} min_y={} max_x={} max_y={}. Cliffs rendering done in {:.1?}",
-        tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
-    );
+        assert!((get_cliff_height(&dem, &points) - 10.).abs() < 1e-9);
+
+        // Away from the step, the DEM is flat
+        assert_eq!(get_cliff_height(&dem, &vec![(1008., 1992.), (1008., 1998.)]), 0.);
+    }
 }
@@ -0,0 +1,146 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, Stdio},
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::error::CassiniError;
+
+/// Default timeout for GDAL commands, which only process a tile and its neighbors.
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// PDAL pipelines read a whole LiDAR file and can be much slower.
+pub const PDAL_COMMAND_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+const POLLING_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct CommandOutput {
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Runs an external program, capturing its stdout and stderr.
+/// The program is killed if it runs longer than the given timeout.
+/// A program that cannot be started, exits with a failure status or times out results in an error.
+pub fn run_command(
+    program: &str,
+    args: &[&str],
+    stdin: Option<&str>,
+    timeout: Duration,
+) -> Result<CommandOutput, CassiniError> {
+    let command_line = get_command_line(program, args);
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| CassiniError::ExternalTool {
+            command: command_line.clone(),
+            exit_code: None,
+            stderr: format!("could not start {}: {}", program, error),
+        })?;
+
+    // Writing stdin and reading outputs on other threads, so that a full pipe never blocks the child
+    let stdin_handle = match (child.stdin.take(), stdin) {
+        (Some(mut child_stdin), Some(input)) => {
+            let input = input.to_owned();
+            Some(thread::spawn(move || {
+                let _ = child_stdin.write_all(input.as_bytes());
+            }))
+        }
+        _ => None,
+    };
+
+    let stdout_handle = spawn_pipe_reader(child.stdout.take());
+    let stderr_handle = spawn_pipe_reader(child.stderr.take());
+
+    let status = match wait_with_timeout(&mut child, timeout)? {
+        Some(status) => status,
+        None => {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            return Err(CassiniError::CommandTimeout {
+                command: command_line,
+                timeout,
+            });
+        }
+    };
+
+    if let Some(handle) = stdin_handle {
+        let _ = handle.join();
+    }
+
+    let output = CommandOutput {
+        stdout: join_pipe_reader(stdout_handle),
+        stderr: join_pipe_reader(stderr_handle),
+    };
+
+    if !status.success() {
+        return Err(CassiniError::ExternalTool {
+            command: command_line,
+            exit_code: status.code(),
+            stderr: output.stderr,
+        });
+    }
+
+    return Ok(output);
+}
+
+fn wait_with_timeout(
+    child: &mut Child,
+    timeout: Duration,
+) -> Result<Option<std::process::ExitStatus>, CassiniError> {
+    let start = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() >= timeout {
+            return Ok(None);
+        }
+
+        sleep(POLLING_INTERVAL);
+    }
+}
+
+fn spawn_pipe_reader<R: Read + Send + 'static>(pipe: Option<R>) -> Option<JoinHandle<Vec<u8>>> {
+    pipe.map(|mut pipe| {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = pipe.read_to_end(&mut buffer);
+            buffer
+        })
+    })
+}
+
+fn join_pipe_reader(handle: Option<JoinHandle<Vec<u8>>>) -> String {
+    handle
+        .and_then(|handle| handle.join().ok())
+        .map(|buffer| String::from_utf8_lossy(&buffer).to_string())
+        .unwrap_or_default()
+}
+
+fn get_command_line(program: &str, args: &[&str]) -> String {
+    let mut command_line = program.to_owned();
+
+    for arg in args {
+        command_line.push(' ');
+
+        if arg.contains(' ') {
+            command_line.push_str(&format!("{:?}", arg));
+        } else {
+            command_line.push_str(arg);
+        }
+    }
+
+    return command_line;
+}
@@ -1,4 +1,9 @@
 use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::{INCH, MIN_SMOOTHED_CONTOUR_VERTICES},
+    error::CassiniError,
+};
 use std::{
     fs::{self, File},
     io::Write,
@@ -12,13 +17,52 @@ const DEFAULT_LOW_VEGETATION_DENSITY_THRESHOLD: f32 = 1.; // Update the docs whe
 const DEFAULT_CLIFF_THRESHOLD_1: f32 = 60.; // Update the docs when modifying
 const DEFAULT_CLIFF_THRESHOLD_2: f32 = 60.; // Update the docs when modifying
 const DEFAULT_DPI_RESOLUTION: f32 = 600.0; // Update the docs when modifying
+const DEFAULT_ISOM_MAP_SCALE: u32 = 10000; // Update the docs when modifying
+const DEFAULT_ISSPROM_MAP_SCALE: u32 = 4000; // Update the docs when modifying
+const DEFAULT_SYMBOL_SET: SymbolSet = SymbolSet::Isom2017_2; // Update the docs when modifying
+
+const DEFAULT_VEGETATION_MODEL: VegetationModelKind = VegetationModelKind::Thresholds; // Update the docs when modifying
+const DEFAULT_FOREST_MIN_CANOPY_COVER: f32 = 0.6; // Update the docs when modifying
+const DEFAULT_SCATTERED_TREES_MIN_CANOPY_COVER: f32 = 0.15; // Update the docs when modifying
+const DEFAULT_ROUGH_OPEN_LAND_MIN_DENSITY: f32 = 1.0; // Update the docs when modifying
+
+const DEFAULT_CONTOURS_INTERVAL: f64 = 5.0; // Update the docs when modifying
+const DEFAULT_CONTOURS_INDEX_INTERVAL: f64 = 25.0; // Update the docs when modifying
+const DEFAULT_CONTOURS_SMOOTHING: f64 = 0.7; // Update the docs when modifying
+const DEFAULT_CONTOURS_CURVINESS: f64 = 1.1; // Update the docs when modifying
+const DEFAULT_CONTOURS_SMOOTHING_WINDOW: usize = 6; // Update the docs when modifying
+
+const DEFAULT_FORM_LINES_STEEPNESS: f64 = 0.37; // Update the docs when modifying
+const DEFAULT_FORM_LINES_ADDITION: f64 = 17.0; // Update the docs when modifying
+const DEFAULT_FORM_LINES_MIN_GAP: u32 = 30; // Update the docs when modifying
+const DEFAULT_FORM_LINES_DASH_LENGTH: f64 = 60.0; // Update the docs when modifying
+const DEFAULT_FORM_LINES_GAP_LENGTH: f64 = 12.0; // Update the docs when modifying
+
+const DEFAULT_KNOLL_MIN_HEIGHT: f64 = 1.0; // Update the docs when modifying
+const DEFAULT_KNOLL_MAX_SIZE: f64 = 8.0; // Update the docs when modifying
+const DEFAULT_BOULDER_MIN_HEIGHT: f64 = 1.0; // Update the docs when modifying
+const DEFAULT_BOULDER_MAX_SIZE: f64 = 3.0; // Update the docs when modifying
+const DEFAULT_DEPRESSION_MIN_DEPTH: f64 = 1.0; // Update the docs when modifying
+const DEFAULT_DEPRESSION_MAX_SIZE: f64 = 8.0; // Update the docs when modifying
+const DEFAULT_PIT_MIN_DEPTH: f64 = 1.0; // Update the docs when modifying
+const DEFAULT_PIT_MAX_SIZE: f64 = 3.0; // Update the docs when modifying
 
-const DEFAULT_FORM_LINES_THRESHOLD: f64 = 0.05; // Update the docs when modifying
-const DEFAULT_FORM_LINES_MIN_DISTANCE_TO_CONTOUR: f64 = 5.0; // Update the docs when modifying
-const DEFAULT_FORM_LINES_MAX_DISTANCE_TO_CONTOUR: f64 = 100.0; // Update the docs when modifying
-const DEFAULT_FORM_LINES_MIN_LENGTH: f64 = 10.0; // Update the docs when modifying
-const DEFAULT_FORM_LINES_MIN_GAP_LENGTH: f64 = 50.0; // Update the docs when modifying
-const DEFAULT_FORM_LINES_ADDITIONAL_TAIL_LENGTH: f64 = 15.0; // Update the docs when modifying
+const DEFAULT_STREAMS_ENABLED: bool = true; // Update the docs when modifying
+const DEFAULT_STREAMS_MINOR_CHANNEL_MIN_AREA: f64 = 50000.0; // Update the docs when modifying
+const DEFAULT_STREAMS_WATERCOURSE_MIN_AREA: f64 = 200000.0; // Update the docs when modifying
+const DEFAULT_STREAMS_SNAP_DISTANCE: f64 = 10.0; // Update the docs when modifying
+
+const DEFAULT_WATER_MIN_AREA: f64 = 100.0; // Update the docs when modifying
+const DEFAULT_PAVED_AREA_MIN_AREA: f64 = 200.0; // Update the docs when modifying
+const DEFAULT_PAVED_AREA_MAX_RELATIVE_INTENSITY: f64 = 0.5; // Update the docs when modifying
+
+const DEFAULT_BUILDINGS_MODE: BuildingsMode = BuildingsMode::Merge; // Update the docs when modifying
+const DEFAULT_BUILDINGS_MIN_AREA: f64 = 20.0; // Update the docs when modifying
+const DEFAULT_BUILDINGS_SIMPLIFICATION_TOLERANCE: f64 = 1.0; // Update the docs when modifying
+
+const DEFAULT_PRINT_LAYOUT_ENABLED: bool = false; // Update the docs when modifying
+const DEFAULT_PRINT_LAYOUT_MARGIN: f32 = 10.0; // Update the docs when modifying
+const DEFAULT_PRINT_LAYOUT_DECLINATION: f32 = 0.0; // Update the docs when modifying
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
@@ -38,49 +82,444 @@ pub struct Config {
     pub cliff_threshold_2: f32,
     #[serde(default = "default_dpi_resolution")]
     pub dpi_resolution: f32,
-    // #[serde(default = "FormLineConfig::default")]
-    // pub form_lines: FormLineConfig,
+    /// Defaults to 10000 with the ISOM symbol set and 4000 with the ISSprOM one, use get_map_scale to read it
+    #[serde(default)]
+    pub map_scale: Option<u32>,
+    #[serde(default = "default_symbol_set")]
+    pub symbol_set: SymbolSet,
+    #[serde(default)]
+    pub crs: Option<String>,
+    #[serde(default)]
+    pub vegetation: VegetationConfig,
+    #[serde(default)]
+    pub contours: ContoursConfig,
+    #[serde(default)]
+    pub form_lines: FormLinesConfig,
+    #[serde(default)]
+    pub micro_relief: MicroReliefConfig,
+    #[serde(default)]
+    pub streams: StreamsConfig,
+    #[serde(default)]
+    pub land_cover: LandCoverConfig,
+    #[serde(default)]
+    pub buildings: BuildingsConfig,
+    #[serde(default)]
+    pub print_layout: PrintLayoutConfig,
+}
+
+impl Config {
+    /// Returns the number of pixels of the rendered map for one meter on the ground.
+    pub fn get_pixels_per_meter(&self) -> f32 {
+        return self.dpi_resolution / INCH * (10000. / self.get_map_scale() as f32);
+    }
+
+    /// Returns the configured map scale, or the default one of the symbol set when it is not set.
+    pub fn get_map_scale(&self) -> u32 {
+        return self
+            .map_scale
+            .unwrap_or_else(|| get_default_map_scale(self.symbol_set));
+    }
+}
+
+/// The specification the symbol dimensions are taken from
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum SymbolSet {
+    /// International Specification for Orienteering Maps, for forest maps at 1:10000 or 1:15000
+    #[serde(rename = "isom-2017-2")]
+    Isom2017_2,
+    /// International Specification for Sprint Orienteering Maps, for urban maps at 1:3000 or 1:4000
+    #[serde(rename = "issprom-2019")]
+    Issprom2019,
+}
+
+/// The way the vegetation densities are turned into ground covers and runnability classes
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum VegetationModelKind {
+    /// Forest or open land from yellow_threshold, greens from the green thresholds
+    Thresholds,
+    /// Forest, open land with scattered trees, or open land from the canopy cover, rough open land from the
+    /// low vegetation density, greens from the green thresholds
+    CanopyCover,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VegetationConfig {
+    #[serde(default = "default_vegetation_model")]
+    pub model: VegetationModelKind,
+    #[serde(default = "default_forest_min_canopy_cover")]
+    pub forest_min_canopy_cover: f32,
+    #[serde(default = "default_scattered_trees_min_canopy_cover")]
+    pub scattered_trees_min_canopy_cover: f32,
+    #[serde(default = "default_rough_open_land_min_density")]
+    pub rough_open_land_min_density: f32,
+}
+
+impl Default for VegetationConfig {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_VEGETATION_MODEL,
+            forest_min_canopy_cover: DEFAULT_FOREST_MIN_CANOPY_COVER,
+            scattered_trees_min_canopy_cover: DEFAULT_SCATTERED_TREES_MIN_CANOPY_COVER,
+            rough_open_land_min_density: DEFAULT_ROUGH_OPEN_LAND_MIN_DENSITY,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ContoursConfig {
+    #[serde(default = "default_contours_interval")]
+    pub interval: f64,
+    #[serde(default = "default_contours_index_interval")]
+    pub index_interval: f64,
+    #[serde(default = "default_contours_smoothing")]
+    pub smoothing: f64,
+    #[serde(default = "default_contours_curviness")]
+    pub curviness: f64,
+    #[serde(default = "default_contours_smoothing_window")]
+    pub smoothing_window: usize,
+    #[serde(default)]
+    pub smoothing_regions: Vec<ContoursSmoothingRegionConfig>,
+}
+
+/// Smoothing parameters overriding the default ones for the contours inside the polygons of a shapefile
+#[derive(Serialize, Deserialize)]
+pub struct ContoursSmoothingRegionConfig {
+    pub mask: String,
+    #[serde(default)]
+    pub smoothing: Option<f64>,
+    #[serde(default)]
+    pub curviness: Option<f64>,
+    #[serde(default)]
+    pub smoothing_window: Option<usize>,
+}
+
+impl Default for ContoursConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_CONTOURS_INTERVAL,
+            index_interval: DEFAULT_CONTOURS_INDEX_INTERVAL,
+            smoothing: DEFAULT_CONTOURS_SMOOTHING,
+            curviness: DEFAULT_CONTOURS_CURVINESS,
+            smoothing_window: DEFAULT_CONTOURS_SMOOTHING_WINDOW,
+            smoothing_regions: vec![],
+        }
+    }
+}
+
+impl ContoursConfig {
+    /// Contours are traced every half interval, the intermediate ones being candidates for form lines.
+    pub fn half_interval(&self) -> f64 {
+        self.interval / 2.
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FormLinesConfig {
+    #[serde(default = "default_form_lines_steepness")]
+    pub steepness: f64,
+    #[serde(default = "default_form_lines_addition")]
+    pub addition: f64,
+    #[serde(default = "default_form_lines_min_gap")]
+    pub min_gap: u32,
+    #[serde(default = "default_form_lines_dash_length")]
+    pub dash_length: f64,
+    #[serde(default = "default_form_lines_gap_length")]
+    pub gap_length: f64,
+}
+
+impl Default for FormLinesConfig {
+    fn default() -> Self {
+        Self {
+            steepness: DEFAULT_FORM_LINES_STEEPNESS,
+            addition: DEFAULT_FORM_LINES_ADDITION,
+            min_gap: DEFAULT_FORM_LINES_MIN_GAP,
+            dash_length: DEFAULT_FORM_LINES_DASH_LENGTH,
+            gap_length: DEFAULT_FORM_LINES_GAP_LENGTH,
+        }
+    }
+}
+
+/// Thresholds of the knolls, boulders, depressions and pits detection. Sizes are footprint diameters,
+/// in meters.
+#[derive(Serialize, Deserialize)]
+pub struct MicroReliefConfig {
+    #[serde(default = "default_knoll_min_height")]
+    pub knoll_min_height: f64,
+    #[serde(default = "default_knoll_max_size")]
+    pub knoll_max_size: f64,
+    #[serde(default = "default_boulder_min_height")]
+    pub boulder_min_height: f64,
+    #[serde(default = "default_boulder_max_size")]
+    pub boulder_max_size: f64,
+    #[serde(default = "default_depression_min_depth")]
+    pub depression_min_depth: f64,
+    #[serde(default = "default_depression_max_size")]
+    pub depression_max_size: f64,
+    #[serde(default = "default_pit_min_depth")]
+    pub pit_min_depth: f64,
+    #[serde(default = "default_pit_max_size")]
+    pub pit_max_size: f64,
+}
+
+impl Default for MicroReliefConfig {
+    fn default() -> Self {
+        Self {
+            knoll_min_height: DEFAULT_KNOLL_MIN_HEIGHT,
+            knoll_max_size: DEFAULT_KNOLL_MAX_SIZE,
+            boulder_min_height: DEFAULT_BOULDER_MIN_HEIGHT,
+            boulder_max_size: DEFAULT_BOULDER_MAX_SIZE,
+            depression_min_depth: DEFAULT_DEPRESSION_MIN_DEPTH,
+            depression_max_size: DEFAULT_DEPRESSION_MAX_SIZE,
+            pit_min_depth: DEFAULT_PIT_MIN_DEPTH,
+            pit_max_size: DEFAULT_PIT_MAX_SIZE,
+        }
+    }
+}
+
+/// Parameters of the watercourses derived from the DEM. Areas are catchment areas, in square meters.
+#[derive(Serialize, Deserialize)]
+pub struct StreamsConfig {
+    #[serde(default = "default_streams_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_streams_minor_channel_min_area")]
+    pub minor_channel_min_area: f64,
+    #[serde(default = "default_streams_watercourse_min_area")]
+    pub watercourse_min_area: f64,
+    #[serde(default = "default_streams_snap_distance")]
+    pub snap_distance: f64,
+}
+
+impl Default for StreamsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: DEFAULT_STREAMS_ENABLED,
+            minor_channel_min_area: DEFAULT_STREAMS_MINOR_CHANNEL_MIN_AREA,
+            watercourse_min_area: DEFAULT_STREAMS_WATERCOURSE_MIN_AREA,
+            snap_distance: DEFAULT_STREAMS_SNAP_DISTANCE,
+        }
+    }
+}
+
+/// Parameters of the water and paved areas found in the LiDAR data. Areas are in square meters.
+#[derive(Serialize, Deserialize)]
+pub struct LandCoverConfig {
+    #[serde(default = "default_water_min_area")]
+    pub water_min_area: f64,
+    #[serde(default = "default_paved_area_min_area")]
+    pub paved_area_min_area: f64,
+    #[serde(default = "default_paved_area_max_relative_intensity")]
+    pub paved_area_max_relative_intensity: f64,
+}
+
+impl Default for LandCoverConfig {
+    fn default() -> Self {
+        Self {
+            water_min_area: DEFAULT_WATER_MIN_AREA,
+            paved_area_min_area: DEFAULT_PAVED_AREA_MIN_AREA,
+            paved_area_max_relative_intensity: DEFAULT_PAVED_AREA_MAX_RELATIVE_INTENSITY,
+        }
+    }
 }
 
+/// How the buildings found in the LiDAR data are combined with the OSM ones
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildingsMode {
+    /// Only the OSM buildings are drawn
+    Osm,
+    /// The LiDAR buildings are drawn where no OSM building is
+    Merge,
+    /// Only the LiDAR buildings are drawn
+    Replace,
+}
+
+/// Parameters of the buildings found in the LiDAR data
 #[derive(Serialize, Deserialize)]
-pub struct _FormLineConfig {
-    #[serde(default = "default_form_lines_threshold")]
-    pub threshold: f64,
-    #[serde(default = "default_form_lines_min_distance_to_contour")]
-    pub min_distance_to_contour: f64,
-    #[serde(default = "default_form_lines_max_distance_to_contour")]
-    pub max_distance_to_contour: f64,
-    #[serde(default = "default_form_lines_min_length")]
-    pub min_length: f64,
-    #[serde(default = "default_form_lines_min_gap_length")]
-    pub min_gap_length: f64,
-    #[serde(default = "default_form_lines_additional_tail_length")]
-    pub additional_tail_length: f64,
-}
-
-impl _FormLineConfig {
-    fn _default() -> Self {
+pub struct BuildingsConfig {
+    #[serde(default = "default_buildings_mode")]
+    pub mode: BuildingsMode,
+    #[serde(default = "default_buildings_min_area")]
+    pub min_area: f64,
+    #[serde(default = "default_buildings_simplification_tolerance")]
+    pub simplification_tolerance: f64,
+}
+
+impl Default for BuildingsConfig {
+    fn default() -> Self {
         Self {
-            threshold: DEFAULT_FORM_LINES_THRESHOLD,
-            min_distance_to_contour: DEFAULT_FORM_LINES_MIN_DISTANCE_TO_CONTOUR,
-            max_distance_to_contour: DEFAULT_FORM_LINES_MAX_DISTANCE_TO_CONTOUR,
-            min_length: DEFAULT_FORM_LINES_MIN_LENGTH,
-            min_gap_length: DEFAULT_FORM_LINES_MIN_GAP_LENGTH,
-            additional_tail_length: DEFAULT_FORM_LINES_ADDITIONAL_TAIL_LENGTH,
+            mode: DEFAULT_BUILDINGS_MODE,
+            min_area: DEFAULT_BUILDINGS_MIN_AREA,
+            simplification_tolerance: DEFAULT_BUILDINGS_SIMPLIFICATION_TOLERANCE,
         }
     }
 }
 
-pub fn get_config() -> Config {
+/// Parameters of the print layout, the map face framed with a title block, a scale bar, magnetic north
+/// lines, a legend and the attributions
+#[derive(Serialize, Deserialize)]
+pub struct PrintLayoutConfig {
+    #[serde(default = "default_print_layout_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub title: String,
+    /// Margin around the map face and the side panel, in millimeters
+    #[serde(default = "default_print_layout_margin")]
+    pub margin: f32,
+    /// Magnetic declination, the angle from the true north to the magnetic north, in degrees, positive to the
+    /// east
+    #[serde(default = "default_print_layout_declination")]
+    pub declination: f32,
+    #[serde(default)]
+    pub lidar_attribution: String,
+}
+
+impl Default for PrintLayoutConfig {
+    fn default() -> Self {
+        Self {
+            enabled: DEFAULT_PRINT_LAYOUT_ENABLED,
+            title: String::new(),
+            margin: DEFAULT_PRINT_LAYOUT_MARGIN,
+            declination: DEFAULT_PRINT_LAYOUT_DECLINATION,
+            lidar_attribution: String::new(),
+        }
+    }
+}
+
+pub fn get_config() -> Result<Config, CassiniError> {
     let raw_config = fs::read_to_string("./config.json").unwrap_or("{}".to_owned());
-    return serde_json::from_str(&raw_config).unwrap();
+    let config: Config = serde_json::from_str(&raw_config)?;
+    validate_map_scale(&config)?;
+    validate_vegetation_config(&config.vegetation)?;
+    validate_contours_config(&config.contours)?;
+    validate_streams_config(&config.streams)?;
+    validate_print_layout_config(&config.print_layout)?;
+
+    return Ok(config);
 }
 
-pub fn default_config() {
-    let default_config: Config = serde_json::from_str("{}").unwrap();
-    let json_string = serde_json::to_string_pretty(&default_config).unwrap();
-    let mut file = File::create("config.json").unwrap();
-    file.write_all(json_string.as_bytes()).unwrap();
+fn validate_map_scale(config: &Config) -> Result<(), CassiniError> {
+    let (symbol_set_name, allowed_scales) = match config.symbol_set {
+        SymbolSet::Isom2017_2 => ("isom-2017-2", [10000, 15000]),
+        SymbolSet::Issprom2019 => ("issprom-2019", [3000, 4000]),
+    };
+
+    if !allowed_scales.contains(&config.get_map_scale()) {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: map_scale should be {} or {} with the {} symbol set, got {}",
+            allowed_scales[0],
+            allowed_scales[1],
+            symbol_set_name,
+            config.get_map_scale()
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_vegetation_config(vegetation_config: &VegetationConfig) -> Result<(), CassiniError> {
+    let canopy_covers = [
+        (
+            "forest_min_canopy_cover",
+            vegetation_config.forest_min_canopy_cover,
+        ),
+        (
+            "scattered_trees_min_canopy_cover",
+            vegetation_config.scattered_trees_min_canopy_cover,
+        ),
+    ];
+
+    for (name, canopy_cover) in canopy_covers {
+        if !(0. ..=1.).contains(&canopy_cover) {
+            return Err(CassiniError::InvalidData(format!(
+                "Invalid config.json file: vegetation.{} should be between 0 and 1, got {}",
+                name, canopy_cover
+            )));
+        }
+    }
+
+    if vegetation_config.scattered_trees_min_canopy_cover > vegetation_config.forest_min_canopy_cover {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: vegetation.scattered_trees_min_canopy_cover ({}) should not be greater than vegetation.forest_min_canopy_cover ({})",
+            vegetation_config.scattered_trees_min_canopy_cover, vegetation_config.forest_min_canopy_cover
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_contours_config(contours_config: &ContoursConfig) -> Result<(), CassiniError> {
+    if contours_config.interval.is_nan() || contours_config.interval <= 0. {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: contours.interval should be positive, got {}",
+            contours_config.interval
+        )));
+    }
+
+    let intervals_per_index = contours_config.index_interval / contours_config.interval;
+
+    if intervals_per_index < 1. || (intervals_per_index - intervals_per_index.round()).abs() > 1e-6 {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: contours.index_interval ({}) should be a multiple of contours.interval ({})",
+            contours_config.index_interval, contours_config.interval
+        )));
+    }
+
+    let smoothing_windows = std::iter::once(contours_config.smoothing_window).chain(
+        contours_config
+            .smoothing_regions
+            .iter()
+            .filter_map(|region| region.smoothing_window),
+    );
+
+    for smoothing_window in smoothing_windows {
+        if !(1..=MIN_SMOOTHED_CONTOUR_VERTICES).contains(&smoothing_window) {
+            return Err(CassiniError::InvalidData(format!(
+                "Invalid config.json file: contours smoothing_window should be between 1 and {}, got {}",
+                MIN_SMOOTHED_CONTOUR_VERTICES, smoothing_window
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_streams_config(streams_config: &StreamsConfig) -> Result<(), CassiniError> {
+    if streams_config.minor_channel_min_area.is_nan() || streams_config.minor_channel_min_area <= 0. {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: streams.minor_channel_min_area should be positive, got {}",
+            streams_config.minor_channel_min_area
+        )));
+    }
+
+    if streams_config.snap_distance.is_nan() || streams_config.snap_distance < 0. {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: streams.snap_distance should not be negative, got {}",
+            streams_config.snap_distance
+        )));
+    }
+
+    Ok(())
+}
+
+fn validate_print_layout_config(print_layout_config: &PrintLayoutConfig) -> Result<(), CassiniError> {
+    if print_layout_config.margin.is_nan() || print_layout_config.margin < 0. {
+        return Err(CassiniError::InvalidData(format!(
+            "Invalid config.json file: print_layout.margin should not be negative, got {}",
+            print_layout_config.margin
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn default_config() -> Result<(), CassiniError> {
+    let default_config: Config = serde_json::from_str("{}")?;
+    let json_string = serde_json::to_string_pretty(&default_config)?;
+    let mut file = File::create("config.json")?;
+    file.write_all(json_string.as_bytes())?;
+
+    Ok(())
 }
 
 fn default_yellow_threshold() -> f32 {
@@ -115,26 +554,153 @@ fn default_dpi_resolution() -> f32 {
     DEFAULT_DPI_RESOLUTION
 }
 
-fn default_form_lines_threshold() -> f64 {
-    DEFAULT_FORM_LINES_THRESHOLD
+fn get_default_map_scale(symbol_set: SymbolSet) -> u32 {
+    match symbol_set {
+        SymbolSet::Isom2017_2 => DEFAULT_ISOM_MAP_SCALE,
+        SymbolSet::Issprom2019 => DEFAULT_ISSPROM_MAP_SCALE,
+    }
+}
+
+fn default_symbol_set() -> SymbolSet {
+    DEFAULT_SYMBOL_SET
+}
+
+fn default_contours_interval() -> f64 {
+    DEFAULT_CONTOURS_INTERVAL
+}
+
+fn default_contours_index_interval() -> f64 {
+    DEFAULT_CONTOURS_INDEX_INTERVAL
+}
+
+fn default_contours_smoothing() -> f64 {
+    DEFAULT_CONTOURS_SMOOTHING
+}
+
+fn default_contours_curviness() -> f64 {
+    DEFAULT_CONTOURS_CURVINESS
+}
+
+fn default_contours_smoothing_window() -> usize {
+    DEFAULT_CONTOURS_SMOOTHING_WINDOW
+}
+
+fn default_form_lines_steepness() -> f64 {
+    DEFAULT_FORM_LINES_STEEPNESS
+}
+
+fn default_form_lines_addition() -> f64 {
+    DEFAULT_FORM_LINES_ADDITION
+}
+
+fn default_form_lines_min_gap() -> u32 {
+    DEFAULT_FORM_LINES_MIN_GAP
+}
+
+fn default_form_lines_dash_length() -> f64 {
+    DEFAULT_FORM_LINES_DASH_LENGTH
+}
+
+fn default_form_lines_gap_length() -> f64 {
+    DEFAULT_FORM_LINES_GAP_LENGTH
+}
+
+fn default_knoll_min_height() -> f64 {
+    DEFAULT_KNOLL_MIN_HEIGHT
+}
+
+fn default_knoll_max_size() -> f64 {
+    DEFAULT_KNOLL_MAX_SIZE
+}
+
+fn default_boulder_min_height() -> f64 {
+    DEFAULT_BOULDER_MIN_HEIGHT
+}
+
+fn default_boulder_max_size() -> f64 {
+    DEFAULT_BOULDER_MAX_SIZE
+}
+
+fn default_depression_min_depth() -> f64 {
+    DEFAULT_DEPRESSION_MIN_DEPTH
+}
+
+fn default_depression_max_size() -> f64 {
+    DEFAULT_DEPRESSION_MAX_SIZE
+}
+
+fn default_pit_min_depth() -> f64 {
+    DEFAULT_PIT_MIN_DEPTH
+}
+
+fn default_pit_max_size() -> f64 {
+    DEFAULT_PIT_MAX_SIZE
+}
+
+fn default_streams_enabled() -> bool {
+    DEFAULT_STREAMS_ENABLED
+}
+
+fn default_streams_minor_channel_min_area() -> f64 {
+    DEFAULT_STREAMS_MINOR_CHANNEL_MIN_AREA
+}
+
+fn default_streams_watercourse_min_area() -> f64 {
+    DEFAULT_STREAMS_WATERCOURSE_MIN_AREA
+}
+
+fn default_streams_snap_distance() -> f64 {
+    DEFAULT_STREAMS_SNAP_DISTANCE
+}
+
+fn default_water_min_area() -> f64 {
+    DEFAULT_WATER_MIN_AREA
+}
+
+fn default_paved_area_min_area() -> f64 {
+    DEFAULT_PAVED_AREA_MIN_AREA
+}
+
+fn default_paved_area_max_relative_intensity() -> f64 {
+    DEFAULT_PAVED_AREA_MAX_RELATIVE_INTENSITY
+}
+
+fn default_buildings_mode() -> BuildingsMode {
+    DEFAULT_BUILDINGS_MODE
+}
+
+fn default_buildings_min_area() -> f64 {
+    DEFAULT_BUILDINGS_MIN_AREA
+}
+
+fn default_buildings_simplification_tolerance() -> f64 {
+    DEFAULT_BUILDINGS_SIMPLIFICATION_TOLERANCE
+}
+
+fn default_vegetation_model() -> VegetationModelKind {
+    DEFAULT_VEGETATION_MODEL
+}
+
+fn default_forest_min_canopy_cover() -> f32 {
+    DEFAULT_FOREST_MIN_CANOPY_COVER
 }
 
-fn default_form_lines_min_distance_to_contour() -> f64 {
-    DEFAULT_FORM_LINES_MIN_DISTANCE_TO_CONTOUR
+fn default_scattered_trees_min_canopy_cover() -> f32 {
+    DEFAULT_SCATTERED_TREES_MIN_CANOPY_COVER
 }
 
-fn default_form_lines_max_distance_to_contour() -> f64 {
-    DEFAULT_FORM_LINES_MAX_DISTANCE_TO_CONTOUR
+fn default_rough_open_land_min_density() -> f32 {
+    DEFAULT_ROUGH_OPEN_LAND_MIN_DENSITY
 }
 
-fn default_form_lines_min_length() -> f64 {
-    DEFAULT_FORM_LINES_MIN_LENGTH
+fn default_print_layout_enabled() -> bool {
+    DEFAULT_PRINT_LAYOUT_ENABLED
 }
 
-fn default_form_lines_min_gap_length() -> f64 {
-    DEFAULT_FORM_LINES_MIN_GAP_LENGTH
+fn default_print_layout_margin() -> f32 {
+    DEFAULT_PRINT_LAYOUT_MARGIN
 }
 
-fn default_form_lines_additional_tail_length() -> f64 {
-    DEFAULT_FORM_LINES_ADDITIONAL_TAIL_LENGTH
+fn default_print_layout_declination() -> f32 {
+    DEFAULT_PRINT_LAYOUT_DECLINATION
 }
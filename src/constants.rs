@@ -4,15 +4,16 @@ pub const DEM_BLOCK_SIZE: f32 = 0.5;
 pub const VEGETATION_BLOCK_SIZE: u32 = 1;
 pub const INCH: f32 = 254.0;
 pub const BUFFER: usize = 200;
+pub const DEM_NODATA_VALUE: f64 = -9999.;
 pub const COASTLINE_EDGE_BUFFER: f32 = 10.; // Should be lower than osm download buffer
 
 pub const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
 pub const TRANSPARENT: Rgba<u8> = Rgba([255, 255, 255, 0]);
 pub const YELLOW: Rgba<u8> = Rgba([255, 221, 154, 255]);
+pub const ROUGH_OPEN_LAND_YELLOW: Rgba<u8> = Rgba([255, 238, 204, 255]);
 pub const GREEN_1: Rgba<u8> = Rgba([197, 255, 185, 255]);
 pub const GREEN_2: Rgba<u8> = Rgba([139, 255, 116, 255]);
 pub const GREEN_3: Rgba<u8> = Rgba([61, 255, 23, 255]);
-pub const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
 pub const BROWN: Rgba<u8> = Rgba([209, 92, 0, 255]);
 pub const PURPLE: Rgba<u8> = Rgba([166, 38, 255, 255]);
 
@@ -20,16 +21,32 @@ pub const VECTOR_WHITE: (u8, u8, u8) = (255, 255, 255);
 pub const VECTOR_BLUE: (u8, u8, u8) = (0, 255, 255);
 pub const VECTOR_BLACK: (u8, u8, u8) = (0, 0, 0);
 pub const VECTOR_BUILDING_GRAY: (u8, u8, u8) = (89, 89, 89);
+pub const VECTOR_BUILDING_PASSAGE_GRAY: (u8, u8, u8) = (191, 191, 191);
 pub const VECTOR_PAVED_AREA_BROWN: (u8, u8, u8) = (232, 174, 128);
 pub const VECTOR_OLIVE_GREEN: (u8, u8, u8) = (158, 186, 29);
+pub const VECTOR_BROWN: (u8, u8, u8) = (209, 92, 0);
+pub const VECTOR_GREEN: (u8, u8, u8) = (61, 255, 23);
 
 pub const CLIFF_THICKNESS_1: f32 = 0.25;
 pub const CLIFF_THICKNESS_2: f32 = 0.35;
-pub const _CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.14;
-pub const _MASTER_CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.25;
-pub const _FORM_CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.1;
-pub const _FORM_CONTOUR_DASH_LENGTH: f32 = 2.0;
-pub const _FORM_CONTOUR_DASH_INTERVAL_LENGTH: f32 = 0.2;
+pub const CLIFF_TAG_WIDTH: f32 = 0.18;
+pub const CLIFF_TAG_LENGTH: f32 = 0.5;
+pub const CLIFF_TAG_SPACING: f32 = 1.0;
+pub const SMALL_KNOLL_DIAMETER: f32 = 0.75;
+pub const SMALL_DEPRESSION_WIDTH: f32 = 1.0;
+pub const SMALL_DEPRESSION_HEIGHT: f32 = 0.6;
+pub const SMALL_DEPRESSION_LINE_WIDTH: f32 = 0.25;
+pub const PIT_WIDTH: f32 = 0.8;
+pub const PIT_HEIGHT: f32 = 0.6;
+pub const PIT_LINE_WIDTH: f32 = 0.18;
+pub const BOULDER_DIAMETER: f32 = 0.4;
+pub const SCATTERED_TREES_DOT_DIAMETER: f32 = 0.4;
+pub const SCATTERED_TREES_DOT_SPACING: f32 = 0.8;
+pub const CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.14;
+pub const MASTER_CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.25;
+pub const FORM_CONTOUR_THICKNESS_MILLIMETTER: f32 = 0.1;
+pub const FORM_CONTOUR_DASH_LENGTH: f32 = 2.0;
+pub const FORM_CONTOUR_DASH_INTERVAL_LENGTH: f32 = 0.2;
 pub const INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH: f32 = 0.18;
 pub const BUILDING_OUTLINE_WIDTH: f32 = 0.2;
 pub const WIDE_ROAD_INNER_WIDTH: f32 = 0.45;
@@ -61,4 +78,23 @@ pub const POWERLINE_WIDTH: f32 = 0.14;
 pub const _MAJOR_POWERLINE_INNER_WIDTH: f32 = 0.4;
 pub const _MAJOR_POWERLINE_OUTER_WIDTH: f32 = 0.68;
 
+// ISSprOM 2019-2 dimensions, where they differ from the ISOM ones above
+pub const ISSPROM_CLIFF_THICKNESS_2: f32 = 0.5;
+pub const ISSPROM_BOULDER_DIAMETER: f32 = 0.6;
+pub const ISSPROM_INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH: f32 = 0.25;
+pub const ISSPROM_BUILDING_OUTLINE_WIDTH: f32 = 0.14;
+
+// ISSprOM 2019-2 urban symbols
+pub const IMPASSABLE_WALL_WIDTH: f32 = 0.5;
+pub const FENCE_WIDTH: f32 = 0.14;
+pub const FENCE_TAG_LENGTH: f32 = 0.4;
+pub const FENCE_TAG_SPACING: f32 = 1.5;
+pub const IMPASSABLE_FENCE_WIDTH: f32 = 0.25;
+pub const IMPASSABLE_FENCE_TAG_LENGTH: f32 = 0.4;
+pub const IMPASSABLE_FENCE_TAG_SPACING: f32 = 1.0;
+pub const STEP_OR_EDGE_OF_PAVED_AREA_WIDTH: f32 = 0.14;
+
 pub const MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT: i32 = 15_000;
+
+/// Contours with fewer vertices are discarded before smoothing
+pub const MIN_SMOOTHED_CONTOUR_VERTICES: usize = 15;
@@ -1,26 +1,48 @@
 use crate::{
     config::Config,
+    constants::BUFFER,
+    error::CassiniError,
+    marching_squares::trace_contours_with_marching_squares,
     pullautin_contours_render::pullautin_cull_formlines_render_contours,
     pullautin_smooth_contours::{get_elevation_matrix_from_dem, pullautin_smooth_contours},
+    symbol_styles::SymbolStyles,
     tile::Tile,
 };
+use std::path::PathBuf;
+
+const DEM_LOW_RESOLUTION_CELL_SIZE: f64 = 2.0;
 
 pub fn generate_contours_with_pullautin_algorithme(
     tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
     image_width: u32,
     image_height: u32,
     config: &Config,
-) {
-    let avg_alt = get_elevation_matrix_from_dem(tile);
+    symbol_styles: &SymbolStyles,
+) -> Result<(), CassiniError> {
+    let avg_alt = get_elevation_matrix_from_dem(tile, neighbor_tiles)?;
+
+    // avg_alt[x][y] holds the center of the pixel at column x and row (height - y) of the dem
+    let origin_x = (tile.min_x - BUFFER as i64) as f64 + DEM_LOW_RESOLUTION_CELL_SIZE / 2.;
+    let origin_y = (tile.min_y - BUFFER as i64) as f64 - DEM_LOW_RESOLUTION_CELL_SIZE / 2.;
+
+    let contours = trace_contours_with_marching_squares(
+        &avg_alt,
+        origin_x,
+        origin_y,
+        DEM_LOW_RESOLUTION_CELL_SIZE,
+        config.contours.half_interval(),
+    );
 
-    let smoothed_contours = pullautin_smooth_contours(&tile, &avg_alt);
+    let smoothed_contours = pullautin_smooth_contours(&tile, &avg_alt, contours, &config.contours)?;
 
     pullautin_cull_formlines_render_contours(
         &tile,
         image_width,
         image_height,
         &config,
+        symbol_styles,
         &avg_alt,
         smoothed_contours,
-    );
+    )
 }
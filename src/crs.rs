@@ -0,0 +1,230 @@
+use las::Reader;
+use log::{error, warn};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    command::{run_command, DEFAULT_COMMAND_TIMEOUT},
+    config::Config,
+    error::CassiniError,
+};
+
+// Lambert 93, used when neither the config nor the LiDAR file define a CRS
+pub const DEFAULT_CRS: &str = "EPSG:2154";
+
+const LASF_PROJECTION_USER_ID: &str = "LASF_Projection";
+const GEO_KEY_DIRECTORY_RECORD_ID: u16 = 34735;
+const OGC_WKT_RECORD_ID: u16 = 2112;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const GEOGRAPHIC_TYPE_GEO_KEY: u16 = 2048;
+const USER_DEFINED_GEO_KEY_VALUE: u16 = 32767;
+
+/// Returns the CRS to use for a LiDAR file: the `crs` key of the config if it is set,
+/// otherwise the CRS found in the LAZ header, otherwise Lambert 93.
+pub fn resolve_crs_for_laz_file(laz_path: &PathBuf, config: &Config) -> String {
+    if let Some(crs) = &config.crs {
+        return crs.clone();
+    }
+
+    match get_crs_from_laz_file(laz_path) {
+        Some(crs) => crs,
+        None => {
+            warn!(
+                "No CRS found in {:?} header, falling back to {}. Set the \"crs\" key in config.json to override it",
+                laz_path, DEFAULT_CRS
+            );
+
+            DEFAULT_CRS.to_owned()
+        }
+    }
+}
+
+/// Reads the CRS from the GeoTIFF keys or the WKT VLRs of a LAZ file.
+/// Returns an "EPSG:XXXX" string when an EPSG code is available, the raw WKT otherwise.
+pub fn get_crs_from_laz_file(laz_path: &PathBuf) -> Option<String> {
+    let reader = match Reader::from_path(laz_path) {
+        Ok(reader) => reader,
+        Err(error) => {
+            error!("Could not read {:?} header to find its CRS: {}", laz_path, error);
+            return None;
+        }
+    };
+
+    let header = reader.header();
+    let vlrs = header.vlrs().iter().chain(header.evlrs().iter());
+
+    let mut wkt_crs: Option<String> = None;
+    let mut geo_keys_crs: Option<String> = None;
+
+    for vlr in vlrs {
+        if vlr.user_id != LASF_PROJECTION_USER_ID {
+            continue;
+        }
+
+        if vlr.record_id == OGC_WKT_RECORD_ID {
+            let wkt = String::from_utf8_lossy(&vlr.data)
+                .trim_end_matches('\0')
+                .trim()
+                .to_owned();
+
+            if !wkt.is_empty() {
+                wkt_crs = Some(wkt);
+            }
+        }
+
+        if vlr.record_id == GEO_KEY_DIRECTORY_RECORD_ID {
+            geo_keys_crs =
+                get_epsg_code_from_geo_key_directory(&vlr.data).map(|code| format!("EPSG:{}", code));
+        }
+    }
+
+    if let Some(wkt) = wkt_crs {
+        return Some(match get_epsg_code_from_wkt(&wkt) {
+            Some(code) => format!("EPSG:{}", code),
+            None => wkt,
+        });
+    }
+
+    return geo_keys_crs;
+}
+
+fn get_epsg_code_from_geo_key_directory(data: &[u8]) -> Option<u16> {
+    let keys: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+
+    if keys.len() < 4 {
+        return None;
+    }
+
+    let number_of_keys = keys[3] as usize;
+    let mut geographic_code: Option<u16> = None;
+
+    for key_index in 0..number_of_keys {
+        let offset = 4 + key_index * 4;
+
+        if offset + 3 >= keys.len() {
+            break;
+        }
+
+        let (key_id, tiff_tag_location, value) = (keys[offset], keys[offset + 1], keys[offset + 3]);
+
+        // Values stored in other tags are not EPSG codes
+        if tiff_tag_location != 0 || value == 0 || value == USER_DEFINED_GEO_KEY_VALUE {
+            continue;
+        }
+
+        if key_id == PROJECTED_CS_TYPE_GEO_KEY {
+            return Some(value);
+        }
+
+        if key_id == GEOGRAPHIC_TYPE_GEO_KEY {
+            geographic_code = Some(value);
+        }
+    }
+
+    return geographic_code;
+}
+
+/// Extracts the EPSG code of the root element of a WKT string (its last AUTHORITY or ID node).
+pub fn get_epsg_code_from_wkt(wkt: &str) -> Option<u32> {
+    let wkt = wkt.trim();
+    let authority_index = wkt
+        .rfind("AUTHORITY[")
+        .or_else(|| wkt.rfind(",ID[").map(|index| index + 1))?;
+
+    // Making sure the authority belongs to the root element and not to a nested one
+    let depth = wkt[..authority_index]
+        .chars()
+        .fold(0i32, |depth, character| match character {
+            '[' => depth + 1,
+            ']' => depth - 1,
+            _ => depth,
+        });
+
+    if depth != 1 {
+        return None;
+    }
+
+    let parts: Vec<&str> = wkt[authority_index..]
+        .split(|character| character == '[' || character == ',' || character == ']')
+        .map(|part| part.trim().trim_matches('"'))
+        .collect();
+
+    if parts.len() < 3 || !parts[1].eq_ignore_ascii_case("EPSG") {
+        return None;
+    }
+
+    return parts[2].parse::<u32>().ok();
+}
+
+pub fn write_crs_file(output_dir_path: &PathBuf, crs: &str) -> Result<(), std::io::Error> {
+    let mut crs_file = File::create(output_dir_path.join("crs.txt"))?;
+    crs_file.write_all(crs.as_bytes())
+}
+
+/// Returns the CRS of a LiDAR step output directory: the `crs` key of the config if it is set,
+/// otherwise the content of the crs.txt file, otherwise Lambert 93.
+pub fn get_crs_from_lidar_dir_path(lidar_dir_path: &PathBuf, config: &Config) -> String {
+    if let Some(crs) = &config.crs {
+        return crs.clone();
+    }
+
+    let crs_file_path = lidar_dir_path.join("crs.txt");
+
+    let mut crs = String::new();
+
+    let is_crs_file_read = File::open(&crs_file_path)
+        .and_then(|mut file| file.read_to_string(&mut crs))
+        .is_ok();
+
+    if !is_crs_file_read || crs.trim().is_empty() {
+        warn!(
+            "No crs.txt file found in {:?}, falling back to {}",
+            lidar_dir_path, DEFAULT_CRS
+        );
+
+        return DEFAULT_CRS.to_owned();
+    }
+
+    return crs.trim().to_owned();
+}
+
+/// Writes a .prj sidecar file containing the ESRI WKT definition of the CRS.
+pub fn create_prj_file(crs: &str, prj_file_path: &PathBuf) -> Result<(), CassiniError> {
+    if is_wkt(crs) {
+        std::fs::write(prj_file_path, crs)?;
+        return Ok(());
+    }
+
+    let gdalsrsinfo_output = run_command(
+        "gdalsrsinfo",
+        &["-o", "wkt1_esri", "--single-line", crs],
+        None,
+        DEFAULT_COMMAND_TIMEOUT,
+    )?;
+
+    std::fs::write(prj_file_path, gdalsrsinfo_output.stdout.trim())?;
+
+    Ok(())
+}
+
+fn is_wkt(crs: &str) -> bool {
+    let trimmed_crs = crs.trim_start();
+
+    [
+        "PROJCS[",
+        "GEOGCS[",
+        "COMPD_CS[",
+        "PROJCRS[",
+        "GEOGCRS[",
+        "COMPOUNDCRS[",
+        "BOUNDCRS[",
+    ]
+    .iter()
+    .any(|prefix| trimmed_crs.starts_with(prefix))
+}
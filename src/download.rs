@@ -4,14 +4,25 @@ use std::{
     fs::File,
     io::{copy, BufRead, BufReader, BufWriter, Write},
     path::PathBuf,
-    process::{Command, Stdio},
     thread::sleep,
     time::Instant,
 };
 
-use crate::constants::BUFFER;
+use crate::{
+    command::{run_command, DEFAULT_COMMAND_TIMEOUT},
+    constants::BUFFER,
+    error::CassiniError,
+    projection::Projection,
+};
 
-pub fn download_osm_file(min_x: i64, min_y: i64, max_x: i64, max_y: i64, output_dir_path: &PathBuf) {
+pub fn download_osm_file(
+    min_x: i64,
+    min_y: i64,
+    max_x: i64,
+    max_y: i64,
+    crs: &str,
+    output_dir_path: &PathBuf,
+) -> Result<(), CassiniError> {
     let raw_osm_file_path = output_dir_path.join(format!("{:0>7}_{:0>7}_raw.osm", min_x, max_y));
     let osm_file_path = output_dir_path.join(format!("{:0>7}_{:0>7}.osm", min_x, max_y));
 
@@ -22,11 +33,17 @@ pub fn download_osm_file(min_x: i64, min_y: i64, max_x: i64, max_y: i64, output_
 
     let start = Instant::now();
 
-    let (min_lon, min_lat) =
-        convert_coords_from_lambert_93_to_gps((min_x - BUFFER as i64) as f64, (min_y - BUFFER as i64) as f64);
+    let (min_lon, min_lat) = convert_coords_to_gps(
+        (min_x - BUFFER as i64) as f64,
+        (min_y - BUFFER as i64) as f64,
+        crs,
+    )?;
 
-    let (max_lon, max_lat) =
-        convert_coords_from_lambert_93_to_gps((max_x + BUFFER as i64) as f64, (max_y + BUFFER as i64) as f64);
+    let (max_lon, max_lat) = convert_coords_to_gps(
+        (max_x + BUFFER as i64) as f64,
+        (max_y + BUFFER as i64) as f64,
+        crs,
+    )?;
 
     // Overpass Query
     let query = r#"
@@ -78,7 +95,13 @@ out skel qt;
                 }
 
                 if retries_left == 0 {
-                    panic!("Overpass API returned error status: {}", status);
+                    return Err(match response.error_for_status() {
+                        Err(error) => CassiniError::Network(error),
+                        Ok(_) => CassiniError::InvalidData(format!(
+                            "Overpass API returned unexpected status: {}",
+                            status
+                        )),
+                    });
                 }
 
                 warn!(
@@ -88,7 +111,7 @@ out skel qt;
             }
             Err(error) => {
                 if retries_left == 0 {
-                    panic!("Could not get osm data from Overpass API: {}", error);
+                    return Err(CassiniError::Network(error));
                 }
 
                 warn!(
@@ -102,11 +125,11 @@ out skel qt;
         sleep(std::time::Duration::from_secs(5));
     };
 
-    let mut file = File::create(&raw_osm_file_path).expect("Could not create file for osm download.");
-    copy(&mut response, &mut file).expect("Could not copy file content.");
+    let mut file = File::create(&raw_osm_file_path)?;
+    copy(&mut response, &mut file)?;
 
-    fix_osm_file(&raw_osm_file_path, &osm_file_path);
-    std::fs::remove_file(&raw_osm_file_path).expect("Could not remove raw osm file");
+    fix_osm_file(&raw_osm_file_path, &osm_file_path)?;
+    std::fs::remove_file(&raw_osm_file_path)?;
 
     let duration = start.elapsed();
 
@@ -114,55 +137,57 @@ out skel qt;
         "Tile min_x={} min_y={} max_x={} max_y={}. Osm files downloaded in {:.1?}",
         min_x, min_y, max_x, max_y, duration
     );
-}
-
-fn convert_coords_from_lambert_93_to_gps(x: f64, y: f64) -> (f64, f64) {
-    let mut cs2cs = Command::new("cs2cs")
-        .args(["+init=epsg:2154", "+to", "+init=epsg:4326", "-f", "%.8f"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("Failed to start cs2cs");
-
-    if let Some(mut stdin) = cs2cs.stdin.take() {
-        writeln!(stdin, "{:.1} {:.1}", x, y).expect("Failed to write to stdin");
-    }
 
-    let output = cs2cs.wait_with_output().expect("Failed to read cs2cs output");
+    Ok(())
+}
 
-    if !output.status.success() {
-        panic!("Proj conversion failed.")
+fn convert_coords_to_gps(x: f64, y: f64, crs: &str) -> Result<(f64, f64), CassiniError> {
+    if let Some(projection) = Projection::from_crs(crs) {
+        return Ok(projection.to_wgs84(x, y));
     }
 
-    let result = String::from_utf8(output.stdout).unwrap();
-    let coords: Vec<&str> = result.trim().split_whitespace().collect();
+    // Falling back to PROJ for the CRS the built-in projections do not support
+    let cs2cs_output = run_command(
+        "cs2cs",
+        &["-f", "%.8f", crs, "+proj=longlat +datum=WGS84 +no_defs"],
+        Some(&format!("{:.3} {:.3}\n", x, y)),
+        DEFAULT_COMMAND_TIMEOUT,
+    )?;
+
+    let coords: Vec<f64> = cs2cs_output
+        .stdout
+        .split_whitespace()
+        .take(2)
+        .filter_map(|coord| coord.parse().ok())
+        .collect();
 
     if coords.len() < 2 {
-        panic!("Wrong Proj conversion result format")
+        return Err(CassiniError::InvalidData(format!(
+            "Wrong cs2cs conversion result for CRS {}: {}",
+            crs,
+            cs2cs_output.stdout.trim()
+        )));
     }
 
-    let lon: f64 = coords[0].parse().expect("Failed to parse longitude");
-    let lat: f64 = coords[1].parse().expect("Failed to parse latitude");
-
-    return (lon, lat);
+    return Ok((coords[0], coords[1]));
 }
 
-fn fix_osm_file(input: &PathBuf, output: &PathBuf) {
-    let reader = BufReader::new(File::open(&input).unwrap());
-    let mut writer = BufWriter::new(File::create(&output).unwrap());
+fn fix_osm_file(input: &PathBuf, output: &PathBuf) -> Result<(), std::io::Error> {
+    let reader = BufReader::new(File::open(&input)?);
+    let mut writer = BufWriter::new(File::create(&output)?);
     let mut relations_lines: Vec<String> = vec![];
 
     let mut is_inside_relation = false;
 
     for line in reader.lines() {
-        let line = line.unwrap();
+        let line = line?;
 
         if line.contains("</osm>") {
             for relations_line in &relations_lines {
-                writeln!(writer, "{}", relations_line).unwrap();
+                writeln!(writer, "{}", relations_line)?;
             }
 
-            writeln!(writer, "{}", line).unwrap();
+            writeln!(writer, "{}", line)?;
             break;
         }
 
@@ -183,6 +208,8 @@ fn fix_osm_file(input: &PathBuf, output: &PathBuf) {
             continue;
         }
 
-        writeln!(writer, "{}", line).unwrap();
+        writeln!(writer, "{}", line)?;
     }
+
+    Ok(())
 }
@@ -0,0 +1,116 @@
+use std::{fmt, time::Duration};
+
+#[derive(Debug)]
+pub enum CassiniError {
+    Io(std::io::Error),
+    /// An external program (PDAL, GDAL...) could not be started or exited with a failure status
+    ExternalTool {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+    /// An external program was killed because it ran longer than its timeout
+    CommandTimeout {
+        command: String,
+        timeout: Duration,
+    },
+    Tiff(tiff::TiffError),
+    Las(las::Error),
+    Shapefile(shapefile::Error),
+    Image(image::ImageError),
+    Network(reqwest::Error),
+    Config(serde_json::Error),
+    /// A file produced by a previous step or provided by the user has an unexpected content
+    InvalidData(String),
+}
+
+impl fmt::Display for CassiniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CassiniError::Io(error) => write!(f, "IO error: {}", error),
+            CassiniError::ExternalTool {
+                command,
+                exit_code,
+                stderr,
+            } => match exit_code {
+                Some(exit_code) => write!(
+                    f,
+                    "Command `{}` failed with exit code {}: {}",
+                    command,
+                    exit_code,
+                    stderr.trim()
+                ),
+                None => write!(f, "Command `{}` failed: {}", command, stderr.trim()),
+            },
+            CassiniError::CommandTimeout { command, timeout } => {
+                write!(f, "Command `{}` timed out after {:?}", command, timeout)
+            }
+            CassiniError::Tiff(error) => write!(f, "Could not decode tiff image: {}", error),
+            CassiniError::Las(error) => write!(f, "Could not read LiDAR file: {}", error),
+            CassiniError::Shapefile(error) => write!(f, "Could not read shapefile: {}", error),
+            CassiniError::Image(error) => write!(f, "Could not read or write image: {}", error),
+            CassiniError::Network(error) => write!(f, "Network error: {}", error),
+            CassiniError::Config(error) => write!(f, "Invalid config.json file: {}", error),
+            CassiniError::InvalidData(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for CassiniError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CassiniError::Io(error) => Some(error),
+            CassiniError::Tiff(error) => Some(error),
+            CassiniError::Las(error) => Some(error),
+            CassiniError::Shapefile(error) => Some(error),
+            CassiniError::Image(error) => Some(error),
+            CassiniError::Network(error) => Some(error),
+            CassiniError::Config(error) => Some(error),
+            CassiniError::ExternalTool { .. }
+            | CassiniError::CommandTimeout { .. }
+            | CassiniError::InvalidData(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CassiniError {
+    fn from(error: std::io::Error) -> Self {
+        CassiniError::Io(error)
+    }
+}
+
+impl From<tiff::TiffError> for CassiniError {
+    fn from(error: tiff::TiffError) -> Self {
+        CassiniError::Tiff(error)
+    }
+}
+
+impl From<las::Error> for CassiniError {
+    fn from(error: las::Error) -> Self {
+        CassiniError::Las(error)
+    }
+}
+
+impl From<shapefile::Error> for CassiniError {
+    fn from(error: shapefile::Error) -> Self {
+        CassiniError::Shapefile(error)
+    }
+}
+
+impl From<image::ImageError> for CassiniError {
+    fn from(error: image::ImageError) -> Self {
+        CassiniError::Image(error)
+    }
+}
+
+impl From<reqwest::Error> for CassiniError {
+    fn from(error: reqwest::Error) -> Self {
+        CassiniError::Network(error)
+    }
+}
+
+impl From<serde_json::Error> for CassiniError {
+    fn from(error: serde_json::Error) -> Self {
+        CassiniError::Config(error)
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use tiff::{
+    encoder::{
+        colortype::{self, ColorType},
+        compression::Deflate,
+        TiffEncoder, TiffValue,
+    },
+    tags::Tag,
+};
+
+use crate::error::CassiniError;
+
+/// GeoKey ids from the GeoTIFF specification
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const PROJECTED_CS_TYPE_GEO_KEY: u16 = 3072;
+const MODEL_TYPE_PROJECTED: u16 = 1;
+const RASTER_PIXEL_IS_AREA: u16 = 1;
+
+/// The georeferencing of a north-up raster.
+pub struct GeoTiffGrid {
+    /// Coordinates of the top left corner of the top left pixel
+    pub left: f64,
+    pub top: f64,
+    pub resolution: f64,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn write_f64_geotiff(
+    path: &PathBuf,
+    grid: &GeoTiffGrid,
+    data: &[f64],
+    nodata: f64,
+    crs: &str,
+) -> Result<(), CassiniError> {
+    write_geotiff::<colortype::Gray64Float>(path, grid, data, Some(nodata), crs)
+}
+
+pub fn write_u8_geotiff(
+    path: &PathBuf,
+    grid: &GeoTiffGrid,
+    data: &[u8],
+    crs: &str,
+) -> Result<(), CassiniError> {
+    write_geotiff::<colortype::Gray8>(path, grid, data, None, crs)
+}
+
+fn write_geotiff<C: ColorType>(
+    path: &PathBuf,
+    grid: &GeoTiffGrid,
+    data: &[C::Inner],
+    nodata: Option<f64>,
+    crs: &str,
+) -> Result<(), CassiniError>
+where
+    [C::Inner]: TiffValue,
+{
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = TiffEncoder::new(file)?;
+    let mut image =
+        encoder.new_image_with_compression::<C, Deflate>(grid.width, grid.height, Deflate::default())?;
+
+    image.encoder().write_tag(
+        Tag::ModelPixelScaleTag,
+        &[grid.resolution, grid.resolution, 0.][..],
+    )?;
+
+    image
+        .encoder()
+        .write_tag(Tag::ModelTiepointTag, &[0., 0., 0., grid.left, grid.top, 0.][..])?;
+
+    if let Some(epsg_code) = get_epsg_code(crs) {
+        image.encoder().write_tag(
+            Tag::GeoKeyDirectoryTag,
+            &[
+                1,
+                1,
+                0,
+                3,
+                GT_MODEL_TYPE_GEO_KEY,
+                0,
+                1,
+                MODEL_TYPE_PROJECTED,
+                GT_RASTER_TYPE_GEO_KEY,
+                0,
+                1,
+                RASTER_PIXEL_IS_AREA,
+                PROJECTED_CS_TYPE_GEO_KEY,
+                0,
+                1,
+                epsg_code,
+            ][..],
+        )?;
+    }
+
+    if let Some(nodata) = nodata {
+        image
+            .encoder()
+            .write_tag(Tag::GdalNodata, &nodata.to_string()[..])?;
+    }
+
+    image.write_data(data)?;
+
+    Ok(())
+}
+
+/// Only EPSG codes can be written as GeoKeys, other CRS definitions are left to GDAL defaults.
+fn get_epsg_code(crs: &str) -> Option<u16> {
+    crs.trim()
+        .to_uppercase()
+        .strip_prefix("EPSG:")
+        .and_then(|code| code.parse::<u16>().ok())
+}
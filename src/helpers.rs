@@ -1,9 +1,16 @@
+use shapefile::{
+    dbase::{FieldIOError, FieldName, FieldWriter, TableWriterBuilder, WritableRecord},
+    Polygon, Writer,
+};
 use std::{
-    fs::{read_dir, remove_dir_all, remove_file},
-    io::{self, ErrorKind},
-    path::Path,
+    f32::consts::PI,
+    fs::{read_dir, remove_dir_all, remove_file, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
 };
 
+use crate::{error::CassiniError, symbol_set::SymbolDimensions};
+
 pub fn remove_dir_content<P: AsRef<Path>>(path: P) -> io::Result<()> {
     for entry in read_dir(path)? {
         let entry = entry?;
@@ -19,7 +26,7 @@ pub fn remove_dir_content<P: AsRef<Path>>(path: P) -> io::Result<()> {
     Ok(())
 }
 
-pub fn remove_if_exists<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
+pub fn _remove_if_exists<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
     let path = path.as_ref();
 
     match std::fs::metadata(path) {
@@ -30,6 +37,28 @@ pub fn remove_if_exists<P: AsRef<Path>>(path: P) -> std::io::Result<()> {
     }
 }
 
+/// Even-odd test over all the rings of the polygon, so that holes are excluded.
+pub fn is_point_in_polygon(x: f64, y: f64, polygon: &Polygon) -> bool {
+    let mut is_inside = false;
+
+    for ring in polygon.rings() {
+        let points = ring.points();
+
+        for i in 0..points.len() {
+            let start = points[i];
+            let end = points[(i + 1) % points.len()];
+
+            if (start.y > y) != (end.y > y)
+                && x < (end.x - start.x) * (y - start.y) / (end.y - start.y) + start.x
+            {
+                is_inside = !is_inside;
+            }
+        }
+    }
+
+    return is_inside;
+}
+
 pub fn does_segment_intersect_tile(
     segment: ((f32, f32), (f32, f32)),
     min_x: f32,
@@ -127,3 +156,230 @@ fn do_intersect(segment1: ((f32, f32), (f32, f32)), segment2: ((f32, f32), (f32,
 
     false
 }
+
+/// Escapes the characters that are not allowed in XML attribute values and text.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Simplifies a polyline with the Douglas-Peucker algorithm, keeping its first and last points.
+pub fn simplify_polyline(points: &Vec<(f64, f64)>, tolerance: f64) -> Vec<(f64, f64)> {
+    if points.len() < 3 {
+        return points.clone();
+    }
+
+    let mut is_kept = vec![false; points.len()];
+    is_kept[0] = true;
+    is_kept[points.len() - 1] = true;
+
+    let mut ranges: Vec<(usize, usize)> = vec![(0, points.len() - 1)];
+
+    while let Some((first, last)) = ranges.pop() {
+        let mut max_distance = 0.;
+        let mut max_distance_index = first;
+
+        for index in (first + 1)..last {
+            let distance = get_distance_to_segment(points[index], points[first], points[last]);
+
+            if distance > max_distance {
+                max_distance = distance;
+                max_distance_index = index;
+            }
+        }
+
+        if max_distance > tolerance {
+            is_kept[max_distance_index] = true;
+            ranges.push((first, max_distance_index));
+            ranges.push((max_distance_index, last));
+        }
+    }
+
+    return points
+        .iter()
+        .zip(is_kept)
+        .filter(|(_, is_kept)| *is_kept)
+        .map(|(point, _)| *point)
+        .collect();
+}
+
+pub fn get_distance_to_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> f64 {
+    let (closest_x, closest_y) = get_closest_point_on_segment(point, start, end);
+    return ((point.0 - closest_x).powi(2) + (point.1 - closest_y).powi(2)).sqrt();
+}
+
+pub fn get_closest_point_on_segment(point: (f64, f64), start: (f64, f64), end: (f64, f64)) -> (f64, f64) {
+    let segment_x = end.0 - start.0;
+    let segment_y = end.1 - start.1;
+    let squared_length = segment_x * segment_x + segment_y * segment_y;
+
+    if squared_length == 0. {
+        return start;
+    }
+
+    let ratio =
+        (((point.0 - start.0) * segment_x + (point.1 - start.1) * segment_y) / squared_length).clamp(0., 1.);
+
+    return (start.0 + ratio * segment_x, start.1 + ratio * segment_y);
+}
+
+pub fn get_polyline_length(points: &Vec<(f64, f64)>) -> f64 {
+    points
+        .windows(2)
+        .map(|segment| ((segment[1].0 - segment[0].0).powi(2) + (segment[1].1 - segment[0].1).powi(2)).sqrt())
+        .sum()
+}
+
+/// Clips a polyline to a (min_x, min_y, max_x, max_y) extent, returning its parts inside the extent.
+pub fn clip_polyline(points: &Vec<(f64, f64)>, extent: (f64, f64, f64, f64)) -> Vec<Vec<(f64, f64)>> {
+    let mut parts: Vec<Vec<(f64, f64)>> = vec![];
+    let mut part: Vec<(f64, f64)> = vec![];
+
+    for segment in points.windows(2) {
+        let Some((start, end)) = clip_segment(segment[0], segment[1], extent) else {
+            continue;
+        };
+
+        if start == end {
+            continue;
+        }
+
+        // A segment clipped at its start leaves the extent before it, so it begins a new part
+        if part.last() != Some(&start) {
+            if part.len() > 1 {
+                parts.push(part);
+            }
+
+            part = vec![start];
+        }
+
+        part.push(end);
+    }
+
+    if part.len() > 1 {
+        parts.push(part);
+    }
+
+    return parts;
+}
+
+/// Clips a segment to a (min_x, min_y, max_x, max_y) extent with the Liang-Barsky algorithm.
+fn clip_segment(
+    (start_x, start_y): (f64, f64),
+    (end_x, end_y): (f64, f64),
+    (min_x, min_y, max_x, max_y): (f64, f64, f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (end_x - start_x, end_y - start_y);
+    let mut start_ratio: f64 = 0.;
+    let mut end_ratio: f64 = 1.;
+
+    for (direction, distance) in [
+        (-dx, start_x - min_x),
+        (dx, max_x - start_x),
+        (-dy, start_y - min_y),
+        (dy, max_y - start_y),
+    ] {
+        if direction == 0. {
+            if distance < 0. {
+                return None;
+            }
+
+            continue;
+        }
+
+        let ratio = distance / direction;
+
+        if direction < 0. {
+            start_ratio = start_ratio.max(ratio);
+        } else {
+            end_ratio = end_ratio.min(ratio);
+        }
+    }
+
+    if start_ratio > end_ratio {
+        return None;
+    }
+
+    return Some((
+        (start_x + start_ratio * dx, start_y + start_ratio * dy),
+        (start_x + end_ratio * dx, start_y + end_ratio * dy),
+    ));
+}
+
+/// Returns the points of the U of the 111 small depression symbol, in millimeters from the center of the
+/// symbol with the y axis going down.
+pub fn get_small_depression_mark(dimensions: &SymbolDimensions) -> Vec<(f32, f32)> {
+    let number_of_segments = 12;
+
+    return (0..=number_of_segments)
+        .map(|index| {
+            let angle = PI * index as f32 / number_of_segments as f32;
+
+            (
+                dimensions.small_depression_width / 2. * angle.cos(),
+                dimensions.small_depression_height * (angle.sin() - 0.5),
+            )
+        })
+        .collect();
+}
+
+/// Returns the points of the V of the 112 pit symbol, in millimeters from the center of the symbol with
+/// the y axis going down.
+pub fn get_pit_mark(dimensions: &SymbolDimensions) -> Vec<(f32, f32)> {
+    let (width, height) = (dimensions.pit_width, dimensions.pit_height);
+
+    return vec![
+        (-width / 2., -height / 2.),
+        (0., height / 2.),
+        (width / 2., -height / 2.),
+    ];
+}
+
+/// The attributes of the shapefiles of features measured by an area: the water, paved areas and buildings
+/// polygons, and the streams with their catchment area
+pub struct AreaRecord {
+    pub id: i32,
+    /// In square meters
+    pub area: f64,
+}
+
+impl WritableRecord for AreaRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.area)?;
+        Ok(())
+    }
+}
+
+pub fn create_area_shapefile_writer(path: &PathBuf) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("area").expect("Invalid field name"), 12, 1);
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polyline_crossing_extent_twice_is_clipped_in_two_parts() {
+        let points = vec![(-5., 2.), (5., 2.), (5., 15.), (8., 15.), (8., 5.), (15., 5.)];
+        let parts = clip_polyline(&points, (0., 0., 10., 10.));
+
+        assert_eq!(
+            parts,
+            vec![
+                vec![(0., 2.), (5., 2.), (5., 10.)],
+                vec![(8., 10.), (8., 5.), (10., 5.)]
+            ]
+        );
+        assert!(clip_polyline(&vec![(20., 20.), (30., 20.)], (0., 0., 10., 10.)).is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use log::info;
+use shapefile::{Point, Polygon, PolygonRing};
+use std::{fs::create_dir_all, path::PathBuf, time::Instant};
+
+use crate::{
+    config::Config,
+    constants::{BUFFER, DEM_NODATA_VALUE},
+    error::CassiniError,
+    helpers::{create_area_shapefile_writer, is_point_in_polygon, simplify_polyline, AreaRecord},
+    marching_squares::trace_contours_with_marching_squares,
+    mosaic::create_raster_with_buffer,
+    raster::{is_nodata, Raster},
+    tile::Tile,
+};
+
+/// Resolution of the water, buildings, returns and intensity rasters of the LiDAR step, in meters
+pub const COVER_RESOLUTION: f64 = 1.;
+
+/// Finds the water and paved areas of the tile in the LiDAR rasters, and writes them to polygon shapefiles
+/// in the land-cover directory. Water is where points are classified as water, or where the laser got no
+/// return at all. Paved areas are where the ground intensity is well below the median one of the tile.
+pub fn detect_water_and_paved_areas(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    config: &Config,
+) -> Result<(), CassiniError> {
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. Detecting water and paved areas",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y
+    );
+
+    let start = Instant::now();
+    let land_cover_config = &config.land_cover;
+
+    let water = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "water",
+        COVER_RESOLUTION,
+        0u8,
+    )?;
+
+    // Pixels covered by no LiDAR file are filled with the maximum count, not to be taken for return-less ones
+    let returns = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "returns",
+        COVER_RESOLUTION,
+        u8::MAX,
+    )?;
+
+    let intensity = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "intensity",
+        COVER_RESOLUTION,
+        DEM_NODATA_VALUE,
+    )?;
+
+    let water_mask: Vec<bool> = water
+        .data
+        .iter()
+        .zip(returns.data.iter())
+        .map(|(water_count, returns_count)| *water_count > 0 || *returns_count == 0)
+        .collect();
+
+    let water_mask = apply_majority_filter(&water_mask, water.width, water.height);
+
+    let mut valid_intensities: Vec<f64> = intensity
+        .data
+        .iter()
+        .filter(|value| !is_nodata(**value))
+        .cloned()
+        .collect();
+
+    let paved_mask: Vec<bool> = if valid_intensities.is_empty() {
+        vec![false; intensity.data.len()]
+    } else {
+        let median_index = valid_intensities.len() / 2;
+        let (_, median_intensity, _) = valid_intensities.select_nth_unstable_by(median_index, f64::total_cmp);
+        let max_paved_intensity = *median_intensity * land_cover_config.paved_area_max_relative_intensity;
+
+        intensity
+            .data
+            .iter()
+            .zip(water_mask.iter())
+            .map(|(value, is_water)| !is_water && !is_nodata(*value) && *value <= max_paved_intensity)
+            .collect()
+    };
+
+    let paved_mask = apply_majority_filter(&paved_mask, intensity.width, intensity.height);
+
+    let water_areas = vectorize_mask(&water_mask, &water, tile, land_cover_config.water_min_area);
+    let paved_areas = vectorize_mask(
+        &paved_mask,
+        &intensity,
+        tile,
+        land_cover_config.paved_area_min_area,
+    );
+
+    let land_cover_dir = tile.render_dir_path.join("land-cover");
+    create_dir_all(&land_cover_dir)?;
+
+    write_areas(&land_cover_dir.join("water.shp"), &water_areas)?;
+    write_areas(&land_cover_dir.join("paved-areas.shp"), &paved_areas)?;
+
+    let duration = start.elapsed();
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. {} water areas and {} paved areas detected in {:.1?}",
+        tile.min_x,
+        tile.min_y,
+        tile.max_x,
+        tile.max_y,
+        water_areas.len(),
+        paved_areas.len(),
+        duration
+    );
+
+    Ok(())
+}
+
+/// Returns true if most of the outline of a polygon is inside the given areas.
+pub fn is_area_covered(polygon: &Polygon, areas: &Vec<Polygon>) -> bool {
+    let Some(outer_ring) = polygon.rings().first() else {
+        return false;
+    };
+
+    let points = outer_ring.points();
+
+    let number_of_covered_points = points
+        .iter()
+        .filter(|point| {
+            areas
+                .iter()
+                .any(|area| is_point_in_polygon(point.x, point.y, area))
+        })
+        .count();
+
+    return number_of_covered_points * 2 > points.len();
+}
+
+/// Keeps the pixels whose 3x3 window is mostly in the mask, which removes the isolated pixels and fills the
+/// small gaps.
+pub fn apply_majority_filter(mask: &Vec<bool>, width: usize, height: usize) -> Vec<bool> {
+    (0..mask.len())
+        .map(|index| {
+            let (x, y) = ((index % width) as i64, (index / width) as i64);
+            let mut count = 0;
+
+            for neighbor_y in (y - 1)..=(y + 1) {
+                for neighbor_x in (x - 1)..=(x + 1) {
+                    if neighbor_x >= 0
+                        && neighbor_y >= 0
+                        && neighbor_x < width as i64
+                        && neighbor_y < height as i64
+                        && mask[neighbor_y as usize * width + neighbor_x as usize]
+                    {
+                        count += 1;
+                    }
+                }
+            }
+
+            count >= 5
+        })
+        .collect()
+}
+
+/// Traces the outlines of a mask with marching squares and assembles them into polygons, with clockwise
+/// outer rings and counterclockwise holes like in shapefiles. Polygons and holes smaller than min_area are
+/// dropped, as well as the polygons outside of the tile. Returns the polygons with their area.
+pub fn vectorize_mask<T>(
+    mask: &Vec<bool>,
+    raster: &Raster<T>,
+    tile: &Tile,
+    min_area: f64,
+) -> Vec<(Polygon, f64)> {
+    // One pixel of padding closes the outlines on the edges of the raster
+    let mut values = vec![vec![-1.; raster.height + 2]; raster.width + 2];
+
+    for y in 0..raster.height {
+        for x in 0..raster.width {
+            if mask[y * raster.width + x] {
+                values[x + 1][raster.height - y] = 1.;
+            }
+        }
+    }
+
+    let origin_x = raster.left - raster.cell_size / 2.;
+    let origin_y = raster.top - (raster.height as f64 + 0.5) * raster.cell_size;
+    let outlines = trace_contours_with_marching_squares(&values, origin_x, origin_y, raster.cell_size, 2.);
+
+    let mut outer_rings: Vec<(Vec<(f64, f64)>, f64)> = vec![];
+    let mut holes: Vec<(Vec<(f64, f64)>, f64)> = vec![];
+
+    for (xs, ys, _) in outlines {
+        let points: Vec<(f64, f64)> = xs.into_iter().zip(ys).collect();
+        let points = simplify_polyline(&points, raster.cell_size / 2.);
+
+        if points.len() < 4 {
+            continue;
+        }
+
+        let signed_area = get_signed_area(&points);
+
+        if signed_area.abs() < min_area {
+            continue;
+        }
+
+        if signed_area < 0. {
+            outer_rings.push((points, -signed_area));
+        } else {
+            holes.push((points, signed_area));
+        }
+    }
+
+    let mut rings_by_outer_ring: Vec<Vec<PolygonRing<Point>>> = outer_rings
+        .iter()
+        .map(|(points, _)| vec![PolygonRing::Outer(to_shapefile_points(points))])
+        .collect();
+
+    let mut areas: Vec<f64> = outer_rings.iter().map(|(_, area)| *area).collect();
+
+    // A hole belongs to the smallest outer ring containing it
+    for (hole, hole_area) in holes {
+        let containing_ring_index = outer_rings
+            .iter()
+            .enumerate()
+            .filter(|(index, (_, area))| {
+                *area > hole_area
+                    && is_point_in_polygon(
+                        hole[0].0,
+                        hole[0].1,
+                        &Polygon::with_rings(vec![rings_by_outer_ring[*index][0].clone()]),
+                    )
+            })
+            .min_by(|(_, (_, area)), (_, (_, other_area))| area.total_cmp(other_area))
+            .map(|(index, _)| index);
+
+        if let Some(index) = containing_ring_index {
+            rings_by_outer_ring[index].push(PolygonRing::Inner(to_shapefile_points(&hole)));
+            areas[index] -= hole_area;
+        }
+    }
+
+    return rings_by_outer_ring
+        .into_iter()
+        .zip(areas)
+        .filter(|(rings, _)| {
+            rings[0].points().iter().any(|point| {
+                point.x >= tile.min_x as f64
+                    && point.x <= tile.max_x as f64
+                    && point.y >= tile.min_y as f64
+                    && point.y <= tile.max_y as f64
+            })
+        })
+        .map(|(rings, area)| (Polygon::with_rings(rings), area))
+        .collect();
+}
+
+/// Shoelace formula, positive for counterclockwise rings.
+pub fn get_signed_area(points: &Vec<(f64, f64)>) -> f64 {
+    points
+        .windows(2)
+        .map(|segment| segment[0].0 * segment[1].1 - segment[1].0 * segment[0].1)
+        .sum::<f64>()
+        / 2.
+}
+
+pub fn to_shapefile_points(points: &Vec<(f64, f64)>) -> Vec<Point> {
+    points.iter().map(|(x, y)| Point { x: *x, y: *y }).collect()
+}
+
+/// Writes polygons with their area to a shapefile.
+pub fn write_areas(path: &PathBuf, areas: &Vec<(Polygon, f64)>) -> Result<(), CassiniError> {
+    let mut writer = create_area_shapefile_writer(path)?;
+
+    for (id, (polygon, area)) in areas.iter().enumerate() {
+        let record = AreaRecord {
+            id: id as i32,
+            area: *area,
+        };
+
+        writer.write_shape_and_record(polygon, &record)?;
+    }
+
+    Ok(())
+}
@@ -1,29 +1,54 @@
 mod batch;
-mod buffer;
+mod buildings;
 mod canvas;
 mod cliffs;
 mod coastlines;
+mod command;
 mod config;
 mod constants;
 mod contours;
-mod dem;
+mod crs;
 mod download;
+mod error;
+mod geotiff;
 mod helpers;
+mod land_cover;
 mod lidar;
+mod lidar_native;
 mod map_renderer;
+mod marching_squares;
 mod merge;
+mod micro_relief;
+mod mosaic;
+mod omap;
+mod print_layout;
+mod projection;
 mod pullautin_contours_render;
 mod pullautin_smooth_contours;
+mod raster;
 mod render;
+mod scheduler;
+mod skeleton;
+mod state;
+mod streams;
+mod symbol_rules;
+mod symbol_set;
+mod symbol_styles;
 mod tile;
+mod vector_map;
 mod vectors;
 mod vegetation;
+mod vegetation_model;
 mod world_file;
 
+pub use error::CassiniError;
+pub use lidar::LidarBackend;
+pub use vector_map::VectorMapFormat;
 pub use vegetation::UndergrowthMode;
 
 use batch::batch;
-use config::default_config;
+use config::{default_config, get_config};
+use crs::{get_crs_from_lidar_dir_path, resolve_crs_for_laz_file};
 use las::raw::Header;
 use lidar::generate_dem_and_vegetation_density_tiff_images_from_laz_file;
 use render::generate_png_from_dem_vegetation_density_tiff_images_and_vector_file;
@@ -40,16 +65,23 @@ pub fn process_single_tile(
     output_dir_path: &PathBuf,
     skip_vector: bool,
     skip_520: bool,
+    symbol_rules_path: Option<PathBuf>,
+    symbol_styles_path: Option<PathBuf>,
     undergrowth_mode: &UndergrowthMode,
     shapefiles_dir: Option<PathBuf>,
-) {
+    lidar_backend: &LidarBackend,
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
     generate_dem_and_vegetation_density_tiff_images_from_laz_file(
         &file_path.to_path_buf(),
         &output_dir_path.to_path_buf(),
-    );
+        lidar_backend,
+    )?;
 
-    let mut file = File::open(&file_path).expect("Cound not open laz file");
-    let header = Header::read_from(&mut file).unwrap();
+    let mut file = File::open(&file_path)?;
+    let header = Header::read_from(&mut file)?;
+    let config = get_config()?;
 
     let tile = Tile {
         lidar_dir_path: output_dir_path.to_path_buf(),
@@ -58,6 +90,7 @@ pub fn process_single_tile(
         min_y: header.min_y.round() as i64,
         max_x: header.max_x.round() as i64,
         max_y: header.max_y.round() as i64,
+        crs: resolve_crs_for_laz_file(file_path, &config),
     };
 
     generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
@@ -65,13 +98,21 @@ pub fn process_single_tile(
         vec![],
         skip_vector,
         skip_520,
+        symbol_rules_path,
+        symbol_styles_path,
         undergrowth_mode,
         shapefiles_dir,
-    );
+        vector_map_format,
+        omap,
+    )
 }
 
-pub fn process_single_tile_lidar_step(file_path: &PathBuf, output_dir_path: &PathBuf) {
-    generate_dem_and_vegetation_density_tiff_images_from_laz_file(&file_path, &output_dir_path);
+pub fn process_single_tile_lidar_step(
+    file_path: &PathBuf,
+    output_dir_path: &PathBuf,
+    lidar_backend: &LidarBackend,
+) -> Result<(), CassiniError> {
+    generate_dem_and_vegetation_density_tiff_images_from_laz_file(&file_path, &output_dir_path, lidar_backend)
 }
 
 pub fn process_single_tile_render_step(
@@ -80,12 +121,17 @@ pub fn process_single_tile_render_step(
     neighbor_tiles: Vec<PathBuf>,
     skip_vector: bool,
     skip_520: bool,
+    symbol_rules_path: Option<PathBuf>,
+    symbol_styles_path: Option<PathBuf>,
     undergrowth_mode: &UndergrowthMode,
     shapefiles_dir: Option<PathBuf>,
-) {
-    create_dir_all(&output_dir_path).expect("Could not create out dir");
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
+    create_dir_all(&output_dir_path)?;
 
-    let (min_x, min_y, max_x, max_y) = get_extent_from_lidar_dir_path(&input_dir_path.to_path_buf());
+    let (min_x, min_y, max_x, max_y) = get_extent_from_lidar_dir_path(&input_dir_path.to_path_buf())?;
+    let config = get_config()?;
 
     let tile = Tile {
         lidar_dir_path: input_dir_path.to_path_buf(),
@@ -94,6 +140,7 @@ pub fn process_single_tile_render_step(
         min_y,
         max_x,
         max_y,
+        crs: get_crs_from_lidar_dir_path(input_dir_path, &config),
     };
 
     generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
@@ -101,9 +148,13 @@ pub fn process_single_tile_render_step(
         neighbor_tiles,
         skip_vector,
         skip_520,
+        symbol_rules_path,
+        symbol_styles_path,
         undergrowth_mode,
         shapefiles_dir,
-    );
+        vector_map_format,
+        omap,
+    )
 }
 
 pub fn batch_process_tiles(
@@ -113,8 +164,13 @@ pub fn batch_process_tiles(
     skip_lidar: bool,
     skip_vector: bool,
     skip_520: bool,
+    symbol_rules_path: Option<PathBuf>,
+    symbol_styles_path: Option<PathBuf>,
     undergrowth_mode: &UndergrowthMode,
-) {
+    lidar_backend: &LidarBackend,
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
     batch(
         &input_dir,
         &output_dir,
@@ -122,10 +178,15 @@ pub fn batch_process_tiles(
         skip_lidar,
         skip_vector,
         skip_520,
+        symbol_rules_path,
+        symbol_styles_path,
         undergrowth_mode,
-    );
+        lidar_backend,
+        vector_map_format,
+        omap,
+    )
 }
 
-pub fn generate_default_config() {
-    default_config();
+pub fn generate_default_config() -> Result<(), CassiniError> {
+    default_config()
 }
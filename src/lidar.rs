@@ -1,43 +1,98 @@
 use las::raw::Header;
-use log::{error, info};
+use log::info;
 use std::fs::{create_dir_all, File};
 use std::io::Write;
 use std::path::PathBuf;
-use std::process::{Command, ExitStatus, Stdio};
 use std::time::Instant;
 
+use crate::command::{run_command, PDAL_COMMAND_TIMEOUT};
+use crate::config::get_config;
+use crate::crs::{resolve_crs_for_laz_file, write_crs_file};
+use crate::error::CassiniError;
 use crate::helpers::remove_dir_content;
+use crate::lidar_native::generate_rasters_with_native_backend;
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum LidarBackend {
+    /// Rasterize with a PDAL pipeline (PDAL must be installed)
+    Pdal,
+    /// Rasterize in-process with the las crate
+    Native,
+}
 
 pub fn generate_dem_and_vegetation_density_tiff_images_from_laz_file(
     laz_path: &PathBuf,
     output_dir_path: &PathBuf,
-) {
+    lidar_backend: &LidarBackend,
+) -> Result<(), CassiniError> {
     let start = Instant::now();
 
-    let mut file = File::open(&laz_path).unwrap();
-    let header = Header::read_from(&mut file).unwrap();
+    let mut file = File::open(&laz_path)?;
+    let header = Header::read_from(&mut file)?;
     let min_x = header.min_x.round() as i64;
     let min_y = header.min_y.round() as i64;
     let max_x = header.max_x.round() as i64;
     let max_y = header.max_y.round() as i64;
 
     info!(
-        "Tile min_x={} min_y={} max_x={} max_y={}. Executing PDAL pipeline",
-        min_x, min_y, max_x, max_y
+        "Tile min_x={} min_y={} max_x={} max_y={}. Rasterizing LiDAR file with the {:?} backend",
+        min_x, min_y, max_x, max_y, lidar_backend
     );
 
     // Cleaning up output directory to fix https://github.com/NicoRio42/cassini/issues/7
     if output_dir_path.exists() {
-        remove_dir_content(output_dir_path).unwrap();
+        remove_dir_content(output_dir_path)?;
     } else {
-        create_dir_all(&output_dir_path).expect("Could not create out dir");
+        create_dir_all(&output_dir_path)?;
+    }
+
+    let config = get_config()?;
+    let crs = resolve_crs_for_laz_file(laz_path, &config);
+
+    match lidar_backend {
+        LidarBackend::Pdal => {
+            run_pdal_pipeline(laz_path, output_dir_path, (min_x, min_y, max_x, max_y))?;
+        }
+        LidarBackend::Native => {
+            generate_rasters_with_native_backend(
+                laz_path,
+                output_dir_path,
+                (min_x, min_y, max_x, max_y),
+                &crs,
+            )?;
+        }
     }
 
+    // The existence of the extent.txt file is used as a proof of right execution of lidar pipeline by mapant-fr-worker
+    let mut extent_file = File::create(&output_dir_path.join("extent.txt"))?;
+    extent_file.write_all(format!("{}|{}|{}|{}", min_x, min_y, max_x, max_y).as_bytes())?;
+
+    write_crs_file(output_dir_path, &crs)?;
+
+    let duration = start.elapsed();
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. LiDAR file rasterized in {:.1?}",
+        min_x, min_y, max_x, max_y, duration
+    );
+
+    Ok(())
+}
+
+fn run_pdal_pipeline(
+    laz_path: &PathBuf,
+    output_dir_path: &PathBuf,
+    (min_x, min_y, max_x, max_y): (i64, i64, i64, i64),
+) -> Result<(), CassiniError> {
     let dem_path = output_dir_path.join("dem.tif");
     let dem_low_resolution_path = output_dir_path.join("dem-low-resolution.tif");
     let low_vegetation_path = output_dir_path.join("low-vegetation.tif");
     let medium_vegetation_path = output_dir_path.join("medium-vegetation.tif");
     let high_vegetation_path = output_dir_path.join("high-vegetation.tif");
+    let intensity_path = output_dir_path.join("intensity.tif");
+    let water_path = output_dir_path.join("water.tif");
+    let buildings_path = output_dir_path.join("buildings.tif");
+    let returns_path = output_dir_path.join("returns.tif");
 
     let gdal_dem_options = format!(
         r#""origin_x": {},
@@ -61,6 +116,17 @@ pub fn generate_dem_and_vegetation_density_tiff_images_from_laz_file(
         ((max_y - min_y) as f64 / 2.).ceil() as i64
     );
 
+    let gdal_intensity_options = format!(
+        r#""origin_x": {},
+        "origin_y": {},
+        "width": {},
+        "height": {},"#,
+        min_x,
+        min_y,
+        max_x - min_x,
+        max_y - min_y
+    );
+
     let gdal_vegetation_options = format!(
         r#""binmode": true,
         "resolution": 1,
@@ -100,6 +166,34 @@ pub fn generate_dem_and_vegetation_density_tiff_images_from_laz_file(
         "where": "Classification == 2",
         "output_type": "mean"
     }},
+    {{
+        "type": "writers.gdal",
+        "filename": {:?},
+        "binmode": true,
+        "resolution": 1,
+        "gdalopts": "COMPRESS=DEFLATE,PREDICTOR=3,ZLEVEL=9",
+        {}
+        "where": "Classification == 2",
+        "dimension": "Intensity",
+        "output_type": "mean"
+    }},
+    {{
+        "type": "writers.gdal",
+        "filename": {:?},
+        {}
+        "where": "Classification == 9"
+    }},
+    {{
+        "type": "writers.gdal",
+        "filename": {:?},
+        {}
+        "where": "Classification == 6"
+    }},
+    {{
+        "type": "writers.gdal",
+        {}
+        "filename": {:?}
+    }},
     {{
         "type": "filters.hag_dem",
         "raster": {:?}
@@ -148,6 +242,14 @@ pub fn generate_dem_and_vegetation_density_tiff_images_from_laz_file(
         gdal_dem_options,
         dem_low_resolution_path,
         gdal_dem_low_resolution_options,
+        intensity_path,
+        gdal_intensity_options,
+        water_path,
+        gdal_vegetation_options,
+        buildings_path,
+        gdal_vegetation_options,
+        gdal_vegetation_options,
+        returns_path,
         dem_path,
         low_vegetation_path,
         gdal_vegetation_options,
@@ -157,47 +259,12 @@ pub fn generate_dem_and_vegetation_density_tiff_images_from_laz_file(
         gdal_vegetation_options,
     );
 
-    let mut pdal_process = Command::new("pdal")
-        .args(["pipeline", "-s"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .expect("failed to execute pdal command");
-
-    if let Some(mut stdin) = pdal_process.stdin.take() {
-        stdin
-            .write_all(pdal_pipeline.as_bytes())
-            .expect("Failed to write to pdal stdin");
-    }
-
-    let pdal_output = pdal_process
-        .wait_with_output()
-        .expect("Failed to wait on pdal process");
-
-    if !ExitStatus::success(&pdal_output.status) {
-        error!(
-            "Tile min_x={} min_y={} max_x={} max_y={}. Pdal command failed {:?}",
-            min_x,
-            min_y,
-            max_x,
-            max_y,
-            String::from_utf8(pdal_output.stderr).unwrap()
-        );
-    } else {
-        // The existence of the extent.txt file is used as a proof of right execution of lidar pipeline by mapant-fr-worker
-
-        let mut extent_file =
-            File::create(&output_dir_path.join("extent.txt")).expect("Could not create extent.txt file");
-
-        extent_file
-            .write_all(format!("{}|{}|{}|{}", min_x, min_y, max_x, max_y).as_bytes())
-            .expect("Could not write to the extent.txt file");
-    }
-
-    let duration = start.elapsed();
+    run_command(
+        "pdal",
+        &["pipeline", "-s"],
+        Some(&pdal_pipeline),
+        PDAL_COMMAND_TIMEOUT,
+    )?;
 
-    info!(
-        "Tile min_x={} min_y={} max_x={} max_y={}. PDAL pipeline executed in {:.1?}",
-        min_x, min_y, max_x, max_y, duration
-    );
+    Ok(())
 }
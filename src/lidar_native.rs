@@ -0,0 +1,320 @@
+use las::{Read, Reader};
+use std::{collections::HashSet, path::PathBuf};
+
+use crate::{
+    constants::DEM_NODATA_VALUE,
+    error::CassiniError,
+    geotiff::{write_f64_geotiff, write_u8_geotiff, GeoTiffGrid},
+};
+
+const DEM_RESOLUTION: f64 = 0.5;
+const DEM_LOW_RESOLUTION_RESOLUTION: f64 = 2.0;
+const VEGETATION_RESOLUTION: f64 = 1.0;
+const COVER_RESOLUTION: f64 = 1.0;
+/// The voxel columns are the cells of the DEM
+const VOXEL_SIZE: f64 = DEM_RESOLUTION;
+
+const GROUND_CLASSIFICATION: u8 = 2;
+const LOW_VEGETATION_CLASSIFICATION: u8 = 3;
+const MEDIUM_VEGETATION_CLASSIFICATION: u8 = 4;
+const HIGH_VEGETATION_CLASSIFICATION: u8 = 5;
+const BUILDING_CLASSIFICATION: u8 = 6;
+const WATER_CLASSIFICATION: u8 = 9;
+
+/// A raster aligned on the tile bottom left corner, with rows ordered from north to south.
+struct Grid {
+    min_x: f64,
+    min_y: f64,
+    resolution: f64,
+    width: usize,
+    height: usize,
+}
+
+impl Grid {
+    fn new(min_x: i64, min_y: i64, max_x: i64, max_y: i64, resolution: f64) -> Grid {
+        Grid {
+            min_x: min_x as f64,
+            min_y: min_y as f64,
+            resolution,
+            width: ((max_x - min_x) as f64 / resolution).ceil() as usize,
+            height: ((max_y - min_y) as f64 / resolution).ceil() as usize,
+        }
+    }
+
+    fn get_index(&self, x: f64, y: f64) -> Option<usize> {
+        let column = ((x - self.min_x) / self.resolution).floor();
+        let row_from_bottom = ((y - self.min_y) / self.resolution).floor();
+
+        if column < 0. || row_from_bottom < 0. {
+            return None;
+        }
+
+        let column = column as usize;
+        let row_from_bottom = row_from_bottom as usize;
+
+        if column >= self.width || row_from_bottom >= self.height {
+            return None;
+        }
+
+        return Some((self.height - 1 - row_from_bottom) * self.width + column);
+    }
+
+    fn to_geotiff_grid(&self) -> GeoTiffGrid {
+        GeoTiffGrid {
+            left: self.min_x,
+            top: self.min_y + self.height as f64 * self.resolution,
+            resolution: self.resolution,
+            width: self.width as u32,
+            height: self.height as u32,
+        }
+    }
+}
+
+/// Accumulates the mean of a value of the points (elevation, intensity) falling in every cell of a grid.
+struct MeanRaster {
+    grid: Grid,
+    sums: Vec<f64>,
+    counts: Vec<u32>,
+}
+
+impl MeanRaster {
+    fn new(grid: Grid) -> MeanRaster {
+        let size = grid.width * grid.height;
+
+        MeanRaster {
+            grid,
+            sums: vec![0.; size],
+            counts: vec![0; size],
+        }
+    }
+
+    fn add(&mut self, x: f64, y: f64, value: f64) {
+        if let Some(index) = self.grid.get_index(x, y) {
+            self.sums[index] += value;
+            self.counts[index] += 1;
+        }
+    }
+
+    fn into_means(self) -> (Grid, Vec<f64>) {
+        let means = self
+            .sums
+            .iter()
+            .zip(self.counts.iter())
+            .map(|(sum, count)| {
+                if *count == 0 {
+                    DEM_NODATA_VALUE
+                } else {
+                    sum / *count as f64
+                }
+            })
+            .collect();
+
+        (self.grid, means)
+    }
+}
+
+/// Produces the same rasters as the PDAL pipeline, streaming the points of the LAZ file with the las crate.
+/// The file is read twice: once for the ground DEMs, the water, buildings, returns and intensity rasters,
+/// once for the vegetation, which needs the height above ground of every point.
+pub fn generate_rasters_with_native_backend(
+    laz_path: &PathBuf,
+    output_dir_path: &PathBuf,
+    (min_x, min_y, max_x, max_y): (i64, i64, i64, i64),
+    crs: &str,
+) -> Result<(), CassiniError> {
+    let mut dem = MeanRaster::new(Grid::new(min_x, min_y, max_x, max_y, DEM_RESOLUTION));
+    let mut dem_low_resolution = MeanRaster::new(Grid::new(
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+        DEM_LOW_RESOLUTION_RESOLUTION,
+    ));
+
+    let cover_grid = Grid::new(min_x, min_y, max_x, max_y, COVER_RESOLUTION);
+    let cover_size = cover_grid.width * cover_grid.height;
+    let mut water = vec![0u8; cover_size];
+    let mut buildings = vec![0u8; cover_size];
+    let mut returns = vec![0u8; cover_size];
+    let mut intensity = MeanRaster::new(Grid::new(min_x, min_y, max_x, max_y, COVER_RESOLUTION));
+
+    let mut reader = Reader::from_path(laz_path)?;
+
+    for point in reader.points() {
+        let point = point?;
+        let classification = u8::from(point.classification);
+
+        if let Some(cover_index) = cover_grid.get_index(point.x, point.y) {
+            returns[cover_index] = returns[cover_index].saturating_add(1);
+
+            if classification == WATER_CLASSIFICATION {
+                water[cover_index] = water[cover_index].saturating_add(1);
+            }
+
+            if classification == BUILDING_CLASSIFICATION {
+                buildings[cover_index] = buildings[cover_index].saturating_add(1);
+            }
+        }
+
+        if classification != GROUND_CLASSIFICATION {
+            continue;
+        }
+
+        dem.add(point.x, point.y, point.z);
+        dem_low_resolution.add(point.x, point.y, point.z);
+        intensity.add(point.x, point.y, point.intensity as f64);
+    }
+
+    let (dem_grid, dem_elevations) = dem.into_means();
+    let (dem_low_resolution_grid, dem_low_resolution_elevations) = dem_low_resolution.into_means();
+
+    write_f64_geotiff(
+        &output_dir_path.join("dem.tif"),
+        &dem_grid.to_geotiff_grid(),
+        &dem_elevations,
+        DEM_NODATA_VALUE,
+        crs,
+    )?;
+
+    write_f64_geotiff(
+        &output_dir_path.join("dem-low-resolution.tif"),
+        &dem_low_resolution_grid.to_geotiff_grid(),
+        &dem_low_resolution_elevations,
+        DEM_NODATA_VALUE,
+        crs,
+    )?;
+
+    let cover_geotiff_grid = cover_grid.to_geotiff_grid();
+
+    write_u8_geotiff(
+        &output_dir_path.join("water.tif"),
+        &cover_geotiff_grid,
+        &water,
+        crs,
+    )?;
+
+    write_u8_geotiff(
+        &output_dir_path.join("buildings.tif"),
+        &cover_geotiff_grid,
+        &buildings,
+        crs,
+    )?;
+
+    write_u8_geotiff(
+        &output_dir_path.join("returns.tif"),
+        &cover_geotiff_grid,
+        &returns,
+        crs,
+    )?;
+
+    let (intensity_grid, intensities) = intensity.into_means();
+
+    write_f64_geotiff(
+        &output_dir_path.join("intensity.tif"),
+        &intensity_grid.to_geotiff_grid(),
+        &intensities,
+        DEM_NODATA_VALUE,
+        crs,
+    )?;
+
+    let vegetation_grid = Grid::new(min_x, min_y, max_x, max_y, VEGETATION_RESOLUTION);
+    let vegetation_size = vegetation_grid.width * vegetation_grid.height;
+    let mut low_vegetation = vec![0u8; vegetation_size];
+    let mut medium_vegetation = vec![0u8; vegetation_size];
+    let mut high_vegetation = vec![0u8; vegetation_size];
+
+    // Keeping only the first point of every voxel, like PDAL's filters.voxeldownsize in "first" mode.
+    // The voxel columns are the DEM cells. Storing every visited voxel of a dense tile takes gigabytes, so the
+    // 64 voxels above the ground of a column, which hold all the vegetation, are stored in a bit mask:
+    // 8 bytes per DEM cell whatever the density. Only the other voxels (noise under the ground, points higher
+    // than 31 m above it or without ground below them) are stored in a set.
+    let mut visited_voxels_above_ground = vec![0u64; dem_elevations.len()];
+    let mut other_visited_voxels: HashSet<(usize, i64)> = HashSet::new();
+    let mut reader = Reader::from_path(laz_path)?;
+
+    for point in reader.points() {
+        let point = point?;
+
+        let (Some(vegetation_index), Some(dem_index)) = (
+            vegetation_grid.get_index(point.x, point.y),
+            dem_grid.get_index(point.x, point.y),
+        ) else {
+            continue;
+        };
+
+        let ground_elevation =
+            Some(dem_elevations[dem_index]).filter(|ground_elevation| *ground_elevation != DEM_NODATA_VALUE);
+
+        let voxel_z = (point.z / VOXEL_SIZE).floor() as i64;
+
+        let voxel_z_above_ground = ground_elevation
+            .map(|ground_elevation| voxel_z - (ground_elevation / VOXEL_SIZE).floor() as i64)
+            .filter(|voxel_z_above_ground| (0..64).contains(voxel_z_above_ground));
+
+        let is_first_point_of_voxel = match voxel_z_above_ground {
+            Some(voxel_z_above_ground) => {
+                let voxel_mask = 1u64 << voxel_z_above_ground;
+                let is_visited = visited_voxels_above_ground[dem_index] & voxel_mask != 0;
+                visited_voxels_above_ground[dem_index] |= voxel_mask;
+                !is_visited
+            }
+            None => other_visited_voxels.insert((dem_index, voxel_z)),
+        };
+
+        if !is_first_point_of_voxel {
+            continue;
+        }
+
+        let height_above_ground = ground_elevation.map(|ground_elevation| point.z - ground_elevation);
+
+        let vegetation_raster =
+            match get_vegetation_classification(u8::from(point.classification), height_above_ground) {
+                LOW_VEGETATION_CLASSIFICATION => &mut low_vegetation,
+                MEDIUM_VEGETATION_CLASSIFICATION => &mut medium_vegetation,
+                HIGH_VEGETATION_CLASSIFICATION => &mut high_vegetation,
+                _ => continue,
+            };
+
+        vegetation_raster[vegetation_index] = vegetation_raster[vegetation_index].saturating_add(1);
+    }
+
+    let vegetation_geotiff_grid = vegetation_grid.to_geotiff_grid();
+
+    write_u8_geotiff(
+        &output_dir_path.join("low-vegetation.tif"),
+        &vegetation_geotiff_grid,
+        &low_vegetation,
+        crs,
+    )?;
+
+    write_u8_geotiff(
+        &output_dir_path.join("medium-vegetation.tif"),
+        &vegetation_geotiff_grid,
+        &medium_vegetation,
+        crs,
+    )?;
+
+    write_u8_geotiff(
+        &output_dir_path.join("high-vegetation.tif"),
+        &vegetation_geotiff_grid,
+        &high_vegetation,
+        crs,
+    )?;
+
+    Ok(())
+}
+
+/// Reclassifies non ground points by height above ground, like the filters.assign stages of the PDAL
+/// pipeline. Points outside of the height ranges, or without ground below them, keep their classification.
+fn get_vegetation_classification(classification: u8, height_above_ground: Option<f64>) -> u8 {
+    if classification == GROUND_CLASSIFICATION {
+        return classification;
+    }
+
+    match height_above_ground {
+        Some(height) if height > 0. && height <= 1. => LOW_VEGETATION_CLASSIFICATION,
+        Some(height) if height > 1. && height <= 4. => MEDIUM_VEGETATION_CLASSIFICATION,
+        Some(height) if height > 4. && height <= 30. => HIGH_VEGETATION_CLASSIFICATION,
+        _ => classification,
+    }
+}
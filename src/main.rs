@@ -1,9 +1,9 @@
 use cassini::{
     batch_process_tiles, generate_default_config, process_single_tile, process_single_tile_lidar_step,
-    process_single_tile_render_step, UndergrowthMode,
+    process_single_tile_render_step, CassiniError, LidarBackend, UndergrowthMode, VectorMapFormat,
 };
 use clap::{CommandFactory, Parser, Subcommand};
-use log::info;
+use log::{error, info};
 use std::{
     path::{Path, PathBuf},
     time::Instant,
@@ -13,7 +13,7 @@ use std::{
 #[derive(Parser, Debug)]
 #[command(
     version,
-    about = "A software that generates highly accurate topographic maps from LiDAR data. See documentation: https://cassini-map.com. GDAL and PDAL must be installed on the system for this program to work.",
+    about = "A software that generates highly accurate topographic maps from LiDAR data. See documentation: https://cassini-map.com. GDAL and PDAL must be installed on the system for this program to work (PDAL is not needed with --lidar-backend native).",
     long_about = "Cassini is a software that generates highly accurate topographic maps from LiDAR data and shapefile vector data in record times."
 )]
 pub struct Args {
@@ -45,6 +45,18 @@ pub enum Commands {
         )]
         skip_520: bool,
 
+        #[arg(
+            long,
+            help = "Path to a JSON file of rules mapping the OpenStreetMap tags to map symbols, replacing the default rules"
+        )]
+        symbol_rules: Option<String>,
+
+        #[arg(
+            long,
+            help = "Path to a JSON file of colors, widths, dashes and hatches overriding the default symbol styles"
+        )]
+        symbol_styles: Option<String>,
+
         #[arg(
             long,
             help = "Path to a directory containing shapefiles to use instead of downloading from OpenStreetMap"
@@ -58,6 +70,27 @@ pub enum Commands {
             default_value = "merge"
         )]
         undergrowth: UndergrowthMode,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Also write the map as a georeferenced vector file (full-map.svg or full-map.pdf), with the vegetation embedded as a raster"
+        )]
+        vector_format: Option<VectorMapFormat>,
+
+        #[arg(
+            long,
+            help = "Also write the generated features to an OpenOrienteering Mapper file (full-map.omap)"
+        )]
+        omap: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Backend used to rasterize the LiDAR file. The native backend does not need PDAL",
+            default_value = "pdal"
+        )]
+        lidar_backend: LidarBackend,
     },
 
     /// Run only the LiDAR processing step for a single tile
@@ -72,6 +105,14 @@ pub enum Commands {
             default_value = "lidar"
         )]
         output_dir: Option<String>,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Backend used to rasterize the LiDAR file. The native backend does not need PDAL",
+            default_value = "pdal"
+        )]
+        lidar_backend: LidarBackend,
     },
 
     /// Run only the map generation step for a single tile
@@ -103,6 +144,18 @@ pub enum Commands {
         )]
         skip_520: bool,
 
+        #[arg(
+            long,
+            help = "Path to a JSON file of rules mapping the OpenStreetMap tags to map symbols, replacing the default rules"
+        )]
+        symbol_rules: Option<String>,
+
+        #[arg(
+            long,
+            help = "Path to a JSON file of colors, widths, dashes and hatches overriding the default symbol styles"
+        )]
+        symbol_styles: Option<String>,
+
         #[arg(
             long,
             help = "Path to a directory containing shapefiles to use instead of downloading from OpenStreetMap"
@@ -116,6 +169,19 @@ pub enum Commands {
             default_value = "merge"
         )]
         undergrowth: UndergrowthMode,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Also write the map as a georeferenced vector file (full-map.svg or full-map.pdf), with the vegetation embedded as a raster"
+        )]
+        vector_format: Option<VectorMapFormat>,
+
+        #[arg(
+            long,
+            help = "Also write the generated features to an OpenOrienteering Mapper file (full-map.omap)"
+        )]
+        omap: bool,
     },
 
     /// Process multiple LiDAR files at once
@@ -157,6 +223,18 @@ pub enum Commands {
         )]
         skip_520: bool,
 
+        #[arg(
+            long,
+            help = "Path to a JSON file of rules mapping the OpenStreetMap tags to map symbols, replacing the default rules"
+        )]
+        symbol_rules: Option<String>,
+
+        #[arg(
+            long,
+            help = "Path to a JSON file of colors, widths, dashes and hatches overriding the default symbol styles"
+        )]
+        symbol_styles: Option<String>,
+
         #[arg(
             long,
             value_enum,
@@ -164,6 +242,27 @@ pub enum Commands {
             default_value = "merge"
         )]
         undergrowth: UndergrowthMode,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Also write the map as a georeferenced vector file (full-map.svg or full-map.pdf), with the vegetation embedded as a raster"
+        )]
+        vector_format: Option<VectorMapFormat>,
+
+        #[arg(
+            long,
+            help = "Also write the generated features to an OpenOrienteering Mapper file (full-map.omap)"
+        )]
+        omap: bool,
+
+        #[arg(
+            long,
+            value_enum,
+            help = "Backend used to rasterize the LiDAR file. The native backend does not need PDAL",
+            default_value = "pdal"
+        )]
+        lidar_backend: LidarBackend,
     },
 
     /// Output a default config.json file.
@@ -198,7 +297,7 @@ fn main() {
     if let Some(command) = args.command {
         match command {
             Commands::Config {} => {
-                generate_default_config();
+                exit_if_error(generate_default_config());
             }
 
             Commands::Process {
@@ -206,8 +305,13 @@ fn main() {
                 output_dir: maybe_output_dir,
                 skip_vector,
                 skip_520,
+                symbol_rules,
+                symbol_styles,
                 shapefiles,
                 undergrowth,
+                vector_format,
+                omap,
+                lidar_backend,
             } => {
                 info!("Tile processing");
                 let start = Instant::now();
@@ -216,7 +320,19 @@ fn main() {
                 let laz_path = Path::new(&file_path).to_path_buf();
                 let dir_path = Path::new(&output_dir).to_path_buf();
                 let shapefiles_dir = shapefiles.map(PathBuf::from);
-                process_single_tile(&laz_path, &dir_path, skip_vector, skip_520, &undergrowth, shapefiles_dir);
+                exit_if_error(process_single_tile(
+                    &laz_path,
+                    &dir_path,
+                    skip_vector,
+                    skip_520,
+                    symbol_rules.map(PathBuf::from),
+                    symbol_styles.map(PathBuf::from),
+                    &undergrowth,
+                    shapefiles_dir,
+                    &lidar_backend,
+                    vector_format,
+                    omap,
+                ));
 
                 let duration = start.elapsed();
                 info!("Tile generated in {:.1?}", duration);
@@ -225,6 +341,7 @@ fn main() {
             Commands::Lidar {
                 file_path,
                 output_dir: maybe_output_dir,
+                lidar_backend,
             } => {
                 info!("LiDAR processing");
                 let start = Instant::now();
@@ -232,7 +349,7 @@ fn main() {
                 let output_dir = maybe_output_dir.unwrap_or("lidar".to_owned());
                 let laz_path = Path::new(&file_path).to_path_buf();
                 let dir_path = Path::new(&output_dir).to_path_buf();
-                process_single_tile_lidar_step(&laz_path, &dir_path);
+                exit_if_error(process_single_tile_lidar_step(&laz_path, &dir_path, &lidar_backend));
 
                 let duration = start.elapsed();
                 info!("LiDAR file processed in {:.1?}", duration);
@@ -244,8 +361,12 @@ fn main() {
                 neighbors,
                 skip_vector,
                 skip_520,
+                symbol_rules,
+                symbol_styles,
                 shapefiles,
                 undergrowth,
+                vector_format,
+                omap,
             } => {
                 info!("Map rendering");
                 let start = Instant::now();
@@ -267,15 +388,19 @@ fn main() {
                 }
 
                 let shapefiles_dir = shapefiles.map(PathBuf::from);
-                process_single_tile_render_step(
+                exit_if_error(process_single_tile_render_step(
                     &input_dir_path,
                     &output_dir_path,
                     neighbor_tiles,
                     skip_vector,
                     skip_520,
+                    symbol_rules.map(PathBuf::from),
+                    symbol_styles.map(PathBuf::from),
                     &undergrowth,
                     shapefiles_dir,
-                );
+                    vector_format,
+                    omap,
+                ));
 
                 let duration = start.elapsed();
                 info!("Map rendered in {:.1?}", duration);
@@ -288,7 +413,12 @@ fn main() {
                 skip_lidar,
                 skip_vector,
                 skip_520,
+                symbol_rules,
+                symbol_styles,
                 undergrowth,
+                vector_format,
+                omap,
+                lidar_backend,
             } => {
                 info!("Batch processing");
                 let start = Instant::now();
@@ -297,15 +427,20 @@ fn main() {
                 let output_dir = maybe_output_dir.unwrap_or("out".to_owned());
                 let threads = maybe_threads.unwrap_or(3);
 
-                batch_process_tiles(
+                exit_if_error(batch_process_tiles(
                     &input_dir,
                     &output_dir,
                     threads,
                     skip_lidar,
                     skip_vector,
                     skip_520,
+                    symbol_rules.map(PathBuf::from),
+                    symbol_styles.map(PathBuf::from),
                     &undergrowth,
-                );
+                    &lidar_backend,
+                    vector_format,
+                    omap,
+                ));
 
                 let duration = start.elapsed();
                 info!("Tiles generated in {:.1?}", duration);
@@ -313,3 +448,10 @@ fn main() {
         }
     }
 }
+
+fn exit_if_error(result: Result<(), CassiniError>) {
+    if let Err(error) = result {
+        error!("{}", error);
+        std::process::exit(1);
+    }
+}
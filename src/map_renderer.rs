@@ -2,24 +2,25 @@ use std::path::PathBuf;
 
 use crate::{
     canvas::Canvas,
-    constants::{
-        BUILDING_OUTLINE_WIDTH, CROSSABLE_WATERCOURSE_WIDTH, DOUBLE_TRACK_WIDE_ROAD_INNER_WIDTH,
-        DOUBLE_TRACK_WIDE_ROAD_OUTER_WIDTH, FOOTPATH_DASH_INTERVAL_LENGTH, FOOTPATH_DASH_LENGTH,
-        FOOTPATH_WIDTH, INCH, INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH, MARSH_LINE_SPACING, MARSH_LINE_WIDTH,
-        MINOR_WATERCOURSE_DASH_INTERVAL_LENGTH, MINOR_WATERCOURSE_DASH_LENGTH, MINOR_WATERCOURSE_WIDTH,
-        POWERLINE_WIDTH, RAILWAY_DASH_INTERVAL_LENGTH, RAILWAY_DASH_LENGTH, RAILWAY_INNER_WIDTH,
-        RAILWAY_OUTER_WIDTH, ROAD_WIDTH, UNDERGROWTH_LINE_SPACING, UNDERGROWTH_LINE_WIDTH, VECTOR_BLACK,
-        VECTOR_BLUE, VECTOR_BUILDING_GRAY, VECTOR_OLIVE_GREEN, VECTOR_PAVED_AREA_BROWN, VECTOR_WHITE,
-        WIDE_ROAD_INNER_WIDTH, WIDE_ROAD_OUTER_WIDTH, XL_WIDE_ROAD_INNER_WIDTH, XL_WIDE_ROAD_OUTER_WIDTH,
-        XXL_WIDE_ROAD_INNER_WIDTH, XXL_WIDE_ROAD_OUTER_WIDTH, _MAJOR_POWERLINE_INNER_WIDTH,
-        _MAJOR_POWERLINE_OUTER_WIDTH,
-    },
+    constants::{VECTOR_BLACK, VECTOR_WHITE},
+    error::CassiniError,
+    helpers::{get_pit_mark, get_small_depression_mark},
+    omap::{OmapMap, OmapSymbol},
+    print_layout::write_symbol_codes,
+    symbol_set::{millimeters_to_pixels, SymbolDimensions},
+    symbol_styles::{SymbolColors, SymbolStyles},
+    vector_map::VectorMap,
 };
 use shapefile::{
     record::{polygon::GenericPolygon, polyline::GenericPolyline},
     Point, PolygonRing,
 };
 
+/// A function drawing a line symbol, from the registry used by the symbol rules
+pub type LineSymbolFunction = fn(MapRenderer, &GenericPolyline<Point>) -> MapRenderer;
+/// A function drawing an area symbol, from the registry used by the symbol rules
+pub type AreaSymbolFunction = fn(MapRenderer, &GenericPolygon<Point>) -> MapRenderer;
+
 enum StripeDirection {
     Horizontal,
     Vertical,
@@ -45,6 +46,11 @@ pub struct MapRenderer {
     image_height: u32,
     scale_factor: f32,
     dpi_resolution: f32,
+    dimensions: SymbolDimensions,
+    colors: SymbolColors,
+    omap_map: Option<OmapMap>,
+    /// The symbols drawn on the map, for the legend of the print layout
+    used_symbols: Vec<OmapSymbol>,
 }
 
 impl MapRenderer {
@@ -55,19 +61,21 @@ impl MapRenderer {
         image_height: u32,
         scale_factor: f32,
         dpi_resolution: f32,
+        symbol_styles: &SymbolStyles,
         vegetation_path: &PathBuf,
         undergrowth_path: &PathBuf,
         contours_path: &PathBuf,
-        cliffs_path: &PathBuf,
-    ) -> MapRenderer {
+        record_vector_shapes: bool,
+        omap_map: Option<OmapMap>,
+    ) -> Result<MapRenderer, CassiniError> {
         let undergrowth_img = if undergrowth_path.is_file() {
-            Some(Canvas::load_from(undergrowth_path.to_str().unwrap()))
+            Some(Canvas::load_from(undergrowth_path.to_str().unwrap())?)
         } else {
             None
         };
 
-        return MapRenderer {
-            vegetation_img: Canvas::load_from(vegetation_path.to_str().unwrap()),
+        let mut map_renderer = MapRenderer {
+            vegetation_img: Canvas::load_from(vegetation_path.to_str().unwrap())?,
             undergrowth_img,
             olive_green_img: Canvas::new(image_width as i32, image_height as i32),
             light_brown_img: Canvas::new(image_width as i32, image_height as i32),
@@ -76,9 +84,9 @@ impl MapRenderer {
             black_road_outlines_img: Canvas::new(image_width as i32, image_height as i32),
             light_brown_road_infill_img: Canvas::new(image_width as i32, image_height as i32),
             gray_img: Canvas::new(image_width as i32, image_height as i32),
-            contours_img: Canvas::load_from(contours_path.to_str().unwrap()),
+            contours_img: Canvas::load_from(contours_path.to_str().unwrap())?,
             blue_lines_and_points_img: Canvas::new(image_width as i32, image_height as i32),
-            cliffs_img: Canvas::load_from(cliffs_path.to_str().unwrap()),
+            cliffs_img: Canvas::new(image_width as i32, image_height as i32),
             black_img: Canvas::new(image_width as i32, image_height as i32),
             min_x,
             min_y,
@@ -86,7 +94,213 @@ impl MapRenderer {
             image_height,
             scale_factor,
             dpi_resolution,
+            dimensions: symbol_styles.dimensions,
+            colors: symbol_styles.colors,
+            omap_map,
+            used_symbols: vec![],
+        };
+
+        if record_vector_shapes {
+            map_renderer.olive_green_img.start_recording();
+            map_renderer.light_brown_img.start_recording();
+            map_renderer.blue_img.start_recording();
+            map_renderer.striped_blue_img.start_recording();
+            map_renderer.black_road_outlines_img.start_recording();
+            map_renderer.light_brown_road_infill_img.start_recording();
+            map_renderer.gray_img.start_recording();
+            map_renderer.contours_img.start_recording();
+            map_renderer.blue_lines_and_points_img.start_recording();
+            map_renderer.cliffs_img.start_recording();
+            map_renderer.black_img.start_recording();
+        }
+
+        return Ok(map_renderer);
+    }
+
+    /// Returns the function drawing the line symbol with the given ID in the symbol rules. The IDs are the
+    /// ISOM or ISSprOM codes, with a suffix for the widths of the 502 wide road.
+    pub fn get_line_symbol_function(symbol_id: &str) -> Option<LineSymbolFunction> {
+        let symbol_function: LineSymbolFunction = match symbol_id {
+            "201" => MapRenderer::impassable_cliff_201,
+            "202" => MapRenderer::cliff_202,
+            "301.4" => MapRenderer::uncrossable_body_of_water_bank_line_301_4,
+            "304" => MapRenderer::crossable_watercourse_304,
+            "306" => MapRenderer::minor_seasonal_water_channel_306,
+            "502" => MapRenderer::wide_road_502,
+            "502-xl" => MapRenderer::xl_wide_road_502,
+            "502-xxl" => MapRenderer::xxl_wide_road_502,
+            "502-double-track" => MapRenderer::double_track_wide_road_502,
+            "503" => MapRenderer::road_503,
+            "505" => MapRenderer::footpath_505,
+            "509" => MapRenderer::railway_509,
+            "510" => MapRenderer::power_line_cableway_or_skilift_510,
+            "515" => MapRenderer::impassable_wall_515,
+            "516" => MapRenderer::fence_516,
+            "518" => MapRenderer::impassable_fence_518,
+            "529.1" => MapRenderer::step_or_edge_of_paved_area_529_1,
+            _ => return None,
         };
+
+        return Some(symbol_function);
+    }
+
+    /// Returns the function drawing the area symbol with the given ID in the symbol rules.
+    pub fn get_area_symbol_function(symbol_id: &str) -> Option<AreaSymbolFunction> {
+        let symbol_function: AreaSymbolFunction = match symbol_id {
+            "301" => MapRenderer::uncrossable_body_of_water_301,
+            "301.1" => MapRenderer::uncrossable_body_of_water_area_301_1,
+            "308" => MapRenderer::marsh_308,
+            "411" => MapRenderer::impassable_vegetation_411,
+            "501.1" => MapRenderer::paved_area_with_bounding_line_501_1,
+            "520" => MapRenderer::area_that_shall_not_be_entered_520,
+            "521" => MapRenderer::building_521,
+            "521.1" => MapRenderer::building_passage_521_1,
+            "529" => MapRenderer::paved_area_529,
+            _ => return None,
+        };
+
+        return Some(symbol_function);
+    }
+
+    #[inline]
+    pub fn small_knoll_109(mut self, point: &Point) -> MapRenderer {
+        self.add_omap_point(OmapSymbol::SmallKnoll, point);
+
+        let center = self.get_point_position(point);
+        let radius = self.millimeters_to_pixels(self.dimensions.small_knoll_diameter) / 2.;
+        self.contours_img.set_color(self.colors.small_knoll);
+        self.contours_img.draw_filled_circle(center, radius);
+
+        return self;
+    }
+
+    #[inline]
+    pub fn small_depression_111(mut self, point: &Point) -> MapRenderer {
+        self.add_omap_point(OmapSymbol::SmallDepression, point);
+        self.draw_brown_mark(
+            point,
+            &get_small_depression_mark(&self.dimensions),
+            self.dimensions.small_depression_line_width,
+            self.colors.small_depression,
+        );
+        return self;
+    }
+
+    #[inline]
+    pub fn pit_112(mut self, point: &Point) -> MapRenderer {
+        self.add_omap_point(OmapSymbol::Pit, point);
+        self.draw_brown_mark(
+            point,
+            &get_pit_mark(&self.dimensions),
+            self.dimensions.pit_line_width,
+            self.colors.pit,
+        );
+        return self;
+    }
+
+    /// Draws a line given in millimeters around the point, with the y axis going down.
+    #[inline]
+    fn draw_brown_mark(&mut self, point: &Point, mark: &Vec<(f32, f32)>, width: f32, color: (u8, u8, u8)) {
+        let (center_x, center_y) = self.get_point_position(point);
+        let millimeter = self.millimeters_to_pixels(1.);
+
+        let points: Vec<(f32, f32)> = mark
+            .iter()
+            .map(|(x, y)| (center_x + x * millimeter, center_y + y * millimeter))
+            .collect();
+
+        self.contours_img.set_color(color);
+        self.contours_img.set_line_width(width * millimeter);
+        self.contours_img.draw_polyline(&points);
+    }
+
+    #[inline]
+    pub fn impassable_cliff_201(self, line: &GenericPolyline<Point>) -> MapRenderer {
+        let width = self.dimensions.impassable_cliff_width;
+        return self.cliff(line, OmapSymbol::ImpassableCliff, width);
+    }
+
+    #[inline]
+    pub fn cliff_202(self, line: &GenericPolyline<Point>) -> MapRenderer {
+        let width = self.dimensions.cliff_width;
+        return self.cliff(line, OmapSymbol::Cliff, width);
+    }
+
+    /// Draws a cliff line with tags on its right side, where the cliff vectorization puts the downhill side.
+    #[inline]
+    fn cliff(mut self, line: &GenericPolyline<Point>, omap_symbol: OmapSymbol, width: f32) -> MapRenderer {
+        self.add_omap_line(omap_symbol, line);
+
+        let color = match omap_symbol {
+            OmapSymbol::ImpassableCliff => self.colors.impassable_cliff,
+            _ => self.colors.cliff,
+        };
+        let tag_length = self.millimeters_to_pixels(self.dimensions.cliff_tag_length);
+        let tag_spacing = self.millimeters_to_pixels(self.dimensions.cliff_tag_spacing);
+
+        for part in line.parts() {
+            let points = self.get_points_from_line_part(part);
+
+            self.cliffs_img.set_color(color);
+            self.cliffs_img
+                .set_line_width(self.millimeters_to_pixels(width));
+            self.cliffs_img.draw_polyline(&points);
+
+            self.cliffs_img
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.cliff_tag_width));
+            Self::draw_tags(&mut self.cliffs_img, &points, tag_length, tag_spacing, false);
+        }
+
+        return self;
+    }
+
+    #[inline]
+    pub fn boulder_204(mut self, point: &Point) -> MapRenderer {
+        self.add_omap_point(OmapSymbol::Boulder, point);
+
+        let center = self.get_point_position(point);
+        let radius = self.millimeters_to_pixels(self.dimensions.boulder_diameter) / 2.;
+        self.cliffs_img.set_color(self.colors.boulder);
+        self.cliffs_img.draw_filled_circle(center, radius);
+
+        return self;
+    }
+
+    #[inline]
+    pub fn impassable_vegetation_411(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::ImpassableVegetation, polygon);
+
+        let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
+
+        self.olive_green_img.set_color(self.colors.impassable_vegetation);
+        self.olive_green_img
+            .draw_filled_polygon_with_holes(&outer_geometry, &holes);
+
+        return self;
+    }
+
+    #[inline]
+    pub fn paved_area_with_bounding_line_501_1(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        // The bounding line is the edge of the paved area
+        self.add_omap_area(OmapSymbol::PavedAreaWithBoundingLine, polygon);
+        self.add_omap_polygon_outline(OmapSymbol::StepOrEdgeOfPavedArea, polygon);
+
+        let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
+
+        self.light_brown_img.set_color(self.colors.paved_area_with_bounding_line);
+        self.light_brown_img
+            .draw_filled_polygon_with_holes(&outer_geometry, &holes);
+
+        self.black_img.set_color(self.colors.step_or_edge_of_paved_area);
+        self.black_img
+            .set_line_width(self.millimeters_to_pixels(self.dimensions.step_or_edge_of_paved_area_width));
+        self.black_img.draw_polyline(&outer_geometry);
+
+        for hole in holes {
+            self.black_img.draw_polyline(&hole);
+        }
+
+        return self;
     }
 
     #[inline]
@@ -94,10 +308,12 @@ impl MapRenderer {
         let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
 
         self = self.uncrossable_body_of_water_area_301_1(&polygon);
+        self.add_omap_polygon_outline(OmapSymbol::UncrossableBodyOfWaterBankLine, polygon);
 
-        self.black_img.set_color(VECTOR_BLACK);
-        self.black_img
-            .set_line_width(INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+        let outline_width =
+            self.millimeters_to_pixels(self.dimensions.uncrossable_body_of_water_outline_width);
+        self.black_img.set_color(self.colors.uncrossable_body_of_water_bank_line);
+        self.black_img.set_line_width(outline_width);
         self.black_img.draw_polyline(&outer_geometry);
 
         for hole in holes {
@@ -109,9 +325,11 @@ impl MapRenderer {
 
     #[inline]
     pub fn uncrossable_body_of_water_area_301_1(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::UncrossableBodyOfWater, polygon);
+
         let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
 
-        self.blue_img.set_color(VECTOR_BLUE);
+        self.blue_img.set_color(self.colors.uncrossable_body_of_water);
         self.blue_img
             .draw_filled_polygon_with_holes(&outer_geometry, &holes);
 
@@ -128,12 +346,16 @@ impl MapRenderer {
 
     #[inline]
     pub fn uncrossable_body_of_water_bank_line_301_4(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::UncrossableBodyOfWaterBankLine, line);
+
+        let outline_width =
+            self.millimeters_to_pixels(self.dimensions.uncrossable_body_of_water_outline_width);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.black_img.set_color(VECTOR_BLACK);
-            self.black_img
-                .set_line_width(INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+            self.black_img.set_color(self.colors.uncrossable_body_of_water_bank_line);
+            self.black_img.set_line_width(outline_width);
             self.black_img.draw_polyline(&points);
         }
 
@@ -142,12 +364,14 @@ impl MapRenderer {
 
     #[inline]
     pub fn crossable_watercourse_304(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::CrossableWatercourse, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.blue_lines_and_points_img.set_color(VECTOR_BLUE);
+            self.blue_lines_and_points_img.set_color(self.colors.crossable_watercourse);
             self.blue_lines_and_points_img
-                .set_line_width(CROSSABLE_WATERCOURSE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.crossable_watercourse_width));
             self.blue_lines_and_points_img.draw_polyline(&points);
         }
 
@@ -156,15 +380,17 @@ impl MapRenderer {
 
     #[inline]
     pub fn minor_seasonal_water_channel_306(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::MinorSeasonalWaterChannel, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.blue_lines_and_points_img.set_color(VECTOR_BLUE);
+            self.blue_lines_and_points_img.set_color(self.colors.minor_seasonal_water_channel);
             self.blue_lines_and_points_img
-                .set_line_width(MINOR_WATERCOURSE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.minor_watercourse_width));
             self.blue_lines_and_points_img.set_dash(
-                MINOR_WATERCOURSE_DASH_LENGTH * self.dpi_resolution * 10.0 / INCH,
-                MINOR_WATERCOURSE_DASH_INTERVAL_LENGTH * self.dpi_resolution * 10.0 / INCH,
+                self.millimeters_to_pixels(self.dimensions.minor_watercourse_dash_length),
+                self.millimeters_to_pixels(self.dimensions.minor_watercourse_dash_interval_length),
             );
             self.blue_lines_and_points_img.draw_polyline(&points);
             self.blue_lines_and_points_img.unset_dash();
@@ -175,8 +401,10 @@ impl MapRenderer {
 
     #[inline]
     pub fn marsh_308(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::Marsh, polygon);
+
         let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
-        self.striped_blue_img.set_color(VECTOR_BLUE);
+        self.striped_blue_img.set_color(self.colors.marsh);
         self.striped_blue_img
             .draw_filled_polygon_with_holes(&outer_geometry, &holes);
 
@@ -185,17 +413,19 @@ impl MapRenderer {
 
     #[inline]
     fn wide_road(mut self, line: &GenericPolyline<Point>, inner_width: f32, outer_width: f32) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::WideRoad, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
             self.black_road_outlines_img.set_color(VECTOR_BLACK);
             self.black_road_outlines_img
-                .set_line_width(outer_width * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(outer_width));
             self.black_road_outlines_img.draw_polyline(&points);
 
             self.light_brown_road_infill_img
-                .set_color(VECTOR_PAVED_AREA_BROWN);
+                .set_color(self.colors.wide_road);
             self.light_brown_road_infill_img
-                .set_line_width(inner_width * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(inner_width));
             self.light_brown_road_infill_img.draw_polyline(&points);
         }
 
@@ -204,17 +434,20 @@ impl MapRenderer {
 
     #[inline]
     pub fn double_track_wide_road_502(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::WideRoad, line);
+
+        let outer_width = self.millimeters_to_pixels(self.dimensions.double_track_wide_road_outer_width);
+        let inner_width = self.millimeters_to_pixels(self.dimensions.double_track_wide_road_inner_width);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
             self.black_road_outlines_img.set_color(VECTOR_BLACK);
-            self.black_road_outlines_img
-                .set_line_width(DOUBLE_TRACK_WIDE_ROAD_OUTER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+            self.black_road_outlines_img.set_line_width(outer_width);
             self.black_road_outlines_img.draw_polyline(&points);
 
             self.light_brown_road_infill_img
-                .set_color(VECTOR_PAVED_AREA_BROWN);
-            self.light_brown_road_infill_img
-                .set_line_width(DOUBLE_TRACK_WIDE_ROAD_INNER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_color(self.colors.wide_road);
+            self.light_brown_road_infill_img.set_line_width(inner_width);
             self.light_brown_road_infill_img.draw_polyline(&points);
 
             // TODO
@@ -230,27 +463,44 @@ impl MapRenderer {
 
     #[inline]
     pub fn wide_road_502(self, line: &GenericPolyline<Point>) -> MapRenderer {
-        return self.wide_road(line, WIDE_ROAD_INNER_WIDTH, WIDE_ROAD_OUTER_WIDTH);
+        let (inner_width, outer_width) = (
+            self.dimensions.wide_road_inner_width,
+            self.dimensions.wide_road_outer_width,
+        );
+
+        return self.wide_road(line, inner_width, outer_width);
     }
 
     #[inline]
     pub fn xl_wide_road_502(self, line: &GenericPolyline<Point>) -> MapRenderer {
-        return self.wide_road(line, XL_WIDE_ROAD_INNER_WIDTH, XL_WIDE_ROAD_OUTER_WIDTH);
+        let (inner_width, outer_width) = (
+            self.dimensions.xl_wide_road_inner_width,
+            self.dimensions.xl_wide_road_outer_width,
+        );
+
+        return self.wide_road(line, inner_width, outer_width);
     }
 
     #[inline]
     pub fn xxl_wide_road_502(self, line: &GenericPolyline<Point>) -> MapRenderer {
-        return self.wide_road(line, XXL_WIDE_ROAD_INNER_WIDTH, XXL_WIDE_ROAD_OUTER_WIDTH);
+        let (inner_width, outer_width) = (
+            self.dimensions.xxl_wide_road_inner_width,
+            self.dimensions.xxl_wide_road_outer_width,
+        );
+
+        return self.wide_road(line, inner_width, outer_width);
     }
 
     #[inline]
     pub fn road_503(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::Road, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.black_img.set_color(VECTOR_BLACK);
+            self.black_img.set_color(self.colors.road);
             self.black_img
-                .set_line_width(ROAD_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.road_width));
             self.black_img.draw_polyline(&points);
         }
 
@@ -259,15 +509,17 @@ impl MapRenderer {
 
     #[inline]
     pub fn footpath_505(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::Footpath, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.black_img.set_color(VECTOR_BLACK);
+            self.black_img.set_color(self.colors.footpath);
             self.black_img
-                .set_line_width(FOOTPATH_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.footpath_width));
             self.black_img.set_dash(
-                FOOTPATH_DASH_LENGTH * self.dpi_resolution * 10.0 / INCH,
-                FOOTPATH_DASH_INTERVAL_LENGTH * self.dpi_resolution * 10.0 / INCH,
+                self.millimeters_to_pixels(self.dimensions.footpath_dash_length),
+                self.millimeters_to_pixels(self.dimensions.footpath_dash_interval_length),
             );
             self.black_img.draw_polyline(&points);
             self.black_img.unset_dash();
@@ -278,20 +530,22 @@ impl MapRenderer {
 
     #[inline]
     pub fn railway_509(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::Railway, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.black_img.set_color(VECTOR_BLACK);
+            self.black_img.set_color(self.colors.railway);
             self.black_img
-                .set_line_width(RAILWAY_OUTER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.railway_outer_width));
             self.black_img.draw_polyline(&points);
 
             self.black_img.set_color(VECTOR_WHITE);
             self.black_img
-                .set_line_width(RAILWAY_INNER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.railway_inner_width));
             self.black_img.set_dash(
-                RAILWAY_DASH_LENGTH * self.dpi_resolution * 10.0 / INCH,
-                RAILWAY_DASH_INTERVAL_LENGTH * self.dpi_resolution * 10.0 / INCH,
+                self.millimeters_to_pixels(self.dimensions.railway_dash_length),
+                self.millimeters_to_pixels(self.dimensions.railway_dash_interval_length),
             );
             self.black_img.draw_polyline(&points);
             self.black_img.unset_dash();
@@ -302,12 +556,14 @@ impl MapRenderer {
 
     #[inline]
     pub fn power_line_cableway_or_skilift_510(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::PowerLine, line);
+
         for part in line.parts() {
             let points = self.get_points_from_line_part(part);
 
-            self.black_img.set_color(VECTOR_BLACK);
+            self.black_img.set_color(self.colors.power_line);
             self.black_img
-                .set_line_width(POWERLINE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.power_line_width));
             self.black_img.draw_polyline(&points);
         }
 
@@ -320,23 +576,97 @@ impl MapRenderer {
             let points = self.get_points_from_line_part(part);
             self.black_img.set_color(VECTOR_BLACK);
             self.black_img
-                .set_line_width(_MAJOR_POWERLINE_OUTER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions._major_power_line_outer_width));
             self.black_img.draw_polyline(&points);
 
             self.black_img.set_transparent_color();
             self.black_img
-                .set_line_width(_MAJOR_POWERLINE_INNER_WIDTH * self.dpi_resolution * 10.0 / INCH);
+                .set_line_width(self.millimeters_to_pixels(self.dimensions._major_power_line_inner_width));
             self.black_img.draw_polyline(&points);
         }
 
         return self;
     }
 
+    #[inline]
+    pub fn impassable_wall_515(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::ImpassableWall, line);
+
+        for part in line.parts() {
+            let points = self.get_points_from_line_part(part);
+
+            self.black_img.set_color(self.colors.impassable_wall);
+            self.black_img
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.impassable_wall_width));
+            self.black_img.draw_polyline(&points);
+        }
+
+        return self;
+    }
+
+    #[inline]
+    pub fn fence_516(self, line: &GenericPolyline<Point>) -> MapRenderer {
+        let (width, tag_length, tag_spacing) = (
+            self.dimensions.fence_width,
+            self.dimensions.fence_tag_length,
+            self.dimensions.fence_tag_spacing,
+        );
+
+        return self.fence(line, OmapSymbol::Fence, width, tag_length, tag_spacing, false);
+    }
+
+    #[inline]
+    pub fn impassable_fence_518(self, line: &GenericPolyline<Point>) -> MapRenderer {
+        let (width, tag_length, tag_spacing) = (
+            self.dimensions.impassable_fence_width,
+            self.dimensions.impassable_fence_tag_length,
+            self.dimensions.impassable_fence_tag_spacing,
+        );
+
+        return self.fence(line, OmapSymbol::ImpassableFence, width, tag_length, tag_spacing, true);
+    }
+
+    /// Draws a fence line with tags of the same width on its right side, or on both sides for the
+    /// uncrossable ones.
+    #[inline]
+    fn fence(
+        mut self,
+        line: &GenericPolyline<Point>,
+        omap_symbol: OmapSymbol,
+        width: f32,
+        tag_length: f32,
+        tag_spacing: f32,
+        both_sides: bool,
+    ) -> MapRenderer {
+        self.add_omap_line(omap_symbol, line);
+
+        let color = match omap_symbol {
+            OmapSymbol::ImpassableFence => self.colors.impassable_fence,
+            _ => self.colors.fence,
+        };
+        let tag_length = self.millimeters_to_pixels(tag_length);
+        let tag_spacing = self.millimeters_to_pixels(tag_spacing);
+
+        for part in line.parts() {
+            let points = self.get_points_from_line_part(part);
+
+            self.black_img.set_color(color);
+            self.black_img
+                .set_line_width(self.millimeters_to_pixels(width));
+            self.black_img.draw_polyline(&points);
+            Self::draw_tags(&mut self.black_img, &points, tag_length, tag_spacing, both_sides);
+        }
+
+        return self;
+    }
+
     #[inline]
     pub fn area_that_shall_not_be_entered_520(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::AreaThatShallNotBeEntered, polygon);
+
         let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
 
-        self.olive_green_img.set_color(VECTOR_OLIVE_GREEN);
+        self.olive_green_img.set_color(self.colors.area_that_shall_not_be_entered);
         self.olive_green_img
             .draw_filled_polygon_with_holes(&outer_geometry, &holes);
 
@@ -345,15 +675,17 @@ impl MapRenderer {
 
     #[inline]
     pub fn building_521(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::Building, polygon);
+
         let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
 
-        self.gray_img.set_color(VECTOR_BUILDING_GRAY);
+        self.gray_img.set_color(self.colors.building);
         self.gray_img
             .draw_filled_polygon_with_holes(&outer_geometry, &holes);
 
         self.black_img.set_color(VECTOR_BLACK);
         self.black_img
-            .set_line_width(BUILDING_OUTLINE_WIDTH * self.dpi_resolution * 10.0 / INCH);
+            .set_line_width(self.millimeters_to_pixels(self.dimensions.building_outline_width));
         self.black_img.draw_polyline(&outer_geometry);
 
         for hole in holes {
@@ -363,6 +695,96 @@ impl MapRenderer {
         return self;
     }
 
+    #[inline]
+    pub fn building_passage_521_1(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::BuildingPassage, polygon);
+
+        let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
+
+        self.gray_img.set_color(self.colors.building_passage);
+        self.gray_img
+            .draw_filled_polygon_with_holes(&outer_geometry, &holes);
+
+        return self;
+    }
+
+    #[inline]
+    pub fn paved_area_529(mut self, polygon: &GenericPolygon<Point>) -> MapRenderer {
+        self.add_omap_area(OmapSymbol::PavedArea, polygon);
+
+        let (outer_geometry, holes) = self.get_outer_geometry_and_holes_from_polygon(polygon);
+
+        self.light_brown_img.set_color(self.colors.paved_area);
+        self.light_brown_img
+            .draw_filled_polygon_with_holes(&outer_geometry, &holes);
+
+        return self;
+    }
+
+    #[inline]
+    pub fn step_or_edge_of_paved_area_529_1(mut self, line: &GenericPolyline<Point>) -> MapRenderer {
+        self.add_omap_line(OmapSymbol::StepOrEdgeOfPavedArea, line);
+
+        for part in line.parts() {
+            let points = self.get_points_from_line_part(part);
+
+            self.black_img.set_color(self.colors.step_or_edge_of_paved_area);
+            self.black_img
+                .set_line_width(self.millimeters_to_pixels(self.dimensions.step_or_edge_of_paved_area_width));
+            self.black_img.draw_polyline(&points);
+        }
+
+        return self;
+    }
+
+    #[inline]
+    fn add_omap_point(&mut self, symbol: OmapSymbol, point: &Point) {
+        self.add_used_symbol(symbol);
+
+        if let Some(omap_map) = &mut self.omap_map {
+            omap_map.add_point(symbol, point);
+        }
+    }
+
+    #[inline]
+    fn add_omap_line(&mut self, symbol: OmapSymbol, line: &GenericPolyline<Point>) {
+        self.add_used_symbol(symbol);
+
+        if let Some(omap_map) = &mut self.omap_map {
+            omap_map.add_line(symbol, line);
+        }
+    }
+
+    #[inline]
+    fn add_omap_area(&mut self, symbol: OmapSymbol, polygon: &GenericPolygon<Point>) {
+        self.add_used_symbol(symbol);
+
+        if let Some(omap_map) = &mut self.omap_map {
+            omap_map.add_area(symbol, polygon);
+        }
+    }
+
+    #[inline]
+    fn add_omap_polygon_outline(&mut self, symbol: OmapSymbol, polygon: &GenericPolygon<Point>) {
+        self.add_used_symbol(symbol);
+
+        if let Some(omap_map) = &mut self.omap_map {
+            omap_map.add_polygon_outline(symbol, polygon);
+        }
+    }
+
+    #[inline]
+    fn add_used_symbol(&mut self, symbol: OmapSymbol) {
+        if !self.used_symbols.contains(&symbol) {
+            self.used_symbols.push(symbol);
+        }
+    }
+
+    #[inline]
+    fn millimeters_to_pixels(&self, millimeters: f32) -> f32 {
+        return millimeters_to_pixels(millimeters, self.dpi_resolution);
+    }
+
     #[inline]
     fn get_points_from_line_part(&self, line_part: &Vec<Point>) -> Vec<(f32, f32)> {
         let mut points: Vec<(f32, f32)> = vec![];
@@ -377,6 +799,14 @@ impl MapRenderer {
         return points;
     }
 
+    #[inline]
+    fn get_point_position(&self, point: &Point) -> (f32, f32) {
+        return (
+            (point.x - self.min_x as f64) as f32 * self.scale_factor,
+            self.image_height as f32 - (point.y - self.min_y as f64) as f32 * self.scale_factor,
+        );
+    }
+
     #[inline]
     fn get_outer_geometry_and_holes_from_polygon(
         &self,
@@ -400,6 +830,50 @@ impl MapRenderer {
         return (outer_geometry, holes);
     }
 
+    /// Draws tags along a line every tag spacing, on its right side or on both sides, with the current
+    /// color and line width of the image. Lengths are in pixels.
+    #[inline]
+    fn draw_tags(
+        image: &mut Canvas,
+        points: &Vec<(f32, f32)>,
+        tag_length: f32,
+        tag_spacing: f32,
+        both_sides: bool,
+    ) {
+        // The first tag is half a spacing from the start of the line
+        let mut distance_to_next_tag = tag_spacing / 2.;
+
+        for segment in points.windows(2) {
+            let (start_x, start_y) = segment[0];
+            let (end_x, end_y) = segment[1];
+            let segment_length = ((end_x - start_x).powi(2) + (end_y - start_y).powi(2)).sqrt();
+
+            if segment_length == 0. {
+                continue;
+            }
+
+            // With the y axis going down, the right of (dx, dy) is (-dy, dx)
+            let right_x = -(end_y - start_y) / segment_length;
+            let right_y = (end_x - start_x) / segment_length;
+            let left_length = if both_sides { tag_length } else { 0. };
+            let mut position = distance_to_next_tag;
+
+            while position <= segment_length {
+                let tag_x = start_x + (end_x - start_x) * position / segment_length;
+                let tag_y = start_y + (end_y - start_y) * position / segment_length;
+
+                image.draw_polyline(&[
+                    (tag_x - right_x * left_length, tag_y - right_y * left_length),
+                    (tag_x + right_x * tag_length, tag_y + right_y * tag_length),
+                ]);
+
+                position += tag_spacing;
+            }
+
+            distance_to_next_tag = position - segment_length;
+        }
+    }
+
     #[inline]
     fn draw_stripes(
         image: &mut Canvas,
@@ -410,7 +884,7 @@ impl MapRenderer {
         spacing: f32,
         direction: StripeDirection,
     ) {
-        let pixel_marsh_interval = (line_width + spacing) * dpi_resolution * 10.0 / INCH;
+        let pixel_marsh_interval = millimeters_to_pixels(line_width + spacing, dpi_resolution);
         image.set_transparent_color();
 
         match direction {
@@ -419,7 +893,7 @@ impl MapRenderer {
 
                 for i in 0..number_of_stripes {
                     let min_y = i as f32 * pixel_marsh_interval;
-                    let max_y = min_y + spacing * dpi_resolution * 10.0 / INCH;
+                    let max_y = min_y + millimeters_to_pixels(spacing, dpi_resolution);
 
                     image.draw_filled_polygon(&vec![
                         (0., min_y),
@@ -435,7 +909,7 @@ impl MapRenderer {
 
                 for i in 0..number_of_stripes {
                     let min_x = i as f32 * pixel_marsh_interval;
-                    let max_x = min_x + spacing * dpi_resolution * 10.0 / INCH;
+                    let max_x = min_x + millimeters_to_pixels(spacing, dpi_resolution);
 
                     image.draw_filled_polygon(&vec![
                         (min_x, 0.),
@@ -450,14 +924,21 @@ impl MapRenderer {
     }
 
     #[inline]
-    pub fn save_as(mut self, path: PathBuf) {
+    pub fn save_as(mut self, path: PathBuf, vector_map: Option<VectorMap>) -> Result<(), CassiniError> {
+        if let Some(omap_map) = &self.omap_map {
+            omap_map.save_as(&path.with_extension("omap"))?;
+        }
+
+        let used_symbol_codes: Vec<&str> = self.used_symbols.iter().map(|symbol| symbol.get_code()).collect();
+        write_symbol_codes(&path.with_file_name("symbols.json"), &used_symbol_codes)?;
+
         Self::draw_stripes(
             &mut self.striped_blue_img,
             self.image_width,
             self.image_height,
             self.dpi_resolution,
-            MARSH_LINE_WIDTH,
-            MARSH_LINE_SPACING,
+            self.dimensions.marsh_line_width,
+            self.dimensions.marsh_line_spacing,
             StripeDirection::Horizontal,
         );
 
@@ -467,14 +948,33 @@ impl MapRenderer {
                 self.image_width,
                 self.image_height,
                 self.dpi_resolution,
-                UNDERGROWTH_LINE_WIDTH,
-                UNDERGROWTH_LINE_SPACING,
+                self.dimensions.undergrowth_line_width,
+                self.dimensions.undergrowth_line_spacing,
                 StripeDirection::Vertical,
             );
 
             self.vegetation_img.overlay(undergrowth_img, 0., 0.);
         }
 
+        if let Some(mut vector_map) = vector_map {
+            // Vegetation stays a raster, the other layers are written as paths
+            vector_map.set_background(self.vegetation_img.data().as_bytes())?;
+            vector_map.add_layer("Forbidden areas", self.olive_green_img.take_recorded_shapes());
+            vector_map.add_layer("Light brown areas", self.light_brown_img.take_recorded_shapes());
+            vector_map.add_layer("Water", self.blue_img.take_recorded_shapes());
+            vector_map.add_layer("Marshes", self.striped_blue_img.take_recorded_shapes());
+            vector_map.add_layer("Road outlines", self.black_road_outlines_img.take_recorded_shapes());
+            vector_map.add_layer("Road infills", self.light_brown_road_infill_img.take_recorded_shapes());
+            vector_map.add_layer("Buildings", self.gray_img.take_recorded_shapes());
+            vector_map.add_contours_layer(self.contours_img.take_recorded_shapes());
+            vector_map.add_layer("Watercourses", self.blue_lines_and_points_img.take_recorded_shapes());
+            vector_map.add_layer("Cliffs", self.cliffs_img.take_recorded_shapes());
+            vector_map.add_layer("Black features", self.black_img.take_recorded_shapes());
+
+            let vector_map_path = path.with_extension(vector_map.extension());
+            vector_map.save_as(&vector_map_path)?;
+        }
+
         self.vegetation_img.overlay(&mut self.olive_green_img, 0., 0.);
         self.vegetation_img.overlay(&mut self.light_brown_img, 0., 0.);
         self.vegetation_img.overlay(&mut self.blue_img, 0., 0.);
@@ -490,6 +990,6 @@ impl MapRenderer {
         self.vegetation_img.overlay(&mut self.cliffs_img, 0., 0.);
         self.vegetation_img.overlay(&mut self.black_img, 0., 0.);
 
-        self.vegetation_img.save_as(path.to_str().unwrap());
+        self.vegetation_img.save_as(path.to_str().unwrap())
     }
 }
@@ -0,0 +1,328 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::raster::is_nodata;
+
+/// An edge between two adjacent nodes of the elevation matrix.
+/// Horizontal(i, j) joins (i, j) to (i + 1, j), Vertical(i, j) joins (i, j) to (i, j + 1).
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Edge {
+    Horizontal(usize, usize),
+    Vertical(usize, usize),
+}
+
+/// Traces the contours of an elevation matrix indexed as `elevations[x][y]`, with `y` growing northward.
+/// The node (i, j) is located at (origin_x + i * cell_size, origin_y + j * cell_size).
+///
+/// Every contour is returned as a (x coordinates, y coordinates, elevation) polyline, oriented so that
+/// the higher ground is on its right. Closed contours end with their first point. NaN and nodata values
+/// break the contours.
+pub fn trace_contours_with_marching_squares(
+    elevations: &Vec<Vec<f64>>,
+    origin_x: f64,
+    origin_y: f64,
+    cell_size: f64,
+    interval: f64,
+) -> Vec<(Vec<f64>, Vec<f64>, f64)> {
+    let width = elevations.len();
+    let height = if width == 0 { 0 } else { elevations[0].len() };
+
+    let mut min_elevation = f64::MAX;
+    let mut max_elevation = f64::MIN;
+
+    for column in elevations {
+        for elevation in column {
+            if !is_nodata(*elevation) {
+                min_elevation = min_elevation.min(*elevation);
+                max_elevation = max_elevation.max(*elevation);
+            }
+        }
+    }
+
+    if min_elevation > max_elevation {
+        return vec![];
+    }
+
+    let first_level_index = (min_elevation / interval).ceil() as i64;
+    let last_level_index = (max_elevation / interval).floor() as i64;
+
+    if first_level_index > last_level_index {
+        return vec![];
+    }
+
+    let mut segments_by_level: Vec<Vec<(Edge, Edge)>> =
+        vec![vec![]; (last_level_index - first_level_index + 1) as usize];
+
+    for i in 0..width.saturating_sub(1) {
+        for j in 0..height.saturating_sub(1) {
+            // Corners in counterclockwise order, and the edges going from each corner to the next one
+            let corners = [
+                elevations[i][j],
+                elevations[i + 1][j],
+                elevations[i + 1][j + 1],
+                elevations[i][j + 1],
+            ];
+
+            if corners.iter().any(|corner| is_nodata(*corner)) {
+                continue;
+            }
+
+            let edges = [
+                Edge::Horizontal(i, j),
+                Edge::Vertical(i + 1, j),
+                Edge::Horizontal(i, j + 1),
+                Edge::Vertical(i, j),
+            ];
+
+            let cell_min = corners.iter().cloned().fold(f64::MAX, f64::min);
+            let cell_max = corners.iter().cloned().fold(f64::MIN, f64::max);
+            let cell_first_level_index = ((cell_min / interval).floor() as i64).max(first_level_index);
+            let cell_last_level_index = ((cell_max / interval).ceil() as i64).min(last_level_index);
+
+            for level_index in cell_first_level_index..=cell_last_level_index {
+                let level = level_index as f64 * interval;
+
+                add_cell_segments(
+                    &corners,
+                    &edges,
+                    level,
+                    &mut segments_by_level[(level_index - first_level_index) as usize],
+                );
+            }
+        }
+    }
+
+    let mut contours: Vec<(Vec<f64>, Vec<f64>, f64)> = vec![];
+
+    for (level_offset, segments) in segments_by_level.iter().enumerate() {
+        let level = (first_level_index + level_offset as i64) as f64 * interval;
+
+        for chain in join_segments(segments) {
+            let mut x_array = Vec::with_capacity(chain.len());
+            let mut y_array = Vec::with_capacity(chain.len());
+
+            for edge in chain {
+                let (x, y) = get_edge_point(elevations, edge, level, origin_x, origin_y, cell_size);
+                x_array.push(x);
+                y_array.push(y);
+            }
+
+            contours.push((x_array, y_array, level));
+        }
+    }
+
+    return contours;
+}
+
+/// Adds the oriented segments of a cell for a given level. Walking the cell boundary counterclockwise,
+/// a segment starts on an edge going from below to above the level, and ends on an edge going from above
+/// to below, which keeps the higher ground on its right.
+fn add_cell_segments(corners: &[f64; 4], edges: &[Edge; 4], level: f64, segments: &mut Vec<(Edge, Edge)>) {
+    // (edge index, is a start edge)
+    let mut crossings: Vec<(usize, bool)> = vec![];
+
+    for k in 0..4 {
+        let is_above = corners[k] > level;
+        let is_next_above = corners[(k + 1) % 4] > level;
+
+        if is_above != is_next_above {
+            crossings.push((k, is_next_above));
+        }
+    }
+
+    let number_of_crossings = crossings.len();
+
+    if number_of_crossings == 0 {
+        return;
+    }
+
+    // Saddle cells are disambiguated with the mean of the corners: when the center is above the level,
+    // the higher corners are connected and the segments cut off the lower ones.
+    let is_center_above = corners.iter().sum::<f64>() / 4. > level;
+
+    for position in 0..number_of_crossings {
+        let (edge_index, is_start) = crossings[position];
+
+        if !is_start {
+            continue;
+        }
+
+        let end_position = if is_center_above {
+            (position + number_of_crossings - 1) % number_of_crossings
+        } else {
+            (position + 1) % number_of_crossings
+        };
+
+        segments.push((edges[edge_index], edges[crossings[end_position].0]));
+    }
+}
+
+/// Chains the segments of a level into polylines, open ones first, then closed ones.
+fn join_segments(segments: &Vec<(Edge, Edge)>) -> Vec<Vec<Edge>> {
+    let segment_index_by_start: HashMap<Edge, usize> = segments
+        .iter()
+        .enumerate()
+        .map(|(index, (start, _))| (*start, index))
+        .collect();
+
+    let ends: HashSet<Edge> = segments.iter().map(|(_, end)| *end).collect();
+    let mut is_visited = vec![false; segments.len()];
+    let mut chains: Vec<Vec<Edge>> = vec![];
+
+    let open_chain_heads: Vec<usize> = (0..segments.len())
+        .filter(|index| !ends.contains(&segments[*index].0))
+        .collect();
+
+    for head in open_chain_heads.into_iter().chain(0..segments.len()) {
+        if is_visited[head] {
+            continue;
+        }
+
+        let mut chain = vec![segments[head].0];
+        let mut current = head;
+
+        loop {
+            is_visited[current] = true;
+            let end = segments[current].1;
+            chain.push(end);
+
+            match segment_index_by_start.get(&end) {
+                Some(next) if !is_visited[*next] => current = *next,
+                _ => break,
+            }
+        }
+
+        chains.push(chain);
+    }
+
+    return chains;
+}
+
+fn get_edge_point(
+    elevations: &Vec<Vec<f64>>,
+    edge: Edge,
+    level: f64,
+    origin_x: f64,
+    origin_y: f64,
+    cell_size: f64,
+) -> (f64, f64) {
+    let ((i0, j0), (i1, j1)) = match edge {
+        Edge::Horizontal(i, j) => ((i, j), (i + 1, j)),
+        Edge::Vertical(i, j) => ((i, j), (i, j + 1)),
+    };
+
+    let elevation0 = elevations[i0][j0];
+    let elevation1 = elevations[i1][j1];
+    let ratio = (level - elevation0) / (elevation1 - elevation0);
+
+    let x = origin_x + (i0 as f64 + ratio * (i1 as f64 - i0 as f64)) * cell_size;
+    let y = origin_y + (j0 as f64 + ratio * (j1 as f64 - j0 as f64)) * cell_size;
+
+    return (x, y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_contours_at_level(
+        elevations: &Vec<Vec<f64>>,
+        interval: f64,
+        level: f64,
+    ) -> Vec<(Vec<f64>, Vec<f64>, f64)> {
+        return trace_contours_with_marching_squares(elevations, 0., 0., 1., interval)
+            .into_iter()
+            .filter(|(_, _, contour_level)| *contour_level == level)
+            .collect();
+    }
+
+    /// Twice the signed area of a closed polyline, positive when it is counterclockwise.
+    fn get_signed_area(x_array: &[f64], y_array: &[f64]) -> f64 {
+        return (0..x_array.len() - 1)
+            .map(|index| x_array[index] * y_array[index + 1] - x_array[index + 1] * y_array[index])
+            .sum();
+    }
+
+    #[test]
+    fn peak_gives_closed_clockwise_ring() {
+        let elevations = vec![vec![1., 1., 1.], vec![1., 10., 1.], vec![1., 1., 1.]];
+        let contours = get_contours_at_level(&elevations, 5., 5.);
+
+        assert_eq!(contours.len(), 1);
+
+        let (x_array, y_array, _) = &contours[0];
+
+        // One point per cell around the peak, and the first point again
+        assert_eq!(x_array.len(), 5);
+        assert_eq!((x_array[0], y_array[0]), (x_array[4], y_array[4]));
+
+        // The higher ground is on the right when the ring is clockwise
+        assert!(get_signed_area(x_array, y_array) < 0.);
+
+        for (x, y) in x_array.iter().zip(y_array) {
+            let distance_to_peak = (x - 1.).abs() + (y - 1.).abs();
+            assert!((distance_to_peak - 5. / 9.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn saddle_is_resolved_with_center_elevation() {
+        // Higher south west and north east corners, the mean of the corners is 6
+        let elevations = vec![vec![11., 1.], vec![1., 11.]];
+        let low_corners = [(1., 0.), (0., 1.)];
+        let high_corners = [(0., 0.), (1., 1.)];
+
+        // Below the center elevation the higher corners are connected, so the segments cut off the lower
+        // ones. Above it the lower corners are connected.
+        for (level, cut_off_corners) in [(5., low_corners), (10., high_corners)] {
+            let contours = get_contours_at_level(&elevations, 5., level);
+
+            assert_eq!(contours.len(), 2);
+
+            for (x_array, y_array, _) in &contours {
+                assert_eq!(x_array.len(), 2);
+
+                let middle = ((x_array[0] + x_array[1]) / 2., (y_array[0] + y_array[1]) / 2.);
+
+                assert!(cut_off_corners
+                    .iter()
+                    .any(|corner| (corner.0 - middle.0).abs() < 0.5 && (corner.1 - middle.1).abs() < 0.5));
+            }
+        }
+    }
+
+    #[test]
+    fn contours_end_on_grid_edges() {
+        // Elevation growing eastward: 5, 15, 25
+        let elevations = vec![vec![5.; 3], vec![15.; 3], vec![25.; 3]];
+        let contours = trace_contours_with_marching_squares(&elevations, 0., 0., 1., 10.);
+
+        assert_eq!(contours.len(), 2);
+
+        for (x_array, y_array, level) in &contours {
+            let expected_x = (level - 5.) / 10.;
+
+            assert!(x_array.iter().all(|x| (x - expected_x).abs() < 1e-9));
+            // Open contours going northward, the higher ground on their right
+            assert_eq!(y_array, &vec![0., 1., 2.]);
+        }
+    }
+
+    #[test]
+    fn levels_equal_to_node_elevations_give_finite_points() {
+        let elevations = vec![vec![0.; 3], vec![10.; 3], vec![20.; 3]];
+        let contours = trace_contours_with_marching_squares(&elevations, 0., 0., 1., 10.);
+
+        assert!(!contours.is_empty());
+
+        for (x_array, y_array, level) in &contours {
+            assert!(x_array
+                .iter()
+                .chain(y_array)
+                .all(|coordinate| coordinate.is_finite()));
+
+            // The nodes at the level are below it, so the contour goes through them
+            let expected_x = level / 10.;
+            assert!(x_array.iter().all(|x| (x - expected_x).abs() < 1e-9));
+        }
+    }
+}
@@ -1,23 +1,36 @@
 use log::{info, warn};
-use std::{path::Path, time::Instant};
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
 use crate::{
     canvas::Canvas,
     config::get_config,
-    constants::{INCH, MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT},
+    constants::MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT,
+    crs::create_prj_file,
+    error::CassiniError,
+    print_layout::generate_print_layout,
+    symbol_styles::SymbolStyles,
     tile::TileWithNeighbors,
     world_file::create_world_file,
 };
 
-pub fn merge_maps(output_dir: &str, tiles_with_neighbors: Vec<TileWithNeighbors>) {
+pub fn merge_maps(
+    output_dir: &str,
+    tiles_with_neighbors: Vec<TileWithNeighbors>,
+    symbol_styles: &SymbolStyles,
+) -> Result<(), CassiniError> {
     info!("Merging maps");
     let start = Instant::now();
 
-    let config = get_config();
+    cleanup_merged_maps(output_dir);
+
+    let config = get_config()?;
 
     if tiles_with_neighbors.len() == 0 {
         warn!("No map to merge.");
-        return;
+        return Ok(());
     }
 
     let first_tile = &tiles_with_neighbors[0].tile;
@@ -42,15 +55,16 @@ pub fn merge_maps(output_dir: &str, tiles_with_neighbors: Vec<TileWithNeighbors>
         }
     }
 
-    let total_width = ((max_x - min_x) as f32 * config.dpi_resolution / INCH).ceil() as i32;
-    let total_height = ((max_y - min_y) as f32 * config.dpi_resolution / INCH).ceil() as i32;
+    let pixels_per_meter = config.get_pixels_per_meter();
+    let total_width = ((max_x - min_x) as f32 * pixels_per_meter).ceil() as i32;
+    let total_height = ((max_y - min_y) as f32 * pixels_per_meter).ceil() as i32;
 
     let cols = (total_width + MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT - 1) / MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT;
     let rows = (total_height + MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT - 1) / MAX_MERGED_PIXEL_WIDTH_AND_HEIGHT;
     let num_chunks = cols * rows;
     let is_single_chunk = num_chunks == 1;
 
-    let resolution = INCH / config.dpi_resolution;
+    let resolution = 1. / pixels_per_meter;
     let geo_width = (max_x - min_x) as f32;
     let geo_height = (max_y - min_y) as f32;
 
@@ -94,11 +108,11 @@ pub fn merge_maps(output_dir: &str, tiles_with_neighbors: Vec<TileWithNeighbors>
                     continue;
                 }
 
-                let mut map = Canvas::load_from(t.render_dir_path.join("full-map.png").to_str().unwrap());
+                let mut map = Canvas::load_from(t.render_dir_path.join("full-map.png").to_str().unwrap())?;
 
                 // Tile pixel offset in the full merged image
-                let tile_full_px_x = ((t.min_x - min_x) as f32 * config.dpi_resolution / INCH).floor();
-                let tile_full_px_y = (((max_y - t.max_y) as f32) * config.dpi_resolution / INCH).floor();
+                let tile_full_px_x = ((t.min_x - min_x) as f32 * pixels_per_meter).floor();
+                let tile_full_px_y = (((max_y - t.max_y) as f32) * pixels_per_meter).floor();
 
                 // Offset relative to this chunk's origin
                 let overlay_x = tile_full_px_x - chunk_px_x0 as f32;
@@ -107,20 +121,25 @@ pub fn merge_maps(output_dir: &str, tiles_with_neighbors: Vec<TileWithNeighbors>
                 chunk_canvas.overlay(&mut map, overlay_x, overlay_y);
             }
 
-            let (png_name, pgw_name) = if is_single_chunk {
-                ("merged-map.png".to_string(), "merged-map.pgw".to_string())
+            let (png_name, pgw_name, prj_name) = if is_single_chunk {
+                (
+                    "merged-map.png".to_string(),
+                    "merged-map.pgw".to_string(),
+                    "merged-map.prj".to_string(),
+                )
             } else {
                 (
                     format!("merged-map-{}.png", chunk_index),
                     format!("merged-map-{}.pgw", chunk_index),
+                    format!("merged-map-{}.prj", chunk_index),
                 )
             };
 
-            chunk_canvas.save_as(Path::new(output_dir).join(&png_name).to_str().unwrap());
+            chunk_canvas.save_as(Path::new(output_dir).join(&png_name).to_str().unwrap())?;
 
             let world_file_path = Path::new(output_dir).join(&pgw_name);
-            create_world_file(chunk_geo_min_x, chunk_geo_max_y, resolution, &world_file_path)
-                .expect("Could not create world file");
+            create_world_file(chunk_geo_min_x, chunk_geo_max_y, resolution, &world_file_path)?;
+            create_prj_file(&first_tile.crs, &Path::new(output_dir).join(&prj_name))?;
 
             info!(
                 "Saved chunk {}/{}: {} ({}x{} px)",
@@ -131,4 +150,38 @@ pub fn merge_maps(output_dir: &str, tiles_with_neighbors: Vec<TileWithNeighbors>
 
     let duration = start.elapsed();
     info!("Map merged in {:.1?}", duration);
+
+    if config.print_layout.enabled {
+        if is_single_chunk {
+            let render_dir_paths: Vec<PathBuf> = tiles_with_neighbors
+                .iter()
+                .map(|tile| tile.tile.render_dir_path.clone())
+                .collect();
+
+            generate_print_layout(
+                &Path::new(output_dir).join("merged-map.png"),
+                (min_x, min_y, max_x, max_y),
+                &first_tile.crs,
+                &render_dir_paths,
+                &config,
+                symbol_styles,
+            )?;
+        } else {
+            warn!("The merged map is split into chunks, no print layout is generated.");
+        }
+    }
+
+    Ok(())
+}
+
+fn cleanup_merged_maps(output_dir: &str) {
+    if let Ok(entries) = std::fs::read_dir(output_dir) {
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+
+            if file_name.starts_with("merged-map") || file_name == "print-layout.svg" {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
 }
@@ -0,0 +1,407 @@
+use log::info;
+use shapefile::{
+    dbase::{FieldIOError, FieldName, FieldWriter, TableWriterBuilder, WritableRecord},
+    Point, Writer,
+};
+use std::{
+    fs::{create_dir_all, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Instant,
+};
+
+use crate::{
+    config::Config,
+    constants::{BUFFER, DEM_BLOCK_SIZE, DEM_NODATA_VALUE},
+    error::CassiniError,
+    mosaic::create_raster_with_buffer,
+    raster::{fill_nodata, is_nodata, Raster, FILL_NODATA_MAX_SEARCH_DISTANCE},
+    tile::Tile,
+};
+
+/// Offsets of the 8 neighbors of a pixel
+const NEIGHBOR_OFFSETS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A knoll, a boulder, a depression or a pit found in the DEM
+struct MicroReliefFeature {
+    x: f64,
+    y: f64,
+    /// Height of a knoll or a boulder, depth of a depression or a pit, in meters
+    height: f64,
+    /// Diameter of the footprint, in meters
+    size: f64,
+}
+
+/// Size and height thresholds of the two kinds of features of a micro-relief family (boulders and knolls,
+/// or pits and depressions). The features up to small_max_size are small ones, the others large ones.
+struct FeatureThresholds {
+    small_max_size: f64,
+    small_min_height: f64,
+    large_max_size: f64,
+    large_min_height: f64,
+}
+
+/// Detects the knolls, boulders, depressions and pits of the tile from the local extrema of the DEM,
+/// and writes them to point shapefiles in the micro-relief directory.
+pub fn detect_micro_relief(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    config: &Config,
+) -> Result<(), CassiniError> {
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. Detecting micro-relief",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y
+    );
+
+    let start = Instant::now();
+
+    let mut dem = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "dem",
+        DEM_BLOCK_SIZE as f64,
+        DEM_NODATA_VALUE,
+    )?;
+    fill_nodata(&mut dem, FILL_NODATA_MAX_SEARCH_DISTANCE);
+
+    let micro_relief_config = &config.micro_relief;
+
+    let (boulders, knolls) = detect_features(
+        &dem,
+        tile,
+        1.,
+        &FeatureThresholds {
+            small_max_size: micro_relief_config.boulder_max_size,
+            small_min_height: micro_relief_config.boulder_min_height,
+            large_max_size: micro_relief_config.knoll_max_size,
+            large_min_height: micro_relief_config.knoll_min_height,
+        },
+    );
+
+    let (pits, depressions) = detect_features(
+        &dem,
+        tile,
+        -1.,
+        &FeatureThresholds {
+            small_max_size: micro_relief_config.pit_max_size,
+            small_min_height: micro_relief_config.pit_min_depth,
+            large_max_size: micro_relief_config.depression_max_size,
+            large_min_height: micro_relief_config.depression_min_depth,
+        },
+    );
+
+    let micro_relief_dir = tile.render_dir_path.join("micro-relief");
+    create_dir_all(&micro_relief_dir)?;
+
+    write_features(&micro_relief_dir.join("knolls.shp"), &knolls)?;
+    write_features(&micro_relief_dir.join("boulders.shp"), &boulders)?;
+    write_features(&micro_relief_dir.join("depressions.shp"), &depressions)?;
+    write_features(&micro_relief_dir.join("pits.shp"), &pits)?;
+
+    let duration = start.elapsed();
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. {} knolls, {} boulders, {} depressions and {} pits detected in {:.1?}",
+        tile.min_x,
+        tile.min_y,
+        tile.max_x,
+        tile.max_y,
+        knolls.len(),
+        boulders.len(),
+        depressions.len(),
+        pits.len(),
+        duration
+    );
+
+    Ok(())
+}
+
+/// Finds the local maxima of the DEM multiplied by sign (1 for knolls and boulders, -1 for depressions
+/// and pits) that stand out of their surroundings. Around every local maximum, rings of growing radius
+/// are searched until the highest elevation of a ring is far enough below the summit for the feature
+/// size. The search stops when a higher point is met. Returns the small and the large features.
+fn detect_features(
+    dem: &Raster,
+    tile: &Tile,
+    sign: f64,
+    thresholds: &FeatureThresholds,
+) -> (Vec<MicroReliefFeature>, Vec<MicroReliefFeature>) {
+    let mut small_features: Vec<MicroReliefFeature> = vec![];
+    let mut large_features: Vec<MicroReliefFeature> = vec![];
+
+    let max_radius = (thresholds.large_max_size.max(thresholds.small_max_size) / 2. / dem.cell_size) as i64;
+    let rings = get_rings_offsets(max_radius);
+
+    let get_signed_elevation = |x: i64, y: i64| -> Option<f64> {
+        if x < 0 || y < 0 || x >= dem.width as i64 || y >= dem.height as i64 {
+            return None;
+        }
+
+        let elevation = dem.get(x as usize, y as usize);
+
+        if is_nodata(elevation) {
+            return None;
+        }
+
+        return Some(elevation * sign);
+    };
+
+    for y in 0..dem.height as i64 {
+        for x in 0..dem.width as i64 {
+            let (center_x, center_y) = dem.get_pixel_center(x as usize, y as usize);
+
+            if center_x < tile.min_x as f64
+                || center_x >= tile.max_x as f64
+                || center_y < tile.min_y as f64
+                || center_y >= tile.max_y as f64
+            {
+                continue;
+            }
+
+            let Some(summit) = get_signed_elevation(x, y) else {
+                continue;
+            };
+
+            // Plateaus are only counted once, on their first pixel
+            let is_local_maximum = NEIGHBOR_OFFSETS.iter().all(|(offset_x, offset_y)| {
+                match get_signed_elevation(x + offset_x, y + offset_y) {
+                    Some(elevation) if (*offset_y, *offset_x) < (0, 0) => elevation < summit,
+                    Some(elevation) => elevation <= summit,
+                    None => true,
+                }
+            });
+
+            if !is_local_maximum {
+                continue;
+            }
+
+            for (ring_index, ring) in rings.iter().enumerate() {
+                let mut ring_maximum = f64::MIN;
+
+                for (offset_x, offset_y) in ring {
+                    if let Some(elevation) = get_signed_elevation(x + offset_x, y + offset_y) {
+                        ring_maximum = ring_maximum.max(elevation);
+                    }
+                }
+
+                // Nodata ring, or a higher point nearby
+                if ring_maximum == f64::MIN || ring_maximum > summit {
+                    break;
+                }
+
+                let height = summit - ring_maximum;
+                let size = 2. * (ring_index + 1) as f64 * dem.cell_size;
+
+                let feature = MicroReliefFeature {
+                    x: center_x,
+                    y: center_y,
+                    height,
+                    size,
+                };
+
+                if size <= thresholds.small_max_size && height >= thresholds.small_min_height {
+                    small_features.push(feature);
+                    break;
+                }
+
+                if size <= thresholds.large_max_size && height >= thresholds.large_min_height {
+                    large_features.push(feature);
+                    break;
+                }
+            }
+        }
+    }
+
+    return (small_features, large_features);
+}
+
+/// Returns the offsets of the pixels at a distance from the center between radius - 1 (excluded) and
+/// radius (included), for every radius from 1 to max_radius.
+fn get_rings_offsets(max_radius: i64) -> Vec<Vec<(i64, i64)>> {
+    let mut rings: Vec<Vec<(i64, i64)>> = vec![vec![]; max_radius.max(1) as usize];
+
+    for offset_y in -max_radius..=max_radius {
+        for offset_x in -max_radius..=max_radius {
+            let distance = ((offset_x * offset_x + offset_y * offset_y) as f64).sqrt();
+            let ring_index = distance.ceil() as usize;
+
+            if ring_index >= 1 && ring_index <= rings.len() {
+                rings[ring_index - 1].push((offset_x, offset_y));
+            }
+        }
+    }
+
+    return rings;
+}
+
+/// The attributes of the micro-relief shapefiles
+struct MicroReliefRecord {
+    id: i32,
+    height: f64,
+    size: f64,
+}
+
+impl WritableRecord for MicroReliefRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.height)?;
+        field_writer.write_next_field_value(&self.size)?;
+        Ok(())
+    }
+}
+
+fn write_features(path: &PathBuf, features: &Vec<MicroReliefFeature>) -> Result<(), CassiniError> {
+    let mut writer = create_micro_relief_shapefile_writer(path)?;
+
+    for (id, feature) in features.iter().enumerate() {
+        let record = MicroReliefRecord {
+            id: id as i32,
+            height: feature.height,
+            size: feature.size,
+        };
+
+        writer.write_shape_and_record(
+            &Point {
+                x: feature.x,
+                y: feature.y,
+            },
+            &record,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn create_micro_relief_shapefile_writer(path: &PathBuf) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("height").expect("Invalid field name"), 8, 2)
+        .add_numeric_field(FieldName::try_from("size").expect("Invalid field name"), 8, 2);
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 40;
+
+    /// A flat DEM at 100 meters with a 1.5 meters high one-pixel bump at (8, 8), a 1.5 meters deep
+    /// one-pixel pit at (20, 8), and a 1.5 meters deep depression at (20, 25): a 1 meter deep disc of
+    /// 5 meters across, with a center pixel 0.5 meter deeper.
+    fn get_dem() -> Raster {
+        let mut data = vec![100.; WIDTH * WIDTH];
+        data[8 * WIDTH + 8] = 101.5;
+        data[8 * WIDTH + 20] = 98.5;
+
+        for offset_y in -2_i64..=2 {
+            for offset_x in -2_i64..=2 {
+                if offset_x * offset_x + offset_y * offset_y <= 4 {
+                    data[(25 + offset_y) as usize * WIDTH + (20 + offset_x) as usize] = 99.;
+                }
+            }
+        }
+
+        data[25 * WIDTH + 20] = 98.5;
+
+        return Raster {
+            width: WIDTH,
+            height: WIDTH,
+            left: 0.,
+            top: WIDTH as f64,
+            cell_size: 1.,
+            data,
+        };
+    }
+
+    fn get_tile() -> Tile {
+        return Tile {
+            lidar_dir_path: PathBuf::new(),
+            render_dir_path: PathBuf::new(),
+            min_x: 0,
+            min_y: 0,
+            max_x: WIDTH as i64,
+            max_y: WIDTH as i64,
+            crs: String::new(),
+        };
+    }
+
+    /// Detects the pits and the depressions with the default sizes and the given depths.
+    fn detect_pits_and_depressions(
+        pit_min_depth: f64,
+        depression_min_depth: f64,
+    ) -> (Vec<MicroReliefFeature>, Vec<MicroReliefFeature>) {
+        return detect_features(
+            &get_dem(),
+            &get_tile(),
+            -1.,
+            &FeatureThresholds {
+                small_max_size: 3.,
+                small_min_height: pit_min_depth,
+                large_max_size: 8.,
+                large_min_height: depression_min_depth,
+            },
+        );
+    }
+
+    fn get_positions(features: &Vec<MicroReliefFeature>) -> Vec<(f64, f64)> {
+        features.iter().map(|feature| (feature.x, feature.y)).collect()
+    }
+
+    #[test]
+    fn bump_is_a_boulder() {
+        let (boulders, knolls) = detect_features(
+            &get_dem(),
+            &get_tile(),
+            1.,
+            &FeatureThresholds {
+                small_max_size: 3.,
+                small_min_height: 1.,
+                large_max_size: 8.,
+                large_min_height: 1.,
+            },
+        );
+
+        assert_eq!(get_positions(&boulders), vec![(8.5, 31.5)]);
+        assert!((boulders[0].height - 1.5).abs() < 1e-9);
+        assert!(knolls.is_empty());
+    }
+
+    #[test]
+    fn pit_and_depression_depths_filter_independently() {
+        let pit_position = (20.5, 31.5);
+        let depression_position = (20.5, 14.5);
+
+        let (pits, depressions) = detect_pits_and_depressions(1., 1.);
+        assert_eq!(get_positions(&pits), vec![pit_position]);
+        assert_eq!(get_positions(&depressions), vec![depression_position]);
+        assert!((depressions[0].height - 1.5).abs() < 1e-9);
+        assert_eq!(depressions[0].size, 6.);
+
+        // Too shallow for a pit, the pit is kept as a small depression
+        let (pits, depressions) = detect_pits_and_depressions(2., 1.);
+        assert!(pits.is_empty());
+        assert_eq!(
+            get_positions(&depressions),
+            vec![pit_position, depression_position]
+        );
+
+        let (pits, depressions) = detect_pits_and_depressions(1., 2.);
+        assert_eq!(get_positions(&pits), vec![pit_position]);
+        assert!(depressions.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use log::{debug, error, log_enabled, Level};
+use std::{fs::File, path::PathBuf};
+use tiff::{
+    decoder::{Decoder, DecodingResult},
+    tags::Tag,
+};
+
+use crate::{
+    error::CassiniError,
+    geotiff::{write_f64_geotiff, write_u8_geotiff, GeoTiffGrid},
+    raster::Raster,
+    tile::Tile,
+};
+
+/// A pixel type of the rasters produced by the LiDAR step.
+pub trait RasterValue: Copy {
+    fn from_decoding_result(decoding_result: DecodingResult) -> Option<Vec<Self>>;
+
+    fn is_nodata(self, nodata: Option<f64>) -> bool;
+
+    fn write_geotiff(
+        path: &PathBuf,
+        grid: &GeoTiffGrid,
+        data: &[Self],
+        nodata: Self,
+        crs: &str,
+    ) -> Result<(), CassiniError>;
+}
+
+impl RasterValue for f64 {
+    fn from_decoding_result(decoding_result: DecodingResult) -> Option<Vec<f64>> {
+        match decoding_result {
+            DecodingResult::F64(data) => Some(data),
+            DecodingResult::F32(data) => Some(data.into_iter().map(|value| value as f64).collect()),
+            _ => None,
+        }
+    }
+
+    fn is_nodata(self, nodata: Option<f64>) -> bool {
+        self.is_nan() || nodata.is_some_and(|nodata| self == nodata)
+    }
+
+    fn write_geotiff(
+        path: &PathBuf,
+        grid: &GeoTiffGrid,
+        data: &[f64],
+        nodata: f64,
+        crs: &str,
+    ) -> Result<(), CassiniError> {
+        write_f64_geotiff(path, grid, data, nodata, crs)
+    }
+}
+
+impl RasterValue for u8 {
+    fn from_decoding_result(decoding_result: DecodingResult) -> Option<Vec<u8>> {
+        match decoding_result {
+            DecodingResult::U8(data) => Some(data),
+            _ => None,
+        }
+    }
+
+    fn is_nodata(self, nodata: Option<f64>) -> bool {
+        nodata.is_some_and(|nodata| self as f64 == nodata)
+    }
+
+    fn write_geotiff(
+        path: &PathBuf,
+        grid: &GeoTiffGrid,
+        data: &[u8],
+        _nodata: u8,
+        crs: &str,
+    ) -> Result<(), CassiniError> {
+        write_u8_geotiff(path, grid, data, crs)
+    }
+}
+
+/// Mosaics a raster of the LiDAR step (dem, low-vegetation...) of the tile and of its neighbors,
+/// cropped to the tile extent plus a buffer and resampled to the given cell size (nearest neighbor).
+/// Pixels covered by no source are set to fill_value.
+///
+/// When debug logs are enabled, the result is also written to a {raster_name}-with-buffer.tif file
+/// in the tile render directory.
+pub fn create_raster_with_buffer<T: RasterValue>(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    buffer: i64,
+    raster_name: &str,
+    cell_size: f64,
+    fill_value: T,
+) -> Result<Raster<T>, CassiniError> {
+    let left = (tile.min_x - buffer) as f64;
+    let top = (tile.max_y + buffer) as f64;
+    let width = (((tile.max_x - tile.min_x + 2 * buffer) as f64) / cell_size).ceil() as usize;
+    let height = (((tile.max_y - tile.min_y + 2 * buffer) as f64) / cell_size).ceil() as usize;
+
+    let mut mosaic = Raster {
+        width,
+        height,
+        left,
+        top,
+        cell_size,
+        data: vec![fill_value; width * height],
+    };
+
+    for neighbor_tile in neighbor_tiles {
+        let neighbor_raster_path = neighbor_tile.join(format!("{}.tif", raster_name));
+
+        match read_source_raster::<T>(&neighbor_raster_path) {
+            Ok((neighbor_raster, nodata)) => paste_source_raster(&mut mosaic, &neighbor_raster, nodata),
+            Err(error) => {
+                error!(
+                    "Tile min_x={} min_y={} max_x={} max_y={}. Could not read neighbor raster {:?}: {}",
+                    tile.min_x, tile.min_y, tile.max_x, tile.max_y, neighbor_raster_path, error
+                );
+            }
+        }
+    }
+
+    // The tile raster comes last so that it takes precedence where sources overlap
+    let (tile_raster, nodata) =
+        read_source_raster::<T>(&tile.lidar_dir_path.join(format!("{}.tif", raster_name)))?;
+    paste_source_raster(&mut mosaic, &tile_raster, nodata);
+
+    if log_enabled!(Level::Debug) {
+        let debug_path = tile
+            .render_dir_path
+            .join(format!("{}-with-buffer.tif", raster_name));
+
+        debug!(
+            "Tile min_x={} min_y={} max_x={} max_y={}. Writing {:?}",
+            tile.min_x, tile.min_y, tile.max_x, tile.max_y, debug_path
+        );
+
+        let grid = GeoTiffGrid {
+            left,
+            top,
+            resolution: cell_size,
+            width: width as u32,
+            height: height as u32,
+        };
+
+        T::write_geotiff(&debug_path, &grid, &mosaic.data, fill_value, &tile.crs)?;
+    }
+
+    return Ok(mosaic);
+}
+
+/// Reads a GeoTIFF with its geotransform and its nodata value.
+fn read_source_raster<T: RasterValue>(path: &PathBuf) -> Result<(Raster<T>, Option<f64>), CassiniError> {
+    let tif_file = File::open(path)?;
+    let mut decoder = Decoder::new(tif_file)?;
+    decoder = decoder.with_limits(tiff::decoder::Limits::unlimited());
+
+    let (width, height) = decoder.dimensions()?;
+    let pixel_scale = decoder.get_tag_f64_vec(Tag::ModelPixelScaleTag)?;
+    let tiepoint = decoder.get_tag_f64_vec(Tag::ModelTiepointTag)?;
+
+    if pixel_scale.len() < 2 || tiepoint.len() < 6 || pixel_scale[0] != pixel_scale[1] {
+        return Err(CassiniError::InvalidData(format!(
+            "The {:?} tif image should be north-up with square pixels",
+            path
+        )));
+    }
+
+    let cell_size = pixel_scale[0];
+
+    let nodata = match decoder.find_tag(Tag::GdalNodata)? {
+        Some(value) => value
+            .into_string()?
+            .trim_matches(char::from(0))
+            .trim()
+            .parse::<f64>()
+            .ok(),
+        None => None,
+    };
+
+    let Some(data) = T::from_decoding_result(decoder.read_image()?) else {
+        return Err(CassiniError::InvalidData(format!(
+            "The {:?} tif image has an unexpected pixel type",
+            path
+        )));
+    };
+
+    let raster = Raster {
+        width: width as usize,
+        height: height as usize,
+        left: tiepoint[3] - tiepoint[0] * cell_size,
+        top: tiepoint[4] + tiepoint[1] * cell_size,
+        cell_size,
+        data,
+    };
+
+    return Ok((raster, nodata));
+}
+
+/// Copies the valid pixels of a source raster into the mosaic, sampling the source at the center
+/// of every mosaic pixel.
+fn paste_source_raster<T: RasterValue>(mosaic: &mut Raster<T>, source: &Raster<T>, nodata: Option<f64>) {
+    let source_right = source.left + source.width as f64 * source.cell_size;
+    let source_bottom = source.top - source.height as f64 * source.cell_size;
+
+    let first_x = (((source.left - mosaic.left) / mosaic.cell_size).floor().max(0.)) as usize;
+    let last_x = (((source_right - mosaic.left) / mosaic.cell_size).ceil() as usize).min(mosaic.width);
+    let first_y = (((mosaic.top - source.top) / mosaic.cell_size).floor().max(0.)) as usize;
+    let last_y = (((mosaic.top - source_bottom) / mosaic.cell_size).ceil() as usize).min(mosaic.height);
+
+    for y in first_y..last_y {
+        let center_y = mosaic.top - (y as f64 + 0.5) * mosaic.cell_size;
+        let source_y = ((source.top - center_y) / source.cell_size).floor();
+
+        if source_y < 0. || source_y >= source.height as f64 {
+            continue;
+        }
+
+        for x in first_x..last_x {
+            let center_x = mosaic.left + (x as f64 + 0.5) * mosaic.cell_size;
+            let source_x = ((center_x - source.left) / source.cell_size).floor();
+
+            if source_x < 0. || source_x >= source.width as f64 {
+                continue;
+            }
+
+            let value = source.get(source_x as usize, source_y as usize);
+
+            if !value.is_nodata(nodata) {
+                mosaic.data[y * mosaic.width + x] = value;
+            }
+        }
+    }
+}
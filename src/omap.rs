@@ -0,0 +1,945 @@
+use log::warn;
+use shapefile::{
+    dbase::{FieldValue, Record},
+    read_as,
+    record::{polygon::GenericPolygon, polyline::GenericPolyline},
+    Point, PolygonRing, Polyline,
+};
+use std::{fs::File, io::Write, path::PathBuf};
+
+use crate::{
+    config::{Config, SymbolSet},
+    error::CassiniError,
+    helpers::{escape_xml, get_pit_mark, get_small_depression_mark},
+    projection::Projection,
+    symbol_set::SymbolDimensions,
+    symbol_styles::{SymbolColors, SymbolStyles},
+    tile::Tile,
+    vector_map::get_hex_color,
+};
+
+/// Flags of the last point of a closed ring in the coordinates of an OpenOrienteering Mapper object
+/// (close point and end of part)
+const CLOSED_RING_END_FLAGS: u32 = 18;
+
+/// The color layers of the exported map, from top to bottom. The colors themselves are the ones of the
+/// symbol styles.
+const OMAP_COLOR_LAYERS: [&str; 8] = [
+    "Black",
+    "Blue",
+    "Brown",
+    "Building passage gray",
+    "Building gray",
+    "Brown 50%",
+    "Olive green",
+    "Green",
+];
+
+const BLACK_COLOR_INDEX: usize = 0;
+const BLUE_COLOR_INDEX: usize = 1;
+const BROWN_COLOR_INDEX: usize = 2;
+const BUILDING_PASSAGE_GRAY_COLOR_INDEX: usize = 3;
+const BUILDING_GRAY_COLOR_INDEX: usize = 4;
+const BROWN_50_COLOR_INDEX: usize = 5;
+const OLIVE_GREEN_COLOR_INDEX: usize = 6;
+const GREEN_COLOR_INDEX: usize = 7;
+
+/// The ISOM and ISSprOM symbols of the exported map.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OmapSymbol {
+    Contour,
+    IndexContour,
+    FormLine,
+    SmallKnoll,
+    SmallDepression,
+    Pit,
+    ImpassableCliff,
+    Cliff,
+    Boulder,
+    UncrossableBodyOfWater,
+    UncrossableBodyOfWaterBankLine,
+    CrossableWatercourse,
+    MinorSeasonalWaterChannel,
+    Marsh,
+    ImpassableVegetation,
+    PavedAreaWithBoundingLine,
+    WideRoad,
+    Road,
+    Footpath,
+    Railway,
+    PowerLine,
+    ImpassableWall,
+    Fence,
+    ImpassableFence,
+    AreaThatShallNotBeEntered,
+    Building,
+    BuildingPassage,
+    PavedArea,
+    StepOrEdgeOfPavedArea,
+}
+
+const OMAP_SYMBOLS: [OmapSymbol; 29] = [
+    OmapSymbol::Contour,
+    OmapSymbol::IndexContour,
+    OmapSymbol::FormLine,
+    OmapSymbol::SmallKnoll,
+    OmapSymbol::SmallDepression,
+    OmapSymbol::Pit,
+    OmapSymbol::ImpassableCliff,
+    OmapSymbol::Cliff,
+    OmapSymbol::Boulder,
+    OmapSymbol::UncrossableBodyOfWater,
+    OmapSymbol::UncrossableBodyOfWaterBankLine,
+    OmapSymbol::CrossableWatercourse,
+    OmapSymbol::MinorSeasonalWaterChannel,
+    OmapSymbol::Marsh,
+    OmapSymbol::ImpassableVegetation,
+    OmapSymbol::PavedAreaWithBoundingLine,
+    OmapSymbol::WideRoad,
+    OmapSymbol::Road,
+    OmapSymbol::Footpath,
+    OmapSymbol::Railway,
+    OmapSymbol::PowerLine,
+    OmapSymbol::ImpassableWall,
+    OmapSymbol::Fence,
+    OmapSymbol::ImpassableFence,
+    OmapSymbol::AreaThatShallNotBeEntered,
+    OmapSymbol::Building,
+    OmapSymbol::BuildingPassage,
+    OmapSymbol::PavedArea,
+    OmapSymbol::StepOrEdgeOfPavedArea,
+];
+
+enum OmapSymbolStyle {
+    /// Color layer index, width and optional (dash, gap) lengths in millimeters
+    Line(usize, f32, Option<(f32, f32)>),
+    /// Color layer index, and optional horizontal hatching (line width, spacing) in millimeters
+    Area(usize, Option<(f32, f32)>),
+    /// Color layer index and diameter in millimeters
+    Dot(usize, f32),
+    /// Color layer index, line width and points of a line drawn around the symbol position, in millimeters
+    Mark(usize, f32, Vec<(f32, f32)>),
+}
+
+impl OmapSymbolStyle {
+    fn get_color_layer_index(&self) -> usize {
+        match self {
+            OmapSymbolStyle::Line(color_layer_index, _, _)
+            | OmapSymbolStyle::Area(color_layer_index, _)
+            | OmapSymbolStyle::Dot(color_layer_index, _)
+            | OmapSymbolStyle::Mark(color_layer_index, _, _) => *color_layer_index,
+        }
+    }
+}
+
+impl OmapSymbol {
+    /// Returns the symbol with the given ISOM code, if it is one of the exported symbols.
+    pub fn from_code(code: &str) -> Option<OmapSymbol> {
+        OMAP_SYMBOLS
+            .iter()
+            .find(|symbol| symbol.get_code() == code)
+            .copied()
+    }
+
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            OmapSymbol::Contour => "101",
+            OmapSymbol::IndexContour => "102",
+            OmapSymbol::FormLine => "103",
+            OmapSymbol::SmallKnoll => "109",
+            OmapSymbol::SmallDepression => "111",
+            OmapSymbol::Pit => "112",
+            OmapSymbol::ImpassableCliff => "201",
+            OmapSymbol::Cliff => "202",
+            OmapSymbol::Boulder => "204",
+            OmapSymbol::UncrossableBodyOfWater => "301.1",
+            OmapSymbol::UncrossableBodyOfWaterBankLine => "301.4",
+            OmapSymbol::CrossableWatercourse => "304",
+            OmapSymbol::MinorSeasonalWaterChannel => "306",
+            OmapSymbol::Marsh => "308",
+            OmapSymbol::ImpassableVegetation => "411",
+            OmapSymbol::PavedAreaWithBoundingLine => "501.1",
+            OmapSymbol::WideRoad => "502",
+            OmapSymbol::Road => "503",
+            OmapSymbol::Footpath => "505",
+            OmapSymbol::Railway => "509",
+            OmapSymbol::PowerLine => "510",
+            OmapSymbol::ImpassableWall => "515",
+            OmapSymbol::Fence => "516",
+            OmapSymbol::ImpassableFence => "518",
+            OmapSymbol::AreaThatShallNotBeEntered => "520",
+            OmapSymbol::Building => "521",
+            OmapSymbol::BuildingPassage => "521.1",
+            OmapSymbol::PavedArea => "529",
+            OmapSymbol::StepOrEdgeOfPavedArea => "529.1",
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            OmapSymbol::Contour => "Contour",
+            OmapSymbol::IndexContour => "Index contour",
+            OmapSymbol::FormLine => "Form line",
+            OmapSymbol::SmallKnoll => "Small knoll",
+            OmapSymbol::SmallDepression => "Small depression",
+            OmapSymbol::Pit => "Pit",
+            OmapSymbol::ImpassableCliff => "Impassable cliff",
+            OmapSymbol::Cliff => "Cliff",
+            OmapSymbol::Boulder => "Boulder",
+            OmapSymbol::UncrossableBodyOfWater => "Uncrossable body of water",
+            OmapSymbol::UncrossableBodyOfWaterBankLine => "Uncrossable body of water, bank line",
+            OmapSymbol::CrossableWatercourse => "Crossable watercourse",
+            OmapSymbol::MinorSeasonalWaterChannel => "Minor/seasonal water channel",
+            OmapSymbol::Marsh => "Marsh",
+            OmapSymbol::ImpassableVegetation => "Vegetation, impassable",
+            OmapSymbol::PavedAreaWithBoundingLine => "Paved area with bounding line",
+            OmapSymbol::WideRoad => "Wide road",
+            OmapSymbol::Road => "Road",
+            OmapSymbol::Footpath => "Footpath",
+            OmapSymbol::Railway => "Railway",
+            OmapSymbol::PowerLine => "Power line, cableway or skilift",
+            OmapSymbol::ImpassableWall => "Impassable wall",
+            OmapSymbol::Fence => "Fence",
+            OmapSymbol::ImpassableFence => "Impassable fence",
+            OmapSymbol::AreaThatShallNotBeEntered => "Area that shall not be entered",
+            OmapSymbol::Building => "Building",
+            OmapSymbol::BuildingPassage => "Building passage",
+            OmapSymbol::PavedArea => "Paved area",
+            OmapSymbol::StepOrEdgeOfPavedArea => "Step or edge of paved area",
+        }
+    }
+
+    pub fn get_color(&self, colors: &SymbolColors) -> (u8, u8, u8) {
+        match self {
+            OmapSymbol::Contour => colors.contour,
+            OmapSymbol::IndexContour => colors.index_contour,
+            OmapSymbol::FormLine => colors.form_line,
+            OmapSymbol::SmallKnoll => colors.small_knoll,
+            OmapSymbol::SmallDepression => colors.small_depression,
+            OmapSymbol::Pit => colors.pit,
+            OmapSymbol::ImpassableCliff => colors.impassable_cliff,
+            OmapSymbol::Cliff => colors.cliff,
+            OmapSymbol::Boulder => colors.boulder,
+            OmapSymbol::UncrossableBodyOfWater => colors.uncrossable_body_of_water,
+            OmapSymbol::UncrossableBodyOfWaterBankLine => colors.uncrossable_body_of_water_bank_line,
+            OmapSymbol::CrossableWatercourse => colors.crossable_watercourse,
+            OmapSymbol::MinorSeasonalWaterChannel => colors.minor_seasonal_water_channel,
+            OmapSymbol::Marsh => colors.marsh,
+            OmapSymbol::ImpassableVegetation => colors.impassable_vegetation,
+            OmapSymbol::PavedAreaWithBoundingLine => colors.paved_area_with_bounding_line,
+            OmapSymbol::WideRoad => colors.wide_road,
+            OmapSymbol::Road => colors.road,
+            OmapSymbol::Footpath => colors.footpath,
+            OmapSymbol::Railway => colors.railway,
+            OmapSymbol::PowerLine => colors.power_line,
+            OmapSymbol::ImpassableWall => colors.impassable_wall,
+            OmapSymbol::Fence => colors.fence,
+            OmapSymbol::ImpassableFence => colors.impassable_fence,
+            OmapSymbol::AreaThatShallNotBeEntered => colors.area_that_shall_not_be_entered,
+            OmapSymbol::Building => colors.building,
+            OmapSymbol::BuildingPassage => colors.building_passage,
+            OmapSymbol::PavedArea => colors.paved_area,
+            OmapSymbol::StepOrEdgeOfPavedArea => colors.step_or_edge_of_paved_area,
+        }
+    }
+
+    /// A simplified style, close to the raster map. The mappers are expected to replace the symbol set
+    /// by the official one, the symbols being matched by code.
+    fn get_style(&self, dimensions: &SymbolDimensions) -> OmapSymbolStyle {
+        match self {
+            OmapSymbol::Contour => OmapSymbolStyle::Line(BROWN_COLOR_INDEX, dimensions.contour_width, None),
+            OmapSymbol::IndexContour => {
+                OmapSymbolStyle::Line(BROWN_COLOR_INDEX, dimensions.index_contour_width, None)
+            }
+            OmapSymbol::FormLine => OmapSymbolStyle::Line(
+                BROWN_COLOR_INDEX,
+                dimensions.form_line_width,
+                Some((
+                    dimensions.form_line_dash_length,
+                    dimensions.form_line_dash_interval_length,
+                )),
+            ),
+            OmapSymbol::SmallKnoll => {
+                OmapSymbolStyle::Dot(BROWN_COLOR_INDEX, dimensions.small_knoll_diameter)
+            }
+            OmapSymbol::SmallDepression => OmapSymbolStyle::Mark(
+                BROWN_COLOR_INDEX,
+                dimensions.small_depression_line_width,
+                get_small_depression_mark(dimensions),
+            ),
+            OmapSymbol::Pit => OmapSymbolStyle::Mark(
+                BROWN_COLOR_INDEX,
+                dimensions.pit_line_width,
+                get_pit_mark(dimensions),
+            ),
+            OmapSymbol::ImpassableCliff => {
+                OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.impassable_cliff_width, None)
+            }
+            OmapSymbol::Cliff => OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.cliff_width, None),
+            OmapSymbol::Boulder => OmapSymbolStyle::Dot(BLACK_COLOR_INDEX, dimensions.boulder_diameter),
+            OmapSymbol::UncrossableBodyOfWater => OmapSymbolStyle::Area(BLUE_COLOR_INDEX, None),
+            OmapSymbol::UncrossableBodyOfWaterBankLine => OmapSymbolStyle::Line(
+                BLACK_COLOR_INDEX,
+                dimensions.uncrossable_body_of_water_outline_width,
+                None,
+            ),
+            OmapSymbol::CrossableWatercourse => {
+                OmapSymbolStyle::Line(BLUE_COLOR_INDEX, dimensions.crossable_watercourse_width, None)
+            }
+            OmapSymbol::MinorSeasonalWaterChannel => OmapSymbolStyle::Line(
+                BLUE_COLOR_INDEX,
+                dimensions.minor_watercourse_width,
+                Some((
+                    dimensions.minor_watercourse_dash_length,
+                    dimensions.minor_watercourse_dash_interval_length,
+                )),
+            ),
+            OmapSymbol::Marsh => OmapSymbolStyle::Area(
+                BLUE_COLOR_INDEX,
+                Some((dimensions.marsh_line_width, dimensions.marsh_line_spacing)),
+            ),
+            OmapSymbol::ImpassableVegetation => OmapSymbolStyle::Area(GREEN_COLOR_INDEX, None),
+            OmapSymbol::PavedAreaWithBoundingLine => OmapSymbolStyle::Area(BROWN_50_COLOR_INDEX, None),
+            OmapSymbol::WideRoad => {
+                OmapSymbolStyle::Line(BROWN_50_COLOR_INDEX, dimensions.wide_road_outer_width, None)
+            }
+            OmapSymbol::Road => OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.road_width, None),
+            OmapSymbol::Footpath => OmapSymbolStyle::Line(
+                BLACK_COLOR_INDEX,
+                dimensions.footpath_width,
+                Some((
+                    dimensions.footpath_dash_length,
+                    dimensions.footpath_dash_interval_length,
+                )),
+            ),
+            OmapSymbol::Railway => {
+                OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.railway_outer_width, None)
+            }
+            OmapSymbol::PowerLine => {
+                OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.power_line_width, None)
+            }
+            OmapSymbol::ImpassableWall => {
+                OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.impassable_wall_width, None)
+            }
+            OmapSymbol::Fence => OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.fence_width, None),
+            OmapSymbol::ImpassableFence => {
+                OmapSymbolStyle::Line(BLACK_COLOR_INDEX, dimensions.impassable_fence_width, None)
+            }
+            OmapSymbol::AreaThatShallNotBeEntered => OmapSymbolStyle::Area(OLIVE_GREEN_COLOR_INDEX, None),
+            OmapSymbol::Building => OmapSymbolStyle::Area(BUILDING_GRAY_COLOR_INDEX, None),
+            OmapSymbol::BuildingPassage => OmapSymbolStyle::Area(BUILDING_PASSAGE_GRAY_COLOR_INDEX, None),
+            OmapSymbol::PavedArea => OmapSymbolStyle::Area(BROWN_50_COLOR_INDEX, None),
+            OmapSymbol::StepOrEdgeOfPavedArea => OmapSymbolStyle::Line(
+                BLACK_COLOR_INDEX,
+                dimensions.step_or_edge_of_paved_area_width,
+                None,
+            ),
+        }
+    }
+
+    /// Draws a sample of the symbol in the box of a legend entry, as SVG elements in millimeters of paper.
+    pub fn get_legend_svg(
+        &self,
+        (x, y, width, height): (f32, f32, f32, f32),
+        styles: &SymbolStyles,
+    ) -> String {
+        let center_y = y + height / 2.;
+        let color = get_hex_color(&self.get_color(&styles.colors));
+
+        match self.get_style(&styles.dimensions) {
+            OmapSymbolStyle::Line(_, line_width, dash) => {
+                let dash_attribute = match dash {
+                    Some((dash_length, break_length)) => {
+                        format!(" stroke-dasharray=\"{:.2} {:.2}\"", dash_length, break_length)
+                    }
+                    None => String::new(),
+                };
+
+                format!(
+                    "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+                    stroke-width=\"{:.2}\"{}/>\n",
+                    x,
+                    center_y,
+                    x + width,
+                    center_y,
+                    color,
+                    line_width,
+                    dash_attribute
+                )
+            }
+            OmapSymbolStyle::Area(_, None) => format!(
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>\n",
+                x, y, width, height, color
+            ),
+            OmapSymbolStyle::Area(_, Some((line_width, line_spacing))) => {
+                let mut svg = String::new();
+                let mut line_y = y + line_spacing / 2.;
+
+                while line_y < y + height {
+                    svg.push_str(&format!(
+                        "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+                        stroke-width=\"{:.2}\"/>\n",
+                        x,
+                        line_y,
+                        x + width,
+                        line_y,
+                        color,
+                        line_width
+                    ));
+
+                    line_y += line_spacing;
+                }
+
+                svg
+            }
+            OmapSymbolStyle::Dot(_, diameter) => format!(
+                "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"/>\n",
+                x + width / 2.,
+                center_y,
+                diameter / 2.,
+                color
+            ),
+            OmapSymbolStyle::Mark(_, line_width, points) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(point_x, point_y)| {
+                        format!("{:.2},{:.2}", x + width / 2. + point_x, center_y + point_y)
+                    })
+                    .collect();
+
+                format!(
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\"/>\n",
+                    points.join(" "),
+                    color,
+                    line_width
+                )
+            }
+        }
+    }
+}
+
+enum OmapGeometry {
+    Point((f64, f64)),
+    Line(Vec<(f64, f64)>),
+    /// The outer ring first, then the holes
+    Area(Vec<Vec<(f64, f64)>>),
+}
+
+struct OmapObject {
+    symbol: OmapSymbol,
+    geometry: OmapGeometry,
+}
+
+/// An OpenOrienteering Mapper map of the generated features, in ground coordinates.
+pub struct OmapMap {
+    scale: u32,
+    symbol_set: SymbolSet,
+    dimensions: SymbolDimensions,
+    colors: SymbolColors,
+    min_x: i64,
+    min_y: i64,
+    max_x: i64,
+    max_y: i64,
+    crs: String,
+    objects: Vec<OmapObject>,
+}
+
+impl OmapMap {
+    /// Loads the contours rendered by the previous stages of the pipeline.
+    pub fn new(tile: &Tile, config: &Config, symbol_styles: &SymbolStyles) -> Result<OmapMap, CassiniError> {
+        let mut omap_map = OmapMap {
+            scale: config.get_map_scale(),
+            symbol_set: config.symbol_set,
+            dimensions: symbol_styles.dimensions,
+            colors: symbol_styles.colors,
+            min_x: tile.min_x,
+            min_y: tile.min_y,
+            max_x: tile.max_x,
+            max_y: tile.max_y,
+            crs: tile.crs.clone(),
+            objects: vec![],
+        };
+
+        omap_map.read_contours(
+            &tile
+                .render_dir_path
+                .join("contours")
+                .join("rendered-contours.shp"),
+        )?;
+
+        return Ok(omap_map);
+    }
+
+    pub fn add_point(&mut self, symbol: OmapSymbol, point: &Point) {
+        self.objects.push(OmapObject {
+            symbol,
+            geometry: OmapGeometry::Point((point.x, point.y)),
+        });
+    }
+
+    pub fn add_line(&mut self, symbol: OmapSymbol, line: &GenericPolyline<Point>) {
+        for part in line.parts() {
+            self.objects.push(OmapObject {
+                symbol,
+                geometry: OmapGeometry::Line(part.iter().map(|point| (point.x, point.y)).collect()),
+            });
+        }
+    }
+
+    pub fn add_area(&mut self, symbol: OmapSymbol, polygon: &GenericPolygon<Point>) {
+        let mut rings: Vec<Vec<(f64, f64)>> = vec![];
+
+        for ring in polygon.rings() {
+            let points: Vec<(f64, f64)> = ring.points().iter().map(|point| (point.x, point.y)).collect();
+
+            match ring {
+                PolygonRing::Outer(_) => {
+                    if !rings.is_empty() {
+                        self.objects.push(OmapObject {
+                            symbol,
+                            geometry: OmapGeometry::Area(rings),
+                        });
+                    }
+
+                    rings = vec![points];
+                }
+                PolygonRing::Inner(_) => rings.push(points),
+            }
+        }
+
+        if !rings.is_empty() {
+            self.objects.push(OmapObject {
+                symbol,
+                geometry: OmapGeometry::Area(rings),
+            });
+        }
+    }
+
+    /// Adds every ring of a polygon as a line.
+    pub fn add_polygon_outline(&mut self, symbol: OmapSymbol, polygon: &GenericPolygon<Point>) {
+        for ring in polygon.rings() {
+            self.objects.push(OmapObject {
+                symbol,
+                geometry: OmapGeometry::Line(ring.points().iter().map(|point| (point.x, point.y)).collect()),
+            });
+        }
+    }
+
+    pub fn save_as(&self, path: &PathBuf) -> Result<(), CassiniError> {
+        let mut file = File::create(path)?;
+        file.write_all(self.get_xml_content()?.as_bytes())?;
+
+        Ok(())
+    }
+
+    fn read_contours(&mut self, path: &PathBuf) -> Result<(), CassiniError> {
+        for (line, record) in read_as::<_, Polyline, Record>(path)? {
+            let symbol = match record.get("kind") {
+                Some(FieldValue::Character(Some(kind))) if kind == "index" => OmapSymbol::IndexContour,
+                Some(FieldValue::Character(Some(kind))) if kind == "formline" => OmapSymbol::FormLine,
+                _ => OmapSymbol::Contour,
+            };
+
+            self.add_line(symbol, &line);
+        }
+
+        Ok(())
+    }
+
+    /// The reference point of the georeferencing, at the center of the tile
+    fn get_reference_point(&self) -> (f64, f64) {
+        (
+            (self.min_x + self.max_x) as f64 / 2.,
+            (self.min_y + self.max_y) as f64 / 2.,
+        )
+    }
+
+    /// Converts ground coordinates to map coordinates, in micrometers of paper with the y axis going down.
+    fn to_map_coordinates(&self, (x, y): (f64, f64)) -> (i64, i64) {
+        let (reference_x, reference_y) = self.get_reference_point();
+        let micrometers_per_meter = 1_000_000. / self.scale as f64;
+
+        (
+            ((x - reference_x) * micrometers_per_meter).round() as i64,
+            ((reference_y - y) * micrometers_per_meter).round() as i64,
+        )
+    }
+
+    fn get_xml_content(&self) -> Result<String, CassiniError> {
+        let mut xml = String::new();
+        xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<map xmlns=\"http://openorienteering.org/apps/mapper/xml/v2\" version=\"9\">\n");
+        xml.push_str("<notes>Generated by Cassini</notes>\n");
+        xml.push_str(&self.get_georeferencing_xml());
+
+        let (colors, symbol_color_indices) = self.get_colors();
+        xml.push_str(&format!("<colors count=\"{}\">\n", colors.len()));
+
+        for (priority, (name, color)) in colors.iter().enumerate() {
+            let (c, m, y, k) = get_cmyk_color(color);
+
+            xml.push_str(&format!(
+                "<color priority=\"{}\" name=\"{}\" c=\"{:.3}\" m=\"{:.3}\" y=\"{:.3}\" k=\"{:.3}\" opacity=\"1\">\
+                <cmyk method=\"custom\"/><rgb method=\"cmyk\" r=\"{:.3}\" g=\"{:.3}\" b=\"{:.3}\"/></color>\n",
+                priority,
+                escape_xml(name),
+                c,
+                m,
+                y,
+                k,
+                color.0 as f32 / 255.,
+                color.1 as f32 / 255.,
+                color.2 as f32 / 255.
+            ));
+        }
+
+        xml.push_str("</colors>\n");
+        xml.push_str("<barrier version=\"6\" required=\"0.6.0\">\n");
+        let symbol_set_id = match self.symbol_set {
+            SymbolSet::Isom2017_2 => "ISOM 2017-2",
+            SymbolSet::Issprom2019 => "ISSprOM 2019",
+        };
+
+        xml.push_str(&format!(
+            "<symbols count=\"{}\" id=\"{}\">\n",
+            OMAP_SYMBOLS.len(),
+            symbol_set_id
+        ));
+
+        for (id, symbol) in OMAP_SYMBOLS.iter().enumerate() {
+            xml.push_str(&get_symbol_xml(
+                id,
+                symbol,
+                &self.dimensions,
+                symbol_color_indices[id],
+            ));
+        }
+
+        xml.push_str("</symbols>\n");
+        xml.push_str("<parts count=\"1\" current=\"0\">\n<part name=\"default part\">\n");
+        xml.push_str(&format!("<objects count=\"{}\">\n", self.objects.len()));
+
+        for object in &self.objects {
+            xml.push_str(&self.get_object_xml(object)?);
+        }
+
+        xml.push_str("</objects>\n</part>\n</parts>\n");
+        xml.push_str(
+            "<templates count=\"0\" first_front_template=\"0\">\
+            <defaults use_meters_per_pixel=\"true\" meters_per_pixel=\"0\" dpi=\"0\" scale_factor=\"0\"/>\
+            </templates>\n",
+        );
+        xml.push_str("</barrier>\n</map>\n");
+
+        return Ok(xml);
+    }
+
+    /// Returns the colors of the map from top to bottom, with their names, and the index of the color of
+    /// every symbol of OMAP_SYMBOLS. The colors are the ones of the symbol styles, ordered by color layer.
+    /// The symbols of a layer given different colors by the style file get one color each.
+    fn get_colors(&self) -> (Vec<(String, (u8, u8, u8))>, Vec<usize>) {
+        let mut colors: Vec<(String, (u8, u8, u8))> = vec![];
+        let mut symbol_color_indices = vec![0; OMAP_SYMBOLS.len()];
+
+        for (layer_index, layer_name) in OMAP_COLOR_LAYERS.iter().enumerate() {
+            let mut is_layer_named = false;
+
+            for (symbol_index, symbol) in OMAP_SYMBOLS.iter().enumerate() {
+                if symbol.get_style(&self.dimensions).get_color_layer_index() != layer_index {
+                    continue;
+                }
+
+                let color = symbol.get_color(&self.colors);
+
+                symbol_color_indices[symbol_index] =
+                    match colors.iter().position(|(_, other_color)| *other_color == color) {
+                        Some(color_index) => color_index,
+                        None => {
+                            let name = if is_layer_named {
+                                format!("{} {}", layer_name, symbol.get_code())
+                            } else {
+                                layer_name.to_string()
+                            };
+
+                            is_layer_named = true;
+                            colors.push((name, color));
+                            colors.len() - 1
+                        }
+                    };
+            }
+        }
+
+        return (colors, symbol_color_indices);
+    }
+
+    fn get_georeferencing_xml(&self) -> String {
+        let (reference_x, reference_y) = self.get_reference_point();
+        let upper_case_crs = self.crs.to_uppercase();
+
+        let projected_crs = if let Some(code) = upper_case_crs.strip_prefix("EPSG:") {
+            format!(
+                "<projected_crs id=\"EPSG\"><spec language=\"PROJ.4\">EPSG:{}</spec><parameter>{}</parameter>",
+                code, code
+            )
+        } else if self.crs.starts_with('+') {
+            format!(
+                "<projected_crs id=\"PROJ.4\"><spec language=\"PROJ.4\">{}</spec>",
+                escape_xml(&self.crs)
+            )
+        } else {
+            warn!(
+                "Tile min_x={} min_y={} max_x={} max_y={}. CRS {} is not an EPSG code nor a PROJ string, \
+                the OpenOrienteering Mapper file only has a local georeferencing",
+                self.min_x, self.min_y, self.max_x, self.max_y, self.crs
+            );
+
+            "<projected_crs id=\"Local\">".to_string()
+        };
+
+        // OpenOrienteering Mapper defines the grivation as the declination minus the grid convergence.
+        // The map is drawn with the grid north up, so the grivation is zero and the declination is the
+        // convergence.
+        let convergence = match Projection::from_crs(&self.crs) {
+            Some(projection) => projection.get_grid_convergence(reference_x, reference_y),
+            None => 0.,
+        };
+
+        return format!(
+            "<georeferencing scale=\"{}\" grid_scale_factor=\"1\" auxiliary_scale_factor=\"1\" \
+            declination=\"{:.3}\" grivation=\"0\">\n<ref_point x=\"0\" y=\"0\"/>\n\
+            {}<ref_point x=\"{:.2}\" y=\"{:.2}\"/></projected_crs>\n</georeferencing>\n",
+            self.scale, convergence, projected_crs, reference_x, reference_y
+        );
+    }
+
+    fn get_object_xml(&self, object: &OmapObject) -> Result<String, CassiniError> {
+        let symbol_id = OMAP_SYMBOLS
+            .iter()
+            .position(|symbol| *symbol == object.symbol)
+            .ok_or_else(|| {
+                CassiniError::InvalidData(format!(
+                    "Symbol {:?} is missing from the OpenOrienteering Mapper symbol list",
+                    object.symbol
+                ))
+            })?;
+
+        let object_xml = match &object.geometry {
+            OmapGeometry::Point(point) => {
+                let (x, y) = self.to_map_coordinates(*point);
+
+                format!(
+                    "<object type=\"0\" symbol=\"{}\" rotation=\"0\">\
+                    <coords count=\"1\">{} {};</coords></object>\n",
+                    symbol_id, x, y
+                )
+            }
+            OmapGeometry::Line(points) => {
+                let (count, coordinates) = self.get_ring_coordinates(points);
+
+                format!(
+                    "<object type=\"1\" symbol=\"{}\"><coords count=\"{}\">{}</coords></object>\n",
+                    symbol_id, count, coordinates
+                )
+            }
+            OmapGeometry::Area(rings) => {
+                let mut total_count = 0;
+                let mut all_coordinates = String::new();
+
+                for ring in rings {
+                    let (count, coordinates) = self.get_ring_coordinates(ring);
+                    total_count += count;
+                    all_coordinates.push_str(&coordinates);
+                }
+
+                format!(
+                    "<object type=\"1\" symbol=\"{}\"><coords count=\"{}\">{}</coords></object>\n",
+                    symbol_id, total_count, all_coordinates
+                )
+            }
+        };
+
+        return Ok(object_xml);
+    }
+
+    /// Writes the coordinates of a line or of a ring. Closed rings get the closing flags on their last point.
+    fn get_ring_coordinates(&self, points: &Vec<(f64, f64)>) -> (usize, String) {
+        let is_closed = points.len() > 2 && points.first() == points.last();
+        let mut coordinates = String::new();
+
+        for (index, point) in points.iter().enumerate() {
+            let (x, y) = self.to_map_coordinates(*point);
+
+            if is_closed && index == points.len() - 1 {
+                coordinates.push_str(&format!("{} {} {};", x, y, CLOSED_RING_END_FLAGS));
+            } else {
+                coordinates.push_str(&format!("{} {};", x, y));
+            }
+        }
+
+        return (points.len(), coordinates);
+    }
+}
+
+fn get_symbol_xml(
+    id: usize,
+    symbol: &OmapSymbol,
+    dimensions: &SymbolDimensions,
+    color_index: usize,
+) -> String {
+    let (symbol_type, definition) = match symbol.get_style(dimensions) {
+        OmapSymbolStyle::Line(_, width, dash) => (2, get_line_symbol_xml(color_index, width, dash)),
+        OmapSymbolStyle::Area(_, None) => (
+            4,
+            format!(
+                "<area_symbol inner_color=\"{}\" min_area=\"0\" patterns=\"0\"/>",
+                color_index
+            ),
+        ),
+        OmapSymbolStyle::Area(_, Some((line_width, line_spacing))) => (
+            4,
+            format!(
+                "<area_symbol inner_color=\"-1\" min_area=\"0\" patterns=\"1\">\
+                <pattern type=\"1\" angle=\"0\" rotatable=\"true\" line_spacing=\"{}\" line_offset=\"0\" \
+                offset_along_line=\"0\" color=\"{}\" line_width=\"{}\"/></area_symbol>",
+                get_micrometers(line_spacing),
+                color_index,
+                get_micrometers(line_width)
+            ),
+        ),
+        OmapSymbolStyle::Dot(_, diameter) => (
+            1,
+            format!(
+                "<point_symbol rotatable=\"false\" inner_radius=\"{}\" inner_color=\"{}\" outer_width=\"0\" \
+                outer_color=\"-1\" elements=\"0\"/>",
+                get_micrometers(diameter / 2.),
+                color_index
+            ),
+        ),
+        OmapSymbolStyle::Mark(_, width, points) => {
+            let coordinates: String = points
+                .iter()
+                .map(|(x, y)| format!("{} {};", get_micrometers(*x), get_micrometers(*y)))
+                .collect();
+
+            (
+                1,
+                format!(
+                    "<point_symbol rotatable=\"false\" inner_radius=\"0\" inner_color=\"-1\" \
+                    outer_width=\"0\" outer_color=\"-1\" elements=\"1\">\
+                    <element><symbol type=\"2\" code=\"\">{}</symbol>\
+                    <object type=\"1\"><coords count=\"{}\">{}</coords></object></element></point_symbol>",
+                    get_line_symbol_xml(color_index, width, None),
+                    points.len(),
+                    coordinates
+                ),
+            )
+        }
+    };
+
+    return format!(
+        "<symbol type=\"{}\" id=\"{}\" code=\"{}\" name=\"{}\">{}</symbol>\n",
+        symbol_type,
+        id,
+        symbol.get_code(),
+        escape_xml(symbol.get_name()),
+        definition
+    );
+}
+
+fn get_line_symbol_xml(color_index: usize, width: f32, dash: Option<(f32, f32)>) -> String {
+    let (dashed, dash_length, break_length) = match dash {
+        Some((dash_length, break_length)) => (true, dash_length, break_length),
+        None => (false, 0., 0.),
+    };
+
+    format!(
+        "<line_symbol color=\"{}\" line_width=\"{}\" minimum_length=\"0\" join_style=\"2\" \
+        cap_style=\"0\" dashed=\"{}\" segment_length=\"4000\" end_length=\"0\" \
+        dash_length=\"{}\" break_length=\"{}\" dashes_in_group=\"1\" in_group_break_length=\"0\"/>",
+        color_index,
+        get_micrometers(width),
+        dashed,
+        get_micrometers(dash_length),
+        get_micrometers(break_length)
+    )
+}
+
+fn get_micrometers(millimeters: f32) -> i64 {
+    (millimeters * 1000.).round() as i64
+}
+
+fn get_cmyk_color((red, green, blue): &(u8, u8, u8)) -> (f32, f32, f32, f32) {
+    let red = *red as f32 / 255.;
+    let green = *green as f32 / 255.;
+    let blue = *blue as f32 / 255.;
+    let black = 1. - red.max(green).max(blue);
+
+    if black >= 1. {
+        return (0., 0., 0., 1.);
+    }
+
+    return (
+        (1. - red - black) / (1. - black),
+        (1. - green - black) / (1. - black),
+        (1. - blue - black) / (1. - black),
+        black,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{constants::VECTOR_BLACK, symbol_styles::get_symbol_styles};
+
+    fn get_omap_map(symbol_styles: &SymbolStyles) -> OmapMap {
+        OmapMap {
+            scale: 15000,
+            symbol_set: SymbolSet::Isom2017_2,
+            dimensions: symbol_styles.dimensions,
+            colors: symbol_styles.colors,
+            min_x: 0,
+            min_y: 0,
+            max_x: 1000,
+            max_y: 1000,
+            crs: "EPSG:2154".to_owned(),
+            objects: vec![],
+        }
+    }
+
+    fn get_symbol_index(symbol: OmapSymbol) -> usize {
+        OMAP_SYMBOLS
+            .iter()
+            .position(|other_symbol| *other_symbol == symbol)
+            .unwrap()
+    }
+
+    #[test]
+    fn default_colors_are_one_per_layer() {
+        let symbol_styles = get_symbol_styles(&None, SymbolSet::Isom2017_2, 15000).unwrap();
+        let (colors, symbol_color_indices) = get_omap_map(&symbol_styles).get_colors();
+
+        let names: Vec<&str> = colors.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, OMAP_COLOR_LAYERS);
+        assert_eq!(colors[BLACK_COLOR_INDEX].1, VECTOR_BLACK);
+
+        for (symbol_index, symbol) in OMAP_SYMBOLS.iter().enumerate() {
+            let color_layer_index = symbol
+                .get_style(&symbol_styles.dimensions)
+                .get_color_layer_index();
+            assert_eq!(symbol_color_indices[symbol_index], color_layer_index);
+        }
+    }
+
+    #[test]
+    fn restyled_symbol_gets_its_own_color() {
+        let mut symbol_styles = get_symbol_styles(&None, SymbolSet::Isom2017_2, 15000).unwrap();
+        symbol_styles.colors.road = (255, 0, 0);
+        let (colors, symbol_color_indices) = get_omap_map(&symbol_styles).get_colors();
+
+        assert_eq!(colors.len(), OMAP_COLOR_LAYERS.len() + 1);
+        assert_eq!(colors[1], ("Black 503".to_owned(), (255, 0, 0)));
+        assert_eq!(symbol_color_indices[get_symbol_index(OmapSymbol::Road)], 1);
+        assert_eq!(symbol_color_indices[get_symbol_index(OmapSymbol::Cliff)], 0);
+        assert_eq!(symbol_color_indices[get_symbol_index(OmapSymbol::Contour)], 3);
+    }
+}
@@ -0,0 +1,498 @@
+use log::{info, warn};
+use shapefile::{
+    dbase::{FieldValue, Record},
+    read_as, Polyline,
+};
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+    time::Instant,
+};
+
+use crate::{
+    config::{Config, SymbolSet},
+    error::CassiniError,
+    helpers::escape_xml,
+    omap::OmapSymbol,
+    projection::Projection,
+    symbol_styles::SymbolStyles,
+    vector_map::get_hex_color,
+};
+
+/// Distance on the ground between two magnetic north lines, in meters (30 mm at 1:15000, 45 mm at 1:10000)
+const MAGNETIC_NORTH_LINES_SPACING: f32 = 450.;
+/// Distance on the ground between two magnetic north lines of sprint maps, in meters (30 mm at 1:4000)
+const SPRINT_MAGNETIC_NORTH_LINES_SPACING: f32 = 120.;
+/// Width of the magnetic north lines, in millimeters of paper
+const MAGNETIC_NORTH_LINE_WIDTH: f32 = 0.14;
+/// Possible lengths of the scale bar on the ground, in meters, the longest one fitting in
+/// SCALE_BAR_MAX_WIDTH millimeters being drawn
+const SCALE_BAR_LENGTHS: [f32; 3] = [500., 200., 100.];
+const SCALE_BAR_MAX_WIDTH: f32 = 50.;
+const SCALE_BAR_NUMBER_OF_SEGMENTS: usize = 5;
+/// Width of the side panel and of the gap between the map face and the panel, in millimeters
+const PANEL_WIDTH: f32 = 60.;
+const PANEL_GAP: f32 = 5.;
+/// Size of the symbol samples of the legend and height of a legend entry, in millimeters
+const LEGEND_SAMPLE_WIDTH: f32 = 8.;
+const LEGEND_SAMPLE_HEIGHT: f32 = 4.;
+const LEGEND_ENTRY_HEIGHT: f32 = 5.5;
+const OSM_ATTRIBUTION: &str = "Map data © OpenStreetMap contributors";
+
+/// Writes the ISOM codes of the symbols drawn by a stage, for the legend of the print layout.
+pub fn write_symbol_codes(path: &PathBuf, codes: &Vec<&str>) -> Result<(), CassiniError> {
+    let mut file = File::create(path)?;
+    file.write_all(serde_json::to_string(codes)?.as_bytes())?;
+
+    Ok(())
+}
+
+/// Writes a print-layout.svg file next to a map image: the map face crossed by the magnetic north lines,
+/// and a side panel with the title block, the scale bar, the legend of the symbols drawn in the given
+/// tile directories and the attributions. The map image is linked, not embedded, so the two files should
+/// be kept together.
+pub fn generate_print_layout(
+    map_path: &PathBuf,
+    (min_x, min_y, max_x, max_y): (i64, i64, i64, i64),
+    crs: &str,
+    render_dir_paths: &Vec<PathBuf>,
+    config: &Config,
+    symbol_styles: &SymbolStyles,
+) -> Result<(), CassiniError> {
+    info!("Generating print layout");
+    let start = Instant::now();
+
+    let print_layout_config = &config.print_layout;
+    let millimeters_per_meter = 1000. / config.get_map_scale() as f32;
+
+    let magnetic_north_lines_spacing = match config.symbol_set {
+        SymbolSet::Isom2017_2 => MAGNETIC_NORTH_LINES_SPACING,
+        SymbolSet::Issprom2019 => SPRINT_MAGNETIC_NORTH_LINES_SPACING,
+    };
+
+    let magnetic_north_angle = get_magnetic_north_angle(
+        print_layout_config.declination,
+        crs,
+        ((min_x + max_x) as f64 / 2., (min_y + max_y) as f64 / 2.),
+    );
+
+    let margin = print_layout_config.margin;
+    let map_width = (max_x - min_x) as f32 * millimeters_per_meter;
+    let map_height = (max_y - min_y) as f32 * millimeters_per_meter;
+    let panel_x = margin + map_width + PANEL_GAP;
+
+    let mut panel = String::new();
+    let mut y = margin;
+
+    if !print_layout_config.title.is_empty() {
+        panel.push_str(&get_text_svg(
+            panel_x,
+            y + 6.,
+            6.,
+            "bold",
+            &print_layout_config.title,
+        ));
+        y += 9.;
+    }
+
+    panel.push_str(&get_text_svg(
+        panel_x,
+        y + 3.5,
+        3.5,
+        "normal",
+        &format!("Scale {}", get_scale_label(config.get_map_scale())),
+    ));
+    y += 5.5;
+
+    panel.push_str(&get_text_svg(
+        panel_x,
+        y + 3.,
+        3.,
+        "normal",
+        &format!("Contour interval {} m", config.contours.interval),
+    ));
+    y += 7.;
+
+    panel.push_str(&get_scale_bar_svg(panel_x, y, millimeters_per_meter));
+    panel.push_str(&get_north_arrow_svg(
+        panel_x + PANEL_WIDTH - 4.,
+        y,
+        magnetic_north_angle,
+    ));
+    y += 10.;
+
+    panel.push_str(&get_text_svg(panel_x, y + 3.5, 3.5, "bold", "Legend"));
+    y += 6.;
+
+    for code in read_used_symbol_codes(render_dir_paths)? {
+        let sample_box = (panel_x, y, LEGEND_SAMPLE_WIDTH, LEGEND_SAMPLE_HEIGHT);
+
+        let entry = match OmapSymbol::from_code(&code) {
+            Some(symbol) => Some((
+                symbol.get_name(),
+                symbol.get_legend_svg(sample_box, symbol_styles),
+            )),
+            None => get_vegetation_legend_entry(&code, sample_box, symbol_styles),
+        };
+
+        let Some((name, sample)) = entry else {
+            continue;
+        };
+
+        panel.push_str(&sample);
+        panel.push_str(&get_text_svg(
+            panel_x + LEGEND_SAMPLE_WIDTH + 2.,
+            y + LEGEND_SAMPLE_HEIGHT / 2. + 0.9,
+            2.5,
+            "normal",
+            &format!("{} {}", code, name),
+        ));
+        y += LEGEND_ENTRY_HEIGHT;
+    }
+
+    y += 3.;
+
+    let mut attributions: Vec<&str> = vec![];
+
+    if render_dir_paths.iter().any(|path| path.join("shapes").is_dir()) {
+        attributions.push(OSM_ATTRIBUTION);
+    }
+
+    if !print_layout_config.lidar_attribution.is_empty() {
+        attributions.push(&print_layout_config.lidar_attribution);
+    }
+
+    attributions.push("Generated with Cassini");
+
+    for attribution in attributions {
+        panel.push_str(&get_text_svg(panel_x, y + 2.2, 2.2, "normal", attribution));
+        y += 3.2;
+    }
+
+    let page_width = panel_x + PANEL_WIDTH + margin;
+    let page_height = 2. * margin + map_height.max(y - margin);
+    let map_file_name = map_path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+
+    let mut svg = String::new();
+    svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    svg.push_str(&format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+        width=\"{:.3}mm\" height=\"{:.3}mm\" viewBox=\"0 0 {:.3} {:.3}\">\n",
+        page_width, page_height, page_width, page_height
+    ));
+    svg.push_str(&format!(
+        "<rect x=\"0\" y=\"0\" width=\"{:.3}\" height=\"{:.3}\" fill=\"#ffffff\"/>\n",
+        page_width, page_height
+    ));
+    svg.push_str(&format!(
+        "<image x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\" preserveAspectRatio=\"none\" \
+        xlink:href=\"{}\"/>\n",
+        margin,
+        margin,
+        map_width,
+        map_height,
+        escape_xml(map_file_name)
+    ));
+    svg.push_str(&get_magnetic_north_lines_svg(
+        (margin, margin, map_width, map_height),
+        magnetic_north_lines_spacing * millimeters_per_meter,
+        MAGNETIC_NORTH_LINE_WIDTH,
+        magnetic_north_angle,
+    ));
+    svg.push_str(&format!(
+        "<rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\" fill=\"none\" stroke=\"#000000\" \
+        stroke-width=\"0.2\"/>\n",
+        margin, margin, map_width, map_height
+    ));
+    svg.push_str(&panel);
+    svg.push_str("</svg>\n");
+
+    let mut file = File::create(map_path.with_file_name("print-layout.svg"))?;
+    file.write_all(svg.as_bytes())?;
+
+    let duration = start.elapsed();
+    info!("Print layout generated in {:.1?}", duration);
+
+    Ok(())
+}
+
+/// Returns the ISOM codes of the symbols drawn in the tiles, sorted, from the symbol files of the
+/// vegetation and render stages and from the kinds of the rendered contours.
+fn read_used_symbol_codes(render_dir_paths: &Vec<PathBuf>) -> Result<Vec<String>, CassiniError> {
+    let mut codes: Vec<String> = vec![];
+
+    for render_dir_path in render_dir_paths {
+        for file_name in ["vegetation-symbols.json", "symbols.json"] {
+            let path = render_dir_path.join(file_name);
+
+            if path.is_file() {
+                let tile_codes: Vec<String> = serde_json::from_str(&fs::read_to_string(path)?)?;
+                codes.extend(tile_codes);
+            }
+        }
+
+        let contours_path = render_dir_path.join("contours").join("rendered-contours.shp");
+
+        if contours_path.is_file() {
+            for (_, record) in read_as::<_, Polyline, Record>(&contours_path)? {
+                let symbol = match record.get("kind") {
+                    Some(FieldValue::Character(Some(kind))) if kind == "index" => OmapSymbol::IndexContour,
+                    Some(FieldValue::Character(Some(kind))) if kind == "formline" => OmapSymbol::FormLine,
+                    _ => OmapSymbol::Contour,
+                };
+
+                codes.push(symbol.get_code().to_string());
+            }
+        }
+    }
+
+    codes.sort_by(|code, other_code| get_code_number(code).total_cmp(&get_code_number(other_code)));
+    codes.dedup();
+
+    return Ok(codes);
+}
+
+fn get_code_number(code: &str) -> f64 {
+    code.parse().unwrap_or(f64::MAX)
+}
+
+/// Returns the name and the legend sample of a vegetation symbol, which are not part of the OpenOrienteering
+/// Mapper symbols as the vegetation is a raster.
+fn get_vegetation_legend_entry(
+    code: &str,
+    (x, y, width, height): (f32, f32, f32, f32),
+    symbol_styles: &SymbolStyles,
+) -> Option<(&'static str, String)> {
+    let symbol_dimensions = &symbol_styles.dimensions;
+
+    // The background of the sample is the symbol drawn under the dots or the stripes, if any
+    let colors = &symbol_styles.colors;
+    let (name, background_color, has_scattered_trees) = match code {
+        "401" => ("Open land", colors.open_land, false),
+        "402" => ("Open land with scattered trees", colors.open_land, true),
+        "403" => ("Rough open land", colors.rough_open_land, false),
+        "404" => (
+            "Rough open land with scattered trees",
+            colors.rough_open_land,
+            true,
+        ),
+        "405" => ("Forest", colors.forest, false),
+        "406" => ("Vegetation, slow running", colors.slow_running_vegetation, false),
+        "408" => ("Vegetation, walk", colors.walk_vegetation, false),
+        "409" => ("Vegetation, walk, good visibility", colors.forest, false),
+        "410" => ("Vegetation, fight", colors.fight_vegetation, false),
+        _ => return None,
+    };
+
+    let mut svg = format!(
+        "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>\n",
+        x,
+        y,
+        width,
+        height,
+        get_hex_color(&background_color)
+    );
+
+    if has_scattered_trees {
+        let spacing = symbol_dimensions.scattered_trees_dot_spacing;
+        let mut dot_y = y + spacing / 2.;
+
+        while dot_y < y + height {
+            let mut dot_x = x + spacing / 2.;
+
+            while dot_x < x + width {
+                svg.push_str(&format!(
+                    "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"/>\n",
+                    dot_x,
+                    dot_y,
+                    symbol_dimensions.scattered_trees_dot_diameter / 2.,
+                    get_hex_color(&colors.scattered_trees)
+                ));
+
+                dot_x += spacing;
+            }
+
+            dot_y += spacing;
+        }
+    }
+
+    // The undergrowth is drawn with vertical green stripes
+    if code == "409" {
+        let spacing = symbol_dimensions.undergrowth_line_width + symbol_dimensions.undergrowth_line_spacing;
+        let mut line_x = x + spacing / 2.;
+
+        while line_x < x + width {
+            svg.push_str(&format!(
+                "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\" stroke=\"{}\" \
+                stroke-width=\"{:.2}\"/>\n",
+                line_x,
+                y,
+                line_x,
+                y + height,
+                get_hex_color(&colors.undergrowth),
+                symbol_dimensions.undergrowth_line_width
+            ));
+
+            line_x += spacing;
+        }
+    }
+
+    // Outline, for the white areas to be visible on the paper
+    svg.push_str(&format!(
+        "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" stroke=\"#000000\" \
+        stroke-width=\"0.05\"/>\n",
+        x, y, width, height
+    ));
+
+    return Some((name, svg));
+}
+
+/// Returns the angle from the grid north to the magnetic north at the map center, in degrees, positive to
+/// the east: the magnetic declination minus the grid convergence of the projection, as the map is drawn
+/// with the grid north up.
+fn get_magnetic_north_angle(declination: f32, crs: &str, (center_x, center_y): (f64, f64)) -> f32 {
+    let convergence = match Projection::from_crs(crs) {
+        Some(projection) => projection.get_grid_convergence(center_x, center_y),
+        None => {
+            warn!(
+                "The {} CRS is not supported by the built-in projections, the magnetic north lines are \
+                rotated by the declination without the grid convergence",
+                crs
+            );
+
+            0.
+        }
+    };
+
+    return declination - convergence as f32;
+}
+
+/// Draws the magnetic north lines across the map face, rotated by the given angle around its center.
+fn get_magnetic_north_lines_svg(
+    (x, y, width, height): (f32, f32, f32, f32),
+    spacing: f32,
+    line_width: f32,
+    magnetic_north_angle: f32,
+) -> String {
+    let (center_x, center_y) = (x + width / 2., y + height / 2.);
+    // Lines long enough to cross the map face whatever their angle
+    let half_length = (width * width + height * height).sqrt() / 2.;
+    let number_of_lines_per_side = (half_length / spacing).ceil() as i64;
+
+    let mut svg = format!(
+        "<clipPath id=\"map-face\">\
+        <rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"{:.3}\"/></clipPath>\n\
+        <g clip-path=\"url(#map-face)\"><g transform=\"rotate({:.3} {:.3} {:.3})\" stroke=\"#000000\" \
+        stroke-width=\"{:.3}\">\n",
+        x, y, width, height, magnetic_north_angle, center_x, center_y, line_width
+    );
+
+    for index in -number_of_lines_per_side..=number_of_lines_per_side {
+        let line_x = center_x + index as f32 * spacing;
+
+        svg.push_str(&format!(
+            "<line x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\"/>\n",
+            line_x,
+            center_y - half_length,
+            line_x,
+            center_y + half_length
+        ));
+    }
+
+    svg.push_str("</g></g>\n");
+
+    return svg;
+}
+
+/// Draws a scale bar of alternating black and white segments, with its length below.
+fn get_scale_bar_svg(x: f32, y: f32, millimeters_per_meter: f32) -> String {
+    let length = SCALE_BAR_LENGTHS
+        .into_iter()
+        .find(|length| length * millimeters_per_meter <= SCALE_BAR_MAX_WIDTH)
+        .unwrap_or(SCALE_BAR_LENGTHS[SCALE_BAR_LENGTHS.len() - 1]);
+
+    let segment_width = length * millimeters_per_meter / SCALE_BAR_NUMBER_OF_SEGMENTS as f32;
+    let mut svg = String::new();
+
+    for index in 0..SCALE_BAR_NUMBER_OF_SEGMENTS {
+        svg.push_str(&format!(
+            "<rect x=\"{:.3}\" y=\"{:.3}\" width=\"{:.3}\" height=\"1.5\" fill=\"{}\" stroke=\"#000000\" \
+            stroke-width=\"0.1\"/>\n",
+            x + index as f32 * segment_width,
+            y,
+            segment_width,
+            if index % 2 == 0 { "#000000" } else { "#ffffff" }
+        ));
+    }
+
+    svg.push_str(&get_text_svg(x, y + 4.5, 2.5, "normal", "0"));
+    svg.push_str(&get_text_svg(
+        x + SCALE_BAR_NUMBER_OF_SEGMENTS as f32 * segment_width - 6.,
+        y + 4.5,
+        2.5,
+        "normal",
+        &format!("{} m", length),
+    ));
+
+    return svg;
+}
+
+/// Draws an arrow pointing to the magnetic north, centered on (x, y).
+fn get_north_arrow_svg(x: f32, y: f32, magnetic_north_angle: f32) -> String {
+    format!(
+        "<g transform=\"rotate({:.3} {:.3} {:.3})\">\
+        <line x1=\"{:.3}\" y1=\"{:.3}\" x2=\"{:.3}\" y2=\"{:.3}\" stroke=\"#000000\" stroke-width=\"0.3\"/>\
+        <polygon points=\"{:.3},{:.3} {:.3},{:.3} {:.3},{:.3}\" fill=\"#000000\"/></g>\n{}",
+        magnetic_north_angle,
+        x,
+        y,
+        x,
+        y + 4.,
+        x,
+        y - 2.,
+        x,
+        y - 4.,
+        x - 1.2,
+        y - 1.5,
+        x + 1.2,
+        y - 1.5,
+        get_text_svg(x - 1.2, y - 5., 2.5, "bold", "N")
+    )
+}
+
+fn get_text_svg(x: f32, y: f32, font_size: f32, font_weight: &str, text: &str) -> String {
+    format!(
+        "<text x=\"{:.3}\" y=\"{:.3}\" font-family=\"sans-serif\" font-size=\"{:.2}\" \
+        font-weight=\"{}\">{}</text>\n",
+        x,
+        y,
+        font_size,
+        font_weight,
+        escape_xml(text)
+    )
+}
+
+/// Formats the scale with a space between the thousands, like 1:10 000.
+fn get_scale_label(scale: u32) -> String {
+    format!("1:{} {:03}", scale / 1000, scale % 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magnetic_north_angle_removes_grid_convergence() {
+        // 4 degrees east of the Lambert 93 central meridian, the grid north is 2.9 degrees east of true north
+        let lambert_93 = Projection::from_crs("EPSG:2154").unwrap();
+        let center = lambert_93.from_wgs84(7.0, 45.0);
+        let angle = get_magnetic_north_angle(3.0, "EPSG:2154", center);
+        assert!((angle - (3.0 - 0.725_607_765 * 4.0)).abs() < 1e-4);
+
+        assert_eq!(
+            get_magnetic_north_angle(3.0, "EPSG:32631", (500_000.0, 5_000_000.0)),
+            3.0
+        );
+    }
+}
@@ -0,0 +1,913 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+use crate::crs::get_epsg_code_from_wkt;
+
+const ITERATIONS_MAX: usize = 15;
+const ITERATIONS_TOLERANCE: f64 = 1e-12;
+/// Half the length of the meridian arc the grid convergence is measured on, in degrees (about 10 meters)
+const CONVERGENCE_LATITUDE_STEP: f64 = 1e-4;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ellipsoid {
+    pub a: f64,
+    pub f: f64,
+}
+
+pub const GRS80: Ellipsoid = Ellipsoid {
+    a: 6_378_137.0,
+    f: 1.0 / 298.257222101,
+};
+
+pub const WGS84: Ellipsoid = Ellipsoid {
+    a: 6_378_137.0,
+    f: 1.0 / 298.257223563,
+};
+
+pub const INTERNATIONAL_1924: Ellipsoid = Ellipsoid {
+    a: 6_378_388.0,
+    f: 1.0 / 297.0,
+};
+
+pub const BESSEL_1841: Ellipsoid = Ellipsoid {
+    a: 6_377_397.155,
+    f: 1.0 / 299.1528128,
+};
+
+impl Ellipsoid {
+    fn eccentricity_squared(&self) -> f64 {
+        self.f * (2.0 - self.f)
+    }
+
+    fn eccentricity(&self) -> f64 {
+        self.eccentricity_squared().sqrt()
+    }
+}
+
+/// A datum, defined by its ellipsoid and by the 7 parameters (position vector convention)
+/// of the Helmert transformation to WGS84: tx, ty, tz in meters, rx, ry, rz in arc seconds
+/// and the scale difference in ppm. `None` means the datum is considered identical to WGS84.
+#[derive(Debug, Clone, Copy)]
+pub struct Datum {
+    pub ellipsoid: Ellipsoid,
+    pub to_wgs84: Option<[f64; 7]>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectionMethod {
+    /// Longitudes and latitudes in degrees
+    Geodetic,
+    LambertConformalConic {
+        lon_0: f64,
+        lat_0: f64,
+        lat_1: f64,
+        lat_2: f64,
+        k_0: f64,
+        x_0: f64,
+        y_0: f64,
+    },
+    TransverseMercator {
+        lon_0: f64,
+        lat_0: f64,
+        k_0: f64,
+        x_0: f64,
+        y_0: f64,
+    },
+    /// Swiss oblique Mercator, computed with the swisstopo approximate formulas (about 1 meter accuracy).
+    /// The false easting and northing are the LV95 ones minus the given offsets (LV03: 2000000 and 1000000).
+    SwissObliqueMercator {
+        false_easting_offset: f64,
+        false_northing_offset: f64,
+    },
+}
+
+/// A coordinate reference system that can be converted to and from WGS84 geodetic coordinates
+/// without relying on the PROJ binaries.
+#[derive(Debug, Clone, Copy)]
+pub struct Projection {
+    pub datum: Datum,
+    pub method: ProjectionMethod,
+}
+
+impl Projection {
+    /// Builds a projection from an "EPSG:XXXX" code, a PROJ string ("+proj=lcc ...")
+    /// or a WKT string carrying an EPSG authority.
+    pub fn from_crs(crs: &str) -> Option<Projection> {
+        let trimmed_crs = crs.trim();
+
+        if trimmed_crs.starts_with("+proj=") || trimmed_crs.starts_with("proj=") {
+            return Self::from_proj_string(trimmed_crs);
+        }
+
+        let upper_case_crs = trimmed_crs.to_uppercase();
+
+        if let Some(code) = upper_case_crs.strip_prefix("EPSG:") {
+            return Self::from_epsg_code(code.trim().parse().ok()?);
+        }
+
+        return Self::from_epsg_code(get_epsg_code_from_wkt(trimmed_crs)?);
+    }
+
+    pub fn from_epsg_code(code: u32) -> Option<Projection> {
+        let grs80 = Datum {
+            ellipsoid: GRS80,
+            to_wgs84: None,
+        };
+
+        let wgs84 = Datum {
+            ellipsoid: WGS84,
+            to_wgs84: None,
+        };
+
+        let projection = match code {
+            // WGS84, RGF93, ETRS89
+            4326 | 4171 | 4258 => Projection {
+                datum: wgs84,
+                method: ProjectionMethod::Geodetic,
+            },
+            // RGF93 / Lambert-93
+            2154 => Projection {
+                datum: grs80,
+                method: ProjectionMethod::LambertConformalConic {
+                    lon_0: 3.0,
+                    lat_0: 46.5,
+                    lat_1: 49.0,
+                    lat_2: 44.0,
+                    k_0: 1.0,
+                    x_0: 700_000.0,
+                    y_0: 6_600_000.0,
+                },
+            },
+            // RGF93 / CC42 to CC50
+            3942..=3950 => {
+                let zone = (code - 3900) as f64;
+
+                Projection {
+                    datum: grs80,
+                    method: ProjectionMethod::LambertConformalConic {
+                        lon_0: 3.0,
+                        lat_0: zone,
+                        lat_1: zone - 0.75,
+                        lat_2: zone + 0.75,
+                        k_0: 1.0,
+                        x_0: 1_700_000.0,
+                        y_0: (zone - 41.0) * 1_000_000.0 + 200_000.0,
+                    },
+                }
+            }
+            // ETRS89 / Belgian Lambert 2008
+            3812 => Projection {
+                datum: grs80,
+                method: ProjectionMethod::LambertConformalConic {
+                    lon_0: 4.359215833333333,
+                    lat_0: 50.797815,
+                    lat_1: 49.833333333333336,
+                    lat_2: 51.166666666666664,
+                    k_0: 1.0,
+                    x_0: 649_328.0,
+                    y_0: 665_262.0,
+                },
+            },
+            // Belge 1972 / Belgian Lambert 72
+            31370 => Projection {
+                datum: Datum {
+                    ellipsoid: INTERNATIONAL_1924,
+                    to_wgs84: Some([-106.8686, 52.2978, -103.7239, 0.3366, -0.457, 1.8422, -1.2747]),
+                },
+                method: ProjectionMethod::LambertConformalConic {
+                    lon_0: 4.367486666666666,
+                    lat_0: 90.0,
+                    lat_1: 51.16666723333333,
+                    lat_2: 49.8333339,
+                    k_0: 1.0,
+                    x_0: 150_000.013,
+                    y_0: 5_400_088.438,
+                },
+            },
+            // CH1903+ / LV95
+            2056 => Projection {
+                datum: Datum {
+                    ellipsoid: BESSEL_1841,
+                    to_wgs84: None,
+                },
+                method: ProjectionMethod::SwissObliqueMercator {
+                    false_easting_offset: 0.0,
+                    false_northing_offset: 0.0,
+                },
+            },
+            // CH1903 / LV03
+            21781 => Projection {
+                datum: Datum {
+                    ellipsoid: BESSEL_1841,
+                    to_wgs84: None,
+                },
+                method: ProjectionMethod::SwissObliqueMercator {
+                    false_easting_offset: 2_000_000.0,
+                    false_northing_offset: 1_000_000.0,
+                },
+            },
+            // WGS84 / UTM zones north and south
+            32601..=32660 => Self::utm(wgs84, code - 32600, false),
+            32701..=32760 => Self::utm(wgs84, code - 32700, true),
+            // ETRS89 / UTM zones 28N to 38N
+            25828..=25838 => Self::utm(grs80, code - 25800, false),
+            // French overseas territories UTM zones (RGR92, RGAF09 and RGFG95 are WGS84 compatible)
+            2975 => Self::utm(grs80, 40, true),
+            5490 => Self::utm(grs80, 20, false),
+            2972 => Self::utm(grs80, 22, false),
+            _ => return None,
+        };
+
+        return Some(projection);
+    }
+
+    fn utm(datum: Datum, zone: u32, is_south: bool) -> Projection {
+        Projection {
+            datum,
+            method: ProjectionMethod::TransverseMercator {
+                lon_0: -183.0 + 6.0 * zone as f64,
+                lat_0: 0.0,
+                k_0: 0.9996,
+                x_0: 500_000.0,
+                y_0: if is_south { 10_000_000.0 } else { 0.0 },
+            },
+        }
+    }
+
+    /// Parses the subset of the PROJ string syntax needed for the supported projection methods.
+    pub fn from_proj_string(proj_string: &str) -> Option<Projection> {
+        let mut parameters = std::collections::HashMap::<String, String>::new();
+
+        for token in proj_string.split_whitespace() {
+            let token = token.trim_start_matches('+');
+            let mut key_value = token.splitn(2, '=');
+            let key = key_value.next()?.to_lowercase();
+            let value = key_value.next().unwrap_or("").to_owned();
+            parameters.insert(key, value);
+        }
+
+        let get_number = |key: &str, default: f64| -> Option<f64> {
+            match parameters.get(key) {
+                Some(value) => value.parse::<f64>().ok(),
+                None => Some(default),
+            }
+        };
+
+        let ellipsoid = match parameters
+            .get("ellps")
+            .or(parameters.get("datum"))
+            .map(|x| x.as_str())
+        {
+            Some("GRS80") => GRS80,
+            Some("WGS84") | None => WGS84,
+            Some("intl") => INTERNATIONAL_1924,
+            Some("bessel") => BESSEL_1841,
+            Some(_) => return None,
+        };
+
+        let to_wgs84 = match parameters.get("towgs84") {
+            Some(raw_parameters) => {
+                let mut helmert_parameters = [0.0; 7];
+
+                for (index, raw_parameter) in raw_parameters.split(',').take(7).enumerate() {
+                    helmert_parameters[index] = raw_parameter.trim().parse().ok()?;
+                }
+
+                if helmert_parameters.iter().all(|parameter| *parameter == 0.0) {
+                    None
+                } else {
+                    Some(helmert_parameters)
+                }
+            }
+            None => None,
+        };
+
+        let datum = Datum { ellipsoid, to_wgs84 };
+
+        let method = match parameters.get("proj")?.as_str() {
+            "longlat" | "latlong" | "lonlat" | "latlon" => ProjectionMethod::Geodetic,
+            "lcc" => {
+                let lat_1 = get_number("lat_1", 0.0)?;
+
+                ProjectionMethod::LambertConformalConic {
+                    lon_0: get_number("lon_0", 0.0)?,
+                    lat_0: get_number("lat_0", lat_1)?,
+                    lat_1,
+                    lat_2: get_number("lat_2", lat_1)?,
+                    k_0: get_number("k_0", get_number("k", 1.0)?)?,
+                    x_0: get_number("x_0", 0.0)?,
+                    y_0: get_number("y_0", 0.0)?,
+                }
+            }
+            "tmerc" | "etmerc" => ProjectionMethod::TransverseMercator {
+                lon_0: get_number("lon_0", 0.0)?,
+                lat_0: get_number("lat_0", 0.0)?,
+                k_0: get_number("k_0", get_number("k", 1.0)?)?,
+                x_0: get_number("x_0", 0.0)?,
+                y_0: get_number("y_0", 0.0)?,
+            },
+            "utm" => {
+                let zone = parameters.get("zone")?.parse::<u32>().ok()?;
+
+                if zone == 0 || zone > 60 {
+                    return None;
+                }
+
+                return Some(Self::utm(datum, zone, parameters.contains_key("south")));
+            }
+            _ => return None,
+        };
+
+        return Some(Projection { datum, method });
+    }
+
+    /// Converts projected coordinates to WGS84 longitude and latitude in degrees.
+    pub fn to_wgs84(&self, x: f64, y: f64) -> (f64, f64) {
+        let (lon, lat) = match self.method {
+            ProjectionMethod::Geodetic => (x, y),
+            ProjectionMethod::LambertConformalConic { .. } => self.lambert_conformal_conic_inverse(x, y),
+            ProjectionMethod::TransverseMercator { .. } => self.transverse_mercator_inverse(x, y),
+            ProjectionMethod::SwissObliqueMercator { .. } => {
+                // The swisstopo formulas directly give WGS84 coordinates
+                return self.swiss_oblique_mercator_inverse(x, y);
+            }
+        };
+
+        return match self.datum.to_wgs84 {
+            Some(helmert_parameters) => transform_datum(
+                lon,
+                lat,
+                &self.datum.ellipsoid,
+                &WGS84,
+                &helmert_parameters,
+                false,
+            ),
+            None => (lon, lat),
+        };
+    }
+
+    /// Converts WGS84 longitude and latitude in degrees to projected coordinates.
+    pub fn from_wgs84(&self, lon: f64, lat: f64) -> (f64, f64) {
+        if let ProjectionMethod::SwissObliqueMercator { .. } = self.method {
+            return self.swiss_oblique_mercator_forward(lon, lat);
+        }
+
+        let (lon, lat) = match self.datum.to_wgs84 {
+            Some(helmert_parameters) => {
+                transform_datum(lon, lat, &WGS84, &self.datum.ellipsoid, &helmert_parameters, true)
+            }
+            None => (lon, lat),
+        };
+
+        return match self.method {
+            ProjectionMethod::Geodetic => (lon, lat),
+            ProjectionMethod::LambertConformalConic { .. } => self.lambert_conformal_conic_forward(lon, lat),
+            ProjectionMethod::TransverseMercator { .. } => self.transverse_mercator_forward(lon, lat),
+            ProjectionMethod::SwissObliqueMercator { .. } => unreachable!(),
+        };
+    }
+
+    /// Returns the grid convergence at projected coordinates, in degrees: the angle from the true north to
+    /// the grid north, positive when the grid north is to the east.
+    pub fn get_grid_convergence(&self, x: f64, y: f64) -> f64 {
+        let (lon, lat) = self.to_wgs84(x, y);
+        let (south_x, south_y) = self.from_wgs84(lon, lat - CONVERGENCE_LATITUDE_STEP);
+        let (north_x, north_y) = self.from_wgs84(lon, lat + CONVERGENCE_LATITUDE_STEP);
+
+        // The true north, as seen on the grid, is west of the grid north when the convergence is positive
+        return -(north_x - south_x).atan2(north_y - south_y).to_degrees();
+    }
+
+    fn lambert_conformal_conic_constants(&self) -> (f64, f64, f64) {
+        let ProjectionMethod::LambertConformalConic {
+            lat_0,
+            lat_1,
+            lat_2,
+            k_0,
+            ..
+        } = self.method
+        else {
+            unreachable!()
+        };
+
+        let e = self.datum.ellipsoid.eccentricity();
+        let (phi_0, phi_1, phi_2) = (lat_0.to_radians(), lat_1.to_radians(), lat_2.to_radians());
+        let m_1 = lcc_m(phi_1, e);
+        let t_1 = lcc_t(phi_1, e);
+
+        let n = if (phi_1 - phi_2).abs() < 1e-10 {
+            phi_1.sin()
+        } else {
+            (m_1.ln() - lcc_m(phi_2, e).ln()) / (t_1.ln() - lcc_t(phi_2, e).ln())
+        };
+
+        let big_f = m_1 / (n * t_1.powf(n));
+        let a_f = self.datum.ellipsoid.a * big_f * k_0;
+        let rho_0 = a_f * lcc_t(phi_0, e).powf(n);
+
+        return (n, a_f, rho_0);
+    }
+
+    fn lambert_conformal_conic_forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let ProjectionMethod::LambertConformalConic { lon_0, x_0, y_0, .. } = self.method else {
+            unreachable!()
+        };
+
+        let e = self.datum.ellipsoid.eccentricity();
+        let (n, a_f, rho_0) = self.lambert_conformal_conic_constants();
+        let rho = a_f * lcc_t(lat.to_radians(), e).powf(n);
+        let theta = n * normalize_longitude((lon - lon_0).to_radians());
+
+        return (x_0 + rho * theta.sin(), y_0 + rho_0 - rho * theta.cos());
+    }
+
+    fn lambert_conformal_conic_inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let ProjectionMethod::LambertConformalConic { lon_0, x_0, y_0, .. } = self.method else {
+            unreachable!()
+        };
+
+        let e = self.datum.ellipsoid.eccentricity();
+        let (n, a_f, rho_0) = self.lambert_conformal_conic_constants();
+        let dx = x - x_0;
+        let dy = rho_0 - (y - y_0);
+        let rho = n.signum() * (dx * dx + dy * dy).sqrt();
+        let theta = (n.signum() * dx).atan2(n.signum() * dy);
+        let t = (rho / a_f).powf(1.0 / n);
+
+        let mut phi = FRAC_PI_2 - 2.0 * t.atan();
+
+        for _ in 0..ITERATIONS_MAX {
+            let e_sin_phi = e * phi.sin();
+            let next_phi =
+                FRAC_PI_2 - 2.0 * (t * ((1.0 - e_sin_phi) / (1.0 + e_sin_phi)).powf(e / 2.0)).atan();
+
+            if (next_phi - phi).abs() < ITERATIONS_TOLERANCE {
+                phi = next_phi;
+                break;
+            }
+
+            phi = next_phi;
+        }
+
+        return (theta.to_degrees() / n + lon_0, phi.to_degrees());
+    }
+
+    fn transverse_mercator_forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let ProjectionMethod::TransverseMercator {
+            lon_0,
+            lat_0,
+            k_0,
+            x_0,
+            y_0,
+        } = self.method
+        else {
+            unreachable!()
+        };
+
+        let series = KruegerSeries::new(&self.datum.ellipsoid);
+        let (xi, eta) = series.forward(normalize_longitude((lon - lon_0).to_radians()), lat.to_radians());
+        let (xi_0, _) = series.forward(0.0, lat_0.to_radians());
+
+        return (
+            x_0 + k_0 * series.big_a * eta,
+            y_0 + k_0 * series.big_a * (xi - xi_0),
+        );
+    }
+
+    fn transverse_mercator_inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let ProjectionMethod::TransverseMercator {
+            lon_0,
+            lat_0,
+            k_0,
+            x_0,
+            y_0,
+        } = self.method
+        else {
+            unreachable!()
+        };
+
+        let series = KruegerSeries::new(&self.datum.ellipsoid);
+        let (xi_0, _) = series.forward(0.0, lat_0.to_radians());
+        let xi = (y - y_0) / (k_0 * series.big_a) + xi_0;
+        let eta = (x - x_0) / (k_0 * series.big_a);
+        let (lambda, phi) = series.inverse(xi, eta);
+
+        return (lambda.to_degrees() + lon_0, phi.to_degrees());
+    }
+
+    fn swiss_oblique_mercator_forward(&self, lon: f64, lat: f64) -> (f64, f64) {
+        let ProjectionMethod::SwissObliqueMercator {
+            false_easting_offset,
+            false_northing_offset,
+        } = self.method
+        else {
+            unreachable!()
+        };
+
+        let phi = (lat * 3600.0 - 169_028.66) / 10_000.0;
+        let lambda = (lon * 3600.0 - 26_782.5) / 10_000.0;
+
+        let easting = 2_600_072.37 + 211_455.93 * lambda
+            - 10_938.51 * lambda * phi
+            - 0.36 * lambda * phi.powi(2)
+            - 44.54 * lambda.powi(3);
+
+        let northing = 1_200_147.07 + 308_807.95 * phi + 3_745.25 * lambda.powi(2) + 76.63 * phi.powi(2)
+            - 194.56 * lambda.powi(2) * phi
+            + 119.79 * phi.powi(3);
+
+        return (easting - false_easting_offset, northing - false_northing_offset);
+    }
+
+    fn swiss_oblique_mercator_inverse(&self, x: f64, y: f64) -> (f64, f64) {
+        let ProjectionMethod::SwissObliqueMercator {
+            false_easting_offset,
+            false_northing_offset,
+        } = self.method
+        else {
+            unreachable!()
+        };
+
+        let y_aux = (x + false_easting_offset - 2_600_000.0) / 1_000_000.0;
+        let x_aux = (y + false_northing_offset - 1_200_000.0) / 1_000_000.0;
+
+        let lambda = 2.6779094 + 4.728982 * y_aux + 0.791484 * y_aux * x_aux + 0.1306 * y_aux * x_aux.powi(2)
+            - 0.0436 * y_aux.powi(3);
+
+        let phi = 16.9023892 + 3.238272 * x_aux
+            - 0.270978 * y_aux.powi(2)
+            - 0.002528 * x_aux.powi(2)
+            - 0.0447 * y_aux.powi(2) * x_aux
+            - 0.0140 * x_aux.powi(3);
+
+        return (lambda * 100.0 / 36.0, phi * 100.0 / 36.0);
+    }
+}
+
+fn lcc_m(phi: f64, e: f64) -> f64 {
+    phi.cos() / (1.0 - (e * phi.sin()).powi(2)).sqrt()
+}
+
+fn lcc_t(phi: f64, e: f64) -> f64 {
+    if (FRAC_PI_2 - phi).abs() < 1e-12 {
+        return 0.0;
+    }
+
+    let e_sin_phi = e * phi.sin();
+    (FRAC_PI_4 - phi / 2.0).tan() / ((1.0 - e_sin_phi) / (1.0 + e_sin_phi)).powf(e / 2.0)
+}
+
+fn normalize_longitude(lambda: f64) -> f64 {
+    let mut normalized = lambda;
+
+    while normalized > PI {
+        normalized -= 2.0 * PI;
+    }
+
+    while normalized < -PI {
+        normalized += 2.0 * PI;
+    }
+
+    normalized
+}
+
+/// Krüger series of order 6 for the transverse Mercator projection (Karney 2011),
+/// accurate to a few nanometers within 3900 km of the central meridian.
+struct KruegerSeries {
+    e: f64,
+    big_a: f64,
+    alpha: [f64; 6],
+    beta: [f64; 6],
+}
+
+impl KruegerSeries {
+    fn new(ellipsoid: &Ellipsoid) -> KruegerSeries {
+        let n = ellipsoid.f / (2.0 - ellipsoid.f);
+        let (n2, n3, n4, n5, n6) = (n.powi(2), n.powi(3), n.powi(4), n.powi(5), n.powi(6));
+
+        let big_a = ellipsoid.a / (1.0 + n) * (1.0 + n2 / 4.0 + n4 / 64.0 + n6 / 256.0);
+
+        let alpha = [
+            n / 2.0 - 2.0 * n2 / 3.0 + 5.0 * n3 / 16.0 + 41.0 * n4 / 180.0 - 127.0 * n5 / 288.0
+                + 7891.0 * n6 / 37800.0,
+            13.0 * n2 / 48.0 - 3.0 * n3 / 5.0 + 557.0 * n4 / 1440.0 + 281.0 * n5 / 630.0
+                - 1983433.0 * n6 / 1935360.0,
+            61.0 * n3 / 240.0 - 103.0 * n4 / 140.0 + 15061.0 * n5 / 26880.0 + 167603.0 * n6 / 181440.0,
+            49561.0 * n4 / 161280.0 - 179.0 * n5 / 168.0 + 6601661.0 * n6 / 7257600.0,
+            34729.0 * n5 / 80640.0 - 3418889.0 * n6 / 1995840.0,
+            212378941.0 * n6 / 319334400.0,
+        ];
+
+        let beta = [
+            n / 2.0 - 2.0 * n2 / 3.0 + 37.0 * n3 / 96.0 - n4 / 360.0 - 81.0 * n5 / 512.0
+                + 96199.0 * n6 / 604800.0,
+            n2 / 48.0 + n3 / 15.0 - 437.0 * n4 / 1440.0 + 46.0 * n5 / 105.0 - 1118711.0 * n6 / 3870720.0,
+            17.0 * n3 / 480.0 - 37.0 * n4 / 840.0 - 209.0 * n5 / 4480.0 + 5569.0 * n6 / 90720.0,
+            4397.0 * n4 / 161280.0 - 11.0 * n5 / 504.0 - 830251.0 * n6 / 7257600.0,
+            4583.0 * n5 / 161280.0 - 108847.0 * n6 / 3991680.0,
+            20648693.0 * n6 / 638668800.0,
+        ];
+
+        KruegerSeries {
+            e: ellipsoid.eccentricity(),
+            big_a,
+            alpha,
+            beta,
+        }
+    }
+
+    /// Returns the (xi, eta) coordinates on the normalized transverse Mercator plane.
+    fn forward(&self, lambda: f64, phi: f64) -> (f64, f64) {
+        let t = (phi.sin().atanh() - self.e * (self.e * phi.sin()).atanh()).sinh();
+        let xi_prime = t.atan2(lambda.cos());
+        let eta_prime = (lambda.sin() / (1.0 + t * t).sqrt()).atanh();
+
+        let mut xi = xi_prime;
+        let mut eta = eta_prime;
+
+        for (index, alpha) in self.alpha.iter().enumerate() {
+            let j = 2.0 * (index + 1) as f64;
+            xi += alpha * (j * xi_prime).sin() * (j * eta_prime).cosh();
+            eta += alpha * (j * xi_prime).cos() * (j * eta_prime).sinh();
+        }
+
+        (xi, eta)
+    }
+
+    /// Returns the (lambda, phi) geodetic coordinates in radians relative to the central meridian.
+    fn inverse(&self, xi: f64, eta: f64) -> (f64, f64) {
+        let mut xi_prime = xi;
+        let mut eta_prime = eta;
+
+        for (index, beta) in self.beta.iter().enumerate() {
+            let j = 2.0 * (index + 1) as f64;
+            xi_prime -= beta * (j * xi).sin() * (j * eta).cosh();
+            eta_prime -= beta * (j * xi).cos() * (j * eta).sinh();
+        }
+
+        let chi = (xi_prime.sin() / eta_prime.cosh()).asin();
+        let lambda = eta_prime.sinh().atan2(xi_prime.cos());
+
+        let mut phi = chi;
+
+        for _ in 0..ITERATIONS_MAX {
+            let e_sin_phi = self.e * phi.sin();
+            let next_phi = 2.0
+                * ((FRAC_PI_4 + chi / 2.0).tan()
+                    * ((1.0 + e_sin_phi) / (1.0 - e_sin_phi)).powf(self.e / 2.0))
+                .atan()
+                - FRAC_PI_2;
+
+            if (next_phi - phi).abs() < ITERATIONS_TOLERANCE {
+                phi = next_phi;
+                break;
+            }
+
+            phi = next_phi;
+        }
+
+        (lambda, phi)
+    }
+}
+
+/// Converts geodetic coordinates in degrees between two datums with a Helmert transformation.
+/// The parameters transform from the source datum to WGS84, `is_inverse` applies them the other way.
+fn transform_datum(
+    lon: f64,
+    lat: f64,
+    source_ellipsoid: &Ellipsoid,
+    target_ellipsoid: &Ellipsoid,
+    helmert_parameters: &[f64; 7],
+    is_inverse: bool,
+) -> (f64, f64) {
+    let sign = if is_inverse { -1.0 } else { 1.0 };
+    let [tx, ty, tz, rx, ry, rz, ds] = helmert_parameters.map(|parameter| parameter * sign);
+    let arc_second_to_radian = PI / (180.0 * 3600.0);
+    let (rx, ry, rz) = (
+        rx * arc_second_to_radian,
+        ry * arc_second_to_radian,
+        rz * arc_second_to_radian,
+    );
+    let scale = 1.0 + ds * 1e-6;
+
+    let (x, y, z) = geodetic_to_geocentric(lon.to_radians(), lat.to_radians(), source_ellipsoid);
+
+    let transformed_x = tx + scale * (x - rz * y + ry * z);
+    let transformed_y = ty + scale * (rz * x + y - rx * z);
+    let transformed_z = tz + scale * (-ry * x + rx * y + z);
+
+    let (lambda, phi) = geocentric_to_geodetic(transformed_x, transformed_y, transformed_z, target_ellipsoid);
+
+    (lambda.to_degrees(), phi.to_degrees())
+}
+
+fn geodetic_to_geocentric(lambda: f64, phi: f64, ellipsoid: &Ellipsoid) -> (f64, f64, f64) {
+    let e2 = ellipsoid.eccentricity_squared();
+    let big_n = ellipsoid.a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+
+    (
+        big_n * phi.cos() * lambda.cos(),
+        big_n * phi.cos() * lambda.sin(),
+        big_n * (1.0 - e2) * phi.sin(),
+    )
+}
+
+fn geocentric_to_geodetic(x: f64, y: f64, z: f64, ellipsoid: &Ellipsoid) -> (f64, f64) {
+    let e2 = ellipsoid.eccentricity_squared();
+    let p = (x * x + y * y).sqrt();
+    let lambda = y.atan2(x);
+    let mut phi = z.atan2(p * (1.0 - e2));
+
+    for _ in 0..ITERATIONS_MAX {
+        let big_n = ellipsoid.a / (1.0 - e2 * phi.sin().powi(2)).sqrt();
+        let next_phi = (z + e2 * big_n * phi.sin()).atan2(p);
+
+        if (next_phi - phi).abs() < ITERATIONS_TOLERANCE {
+            phi = next_phi;
+            break;
+        }
+
+        phi = next_phi;
+    }
+
+    (lambda, phi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Degrees, minutes and seconds to decimal degrees
+    fn dms(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+        degrees + minutes / 60.0 + seconds / 3600.0
+    }
+
+    fn assert_close(actual: (f64, f64), expected: (f64, f64), tolerance: f64) {
+        assert!(
+            (actual.0 - expected.0).abs() < tolerance && (actual.1 - expected.1).abs() < tolerance,
+            "{:?} is not within {} of {:?}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    fn assert_round_trip(projection: &Projection, lon: f64, lat: f64, tolerance: f64) {
+        let (x, y) = projection.from_wgs84(lon, lat);
+        assert_close(projection.to_wgs84(x, y), (lon, lat), tolerance);
+    }
+
+    #[test]
+    fn lambert_93() {
+        let projection = Projection::from_crs("EPSG:2154").unwrap();
+
+        // Natural origin of the projection
+        assert_close(projection.from_wgs84(3.0, 46.5), (700_000.0, 6_600_000.0), 1e-3);
+        assert_close(projection.to_wgs84(700_000.0, 6_600_000.0), (3.0, 46.5), 1e-9);
+
+        // Projection constants published by the IGN: n, C and the Ys of the pole
+        let (n, a_f, rho_0) = projection.lambert_conformal_conic_constants();
+        assert!((n - 0.725_607_765_0).abs() < 1e-10);
+        assert!((a_f - 11_754_255.426).abs() < 1e-3);
+        assert!((rho_0 + 6_600_000.0 - 12_655_612.050).abs() < 1e-3);
+
+        assert_round_trip(&projection, -4.5, 48.4, 1e-9);
+        assert_round_trip(&projection, 7.7, 43.7, 1e-9);
+    }
+
+    #[test]
+    fn lambert_conformal_conic_control_points() {
+        // Example of the IOGP guidance note 7-2, NAD27 / Texas South Central in US survey feet
+        let us_survey_foot = 1200.0 / 3937.0;
+        let texas_projection = Projection {
+            datum: Datum {
+                ellipsoid: Ellipsoid {
+                    a: 6_378_206.4,
+                    f: 1.0 / 294.978_698_2,
+                },
+                to_wgs84: None,
+            },
+            method: ProjectionMethod::LambertConformalConic {
+                lon_0: -99.0,
+                lat_0: dms(27.0, 50.0, 0.0),
+                lat_1: dms(28.0, 23.0, 0.0),
+                lat_2: dms(30.0, 17.0, 0.0),
+                k_0: 1.0,
+                x_0: 2_000_000.0 * us_survey_foot,
+                y_0: 0.0,
+            },
+        };
+
+        let (x, y) = texas_projection.from_wgs84(-96.0, 28.5);
+        assert_close(
+            (x / us_survey_foot, y / us_survey_foot),
+            (2_963_503.91, 254_759.80),
+            0.01,
+        );
+
+        // Test points of the PROJ test suite, on the GRS80 ellipsoid of Lambert 93
+        let projection = Projection::from_crs("+proj=lcc +ellps=GRS80 +lat_1=0.5 +lat_2=2 +lat_0=0").unwrap();
+        assert_close(
+            projection.from_wgs84(2.0, 1.0),
+            (222_588.439_736, 110_660.533_871),
+            1e-3,
+        );
+        assert_close(
+            projection.from_wgs84(-2.0, -1.0),
+            (-222_756.879_700, -110_532.797_661),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn grid_convergence() {
+        // The convergence of a conic projection is n times the longitude difference to the central meridian
+        let lambert_93 = Projection::from_crs("EPSG:2154").unwrap();
+        let (x, y) = lambert_93.from_wgs84(7.0, 45.0);
+        assert!((lambert_93.get_grid_convergence(x, y) - 0.725_607_765_0 * 4.0).abs() < 1e-6);
+
+        let utm_zone_31 = Projection::from_crs("EPSG:32631").unwrap();
+        assert!(utm_zone_31.get_grid_convergence(500_000.0, 5_000_000.0).abs() < 1e-6);
+
+        // West of the central meridian, the grid north is to the west of the true north
+        let (x, y) = utm_zone_31.from_wgs84(0.0, 45.0);
+        let expected_convergence = -(3.0_f64.to_radians().tan() * 45.0_f64.to_radians().sin())
+            .atan()
+            .to_degrees();
+        assert!((utm_zone_31.get_grid_convergence(x, y) - expected_convergence).abs() < 1e-2);
+
+        let geodetic = Projection::from_crs("EPSG:4326").unwrap();
+        assert_eq!(geodetic.get_grid_convergence(2.0, 48.0), 0.0);
+    }
+
+    #[test]
+    fn belgian_lambert_72() {
+        let projection = Projection::from_crs("EPSG:31370").unwrap();
+
+        // Example of the IOGP guidance note 7-2, with geographic coordinates on the Belge 1972 datum
+        let belge_1972_projection = Projection {
+            datum: Datum {
+                ellipsoid: INTERNATIONAL_1924,
+                to_wgs84: None,
+            },
+            method: projection.method,
+        };
+
+        let (lon, lat) = (dms(5.0, 48.0, 26.533), dms(50.0, 40.0, 46.461));
+        assert_close(
+            belge_1972_projection.from_wgs84(lon, lat),
+            (251_763.20, 153_034.13),
+            0.05,
+        );
+        assert_close(
+            belge_1972_projection.to_wgs84(251_763.20, 153_034.13),
+            (lon, lat),
+            1e-6,
+        );
+
+        assert_round_trip(&projection, 4.35, 50.85, 1e-8);
+        assert_round_trip(&projection, 5.57, 50.63, 1e-8);
+    }
+
+    #[test]
+    fn utm_zone_31_north() {
+        let projection = Projection::from_crs("EPSG:32631").unwrap();
+
+        assert_close(projection.from_wgs84(3.0, 0.0), (500_000.0, 0.0), 1e-3);
+        assert_close(projection.from_wgs84(0.0, 0.0), (166_021.443, 0.0), 1e-3);
+        assert_close(projection.to_wgs84(166_021.443, 0.0), (0.0, 0.0), 1e-8);
+
+        assert_round_trip(&projection, 2.35, 48.85, 1e-9);
+        assert_round_trip(&projection, 5.9, 60.1, 1e-9);
+    }
+
+    #[test]
+    fn swiss_lv95_and_lv03() {
+        let lv95_projection = Projection::from_crs("EPSG:2056").unwrap();
+        let lv03_projection = Projection::from_crs("EPSG:21781").unwrap();
+
+        // Example of the swisstopo approximate formulas documentation
+        let (lon, lat) = (dms(8.0, 43.0, 49.79), dms(46.0, 2.0, 38.87));
+        assert_close(
+            lv95_projection.from_wgs84(lon, lat),
+            (2_699_999.76, 1_099_999.97),
+            0.05,
+        );
+        assert_close(
+            lv03_projection.from_wgs84(lon, lat),
+            (699_999.76, 99_999.97),
+            0.05,
+        );
+
+        // The approximate formulas are accurate to about 1 meter, that is 1e-5 degree
+        assert_close(
+            lv95_projection.to_wgs84(2_700_000.0, 1_100_000.0),
+            (lon, lat),
+            1e-5,
+        );
+        assert_close(lv03_projection.to_wgs84(700_000.0, 100_000.0), (lon, lat), 1e-5);
+
+        // The errors of both formulas add up in a round trip
+        assert_round_trip(&lv95_projection, 6.14, 46.2, 3e-5);
+        assert_round_trip(&lv03_projection, 9.53, 47.05, 3e-5);
+    }
+}
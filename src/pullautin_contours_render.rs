@@ -1,44 +1,33 @@
 use image::RgbaImage;
 use imageproc::drawing::draw_line_segment_mut;
 use log::info;
-use shapefile::dbase::{FieldIOError, FieldWriter, WritableRecord};
+use shapefile::dbase::{FieldIOError, FieldName, FieldWriter, TableWriterBuilder, WritableRecord};
 use shapefile::record::polyline::GenericPolyline;
-use shapefile::{Point, Reader, Writer};
+use shapefile::{Point, Writer};
 use std::fs::{create_dir_all, File};
-use std::io::{BufReader, Write};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
 use std::time::Instant;
 
-struct FormLineRecord {
-    id: i32,
-    elev: f64,
-}
-
-impl WritableRecord for FormLineRecord {
-    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
-    where
-        W: Write,
-    {
-        field_writer.write_next_field_value(&self.id)?;
-        field_writer.write_next_field_value(&self.elev)?;
-        Ok(())
-    }
-}
-
 use crate::config::Config;
-use crate::constants::{BUFFER, INCH, PURPLE};
-use crate::{
-    constants::{BROWN, TRANSPARENT},
-    tile::Tile,
+use crate::constants::{
+    BUFFER, CONTOUR_THICKNESS_MILLIMETTER, FORM_CONTOUR_THICKNESS_MILLIMETTER,
+    MASTER_CONTOUR_THICKNESS_MILLIMETTER,
 };
+use crate::error::CassiniError;
+use crate::pullautin_smooth_contours::{create_contours_shapefile_writer, ContourRecord};
+use crate::symbol_styles::{get_rgba, SymbolStyles};
+use crate::{constants::TRANSPARENT, tile::Tile};
 
 pub fn pullautin_cull_formlines_render_contours(
     tile: &Tile,
     image_width: u32,
     image_height: u32,
     config: &Config,
+    symbol_styles: &SymbolStyles,
     avg_alt: &Vec<Vec<f64>>,
     smoothed_contours: Vec<(Vec<f64>, Vec<f64>, f64)>,
-) {
+) -> Result<(), CassiniError> {
     info!(
         "Tile min_x={} min_y={} max_x={} max_y={}. Culling formlines and rendering contours",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y
@@ -47,16 +36,17 @@ pub fn pullautin_cull_formlines_render_contours(
     let start = Instant::now();
 
     let scalefactor = 1.0;
-    let formlineaddition: f64 = 17.;
-    let minimumgap: u32 = 30;
-    let dashlength: f64 = 60.;
-    let gaplength: f64 = 12.;
+    let formlineaddition = config.form_lines.addition;
+    let minimumgap = config.form_lines.min_gap;
+    let dashlength = config.form_lines.dash_length;
+    let gaplength = config.form_lines.gap_length;
     let mut img = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
-    let formlinesteepness: f64 = 0.37;
-    let buffer_in_pixels = BUFFER as f32 * (config.dpi_resolution / INCH) as f32;
-    let indexcontours: f64 = 25.;
-    let contour_interval: f64 = 5.0;
-    let halfinterval = contour_interval / 2.0 * scalefactor;
+    let formlinesteepness = config.form_lines.steepness;
+    let pixels_per_meter = config.get_pixels_per_meter() as f64;
+    let buffer_in_pixels = BUFFER as f32 * pixels_per_meter as f32;
+    let indexcontours = config.contours.index_interval;
+    let contour_interval = config.contours.interval;
+    let halfinterval = config.contours.half_interval() * scalefactor;
 
     let dem_cell_size: f64 = 2.0;
     let xstart: f64 = (tile.min_x - BUFFER as i64) as f64;
@@ -178,44 +168,65 @@ pub fn pullautin_cull_formlines_render_contours(
     }
 
     let mut id: i32 = 0;
-    let contours_polylines_path = tile.render_dir_path.join("contours-raw").join("contours-raw.shp");
-
-    let contours_polylines_reader: shapefile::Reader<BufReader<File>, BufReader<File>> =
-        Reader::from_path(&contours_polylines_path).unwrap();
-
-    let table_info = contours_polylines_reader.into_table_info();
 
     let formlines_dir = tile.render_dir_path.join("formlines");
-    create_dir_all(&formlines_dir).expect("Could not create formlines dir");
+    create_dir_all(&formlines_dir)?;
 
-    let mut writer = Writer::from_path_with_info(formlines_dir.join("formlines.shp"), table_info).unwrap();
+    let mut writer = create_contours_shapefile_writer(&formlines_dir.join("formlines.shp"))?;
+
+    let mut rendered_contour_id: i32 = 0;
+    let mut rendered_contours_writer = create_rendered_contours_shapefile_writer(
+        &tile
+            .render_dir_path
+            .join("contours")
+            .join("rendered-contours.shp"),
+    )?;
 
     for smoothed_contour in smoothed_contours {
-        let color = if is_contour_depression(&smoothed_contour, xstart, ystart, avg_alt, dem_cell_size) {
-            PURPLE
-        } else {
-            BROWN
-        };
+        let is_depression = is_contour_depression(&smoothed_contour, xstart, ystart, avg_alt, dem_cell_size);
 
         let (x_array, y_array, elevation) = smoothed_contour;
         let mut x = Vec::<f64>::new();
         let mut y = Vec::<f64>::new();
 
         for i in 0..x_array.len() {
-            x.push((x_array[i] - x0) * 600.0 / 254.0 / scalefactor);
-            y.push((y0 - y_array[i]) * 600.0 / 254.0 / scalefactor);
+            x.push((x_array[i] - x0) * pixels_per_meter / scalefactor);
+            y.push((y0 - y_array[i]) * pixels_per_meter / scalefactor);
         }
 
         let mut curvew = 2.0;
 
-        if elevation.round() as isize % indexcontours as isize == 0 {
+        if is_multiple_of(elevation, indexcontours) {
             curvew = 3.5;
-        } else if elevation.round() as isize % contour_interval as isize != 0
-            && (elevation * 2.).round() as isize % (halfinterval * 2.).round() as isize == 0
-        {
+        } else if !is_multiple_of(elevation, contour_interval) && is_multiple_of(elevation, halfinterval) {
             curvew = 1.5;
         }
 
+        // The brush sizes are the ones of the default widths, scaled by the widths of the symbol styles
+        let (symbol_color, brush_scale) = if curvew == 3.5 {
+            (
+                symbol_styles.colors.index_contour,
+                symbol_styles.dimensions.index_contour_width / MASTER_CONTOUR_THICKNESS_MILLIMETTER,
+            )
+        } else if curvew == 1.5 {
+            (
+                symbol_styles.colors.form_line,
+                symbol_styles.dimensions.form_line_width / FORM_CONTOUR_THICKNESS_MILLIMETTER,
+            )
+        } else {
+            (
+                symbol_styles.colors.contour,
+                symbol_styles.dimensions.contour_width / CONTOUR_THICKNESS_MILLIMETTER,
+            )
+        };
+
+        let brush_width = curvew * brush_scale as f64;
+        let color = if is_depression {
+            get_rgba(symbol_styles.colors.depression_contour)
+        } else {
+            get_rgba(symbol_color)
+        };
+
         let mut smallringtest = false;
         let mut help = vec![false; x.len()];
         let mut help2 = vec![false; x.len()];
@@ -224,8 +235,8 @@ pub fn pullautin_cull_formlines_render_contours(
             for i in 0..x.len() {
                 help[i] = false;
                 help2[i] = true;
-                let xx = (((x[i] / 600.0 * 254.0 * scalefactor + x0) - xstart) / dem_cell_size).floor();
-                let yy = (((-y[i] / 600.0 * 254.0 * scalefactor + y0) - ystart) / dem_cell_size).floor();
+                let xx = (((x[i] / pixels_per_meter * scalefactor + x0) - xstart) / dem_cell_size).floor();
+                let yy = (((-y[i] / pixels_per_meter * scalefactor + y0) - ystart) / dem_cell_size).floor();
 
                 if curvew != 1.5
                     || &steepness[xx as usize][yy as usize] < &formlinesteepness
@@ -340,6 +351,7 @@ pub fn pullautin_cull_formlines_render_contours(
         let mut formlinestart = false;
 
         let mut current_formline: Vec<(f64, f64)> = vec![];
+        let mut rendered_strokes: Vec<Vec<(f64, f64)>> = vec![];
 
         for i in 1..x.len() {
             if curvew != 1.5 || help2[i] || smallringtest {
@@ -350,8 +362,8 @@ pub fn pullautin_cull_formlines_render_contours(
                     }
 
                     current_formline.push((
-                        x[i] / 600.0 * 254.0 * scalefactor + x0,
-                        -y[i] / 600.0 * 254.0 * scalefactor + y0,
+                        x[i] / pixels_per_meter * scalefactor + x0,
+                        -y[i] / pixels_per_meter * scalefactor + y0,
                     ));
                 }
 
@@ -390,10 +402,10 @@ pub fn pullautin_cull_formlines_render_contours(
                     if gap > 0.0 {
                         gap -= step;
                         if gap < 0.0 && onegapdone && step > 0.0 {
-                            let mut n = -curvew - 0.5;
-                            while n < curvew + 0.5 {
-                                let mut m = -curvew - 0.5;
-                                while m < curvew + 0.5 {
+                            let mut n = -brush_width - 0.5;
+                            while n < brush_width + 0.5 {
+                                let mut m = -brush_width - 0.5;
+                                while m < brush_width + 0.5 {
                                     let start = (
                                         ((-x[i - 1] * gap + (step + gap) * x[i]) / step + n) as f32
                                             - buffer_in_pixels,
@@ -412,13 +424,22 @@ pub fn pullautin_cull_formlines_render_contours(
                                 }
                                 n += 1.0;
                             }
+                            add_rendered_segment(
+                                &mut rendered_strokes,
+                                (
+                                    (-x[i - 1] * gap + (step + gap) * x[i]) / step,
+                                    (-y[i - 1] * gap + (step + gap) * y[i]) / step,
+                                ),
+                                (x[i], y[i]),
+                            );
+
                             gap = 0.0;
                         }
                     } else {
-                        let mut n = -curvew - 0.5;
-                        while n < curvew + 0.5 {
-                            let mut m = -curvew - 0.5;
-                            while m < curvew + 0.5 {
+                        let mut n = -brush_width - 0.5;
+                        while n < brush_width + 0.5 {
+                            let mut m = -brush_width - 0.5;
+                            while m < brush_width + 0.5 {
                                 let start = (
                                     (x[i - 1] + n) as f32 - buffer_in_pixels,
                                     image_height as f32 + buffer_in_pixels + (y[i - 1] + m) as f32,
@@ -434,12 +455,14 @@ pub fn pullautin_cull_formlines_render_contours(
                             }
                             n += 1.0;
                         }
+
+                        add_rendered_segment(&mut rendered_strokes, (x[i - 1], y[i - 1]), (x[i], y[i]));
                     }
                 } else {
-                    let mut n = -curvew;
-                    while n < curvew {
-                        let mut m = -curvew;
-                        while m < curvew {
+                    let mut n = -brush_width;
+                    while n < brush_width {
+                        let mut m = -brush_width;
+                        while m < brush_width {
                             let start = (
                                 (x[i - 1] + n) as f32 - buffer_in_pixels,
                                 image_height as f32 + buffer_in_pixels + (y[i - 1] + m) as f32,
@@ -455,6 +478,8 @@ pub fn pullautin_cull_formlines_render_contours(
                         }
                         n += 1.0;
                     }
+
+                    add_rendered_segment(&mut rendered_strokes, (x[i - 1], y[i - 1]), (x[i], y[i]));
                 }
             } else if formlinestart {
                 write_formline_shape_to_shapefile(&current_formline, id, elevation, &mut writer);
@@ -467,11 +492,38 @@ pub fn pullautin_cull_formlines_render_contours(
             write_formline_shape_to_shapefile(&current_formline, id, elevation, &mut writer);
             id += 1;
         }
+
+        let kind = if curvew == 3.5 {
+            "index"
+        } else if curvew == 1.5 {
+            "formline"
+        } else {
+            "contour"
+        };
+
+        for stroke in rendered_strokes {
+            let points: Vec<Point> = stroke
+                .iter()
+                .map(|(stroke_x, stroke_y)| Point {
+                    x: stroke_x / pixels_per_meter * scalefactor + x0,
+                    y: -stroke_y / pixels_per_meter * scalefactor + y0,
+                })
+                .collect();
+
+            let record = RenderedContourRecord {
+                id: rendered_contour_id,
+                elev: elevation,
+                kind: kind.to_string(),
+                depression: if is_depression { 1 } else { 0 },
+            };
+
+            let _ = rendered_contours_writer.write_shape_and_record(&GenericPolyline::new(points), &record);
+            rendered_contour_id += 1;
+        }
     }
 
     // TODO: img.save takes 8 seconds, maybe mutualize with other images saving
-    img.save(tile.render_dir_path.join("contours.png"))
-        .expect("could not save output png");
+    img.save(tile.render_dir_path.join("contours.png"))?;
 
     let duration = start.elapsed();
 
@@ -479,6 +531,60 @@ pub fn pullautin_cull_formlines_render_contours(
         "Tile min_x={} min_y={} max_x={} max_y={}. Formlines and contours generated in {:.1?}",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
     );
+
+    Ok(())
+}
+
+/// Elevations and intervals can be decimal (2.5 m contours), so the check is done with a tolerance.
+fn is_multiple_of(elevation: f64, interval: f64) -> bool {
+    let quotient = elevation / interval;
+    (quotient - quotient.round()).abs() < 1e-6
+}
+
+/// The attributes of the rendered contours shapefile, used by the vector map outputs
+struct RenderedContourRecord {
+    id: i32,
+    elev: f64,
+    /// "contour", "index" or "formline"
+    kind: String,
+    depression: i32,
+}
+
+impl WritableRecord for RenderedContourRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.elev)?;
+        field_writer.write_next_field_value(&self.kind)?;
+        field_writer.write_next_field_value(&self.depression)?;
+        Ok(())
+    }
+}
+
+fn create_rendered_contours_shapefile_writer(
+    path: &PathBuf,
+) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("elev").expect("Invalid field name"), 12, 3)
+        .add_character_field(FieldName::try_from("kind").expect("Invalid field name"), 10)
+        .add_integer_field(FieldName::try_from("depression").expect("Invalid field name"));
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+/// Collects the drawn segments of a contour into polylines, in pixels of the Pullautin image.
+fn add_rendered_segment(strokes: &mut Vec<Vec<(f64, f64)>>, start: (f64, f64), end: (f64, f64)) {
+    if let Some(stroke) = strokes.last_mut() {
+        if stroke.last() == Some(&start) {
+            stroke.push(end);
+            return;
+        }
+    }
+
+    strokes.push(vec![start, end]);
 }
 
 fn write_formline_shape_to_shapefile(
@@ -497,7 +603,7 @@ fn write_formline_shape_to_shapefile(
         points.push(Point { x: *x, y: *y });
     }
 
-    let record = FormLineRecord { id, elev: elevation };
+    let record = ContourRecord { id, elev: elevation };
 
     let smoothed_polyline = GenericPolyline::new(points);
     let _ = writer.write_shape_and_record(&smoothed_polyline, &record);
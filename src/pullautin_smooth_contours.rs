@@ -1,17 +1,92 @@
 use core::f64;
-use log::info;
-use shapefile::dbase::{FieldValue, Record};
+use log::{info, log_enabled, Level};
+use shapefile::dbase::{FieldIOError, FieldName, FieldWriter, TableWriterBuilder, WritableRecord};
 use shapefile::record::polyline::GenericPolyline;
-use shapefile::{Point, Polyline, Reader};
+use shapefile::{read_shapes_as, Point, Polygon, Writer};
 use std::fs::{create_dir_all, File};
-use std::io::BufReader;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
 use std::time::Instant;
-use tiff::decoder::{Decoder, DecodingResult};
 
-use crate::constants::BUFFER;
+use crate::config::ContoursConfig;
+use crate::constants::{BUFFER, DEM_NODATA_VALUE, MIN_SMOOTHED_CONTOUR_VERTICES};
+use crate::error::CassiniError;
+use crate::helpers::is_point_in_polygon;
+use crate::mosaic::create_raster_with_buffer;
+use crate::raster::{fill_nodata, FILL_NODATA_MAX_SEARCH_DISTANCE};
 use crate::tile::Tile;
 
-pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(Vec<f64>, Vec<f64>, f64)> {
+/// The attributes of the contours and formlines shapefiles
+pub struct ContourRecord {
+    pub id: i32,
+    pub elev: f64,
+}
+
+impl WritableRecord for ContourRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.elev)?;
+        Ok(())
+    }
+}
+
+pub fn create_contours_shapefile_writer(path: &PathBuf) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("elev").expect("Invalid field name"), 12, 3);
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+/// The attributes of the debug shapefile holding every intermediate smoothing pass
+struct SmoothingPassRecord {
+    id: i32,
+    elev: f64,
+    pass: i32,
+}
+
+impl WritableRecord for SmoothingPassRecord {
+    fn write_using<'a, W>(&self, field_writer: &mut FieldWriter<'a, W>) -> Result<(), FieldIOError>
+    where
+        W: Write,
+    {
+        field_writer.write_next_field_value(&self.id)?;
+        field_writer.write_next_field_value(&self.elev)?;
+        field_writer.write_next_field_value(&self.pass)?;
+        Ok(())
+    }
+}
+
+fn create_smoothing_passes_shapefile_writer(path: &PathBuf) -> Result<Writer<BufWriter<File>>, CassiniError> {
+    let table_builder = TableWriterBuilder::new()
+        .add_integer_field(FieldName::try_from("ID").expect("Invalid field name"))
+        .add_numeric_field(FieldName::try_from("elev").expect("Invalid field name"), 12, 3)
+        .add_integer_field(FieldName::try_from("pass").expect("Invalid field name"));
+
+    Ok(Writer::from_path(path, table_builder)?)
+}
+
+#[derive(Clone, Copy)]
+struct SmoothingParameters {
+    smoothing: f64,
+    curviness: f64,
+    window: usize,
+}
+
+struct SmoothingRegion {
+    polygons: Vec<Polygon>,
+    parameters: SmoothingParameters,
+}
+
+pub fn pullautin_smooth_contours(
+    tile: &Tile,
+    avg_alt: &Vec<Vec<f64>>,
+    contours: Vec<(Vec<f64>, Vec<f64>, f64)>,
+    contours_config: &ContoursConfig,
+) -> Result<Vec<(Vec<f64>, Vec<f64>, f64)>, CassiniError> {
     info!(
         "Tile min_x={} min_y={} max_x={} max_y={}. Smoothing contours",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y
@@ -19,8 +94,7 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
 
     let start = Instant::now();
 
-    let smoothing: f64 = 0.7;
-    let curviness: f64 = 1.1;
+    let smoothing_regions = load_smoothing_regions(contours_config)?;
 
     let size: f64 = 2.0;
     let xstart: f64 = (tile.min_x - BUFFER as i64) as f64;
@@ -53,49 +127,26 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
         }
     }
 
-    let contours_polylines_path = tile.render_dir_path.join("contours-raw").join("contours-raw.shp");
-
-    let mut contours_polylines_reader: shapefile::Reader<BufReader<File>, BufReader<File>> =
-        Reader::from_path(&contours_polylines_path).unwrap();
-
-    let contours_polylines_reader_for_table_info: shapefile::Reader<BufReader<File>, BufReader<File>> =
-        Reader::from_path(&contours_polylines_path).unwrap();
-
-    let table_info = contours_polylines_reader_for_table_info.into_table_info();
-
     let contours_dir = tile.render_dir_path.join("contours");
-    create_dir_all(&contours_dir).expect("Could not create contours dir");
+    create_dir_all(&contours_dir)?;
 
-    let mut writer =
-        shapefile::Writer::from_path_with_info(contours_dir.join("contours.shp"), table_info).unwrap();
+    let mut writer = create_contours_shapefile_writer(&contours_dir.join("contours.shp"))?;
 
-    for shape_record in contours_polylines_reader.iter_shapes_and_records_as::<Polyline, Record>() {
-        let (line, record) = shape_record.unwrap();
-        let mut x_array = Vec::<f64>::new();
-        let mut y_array = Vec::<f64>::new();
-
-        // Assuming line.parts().len() == 1
-        for part in line.parts() {
-            for point in part {
-                x_array.push(point.x);
-                y_array.push(point.y);
-            }
-        }
-
-        let elevation = match record.get("elev") {
-            Some(FieldValue::Numeric(Some(x))) => x,
-            Some(_) => &f64::NAN,
-            None => panic!("Field 'elev' is not within polygon-dataset"),
-        };
+    let mut passes_writer = if log_enabled!(Level::Debug) {
+        Some(create_smoothing_passes_shapefile_writer(
+            &contours_dir.join("contours-smoothing-passes.shp"),
+        )?)
+    } else {
+        None
+    };
 
+    for (id, (mut x_array, mut y_array, height)) in contours.into_iter().enumerate() {
         let mut el_x_len = x_array.len();
 
-        if el_x_len < 15 {
+        if el_x_len < MIN_SMOOTHED_CONTOUR_VERTICES {
             continue;
         }
 
-        let height = *elevation;
-
         if el_x_len > 101 {
             let mut newx: Vec<f64> = vec![];
             let mut newy: Vec<f64> = vec![];
@@ -134,26 +185,16 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
             el_x_len = x_array.len();
         }
         // Smoothing
-        let mut dx: Vec<f64> = vec![f64::NAN; el_x_len];
-        let mut dy: Vec<f64> = vec![f64::NAN; el_x_len];
-
-        for k in 2..(el_x_len - 3) {
-            dx[k] = (x_array[k - 2]
-                + x_array[k - 1]
-                + x_array[k]
-                + x_array[k + 1]
-                + x_array[k + 2]
-                + x_array[k + 3])
-                / 6.0;
-            dy[k] = (y_array[k - 2]
-                + y_array[k - 1]
-                + y_array[k]
-                + y_array[k + 1]
-                + y_array[k + 2]
-                + y_array[k + 3])
-                / 6.0;
+        let parameters = get_smoothing_parameters(&x_array, &y_array, contours_config, &smoothing_regions);
+        let smoothing = parameters.smoothing;
+
+        if let Some(passes_writer) = &mut passes_writer {
+            write_smoothing_pass(passes_writer, id, height, 0, &x_array, &y_array);
         }
 
+        let dx = get_moving_average(&x_array, parameters.window);
+        let dy = get_moving_average(&y_array, parameters.window);
+
         let mut xa: Vec<f64> = vec![f64::NAN; el_x_len];
         let mut ya: Vec<f64> = vec![f64::NAN; el_x_len];
         for k in 1..(el_x_len - 1) {
@@ -178,6 +219,11 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
             xa[el_x_len - 1] = x_array[el_x_len - 1];
             ya[el_x_len - 1] = y_array[el_x_len - 1];
         }
+
+        if let Some(passes_writer) = &mut passes_writer {
+            write_smoothing_pass(passes_writer, id, height, 1, &xa, &ya);
+        }
+
         for k in 1..(el_x_len - 1) {
             x_array[k] =
                 (xa[k - 1] + xa[k] / (0.01 + smoothing) + xa[k + 1]) / (2.0 + 1.0 / (0.01 + smoothing));
@@ -200,6 +246,10 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
             y_array[el_x_len - 1] = ya[el_x_len - 1];
         }
 
+        if let Some(passes_writer) = &mut passes_writer {
+            write_smoothing_pass(passes_writer, id, height, 2, &x_array, &y_array);
+        }
+
         for k in 1..(el_x_len - 1) {
             xa[k] = (x_array[k - 1] + x_array[k] / (0.01 + smoothing) + x_array[k + 1])
                 / (2.0 + 1.0 / (0.01 + smoothing));
@@ -227,32 +277,26 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
             y_array[k] = ya[k];
         }
 
-        let mut dx2: Vec<f64> = vec![f64::NAN; el_x_len];
-        let mut dy2: Vec<f64> = vec![f64::NAN; el_x_len];
-        for k in 2..(el_x_len - 3) {
-            dx2[k] = (x_array[k - 2]
-                + x_array[k - 1]
-                + x_array[k]
-                + x_array[k + 1]
-                + x_array[k + 2]
-                + x_array[k + 3])
-                / 6.0;
-            dy2[k] = (y_array[k - 2]
-                + y_array[k - 1]
-                + y_array[k]
-                + y_array[k + 1]
-                + y_array[k + 2]
-                + y_array[k + 3])
-                / 6.0;
+        if let Some(passes_writer) = &mut passes_writer {
+            write_smoothing_pass(passes_writer, id, height, 3, &x_array, &y_array);
         }
 
-        for k in 3..(el_x_len - 3) {
-            let vx = x_array[k] + (dx[k] - dx2[k]) * curviness;
-            let vy = y_array[k] + (dy[k] - dy2[k]) * curviness;
+        let dx2 = get_moving_average(&x_array, parameters.window);
+        let dy2 = get_moving_average(&y_array, parameters.window);
+
+        let (before, after) = get_moving_average_window_bounds(parameters.window);
+
+        for k in (before + 1)..el_x_len.saturating_sub(after) {
+            let vx = x_array[k] + (dx[k] - dx2[k]) * parameters.curviness;
+            let vy = y_array[k] + (dy[k] - dy2[k]) * parameters.curviness;
             x_array[k] = vx;
             y_array[k] = vy;
         }
 
+        if let Some(passes_writer) = &mut passes_writer {
+            write_smoothing_pass(passes_writer, id, height, 4, &x_array, &y_array);
+        }
+
         let mut points: Vec<Point> = vec![];
 
         for k in 0..el_x_len {
@@ -262,6 +306,11 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
             });
         }
 
+        let record = ContourRecord {
+            id: id as i32,
+            elev: height,
+        };
+
         let smoothed_polyline = GenericPolyline::new(points);
         let _ = writer.write_shape_and_record(&smoothed_polyline, &record);
         smoothed_contours.push((x_array, y_array, height));
@@ -274,33 +323,126 @@ pub fn pullautin_smooth_contours(tile: &Tile, avg_alt: &Vec<Vec<f64>>) -> Vec<(V
         tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
     );
 
-    return smoothed_contours;
+    return Ok(smoothed_contours);
 }
 
-pub fn get_elevation_matrix_from_dem(tile: &Tile) -> Vec<Vec<f64>> {
-    let dem_path = tile.render_dir_path.join("dem-low-resolution-with-buffer.tif");
-    let dem_tif_file = File::open(dem_path).expect("Cannot find dem tif image!");
+fn load_smoothing_regions(contours_config: &ContoursConfig) -> Result<Vec<SmoothingRegion>, CassiniError> {
+    let mut smoothing_regions: Vec<SmoothingRegion> = vec![];
+
+    for region_config in &contours_config.smoothing_regions {
+        smoothing_regions.push(SmoothingRegion {
+            polygons: read_shapes_as::<_, Polygon>(&region_config.mask)?,
+            parameters: SmoothingParameters {
+                smoothing: region_config.smoothing.unwrap_or(contours_config.smoothing),
+                curviness: region_config.curviness.unwrap_or(contours_config.curviness),
+                window: region_config
+                    .smoothing_window
+                    .unwrap_or(contours_config.smoothing_window),
+            },
+        });
+    }
 
-    let mut dem_img_decoder = Decoder::new(dem_tif_file).expect("Cannot create decoder");
-    dem_img_decoder = dem_img_decoder.with_limits(tiff::decoder::Limits::unlimited());
+    return Ok(smoothing_regions);
+}
 
-    let (dem_width, dem_height) = dem_img_decoder.dimensions().unwrap();
+/// A contour is smoothed with the parameters of the first region containing most of its vertices,
+/// or with the default parameters if there is none.
+fn get_smoothing_parameters(
+    x_array: &Vec<f64>,
+    y_array: &Vec<f64>,
+    contours_config: &ContoursConfig,
+    smoothing_regions: &Vec<SmoothingRegion>,
+) -> SmoothingParameters {
+    for region in smoothing_regions {
+        let number_of_vertices_inside = x_array
+            .iter()
+            .zip(y_array.iter())
+            .filter(|(x, y)| {
+                region
+                    .polygons
+                    .iter()
+                    .any(|polygon| is_point_in_polygon(**x, **y, polygon))
+            })
+            .count();
+
+        if number_of_vertices_inside * 2 > x_array.len() {
+            return region.parameters;
+        }
+    }
 
-    let width: usize = dem_width as usize;
-    let height: usize = dem_height as usize;
-    let mut avg_alt = vec![vec![f64::NAN; height + 2]; width + 2];
+    return SmoothingParameters {
+        smoothing: contours_config.smoothing,
+        curviness: contours_config.curviness,
+        window: contours_config.smoothing_window,
+    };
+}
 
-    let DecodingResult::F64(image_data) = dem_img_decoder.read_image().unwrap() else {
-        panic!("Cannot read band data")
+/// The number of points before and after the current one in the moving average window.
+/// For an even window, the extra point is taken after, like the original 6 points window of Pullautin.
+fn get_moving_average_window_bounds(window: usize) -> (usize, usize) {
+    let before = (window - 1) / 2;
+    return (before, window - 1 - before);
+}
+
+fn get_moving_average(array: &Vec<f64>, window: usize) -> Vec<f64> {
+    let (before, after) = get_moving_average_window_bounds(window);
+    let mut averages = vec![f64::NAN; array.len()];
+
+    for k in before..array.len().saturating_sub(after) {
+        averages[k] = array[(k - before)..=(k + after)].iter().sum::<f64>() / window as f64;
+    }
+
+    return averages;
+}
+
+fn write_smoothing_pass(
+    writer: &mut Writer<BufWriter<File>>,
+    id: usize,
+    elev: f64,
+    pass: i32,
+    x_array: &Vec<f64>,
+    y_array: &Vec<f64>,
+) {
+    let points: Vec<Point> = x_array
+        .iter()
+        .zip(y_array.iter())
+        .map(|(x, y)| Point { x: *x, y: *y })
+        .collect();
+
+    let record = SmoothingPassRecord {
+        id: id as i32,
+        elev,
+        pass,
     };
 
+    let _ = writer.write_shape_and_record(&GenericPolyline::new(points), &record);
+}
+
+pub fn get_elevation_matrix_from_dem(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+) -> Result<Vec<Vec<f64>>, CassiniError> {
+    let mut dem = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "dem-low-resolution",
+        2.0,
+        DEM_NODATA_VALUE,
+    )?;
+    fill_nodata(&mut dem, FILL_NODATA_MAX_SEARCH_DISTANCE);
+
+    let width = dem.width;
+    let height = dem.height;
+    let mut avg_alt = vec![vec![f64::NAN; height + 2]; width + 2];
+
     // Building avg_alt matrix and defining hmin and hmax
-    for index in 0..image_data.len() {
+    for index in 0..dem.data.len() {
         let x = index % width;
         let y = height - index / width;
-        let elevation = image_data[index] as f64;
+        let elevation = dem.data[index];
         avg_alt[x][y] = elevation;
     }
 
-    return avg_alt;
+    return Ok(avg_alt);
 }
@@ -0,0 +1,261 @@
+use crate::constants::DEM_NODATA_VALUE;
+
+/// Default search distance of gdal_fillnodata, in pixels
+pub const FILL_NODATA_MAX_SEARCH_DISTANCE: usize = 100;
+
+/// A north-up raster, stored row by row from north to south.
+pub struct Raster<T = f64> {
+    pub width: usize,
+    pub height: usize,
+    /// Coordinates of the top left corner of the top left pixel
+    pub left: f64,
+    pub top: f64,
+    /// Size of a pixel in meters
+    pub cell_size: f64,
+    pub data: Vec<T>,
+}
+
+impl<T: Copy> Raster<T> {
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self.data[y * self.width + x]
+    }
+
+    /// Returns the (x, y) indices of the pixel containing a point, if it is inside the raster.
+    pub fn get_pixel_at(&self, x: f64, y: f64) -> Option<(usize, usize)> {
+        let column = ((x - self.left) / self.cell_size).floor();
+        let row = ((self.top - y) / self.cell_size).floor();
+
+        if column < 0. || row < 0. || column >= self.width as f64 || row >= self.height as f64 {
+            return None;
+        }
+
+        return Some((column as usize, row as usize));
+    }
+
+    /// Returns the coordinates of the center of a pixel.
+    pub fn get_pixel_center(&self, x: usize, y: usize) -> (f64, f64) {
+        (
+            self.left + (x as f64 + 0.5) * self.cell_size,
+            self.top - (y as f64 + 0.5) * self.cell_size,
+        )
+    }
+}
+
+pub fn is_nodata(value: f64) -> bool {
+    value.is_nan() || value == DEM_NODATA_VALUE
+}
+
+/// Fills the nodata pixels by inverse distance weighting of the closest valid pixels found in the four
+/// directions (left, right, up and down), within max_search_distance pixels.
+/// Pixels with no valid pixel in reach stay nodata.
+pub fn fill_nodata(raster: &mut Raster, max_search_distance: usize) {
+    let width = raster.width;
+    let height = raster.height;
+    let mut weighted_sums = vec![0.; raster.data.len()];
+    let mut weights = vec![0.; raster.data.len()];
+
+    let mut add_neighbor = |index: usize, last_valid: Option<(usize, f64)>, position: usize| {
+        if let Some((last_position, value)) = last_valid {
+            let distance = position.abs_diff(last_position);
+
+            if distance <= max_search_distance {
+                let weight = 1. / (distance * distance) as f64;
+                weighted_sums[index] += value * weight;
+                weights[index] += weight;
+            }
+        }
+    };
+
+    for y in 0..height {
+        for direction in [false, true] {
+            let mut last_valid: Option<(usize, f64)> = None;
+
+            for step in 0..width {
+                let x = if direction { width - 1 - step } else { step };
+                let index = y * width + x;
+                let value = raster.data[index];
+
+                if is_nodata(value) {
+                    add_neighbor(index, last_valid, x);
+                } else {
+                    last_valid = Some((x, value));
+                }
+            }
+        }
+    }
+
+    for x in 0..width {
+        for direction in [false, true] {
+            let mut last_valid: Option<(usize, f64)> = None;
+
+            for step in 0..height {
+                let y = if direction { height - 1 - step } else { step };
+                let index = y * width + x;
+                let value = raster.data[index];
+
+                if is_nodata(value) {
+                    add_neighbor(index, last_valid, y);
+                } else {
+                    last_valid = Some((y, value));
+                }
+            }
+        }
+    }
+
+    for index in 0..raster.data.len() {
+        if weights[index] > 0. {
+            raster.data[index] = weighted_sums[index] / weights[index];
+        }
+    }
+}
+
+/// Computes the slope in degrees of every pixel with Horn's method, like gdaldem slope.
+/// Edge pixels, and pixels next to a nodata pixel, are set to nodata.
+pub fn compute_slopes(raster: &Raster) -> Raster {
+    let width = raster.width;
+    let height = raster.height;
+    let mut slopes = vec![DEM_NODATA_VALUE; width * height];
+
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            if let Some((dz_dx, dz_dy)) = get_horn_gradient(raster, x, y) {
+                slopes[y * width + x] = (dz_dx * dz_dx + dz_dy * dz_dy).sqrt().atan().to_degrees();
+            }
+        }
+    }
+
+    Raster {
+        width,
+        height,
+        left: raster.left,
+        top: raster.top,
+        cell_size: raster.cell_size,
+        data: slopes,
+    }
+}
+
+/// Computes the elevation gradient of a pixel with Horn's method, as (dz/dx eastward, dz/dy southward).
+/// Returns None for edge pixels and pixels next to a nodata pixel.
+pub fn get_horn_gradient(raster: &Raster, x: usize, y: usize) -> Option<(f64, f64)> {
+    if x == 0 || y == 0 || x + 1 >= raster.width || y + 1 >= raster.height {
+        return None;
+    }
+
+    let a = raster.get(x - 1, y - 1);
+    let b = raster.get(x, y - 1);
+    let c = raster.get(x + 1, y - 1);
+    let d = raster.get(x - 1, y);
+    let e = raster.get(x, y);
+    let f = raster.get(x + 1, y);
+    let g = raster.get(x - 1, y + 1);
+    let h = raster.get(x, y + 1);
+    let i = raster.get(x + 1, y + 1);
+
+    if [a, b, c, d, e, f, g, h, i].iter().any(|value| is_nodata(*value)) {
+        return None;
+    }
+
+    let dz_dx = ((c + 2. * f + i) - (a + 2. * d + g)) / (8. * raster.cell_size);
+    let dz_dy = ((g + 2. * h + i) - (a + 2. * b + c)) / (8. * raster.cell_size);
+
+    return Some((dz_dx, dz_dy));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_raster(
+        width: usize,
+        height: usize,
+        cell_size: f64,
+        get_value: impl Fn(f64, f64) -> f64,
+    ) -> Raster {
+        let mut raster = Raster {
+            width,
+            height,
+            left: 1000.,
+            top: 2000.,
+            cell_size,
+            data: vec![],
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let (easting, northing) = raster.get_pixel_center(x, y);
+                raster.data.push(get_value(easting, northing));
+            }
+        }
+
+        return raster;
+    }
+
+    #[test]
+    fn slope_of_planar_ramp() {
+        // 0.3 m per meter eastward, 0.4 m per meter northward: 0.5 m per meter down the slope
+        let raster = get_raster(6, 5, 2., |easting, northing| 0.3 * easting + 0.4 * northing);
+        let slopes = compute_slopes(&raster);
+        let expected_slope = 0.5_f64.atan().to_degrees();
+
+        for y in 0..raster.height {
+            for x in 0..raster.width {
+                let slope = slopes.get(x, y);
+
+                if x == 0 || y == 0 || x == raster.width - 1 || y == raster.height - 1 {
+                    assert!(is_nodata(slope));
+                } else {
+                    assert!((slope - expected_slope).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hole_in_constant_dem_is_filled_with_constant() {
+        let mut raster = get_raster(7, 7, 1., |_, _| 42.);
+
+        for y in 2..5 {
+            for x in 1..5 {
+                raster.data[y * raster.width + x] = DEM_NODATA_VALUE;
+            }
+        }
+
+        raster.data[3 * raster.width + 3] = f64::NAN;
+
+        fill_nodata(&mut raster, FILL_NODATA_MAX_SEARCH_DISTANCE);
+
+        assert!(raster.data.iter().all(|value| (value - 42.).abs() < 1e-9));
+    }
+
+    #[test]
+    fn slope_of_planar_ramp_with_filled_holes() {
+        // The ramp of slope_of_planar_ramp, on a finer grid
+        let get_elevation = |easting: f64, northing: f64| 0.3 * easting + 0.4 * northing;
+        let mut raster = get_raster(9, 9, 1., get_elevation);
+
+        // Single pixel holes are filled with the mean of their four neighbors, exact on a plane
+        let holes = [(4, 4), (2, 6), (6, 2)];
+
+        for (x, y) in holes {
+            raster.data[y * raster.width + x] = DEM_NODATA_VALUE;
+        }
+
+        assert!(is_nodata(compute_slopes(&raster).get(4, 4)));
+
+        fill_nodata(&mut raster, FILL_NODATA_MAX_SEARCH_DISTANCE);
+
+        for (x, y) in holes {
+            let (easting, northing) = raster.get_pixel_center(x, y);
+            assert!((raster.get(x, y) - get_elevation(easting, northing)).abs() < 1e-9);
+        }
+
+        let slopes = compute_slopes(&raster);
+        let expected_slope = 0.5_f64.atan().to_degrees();
+
+        for y in 1..raster.height - 1 {
+            for x in 1..raster.width - 1 {
+                assert!((slopes.get(x, y) - expected_slope).abs() < 1e-9);
+            }
+        }
+    }
+}
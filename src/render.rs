@@ -1,18 +1,28 @@
-use crate::constants::INCH;
+use crate::command::{run_command, DEFAULT_COMMAND_TIMEOUT};
 use crate::contours::generate_contours_with_pullautin_algorithme;
+use crate::crs::create_prj_file;
 use crate::download::download_osm_file;
-use crate::helpers::{remove_dir_content, remove_if_exists};
-use crate::tile::TileWithNeighbors;
+use crate::error::CassiniError;
+use crate::helpers::remove_dir_content;
+use crate::vector_map::VectorMapFormat;
 use crate::vectors::render_map_with_osm_vector_shapes;
 use crate::world_file::create_world_file;
 use crate::UndergrowthMode;
 use crate::{
-    cliffs::render_cliffs, config::get_config, dem::create_dem_with_buffer_and_slopes_tiff, tile::Tile,
+    buildings::detect_buildings,
+    cliffs::vectorize_cliffs,
+    config::{get_config, Config},
+    land_cover::detect_water_and_paved_areas,
+    micro_relief::detect_micro_relief,
+    print_layout::generate_print_layout,
+    streams::extract_streams,
+    symbol_rules::{get_symbol_rules, SymbolRule},
+    symbol_styles::{get_symbol_styles, SymbolStyles},
+    tile::Tile,
     vegetation::render_vegetation,
 };
-use log::{error, info};
+use log::info;
 use std::path::PathBuf;
-use std::process::{Command, ExitStatus};
 use std::time::Instant;
 
 pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
@@ -20,12 +30,17 @@ pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
     neighbor_tiles: Vec<PathBuf>,
     skip_vector: bool,
     skip_520: bool,
+    symbol_rules_path: Option<PathBuf>,
+    symbol_styles_path: Option<PathBuf>,
     undergrowth_mode: &UndergrowthMode,
     shapefiles_dir: Option<PathBuf>,
-) {
-    let config = get_config();
-    let image_width = ((tile.max_x - tile.min_x) as f32 * config.dpi_resolution / INCH) as u32;
-    let image_height = ((tile.max_y - tile.min_y) as f32 * config.dpi_resolution / INCH) as u32;
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
+    let config = get_config()?;
+    let symbol_rules = get_symbol_rules(&symbol_rules_path)?;
+    let symbol_styles = get_symbol_styles(&symbol_styles_path, config.symbol_set, config.get_map_scale())?;
+    let (image_width, image_height) = get_image_size(&tile, &config);
 
     render_vegetation(
         &tile,
@@ -33,13 +48,155 @@ pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
         image_width,
         image_height,
         &config,
+        &symbol_styles,
         undergrowth_mode,
+    )?;
+
+    generate_contours_with_pullautin_algorithme(
+        &tile,
+        &neighbor_tiles,
+        image_width,
+        image_height,
+        &config,
+        &symbol_styles,
+    )?;
+    vectorize_cliffs(&tile, &neighbor_tiles, &config)?;
+    detect_micro_relief(&tile, &neighbor_tiles, &config)?;
+    extract_streams(&tile, &neighbor_tiles, &config)?;
+    detect_water_and_paved_areas(&tile, &neighbor_tiles, &config)?;
+    detect_buildings(&tile, &neighbor_tiles, &config)?;
+
+    let shapes_path: Option<PathBuf> = if shapefiles_dir.is_some() {
+        shapefiles_dir
+    } else if !skip_vector {
+        Some(generate_shapefiles_from_osm_data(&tile)?)
+    } else {
+        None
+    };
+
+    render_full_map(
+        &tile,
+        image_width,
+        image_height,
+        &config,
+        skip_520,
+        &symbol_rules,
+        &symbol_styles,
+        shapes_path,
+        vector_map_format,
+        omap,
+    )?;
+
+    if config.print_layout.enabled {
+        generate_print_layout(
+            &tile.render_dir_path.join("full-map.png"),
+            (tile.min_x, tile.min_y, tile.max_x, tile.max_y),
+            &tile.crs,
+            &vec![tile.render_dir_path.clone()],
+            &config,
+            &symbol_styles,
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn get_image_size(tile: &Tile, config: &Config) -> (u32, u32) {
+    let pixels_per_meter = config.get_pixels_per_meter();
+    let image_width = ((tile.max_x - tile.min_x) as f32 * pixels_per_meter) as u32;
+    let image_height = ((tile.max_y - tile.min_y) as f32 * pixels_per_meter) as u32;
+
+    return (image_width, image_height);
+}
+
+/// Downloads the OSM data of the tile (if not already downloaded) and converts it to shapefiles.
+/// Returns the path of the shapefiles directory.
+pub fn generate_shapefiles_from_osm_data(tile: &Tile) -> Result<PathBuf, CassiniError> {
+    let shapes_output_path = tile.render_dir_path.join("shapes");
+
+    if shapes_output_path.exists() {
+        remove_dir_content(&shapes_output_path)?;
+    }
+
+    let osm_path = tile
+        .render_dir_path
+        .join(format!("{:0>7}_{:0>7}.osm", tile.min_x, tile.max_y));
+
+    if !osm_path.exists() {
+        download_osm_file(
+            tile.min_x,
+            tile.min_y,
+            tile.max_x,
+            tile.max_y,
+            &tile.crs,
+            &tile.render_dir_path.to_path_buf(),
+        )?;
+    }
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. Transforming osm file to shapefiles",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y
     );
 
-    create_dem_with_buffer_and_slopes_tiff(&tile, &neighbor_tiles);
-    generate_contours_with_pullautin_algorithme(&tile, image_width, image_height, &config);
-    render_cliffs(&tile, image_width, image_height, &config);
+    run_command(
+        "ogr2ogr",
+        &[
+            "--config",
+            "OSM_USE_CUSTOM_INDEXING",
+            "NO",
+            "-f",
+            "ESRI Shapefile",
+            &shapes_output_path.to_str().unwrap(),
+            &osm_path.to_str().unwrap(),
+            "-t_srs",
+            &tile.crs,
+            "-nlt",
+            "MULTIPOLYGON",
+            "-sql",
+            "SELECT * FROM multipolygons",
+            "--quiet",
+        ],
+        None,
+        DEFAULT_COMMAND_TIMEOUT,
+    )?;
+
+    run_command(
+        "ogr2ogr",
+        &[
+            "--config",
+            "OSM_USE_CUSTOM_INDEXING",
+            "NO",
+            "-f",
+            "ESRI Shapefile",
+            &shapes_output_path.to_str().unwrap(),
+            &osm_path.to_str().unwrap(),
+            "-t_srs",
+            &tile.crs,
+            "-nlt",
+            "LINESTRING",
+            "-sql",
+            "SELECT * FROM lines",
+            "--quiet",
+        ],
+        None,
+        DEFAULT_COMMAND_TIMEOUT,
+    )?;
+
+    return Ok(shapes_output_path);
+}
 
+pub fn render_full_map(
+    tile: &Tile,
+    image_width: u32,
+    image_height: u32,
+    config: &Config,
+    skip_520: bool,
+    symbol_rules: &Vec<SymbolRule>,
+    symbol_styles: &SymbolStyles,
+    shapes_path: Option<PathBuf>,
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
     info!(
         "Tile min_x={} min_y={} max_x={} max_y={}. Rendering map to png",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y
@@ -47,106 +204,10 @@ pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
 
     let start = Instant::now();
 
-    let cliffs_path = tile.render_dir_path.join("cliffs.png");
     let vegetation_path = tile.render_dir_path.join("vegetation.png");
     let undergrowth_path = tile.render_dir_path.join("undergrowth.png");
     let contours_path = tile.render_dir_path.join("contours.png");
 
-    let shapes_path: Option<PathBuf> = if shapefiles_dir.is_some() {
-        shapefiles_dir
-    } else if !skip_vector {
-        let shapes_output_path = tile.render_dir_path.join("shapes");
-
-        if shapes_output_path.exists() {
-            remove_dir_content(&shapes_output_path).unwrap();
-        }
-
-        let osm_path = tile
-            .render_dir_path
-            .join(format!("{:0>7}_{:0>7}.osm", tile.min_x, tile.max_y));
-
-        if !osm_path.exists() {
-            download_osm_file(
-                tile.min_x,
-                tile.min_y,
-                tile.max_x,
-                tile.max_y,
-                &tile.render_dir_path.to_path_buf(),
-            );
-        }
-
-        info!(
-            "Tile min_x={} min_y={} max_x={} max_y={}. Transforming osm file to shapefiles",
-            tile.min_x, tile.min_y, tile.max_x, tile.max_y
-        );
-
-        let ogr2ogr_output = Command::new("ogr2ogr")
-            .args([
-                "--config",
-                "OSM_USE_CUSTOM_INDEXING",
-                "NO",
-                "-f",
-                "ESRI Shapefile",
-                &shapes_output_path.to_str().unwrap(),
-                &osm_path.to_str().unwrap(),
-                "-t_srs",
-                "EPSG:2154",
-                "-nlt",
-                "MULTIPOLYGON",
-                "-sql",
-                "SELECT * FROM multipolygons",
-            ])
-            .arg("--quiet")
-            .output()
-            .expect("failed to execute ogr2ogr command");
-
-        if !ExitStatus::success(&ogr2ogr_output.status) {
-            error!(
-                "Tile min_x={} min_y={} max_x={} max_y={}. Ogr2ogr command failed {:?}",
-                tile.min_x,
-                tile.min_y,
-                tile.max_x,
-                tile.max_y,
-                String::from_utf8(ogr2ogr_output.stderr).unwrap()
-            );
-        }
-
-        let ogr2ogr_output = Command::new("ogr2ogr")
-            .args([
-                "--config",
-                "OSM_USE_CUSTOM_INDEXING",
-                "NO",
-                "-f",
-                "ESRI Shapefile",
-                &shapes_output_path.to_str().unwrap(),
-                &osm_path.to_str().unwrap(),
-                "-t_srs",
-                "EPSG:2154",
-                "-nlt",
-                "LINESTRING",
-                "-sql",
-                "SELECT * FROM lines",
-            ])
-            .arg("--quiet")
-            .output()
-            .expect("failed to execute ogr2ogr command");
-
-        if !ExitStatus::success(&ogr2ogr_output.status) {
-            error!(
-                "Tile min_x={} min_y={} max_x={} max_y={}. Ogr2ogr command failed {:?}",
-                tile.min_x,
-                tile.min_y,
-                tile.max_x,
-                tile.max_y,
-                String::from_utf8(ogr2ogr_output.stderr).unwrap()
-            );
-        }
-
-        Some(shapes_output_path)
-    } else {
-        None
-    };
-
     render_map_with_osm_vector_shapes(
         &tile,
         image_width,
@@ -155,16 +216,19 @@ pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
         &vegetation_path,
         &undergrowth_path,
         &contours_path,
-        &cliffs_path,
         skip_520,
+        symbol_rules,
+        symbol_styles,
         shapes_path,
-    );
+        vector_map_format,
+        omap,
+    )?;
 
-    let resolution = INCH / (config.dpi_resolution);
+    let resolution = 1. / config.get_pixels_per_meter();
     let world_file_path = tile.render_dir_path.join("full-map.pgw");
 
-    create_world_file(tile.min_x as f32, tile.max_y as f32, resolution, &world_file_path)
-        .expect("Could not create world file");
+    create_world_file(tile.min_x as f32, tile.max_y as f32, resolution, &world_file_path)?;
+    create_prj_file(&tile.crs, &tile.render_dir_path.join("full-map.prj"))?;
 
     let duration = start.elapsed();
 
@@ -172,43 +236,6 @@ pub fn generate_png_from_dem_vegetation_density_tiff_images_and_vector_file(
         "Tile min_x={} min_y={} max_x={} max_y={}. Map rendered to png in {:.1?}",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
     );
-}
 
-const RENDER_STEP_FILES: [&str; 16] = [
-    "cliffs.png",
-    "contours",
-    "contours.png",
-    "contours-raw",
-    "dem-low-resolution-with-buffer.tif",
-    "dem-with-buffer.tif",
-    "formlines",
-    "full-map.pgw",
-    "full-map.png",
-    "high-vegetation-with-buffer.tif",
-    "low-vegetation-with-buffer.tif",
-    "medium-vegetation-with-buffer.tif",
-    "shapes",
-    "slopes.tif",
-    "undergrowth.png",
-    "vegetation.png",
-];
-
-pub fn cleanup_render_step_files(tiles: &Vec<TileWithNeighbors>, output_dir: &str) {
-    for tile in tiles {
-        cleanup_render_step_files_for_single_tile(tile);
-    }
-
-    if let Ok(entries) = std::fs::read_dir(output_dir) {
-        for entry in entries.flatten() {
-            if entry.file_name().to_string_lossy().starts_with("merged-map") {
-                let _ = std::fs::remove_file(entry.path());
-            }
-        }
-    }
-}
-
-pub fn cleanup_render_step_files_for_single_tile(tile: &TileWithNeighbors) {
-    for path in RENDER_STEP_FILES {
-        let _ = remove_if_exists(tile.tile.render_dir_path.join(path));
-    }
+    Ok(())
 }
@@ -0,0 +1,247 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{Condvar, Mutex},
+};
+
+use crate::tile::TileWithNeighbors;
+
+pub enum Job {
+    Lidar(usize),
+    Render(usize),
+}
+
+struct SchedulerQueue {
+    pending_lidar_jobs: VecDeque<usize>,
+    pending_render_jobs: Vec<usize>,
+    is_lidar_finished: Vec<bool>,
+}
+
+/// A queue shared by the batch threads. Threads pull LiDAR and render jobs from it,
+/// a tile being ready to render as soon as its LiDAR step and the ones of its neighbors are finished.
+pub struct TileScheduler {
+    queue: Mutex<SchedulerQueue>,
+    condvar: Condvar,
+    /// For every tile, the indexes of the tiles whose LiDAR step must be finished before rendering it
+    render_dependencies: Vec<Vec<usize>>,
+}
+
+impl TileScheduler {
+    pub fn new(tiles: &Vec<TileWithNeighbors>, skip_lidar: bool) -> TileScheduler {
+        let tile_indexes: HashMap<&PathBuf, usize> = tiles
+            .iter()
+            .enumerate()
+            .map(|(index, tile)| (&tile.tile.lidar_dir_path, index))
+            .collect();
+
+        let render_dependencies: Vec<Vec<usize>> = tiles
+            .iter()
+            .enumerate()
+            .map(|(index, tile)| {
+                let mut dependencies = vec![index];
+
+                for neighbor in &tile.neighbors {
+                    if let Some(neighbor_index) = tile_indexes.get(neighbor) {
+                        dependencies.push(*neighbor_index);
+                    }
+                }
+
+                dependencies
+            })
+            .collect();
+
+        // Processing tiles row by row, from north to south, so that neighbors are finished close in time
+        let mut ordered_indexes: Vec<usize> = (0..tiles.len()).collect();
+        ordered_indexes.sort_by_key(|index| (-tiles[*index].tile.max_y, tiles[*index].tile.min_x));
+
+        let pending_lidar_jobs = if skip_lidar {
+            VecDeque::new()
+        } else {
+            ordered_indexes.iter().cloned().collect()
+        };
+
+        TileScheduler {
+            queue: Mutex::new(SchedulerQueue {
+                pending_lidar_jobs,
+                pending_render_jobs: ordered_indexes,
+                is_lidar_finished: vec![skip_lidar; tiles.len()],
+            }),
+            condvar: Condvar::new(),
+            render_dependencies,
+        }
+    }
+
+    /// Blocks until a job is available. Returns None when every job has been taken.
+    pub fn next_job(&self) -> Option<Job> {
+        let mut queue = self.queue.lock().unwrap();
+
+        loop {
+            let ready_render_job_position = queue.pending_render_jobs.iter().position(|tile_index| {
+                self.render_dependencies[*tile_index]
+                    .iter()
+                    .all(|dependency| queue.is_lidar_finished[*dependency])
+            });
+
+            if let Some(position) = ready_render_job_position {
+                return Some(Job::Render(queue.pending_render_jobs.remove(position)));
+            }
+
+            if let Some(tile_index) = queue.pending_lidar_jobs.pop_front() {
+                return Some(Job::Lidar(tile_index));
+            }
+
+            if queue.pending_render_jobs.is_empty() {
+                return None;
+            }
+
+            // Render jobs are waiting for LiDAR jobs running on other threads
+            queue = self.condvar.wait(queue).unwrap();
+        }
+    }
+
+    /// Marks the LiDAR step of a tile as finished, whether it succeeded or not.
+    pub fn finish_lidar_job(&self, tile_index: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.is_lidar_finished[tile_index] = true;
+        self.condvar.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::Tile;
+    use std::{thread::sleep, time::Duration};
+
+    /// A grid of tiles of 1000 meters, each with its up to 8 neighbors.
+    fn get_tiles(columns: i64, rows: i64) -> Vec<TileWithNeighbors> {
+        let get_lidar_dir_path = |column: i64, row: i64| PathBuf::from(format!("lidar/{}_{}", column, row));
+        let mut tiles: Vec<TileWithNeighbors> = vec![];
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let mut neighbors: Vec<PathBuf> = vec![];
+
+                for (offset_column, offset_row) in [
+                    (-1, -1),
+                    (0, -1),
+                    (1, -1),
+                    (-1, 0),
+                    (1, 0),
+                    (-1, 1),
+                    (0, 1),
+                    (1, 1),
+                ] {
+                    let (neighbor_column, neighbor_row) = (column + offset_column, row + offset_row);
+
+                    if neighbor_column >= 0
+                        && neighbor_row >= 0
+                        && neighbor_column < columns
+                        && neighbor_row < rows
+                    {
+                        neighbors.push(get_lidar_dir_path(neighbor_column, neighbor_row));
+                    }
+                }
+
+                tiles.push(TileWithNeighbors {
+                    laz_path: PathBuf::from(format!("in/{}_{}.laz", column, row)),
+                    tile: Tile {
+                        lidar_dir_path: get_lidar_dir_path(column, row),
+                        render_dir_path: PathBuf::from(format!("render/{}_{}", column, row)),
+                        min_x: column * 1000,
+                        min_y: row * 1000,
+                        max_x: (column + 1) * 1000,
+                        max_y: (row + 1) * 1000,
+                        crs: "EPSG:2154".to_owned(),
+                    },
+                    neighbors,
+                });
+            }
+        }
+
+        return tiles;
+    }
+
+    #[test]
+    fn tiles_are_rendered_once_their_neighbors_lidar_is_finished() {
+        let tiles = get_tiles(3, 3);
+        let scheduler = TileScheduler::new(&tiles, false);
+        let mut is_lidar_finished = vec![false; tiles.len()];
+        let mut is_rendered = vec![false; tiles.len()];
+        let mut number_of_lidar_jobs = 0;
+
+        while let Some(job) = scheduler.next_job() {
+            match job {
+                Job::Lidar(tile_index) => {
+                    assert!(!is_lidar_finished[tile_index]);
+                    is_lidar_finished[tile_index] = true;
+                    number_of_lidar_jobs += 1;
+                    scheduler.finish_lidar_job(tile_index);
+                }
+                Job::Render(tile_index) => {
+                    assert!(!is_rendered[tile_index]);
+                    assert!(is_lidar_finished[tile_index]);
+
+                    for neighbor in &tiles[tile_index].neighbors {
+                        let neighbor_index = tiles
+                            .iter()
+                            .position(|tile| tile.tile.lidar_dir_path == *neighbor)
+                            .unwrap();
+                        assert!(is_lidar_finished[neighbor_index]);
+                    }
+
+                    is_rendered[tile_index] = true;
+                }
+            }
+        }
+
+        assert_eq!(number_of_lidar_jobs, 9);
+        assert!(is_rendered.iter().all(|is_rendered| *is_rendered));
+    }
+
+    #[test]
+    fn render_jobs_wait_for_lidar_jobs_of_other_threads() {
+        let tiles = get_tiles(3, 3);
+        let scheduler = TileScheduler::new(&tiles, false);
+        let mut lidar_jobs: Vec<usize> = vec![];
+
+        // The LiDAR jobs are all taken before any of them is finished, so no render job is ready
+        while lidar_jobs.len() < tiles.len() {
+            match scheduler.next_job() {
+                Some(Job::Lidar(tile_index)) => lidar_jobs.push(tile_index),
+                _ => panic!("Only LiDAR jobs should be available"),
+            }
+        }
+
+        // Every tile of the grid is a neighbor of the center one
+        let center_index = 4;
+
+        std::thread::scope(|scope| {
+            let waiting_thread = scope.spawn(|| scheduler.next_job());
+
+            for tile_index in lidar_jobs.iter().filter(|index| **index != center_index) {
+                scheduler.finish_lidar_job(*tile_index);
+            }
+
+            sleep(Duration::from_millis(50));
+            assert!(!waiting_thread.is_finished());
+
+            scheduler.finish_lidar_job(center_index);
+            assert!(matches!(waiting_thread.join().unwrap(), Some(Job::Render(_))));
+        });
+    }
+
+    #[test]
+    fn skipped_lidar_tiles_are_rendered_right_away() {
+        let tiles = get_tiles(3, 3);
+        let scheduler = TileScheduler::new(&tiles, true);
+        let mut number_of_render_jobs = 0;
+
+        while let Some(job) = scheduler.next_job() {
+            assert!(matches!(job, Job::Render(_)));
+            number_of_render_jobs += 1;
+        }
+
+        assert_eq!(number_of_render_jobs, 9);
+    }
+}
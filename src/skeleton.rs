@@ -0,0 +1,239 @@
+/// Offsets of the 8 neighbors of a pixel, clockwise from the north one, as in the Zhang-Suen paper
+/// (P2 to P9).
+const NEIGHBOR_OFFSETS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// Offsets of the 8 neighbors of a pixel, the 4-connected ones first so that chains follow
+/// staircases pixel by pixel instead of cutting their corners.
+const TRACING_OFFSETS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Thins a binary mask, stored row by row, to one pixel wide lines with the Zhang-Suen algorithm.
+pub fn thin_mask(mask: &mut Vec<bool>, width: usize, height: usize) {
+    loop {
+        let mut has_changed = false;
+
+        for is_second_subiteration in [false, true] {
+            let mut pixels_to_remove: Vec<usize> = vec![];
+
+            for y in 0..height {
+                for x in 0..width {
+                    if !mask[y * width + x] {
+                        continue;
+                    }
+
+                    let neighbors = get_neighbors(mask, width, height, x, y);
+                    let number_of_neighbors = neighbors.iter().filter(|neighbor| **neighbor).count();
+
+                    if !(2..=6).contains(&number_of_neighbors) {
+                        continue;
+                    }
+
+                    // Number of background to foreground transitions around the pixel
+                    let transitions = (0..8)
+                        .filter(|index| !neighbors[*index] && neighbors[(*index + 1) % 8])
+                        .count();
+
+                    if transitions != 1 {
+                        continue;
+                    }
+
+                    let [p2, _, p4, _, p6, _, p8, _] = neighbors;
+
+                    let should_remove = if is_second_subiteration {
+                        !(p2 && p4 && p8) && !(p2 && p6 && p8)
+                    } else {
+                        !(p2 && p4 && p6) && !(p4 && p6 && p8)
+                    };
+
+                    if should_remove {
+                        pixels_to_remove.push(y * width + x);
+                    }
+                }
+            }
+
+            has_changed |= !pixels_to_remove.is_empty();
+
+            for index in pixels_to_remove {
+                mask[index] = false;
+            }
+        }
+
+        if !has_changed {
+            return;
+        }
+    }
+}
+
+/// Chains the pixels of a thinned mask into polylines of (x, y) pixel indices. Chains start from the line
+/// ends when possible, and are joined to the pixel where they meet an already traced chain.
+pub fn trace_skeleton(mask: &Vec<bool>, width: usize, height: usize) -> Vec<Vec<(usize, usize)>> {
+    let mut is_visited = vec![false; mask.len()];
+    let mut chains: Vec<Vec<(usize, usize)>> = vec![];
+
+    let line_ends: Vec<usize> = (0..mask.len())
+        .filter(|index| {
+            mask[*index]
+                && get_neighbors(mask, width, height, index % width, index / width)
+                    .iter()
+                    .filter(|neighbor| **neighbor)
+                    .count()
+                    == 1
+        })
+        .collect();
+
+    for start in line_ends.into_iter().chain(0..mask.len()) {
+        if !mask[start] || is_visited[start] {
+            continue;
+        }
+
+        let start_pixel = (start % width, start / width);
+        is_visited[start] = true;
+
+        let mut chain = walk_skeleton(mask, &mut is_visited, width, height, start_pixel, &[]);
+        let backward_chain = walk_skeleton(mask, &mut is_visited, width, height, start_pixel, &chain);
+
+        chain.reverse();
+        chain.push(start_pixel);
+        chain.extend(backward_chain);
+        chains.push(chain);
+    }
+
+    return chains;
+}
+
+/// Follows unvisited skeleton pixels from a starting pixel (excluded from the result). The walk ends on
+/// an already visited pixel when it meets one, to keep the junctions connected and close the loops.
+/// The pixels of the other half of the chain are never joined.
+fn walk_skeleton(
+    mask: &Vec<bool>,
+    is_visited: &mut Vec<bool>,
+    width: usize,
+    height: usize,
+    start: (usize, usize),
+    other_half: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let mut pixels: Vec<(usize, usize)> = vec![];
+    let mut current = start;
+
+    loop {
+        let next = get_skeleton_neighbor(mask, width, height, current, |index| !is_visited[index]);
+
+        match next {
+            Some(next) => {
+                is_visited[next.1 * width + next.0] = true;
+                pixels.push(next);
+                current = next;
+            }
+            None => {
+                let junction = get_skeleton_neighbor(mask, width, height, current, |index| {
+                    let pixel = (index % width, index / width);
+
+                    (pixel != start || pixels.len() >= 3)
+                        && !pixels.contains(&pixel)
+                        && !other_half.contains(&pixel)
+                });
+
+                if let Some(junction) = junction {
+                    pixels.push(junction);
+                }
+
+                return pixels;
+            }
+        }
+    }
+}
+
+fn get_skeleton_neighbor<F: Fn(usize) -> bool>(
+    mask: &Vec<bool>,
+    width: usize,
+    height: usize,
+    (x, y): (usize, usize),
+    predicate: F,
+) -> Option<(usize, usize)> {
+    for (offset_x, offset_y) in TRACING_OFFSETS {
+        let neighbor_x = x as i64 + offset_x;
+        let neighbor_y = y as i64 + offset_y;
+
+        if neighbor_x < 0 || neighbor_y < 0 || neighbor_x >= width as i64 || neighbor_y >= height as i64 {
+            continue;
+        }
+
+        let index = neighbor_y as usize * width + neighbor_x as usize;
+
+        if mask[index] && predicate(index) {
+            return Some((neighbor_x as usize, neighbor_y as usize));
+        }
+    }
+
+    return None;
+}
+
+fn get_neighbors(mask: &Vec<bool>, width: usize, height: usize, x: usize, y: usize) -> [bool; 8] {
+    let mut neighbors = [false; 8];
+
+    for (index, (offset_x, offset_y)) in NEIGHBOR_OFFSETS.iter().enumerate() {
+        let neighbor_x = x as i64 + offset_x;
+        let neighbor_y = y as i64 + offset_y;
+
+        if neighbor_x >= 0 && neighbor_y >= 0 && neighbor_x < width as i64 && neighbor_y < height as i64 {
+            neighbors[index] = mask[neighbor_y as usize * width + neighbor_x as usize];
+        }
+    }
+
+    return neighbors;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thick_bar_is_thinned_to_one_pixel_line() {
+        let (width, height) = (20, 9);
+        let mut mask = vec![false; width * height];
+
+        // A 16 pixels long and 5 pixels thick bar
+        for y in 2..7 {
+            for x in 2..18 {
+                mask[y * width + x] = true;
+            }
+        }
+
+        thin_mask(&mut mask, width, height);
+
+        let is_set = |x: usize, y: usize| mask[y * width + x];
+
+        // One pixel per column along the bar, away from its ends
+        for x in 5..15 {
+            assert_eq!((0..height).filter(|y| is_set(x, *y)).count(), 1);
+        }
+
+        // No 2x2 block of pixels anywhere
+        for y in 0..height - 1 {
+            for x in 0..width - 1 {
+                assert!(!(is_set(x, y) && is_set(x + 1, y) && is_set(x, y + 1) && is_set(x + 1, y + 1)));
+            }
+        }
+
+        // The line is not broken
+        let chains = trace_skeleton(&mask, width, height);
+        assert_eq!(chains.len(), 1);
+    }
+}
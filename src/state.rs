@@ -0,0 +1,285 @@
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const STATE_FILE_NAME: &str = "state.json";
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    Lidar,
+    Vegetation,
+    Contours,
+    Cliffs,
+    MicroRelief,
+    Streams,
+    LandCover,
+    Buildings,
+    Vectors,
+    Render,
+}
+
+impl Stage {
+    /// The files or directories that must exist in the tile directory for the stage to be considered done.
+    pub fn output_files(&self) -> &'static [&'static str] {
+        match self {
+            Stage::Lidar => &[
+                "extent.txt",
+                "water.tif",
+                "buildings.tif",
+                "returns.tif",
+                "intensity.tif",
+            ],
+            Stage::Vegetation => &["vegetation.png", "vegetation-symbols.json"],
+            Stage::Contours => &["contours.png", "contours/rendered-contours.shp"],
+            Stage::Cliffs => &["cliffs/cliffs.shp"],
+            Stage::MicroRelief => &[
+                "micro-relief/knolls.shp",
+                "micro-relief/boulders.shp",
+                "micro-relief/depressions.shp",
+                "micro-relief/pits.shp",
+            ],
+            Stage::Streams => &["streams/streams.shp"],
+            Stage::LandCover => &["land-cover/water.shp", "land-cover/paved-areas.shp"],
+            Stage::Buildings => &["buildings/buildings.shp"],
+            Stage::Vectors => &["shapes"],
+            Stage::Render => &["full-map.png", "symbols.json"],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StageStatus {
+    Done,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StageState {
+    pub status: StageStatus,
+    pub inputs_hash: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct BatchState {
+    pub tiles: BTreeMap<String, BTreeMap<Stage, StageState>>,
+}
+
+/// The state.json manifest of a batch output directory, recording for every tile and every stage
+/// whether it succeeded and with which inputs, so that a rerun only redoes stale or failed stages.
+pub struct StateManifest {
+    path: PathBuf,
+    state: Mutex<BatchState>,
+}
+
+impl StateManifest {
+    pub fn load(output_dir: &str) -> StateManifest {
+        let path = Path::new(output_dir).join(STATE_FILE_NAME);
+
+        let state = match fs::read_to_string(&path) {
+            Ok(raw_state) => serde_json::from_str(&raw_state).unwrap_or_else(|error| {
+                error!(
+                    "The {:?} file is corrupted, starting from scratch: {}",
+                    path, error
+                );
+                BatchState::default()
+            }),
+            Err(_) => BatchState::default(),
+        };
+
+        StateManifest {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    pub fn is_up_to_date(&self, tile_dir_path: &PathBuf, stage: Stage, inputs_hash: &str) -> bool {
+        let state = self.state.lock().unwrap();
+
+        let is_recorded_as_done = state
+            .tiles
+            .get(&get_tile_key(tile_dir_path))
+            .and_then(|stages| stages.get(&stage))
+            .is_some_and(|stage_state| {
+                stage_state.status == StageStatus::Done && stage_state.inputs_hash == inputs_hash
+            });
+
+        return is_recorded_as_done
+            && stage
+                .output_files()
+                .iter()
+                .all(|output_file| tile_dir_path.join(output_file).exists());
+    }
+
+    pub fn is_done(&self, tile_dir_path: &PathBuf, stage: Stage) -> bool {
+        let state = self.state.lock().unwrap();
+
+        state
+            .tiles
+            .get(&get_tile_key(tile_dir_path))
+            .and_then(|stages| stages.get(&stage))
+            .is_some_and(|stage_state| stage_state.status == StageStatus::Done)
+    }
+
+    pub fn record(
+        &self,
+        tile_dir_path: &PathBuf,
+        stage: Stage,
+        status: StageStatus,
+        inputs_hash: &str,
+        error: Option<String>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        state
+            .tiles
+            .entry(get_tile_key(tile_dir_path))
+            .or_default()
+            .insert(
+                stage,
+                StageState {
+                    status,
+                    inputs_hash: inputs_hash.to_owned(),
+                    timestamp,
+                    error,
+                },
+            );
+
+        if let Err(error) = save_state(&self.path, &state) {
+            error!("Could not save the {:?} file: {}", self.path, error);
+        }
+    }
+
+    /// Returns the tile directory name, the stage and the error of every failed stage.
+    pub fn get_failed_stages(&self) -> Vec<(String, Stage, String)> {
+        let state = self.state.lock().unwrap();
+        let mut failed_stages = vec![];
+
+        for (tile_key, stages) in &state.tiles {
+            for (stage, stage_state) in stages {
+                if stage_state.status == StageStatus::Failed {
+                    failed_stages.push((
+                        tile_key.clone(),
+                        *stage,
+                        stage_state.error.clone().unwrap_or_default(),
+                    ));
+                }
+            }
+        }
+
+        return failed_stages;
+    }
+}
+
+fn save_state(path: &PathBuf, state: &BatchState) -> std::io::Result<()> {
+    let json_string = serde_json::to_string_pretty(state)?;
+
+    // Writing to a temporary file first so that a crash never leaves a truncated manifest
+    let temporary_path = path.with_extension("json.tmp");
+    let mut file = File::create(&temporary_path)?;
+    file.write_all(json_string.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(temporary_path, path)
+}
+
+fn get_tile_key(tile_dir_path: &PathBuf) -> String {
+    tile_dir_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().to_string())
+        .unwrap_or_else(|| tile_dir_path.to_string_lossy().to_string())
+}
+
+/// Hashes a list of inputs with FNV-1a, which unlike the standard library hasher
+/// is stable across Rust versions.
+pub fn hash_inputs(inputs: &[&str]) -> String {
+    let mut hash = FNV_OFFSET_BASIS;
+
+    for input in inputs {
+        for byte in input.bytes().chain(std::iter::once(0)) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    format!("{:016x}", hash)
+}
+
+/// Hashes a file by its path, size and modification date, without reading its content.
+pub fn hash_file_metadata(path: &PathBuf) -> String {
+    let (size, modified) = match fs::metadata(path) {
+        Ok(metadata) => (
+            metadata.len(),
+            metadata
+                .modified()
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos())
+                .unwrap_or(0),
+        ),
+        Err(_) => (0, 0),
+    };
+
+    hash_inputs(&[&path.to_string_lossy(), &size.to_string(), &modified.to_string()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_config_or_input_hash_reruns_the_stage() {
+        let output_dir = std::env::temp_dir().join(format!("cassini-state-test-{}", std::process::id()));
+        let tile_dir_path = output_dir.join("tile");
+        fs::create_dir_all(tile_dir_path.join("cliffs")).unwrap();
+        File::create(tile_dir_path.join("cliffs/cliffs.shp")).unwrap();
+
+        let state = StateManifest::load(&output_dir.to_string_lossy());
+        let inputs_hash = hash_inputs(&["config", "input"]);
+        assert!(!state.is_up_to_date(&tile_dir_path, Stage::Cliffs, &inputs_hash));
+
+        state.record(
+            &tile_dir_path,
+            Stage::Cliffs,
+            StageStatus::Done,
+            &inputs_hash,
+            None,
+        );
+
+        // Reloading the manifest from disk, as a rerun of the batch would
+        let state = StateManifest::load(&output_dir.to_string_lossy());
+        assert!(state.is_up_to_date(&tile_dir_path, Stage::Cliffs, &inputs_hash));
+        assert!(!state.is_up_to_date(
+            &tile_dir_path,
+            Stage::Cliffs,
+            &hash_inputs(&["changed config", "input"])
+        ));
+        assert!(!state.is_up_to_date(
+            &tile_dir_path,
+            Stage::Cliffs,
+            &hash_inputs(&["config", "changed input"])
+        ));
+        assert!(!state.is_up_to_date(&tile_dir_path, Stage::Streams, &inputs_hash));
+
+        fs::remove_dir_all(output_dir).unwrap();
+    }
+}
@@ -0,0 +1,592 @@
+use log::info;
+use shapefile::{record::polyline::GenericPolyline, Point, Polyline};
+use std::{
+    cmp::Ordering, collections::BinaryHeap, f64::consts::SQRT_2, fs::create_dir_all, path::PathBuf,
+    time::Instant,
+};
+
+use crate::{
+    config::Config,
+    constants::{BUFFER, DEM_BLOCK_SIZE, DEM_NODATA_VALUE},
+    error::CassiniError,
+    helpers::{
+        clip_polyline, create_area_shapefile_writer, get_closest_point_on_segment, simplify_polyline,
+        AreaRecord,
+    },
+    mosaic::create_raster_with_buffer,
+    raster::{fill_nodata, is_nodata, Raster, FILL_NODATA_MAX_SEARCH_DISTANCE},
+    tile::Tile,
+};
+
+/// Offsets of the 8 neighbors of a pixel, the index in this array being the D8 flow direction
+const NEIGHBOR_OFFSETS: [(i64, i64); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// Flow direction of the cells without a lower neighbor: the outlets on the edges of the DEM
+const NO_FLOW_DIRECTION: u8 = u8::MAX;
+
+/// Elevation in meters added to every cell of a filled depression above the one it drains to, so that
+/// the flats keep a flow direction
+const FLAT_INCREMENT: f64 = 1e-5;
+
+/// Distance in meters between the points sampled along the waterways to compare them to the streams
+const WATERWAY_SAMPLING_DISTANCE: f64 = 1.;
+
+/// Simplification tolerance of the snapped waterways, in meters
+const SNAPPED_WATERWAY_TOLERANCE: f64 = 0.5;
+
+/// A cell waiting in the Priority-Flood queue, the lowest one being popped first
+struct QueuedCell {
+    elevation: f64,
+    index: usize,
+}
+
+impl PartialEq for QueuedCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedCell {}
+
+impl PartialOrd for QueuedCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .elevation
+            .total_cmp(&self.elevation)
+            .then_with(|| other.index.cmp(&self.index))
+    }
+}
+
+/// Extracts the watercourses of the tile from the DEM: the depressions are filled, the D8 flow directions
+/// and the flow accumulation are computed, and the cells draining more than streams.minor_channel_min_area
+/// are chained into polylines. The polylines are clipped to the tile and written to streams/streams.shp,
+/// oriented downstream, with their catchment area.
+pub fn extract_streams(
+    tile: &Tile,
+    neighbor_tiles: &Vec<PathBuf>,
+    config: &Config,
+) -> Result<(), CassiniError> {
+    let streams_dir = tile.render_dir_path.join("streams");
+    create_dir_all(&streams_dir)?;
+    let mut writer = create_area_shapefile_writer(&streams_dir.join("streams.shp"))?;
+
+    if !config.streams.enabled {
+        return Ok(());
+    }
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. Extracting streams",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y
+    );
+
+    let start = Instant::now();
+
+    let mut dem = create_raster_with_buffer(
+        tile,
+        neighbor_tiles,
+        BUFFER as i64,
+        "dem",
+        DEM_BLOCK_SIZE as f64,
+        DEM_NODATA_VALUE,
+    )?;
+    fill_nodata(&mut dem, FILL_NODATA_MAX_SEARCH_DISTANCE);
+    fill_sinks(&mut dem);
+
+    let flow_directions = get_flow_directions(&dem);
+    let flow_accumulation = get_flow_accumulation(&flow_directions, dem.width);
+    let cell_area = dem.cell_size * dem.cell_size;
+    let mut id: i32 = 0;
+
+    let streams = trace_streams(
+        &flow_directions,
+        &flow_accumulation,
+        dem.width,
+        config.streams.minor_channel_min_area / cell_area,
+        config.streams.watercourse_min_area / cell_area,
+    );
+
+    for cells in streams {
+        if cells.len() < 2 {
+            continue;
+        }
+
+        let points: Vec<(f64, f64)> = cells
+            .iter()
+            .map(|index| dem.get_pixel_center(index % dem.width, index / dem.width))
+            .collect();
+
+        let points = simplify_polyline(&points, dem.cell_size);
+
+        let area = flow_accumulation[cells[0]] as f64 * cell_area;
+
+        // The neighbor tiles extract the same streams in their buffer, so only the parts inside the tile
+        // are kept
+        for part in clip_polyline(
+            &points,
+            (
+                tile.min_x as f64,
+                tile.min_y as f64,
+                tile.max_x as f64,
+                tile.max_y as f64,
+            ),
+        ) {
+            let record = AreaRecord { id, area };
+
+            let polyline = Polyline::new(part.iter().map(|(x, y)| Point { x: *x, y: *y }).collect());
+            writer.write_shape_and_record(&polyline, &record)?;
+            id += 1;
+        }
+    }
+
+    let duration = start.elapsed();
+
+    info!(
+        "Tile min_x={} min_y={} max_x={} max_y={}. {} streams extracted in {:.1?}",
+        tile.min_x, tile.min_y, tile.max_x, tile.max_y, id, duration
+    );
+
+    Ok(())
+}
+
+/// Fills the depressions of the DEM with the Priority-Flood algorithm (Barnes et al., 2014): the cells are
+/// flooded from the edges of the DEM and of the nodata areas, lowest first, and every cell lower than the
+/// one it is reached from is raised just above it.
+fn fill_sinks(dem: &mut Raster) {
+    let width = dem.width;
+    let height = dem.height;
+    let mut is_queued = vec![false; dem.data.len()];
+    let mut queue: BinaryHeap<QueuedCell> = BinaryHeap::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+
+            if is_nodata(dem.data[index]) {
+                continue;
+            }
+
+            let is_outlet = NEIGHBOR_OFFSETS.iter().any(|offset| {
+                match get_neighbor_index(x, y, *offset, width, height) {
+                    Some(neighbor) => is_nodata(dem.data[neighbor]),
+                    None => true,
+                }
+            });
+
+            if is_outlet {
+                is_queued[index] = true;
+
+                queue.push(QueuedCell {
+                    elevation: dem.data[index],
+                    index,
+                });
+            }
+        }
+    }
+
+    while let Some(cell) = queue.pop() {
+        let (x, y) = (cell.index % width, cell.index / width);
+
+        for offset in NEIGHBOR_OFFSETS {
+            let Some(neighbor) = get_neighbor_index(x, y, offset, width, height) else {
+                continue;
+            };
+
+            if is_queued[neighbor] || is_nodata(dem.data[neighbor]) {
+                continue;
+            }
+
+            if dem.data[neighbor] <= cell.elevation {
+                dem.data[neighbor] = cell.elevation + FLAT_INCREMENT;
+            }
+
+            is_queued[neighbor] = true;
+
+            queue.push(QueuedCell {
+                elevation: dem.data[neighbor],
+                index: neighbor,
+            });
+        }
+    }
+}
+
+/// Returns the D8 flow direction of every cell, towards its neighbor with the steepest drop.
+fn get_flow_directions(dem: &Raster) -> Vec<u8> {
+    let mut flow_directions = vec![NO_FLOW_DIRECTION; dem.data.len()];
+
+    for y in 0..dem.height {
+        for x in 0..dem.width {
+            let elevation = dem.get(x, y);
+
+            if is_nodata(elevation) {
+                continue;
+            }
+
+            let mut steepest_drop = 0.;
+
+            for (direction, offset) in NEIGHBOR_OFFSETS.iter().enumerate() {
+                let Some(neighbor) = get_neighbor_index(x, y, *offset, dem.width, dem.height) else {
+                    continue;
+                };
+
+                if is_nodata(dem.data[neighbor]) {
+                    continue;
+                }
+
+                let distance = if offset.0 != 0 && offset.1 != 0 {
+                    SQRT_2
+                } else {
+                    1.
+                };
+                let drop = (elevation - dem.data[neighbor]) / distance;
+
+                if drop > steepest_drop {
+                    steepest_drop = drop;
+                    flow_directions[y * dem.width + x] = direction as u8;
+                }
+            }
+        }
+    }
+
+    return flow_directions;
+}
+
+/// Returns the number of cells draining through every cell, itself included. The cells are processed
+/// from the sources downstream, a cell being processed once all the cells draining into it are.
+fn get_flow_accumulation(flow_directions: &Vec<u8>, width: usize) -> Vec<u32> {
+    let mut flow_accumulation = vec![1; flow_directions.len()];
+    let mut upstream_counts = vec![0_u8; flow_directions.len()];
+
+    for index in 0..flow_directions.len() {
+        if let Some(downstream) = get_downstream_index(flow_directions, index, width) {
+            upstream_counts[downstream] += 1;
+        }
+    }
+
+    let mut cells_to_process: Vec<usize> = (0..flow_directions.len())
+        .filter(|index| upstream_counts[*index] == 0)
+        .collect();
+
+    while let Some(index) = cells_to_process.pop() {
+        let Some(downstream) = get_downstream_index(flow_directions, index, width) else {
+            continue;
+        };
+
+        flow_accumulation[downstream] += flow_accumulation[index];
+        upstream_counts[downstream] -= 1;
+
+        if upstream_counts[downstream] == 0 {
+            cells_to_process.push(downstream);
+        }
+    }
+
+    return flow_accumulation;
+}
+
+/// Chains the cells draining at least minor_channel_min_accumulation cells into polylines of cell indices,
+/// from upstream to downstream. A polyline starts at a source, a confluence or the cell where the stream
+/// becomes a watercourse, and ends on the start of the next one or on an outlet.
+fn trace_streams(
+    flow_directions: &Vec<u8>,
+    flow_accumulation: &Vec<u32>,
+    width: usize,
+    minor_channel_min_accumulation: f64,
+    watercourse_min_accumulation: f64,
+) -> Vec<Vec<usize>> {
+    let is_stream = |index: usize| flow_accumulation[index] as f64 >= minor_channel_min_accumulation;
+    let is_watercourse = |index: usize| flow_accumulation[index] as f64 >= watercourse_min_accumulation;
+
+    let mut upstream_stream_counts = vec![0_u8; flow_directions.len()];
+    let mut is_start = vec![false; flow_directions.len()];
+
+    for index in 0..flow_directions.len() {
+        if !is_stream(index) {
+            continue;
+        }
+
+        if let Some(downstream) = get_downstream_index(flow_directions, index, width) {
+            upstream_stream_counts[downstream] += 1;
+
+            if is_watercourse(downstream) != is_watercourse(index) {
+                is_start[downstream] = true;
+            }
+        }
+    }
+
+    let mut streams: Vec<Vec<usize>> = vec![];
+
+    for index in 0..flow_directions.len() {
+        if !is_stream(index) || !(is_start[index] || upstream_stream_counts[index] != 1) {
+            continue;
+        }
+
+        let mut cells = vec![index];
+        let mut current = index;
+
+        while let Some(downstream) = get_downstream_index(flow_directions, current, width) {
+            cells.push(downstream);
+
+            if is_start[downstream] || upstream_stream_counts[downstream] != 1 {
+                break;
+            }
+
+            current = downstream;
+        }
+
+        streams.push(cells);
+    }
+
+    return streams;
+}
+
+fn get_neighbor_index(
+    x: usize,
+    y: usize,
+    (offset_x, offset_y): (i64, i64),
+    width: usize,
+    height: usize,
+) -> Option<usize> {
+    let neighbor_x = x as i64 + offset_x;
+    let neighbor_y = y as i64 + offset_y;
+
+    if neighbor_x < 0 || neighbor_y < 0 || neighbor_x >= width as i64 || neighbor_y >= height as i64 {
+        return None;
+    }
+
+    return Some(neighbor_y as usize * width + neighbor_x as usize);
+}
+
+fn get_downstream_index(flow_directions: &Vec<u8>, index: usize, width: usize) -> Option<usize> {
+    let direction = flow_directions[index];
+
+    if direction == NO_FLOW_DIRECTION {
+        return None;
+    }
+
+    let (offset_x, offset_y) = NEIGHBOR_OFFSETS[direction as usize];
+    let x = (index % width) as i64 + offset_x;
+    let y = (index / width) as i64 + offset_y;
+
+    return Some(y as usize * width + x as usize);
+}
+
+/// Moves the points of an OSM waterway, resampled every meter, onto the closest derived stream within
+/// max_distance. The points farther from the streams are kept as they are.
+pub fn snap_waterway_to_streams(
+    waterway: &GenericPolyline<Point>,
+    streams: &Vec<Vec<(f64, f64)>>,
+    max_distance: f64,
+) -> GenericPolyline<Point> {
+    let parts: Vec<Vec<Point>> = waterway
+        .parts()
+        .iter()
+        .map(|part| {
+            let points: Vec<(f64, f64)> = part.iter().map(|point| (point.x, point.y)).collect();
+
+            let snapped_points: Vec<(f64, f64)> = resample_polyline(&points, WATERWAY_SAMPLING_DISTANCE)
+                .into_iter()
+                .map(|point| get_closest_point_on_lines(point, streams, max_distance).unwrap_or(point))
+                .collect();
+
+            simplify_polyline(&snapped_points, SNAPPED_WATERWAY_TOLERANCE)
+                .into_iter()
+                .map(|(x, y)| Point { x, y })
+                .collect()
+        })
+        .collect();
+
+    return GenericPolyline::with_parts(parts);
+}
+
+/// Returns true if most of a derived stream is within max_distance of the waterways.
+pub fn is_stream_along_waterways(
+    stream: &Vec<(f64, f64)>,
+    waterways: &Vec<Vec<(f64, f64)>>,
+    max_distance: f64,
+) -> bool {
+    let points = resample_polyline(stream, WATERWAY_SAMPLING_DISTANCE);
+
+    let number_of_points_along_waterways = points
+        .iter()
+        .filter(|point| get_closest_point_on_lines(**point, waterways, max_distance).is_some())
+        .count();
+
+    return number_of_points_along_waterways * 2 > points.len();
+}
+
+fn get_closest_point_on_lines(
+    point: (f64, f64),
+    lines: &Vec<Vec<(f64, f64)>>,
+    max_distance: f64,
+) -> Option<(f64, f64)> {
+    let mut closest_point: Option<(f64, f64)> = None;
+    let mut min_distance = max_distance;
+
+    for line in lines {
+        for segment in line.windows(2) {
+            let candidate = get_closest_point_on_segment(point, segment[0], segment[1]);
+            let distance = ((point.0 - candidate.0).powi(2) + (point.1 - candidate.1).powi(2)).sqrt();
+
+            if distance <= min_distance {
+                min_distance = distance;
+                closest_point = Some(candidate);
+            }
+        }
+    }
+
+    return closest_point;
+}
+
+/// Adds points along the segments of a polyline so that they are at most step meters apart.
+fn resample_polyline(points: &Vec<(f64, f64)>, step: f64) -> Vec<(f64, f64)> {
+    let mut resampled_points: Vec<(f64, f64)> = vec![];
+
+    for segment in points.windows(2) {
+        let ((start_x, start_y), (end_x, end_y)) = (segment[0], segment[1]);
+        let length = ((end_x - start_x).powi(2) + (end_y - start_y).powi(2)).sqrt();
+        let number_of_steps = (length / step).ceil().max(1.) as usize;
+
+        for step_index in 0..number_of_steps {
+            let ratio = step_index as f64 / number_of_steps as f64;
+            resampled_points.push((
+                start_x + (end_x - start_x) * ratio,
+                start_y + (end_y - start_y) * ratio,
+            ));
+        }
+    }
+
+    if let Some(last_point) = points.last() {
+        resampled_points.push(*last_point);
+    }
+
+    return resampled_points;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_dem(width: usize, height: usize, get_elevation: impl Fn(usize, usize) -> f64) -> Raster {
+        let mut data = vec![];
+
+        for y in 0..height {
+            for x in 0..width {
+                data.push(get_elevation(x, y));
+            }
+        }
+
+        return Raster {
+            width,
+            height,
+            left: 0.,
+            top: height as f64,
+            cell_size: 1.,
+            data,
+        };
+    }
+
+    /// Follows the flow directions from a cell, returning the outlet it drains to, or None if it loops.
+    fn get_outlet(flow_directions: &Vec<u8>, index: usize, width: usize) -> Option<usize> {
+        let mut current = index;
+
+        for _ in 0..flow_directions.len() {
+            match get_downstream_index(flow_directions, current, width) {
+                Some(downstream) => current = downstream,
+                None => return Some(current),
+            }
+        }
+
+        return None;
+    }
+
+    fn is_on_edge(index: usize, width: usize, height: usize) -> bool {
+        let (x, y) = (index % width, index / width);
+        return x == 0 || y == 0 || x == width - 1 || y == height - 1;
+    }
+
+    #[test]
+    fn v_shaped_valley_accumulates_on_thalweg() {
+        // The valley sides drop 1 meter per cell towards the x = 4 thalweg, which drops 0.1 meter per cell
+        // southward
+        let (width, height) = (9, 15);
+        let mut dem = get_dem(width, height, |x, y| {
+            x.abs_diff(4) as f64 + 0.1 * (height - 1 - y) as f64
+        });
+        fill_sinks(&mut dem);
+
+        let flow_directions = get_flow_directions(&dem);
+        let flow_accumulation = get_flow_accumulation(&flow_directions, width);
+
+        for y in 0..height {
+            let row = &flow_accumulation[y * width..(y + 1) * width];
+            let thalweg_accumulation = row[4];
+
+            assert!(row
+                .iter()
+                .enumerate()
+                .all(|(x, accumulation)| x == 4 || *accumulation < thalweg_accumulation));
+        }
+
+        // Every cell drains through the southern end of the thalweg
+        assert_eq!(
+            flow_accumulation[(height - 1) * width + 4],
+            (width * height) as u32
+        );
+    }
+
+    #[test]
+    fn closed_pit_is_filled_and_drains_out() {
+        // A plane rising eastward, with a 2 meters deep pit in its middle
+        let (width, height) = (7, 7);
+        let mut dem = get_dem(width, height, |x, y| {
+            if (x, y) == (3, 3) {
+                return 1.;
+            }
+
+            return 3. + 0.1 * x as f64;
+        });
+        fill_sinks(&mut dem);
+
+        // The pit is raised just above the lowest cell it can drain through
+        let pit = 3 * width + 3;
+        assert!((dem.data[pit] - (3.2 + FLAT_INCREMENT)).abs() < 1e-9);
+
+        let flow_directions = get_flow_directions(&dem);
+        let outlet = get_outlet(&flow_directions, pit, width).unwrap();
+        assert_eq!(outlet % width, 0);
+    }
+
+    #[test]
+    fn flat_area_drains_to_edges() {
+        let (width, height) = (7, 7);
+        let mut dem = get_dem(width, height, |_, _| 10.);
+        fill_sinks(&mut dem);
+
+        // The flat is raised by FLAT_INCREMENT per cell from its edges
+        assert!((dem.get(3, 3) - (10. + 3. * FLAT_INCREMENT)).abs() < 1e-9);
+
+        let flow_directions = get_flow_directions(&dem);
+
+        for index in 0..dem.data.len() {
+            let is_outlet = flow_directions[index] == NO_FLOW_DIRECTION;
+            assert_eq!(is_outlet, is_on_edge(index, width, height));
+
+            let outlet = get_outlet(&flow_directions, index, width).unwrap();
+            assert!(is_on_edge(outlet, width, height));
+        }
+    }
+}
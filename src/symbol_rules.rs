@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use shapefile::dbase::{FieldValue, Record};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::PathBuf,
+};
+
+use crate::{config::SymbolSet, error::CassiniError, map_renderer::MapRenderer};
+
+/// The rules drawing the OSM features with the ISOM and ISSprOM symbols, used when no rules file is given.
+const DEFAULT_SYMBOL_RULES: &str = include_str!("default_symbol_rules.json");
+
+/// The symbol of the rules leaving the matching features out of the map
+pub const NO_SYMBOL: &str = "none";
+/// The symbol of the rules marking the coastlines, from which the sea areas are built
+pub const COASTLINE_SYMBOL: &str = "coastline";
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleGeometry {
+    /// The features of the lines shapefile
+    Line,
+    /// The features of the multipolygons shapefile
+    Area,
+}
+
+/// A rule drawing the OSM features with some tags with a symbol. The tags are read from the shapefile
+/// columns, then from the other_tags column.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SymbolRule {
+    pub geometry: RuleGeometry,
+    /// The features must have every tag, with one of the listed values. "*" matches any value, and
+    /// values starting with a comparison operator (">=1.5") match the numbers at the start of the tag
+    /// values.
+    #[serde(default)]
+    pub tags: BTreeMap<String, Vec<String>>,
+    /// The features must not have any of these tags with one of the listed values
+    #[serde(default)]
+    pub not_tags: BTreeMap<String, Vec<String>>,
+    /// The ID of a line or area symbol of the map renderer, or "none" or "coastline"
+    pub symbol: String,
+    /// The rules are tried from the highest priority to the lowest, the first matching one is used
+    #[serde(default)]
+    pub priority: i32,
+    /// The symbol sets the rule applies to, all of them if empty
+    #[serde(default)]
+    pub symbol_sets: Vec<SymbolSet>,
+}
+
+/// Reads the symbol rules from the given file, or the default ones, sorted by decreasing priority.
+pub fn get_symbol_rules(path: &Option<PathBuf>) -> Result<Vec<SymbolRule>, CassiniError> {
+    let (name, raw_rules) = match path {
+        Some(path) => (path.to_string_lossy().to_string(), fs::read_to_string(path)?),
+        None => ("default".to_owned(), DEFAULT_SYMBOL_RULES.to_owned()),
+    };
+
+    let mut rules: Vec<SymbolRule> = serde_json::from_str(&raw_rules).map_err(|error| {
+        CassiniError::InvalidData(format!("Invalid symbol rules file {}: {}", name, error))
+    })?;
+
+    for rule in &rules {
+        let is_known_symbol = match rule.geometry {
+            RuleGeometry::Line => MapRenderer::get_line_symbol_function(&rule.symbol).is_some(),
+            RuleGeometry::Area => MapRenderer::get_area_symbol_function(&rule.symbol).is_some(),
+        };
+
+        if !is_known_symbol && rule.symbol != NO_SYMBOL && rule.symbol != COASTLINE_SYMBOL {
+            let geometry_name = match rule.geometry {
+                RuleGeometry::Line => "line",
+                RuleGeometry::Area => "area",
+            };
+
+            return Err(CassiniError::InvalidData(format!(
+                "Invalid symbol rules file {}: unknown {} symbol {}",
+                name, geometry_name, rule.symbol
+            )));
+        }
+    }
+
+    // The sort is stable, so the rules with the same priority keep the order of the file
+    rules.sort_by(|a, b| b.priority.cmp(&a.priority));
+
+    return Ok(rules);
+}
+
+/// Returns the first rule matching a feature of the given geometry, if any.
+pub fn find_symbol_rule<'a>(
+    rules: &'a Vec<SymbolRule>,
+    geometry: RuleGeometry,
+    symbol_set: SymbolSet,
+    record: &Record,
+    other_tags: &HashMap<String, String>,
+) -> Option<&'a SymbolRule> {
+    let get_tag = |key: &str| -> String {
+        match record.get(key) {
+            Some(FieldValue::Character(Some(value))) if value != "" => value.to_owned(),
+            _ => other_tags.get(key).cloned().unwrap_or_default(),
+        }
+    };
+
+    rules.iter().find(|rule| {
+        rule.geometry == geometry
+            && (rule.symbol_sets.is_empty() || rule.symbol_sets.contains(&symbol_set))
+            && rule
+                .tags
+                .iter()
+                .all(|(key, values)| does_tag_match(&get_tag(key), values))
+            && !rule
+                .not_tags
+                .iter()
+                .any(|(key, values)| does_tag_match(&get_tag(key), values))
+    })
+}
+
+fn does_tag_match(value: &str, patterns: &Vec<String>) -> bool {
+    if value == "" {
+        return false;
+    }
+
+    patterns.iter().any(|pattern| {
+        if pattern == "*" {
+            return true;
+        }
+
+        for operator in [">=", "<=", ">", "<"] {
+            if let Some(raw_threshold) = pattern.strip_prefix(operator) {
+                let (number, threshold) = match (parse_number(value), raw_threshold.trim().parse::<f32>()) {
+                    (Some(number), Ok(threshold)) => (number, threshold),
+                    _ => return false,
+                };
+
+                return match operator {
+                    ">=" => number >= threshold,
+                    "<=" => number <= threshold,
+                    ">" => number > threshold,
+                    _ => number < threshold,
+                };
+            }
+        }
+
+        return value == pattern;
+    })
+}
+
+/// Returns the number at the start of an OSM tag value, ignoring the unit after it ("2.5 m" gives 2.5).
+fn parse_number(value: &str) -> Option<f32> {
+    let number: String = value
+        .trim()
+        .chars()
+        .take_while(|character| character.is_ascii_digit() || *character == '.')
+        .collect();
+
+    return number.parse::<f32>().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_rules_from_json(file_name: &str, raw_rules: &str) -> Result<Vec<SymbolRule>, CassiniError> {
+        let path = std::env::temp_dir().join(format!("cassini-{}-{}", std::process::id(), file_name));
+        fs::write(&path, raw_rules).unwrap();
+        let rules = get_symbol_rules(&Some(path.clone()));
+        fs::remove_file(path).unwrap();
+
+        return rules;
+    }
+
+    fn get_record(tags: &[(&str, &str)]) -> Record {
+        let mut record = Record::default();
+
+        for (key, value) in tags {
+            record.insert(key.to_string(), FieldValue::Character(Some(value.to_string())));
+        }
+
+        return record;
+    }
+
+    #[test]
+    fn default_rules_round_trip() {
+        let rules = get_symbol_rules(&None).unwrap();
+        assert!(!rules.is_empty());
+
+        let raw_rules = serde_json::to_string_pretty(&rules).unwrap();
+        let read_rules = get_rules_from_json("round-trip.json", &raw_rules).unwrap();
+
+        assert_eq!(serde_json::to_string_pretty(&read_rules).unwrap(), raw_rules);
+    }
+
+    #[test]
+    fn rules_match_tags_from_columns_and_other_tags() {
+        let rules = get_symbol_rules(&None).unwrap();
+        let fence = get_record(&[("barrier", "fence")]);
+        let find_line_symbol = |other_tags: &HashMap<String, String>| {
+            find_symbol_rule(
+                &rules,
+                RuleGeometry::Line,
+                SymbolSet::Issprom2019,
+                &fence,
+                other_tags,
+            )
+            .map(|rule| rule.symbol.as_str())
+        };
+
+        assert_eq!(find_line_symbol(&HashMap::new()), Some("516"));
+        assert_eq!(
+            find_line_symbol(&HashMap::from([("height".to_owned(), "2 m".to_owned())])),
+            Some("518")
+        );
+        assert_eq!(
+            find_line_symbol(&HashMap::from([("height".to_owned(), "1.2".to_owned())])),
+            Some("516")
+        );
+
+        let tunnel = get_record(&[("highway", "footway"), ("tunnel", "yes")]);
+        let building_passage = get_record(&[("highway", "footway"), ("tunnel", "building_passage")]);
+        let find_issprom_line_symbol = |record: &Record| {
+            find_symbol_rule(
+                &rules,
+                RuleGeometry::Line,
+                SymbolSet::Issprom2019,
+                record,
+                &HashMap::new(),
+            )
+            .map(|rule| rule.symbol.as_str())
+        };
+
+        assert_eq!(find_issprom_line_symbol(&tunnel), Some(NO_SYMBOL));
+        assert_ne!(find_issprom_line_symbol(&building_passage), Some(NO_SYMBOL));
+    }
+
+    #[test]
+    fn unknown_symbol_code_is_rejected() {
+        let raw_rules = r#"[{ "geometry": "line", "tags": { "highway": ["path"] }, "symbol": "999" }]"#;
+        let error = get_rules_from_json("unknown-symbol.json", raw_rules).unwrap_err();
+        assert!(error.to_string().contains("unknown line symbol 999"));
+
+        // The area symbols are not line symbols
+        let raw_rules = r#"[{ "geometry": "line", "tags": { "natural": ["water"] }, "symbol": "301" }]"#;
+        assert!(get_rules_from_json("area-symbol.json", raw_rules).is_err());
+
+        let raw_rules = r#"[{ "geometry": "area", "tags": { "natural": ["water"] }, "symbol": "301" }]"#;
+        assert!(get_rules_from_json("area-symbol.json", raw_rules).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+
+use crate::{
+    config::SymbolSet,
+    constants::{
+        _MAJOR_POWERLINE_INNER_WIDTH, _MAJOR_POWERLINE_OUTER_WIDTH, BOULDER_DIAMETER, BUILDING_OUTLINE_WIDTH,
+        CLIFF_TAG_LENGTH, CLIFF_TAG_SPACING, CLIFF_TAG_WIDTH, CLIFF_THICKNESS_1, CLIFF_THICKNESS_2,
+        CONTOUR_THICKNESS_MILLIMETTER, CROSSABLE_WATERCOURSE_WIDTH, DOUBLE_TRACK_WIDE_ROAD_INNER_WIDTH,
+        DOUBLE_TRACK_WIDE_ROAD_OUTER_WIDTH, FENCE_TAG_LENGTH, FENCE_TAG_SPACING, FENCE_WIDTH,
+        FOOTPATH_DASH_INTERVAL_LENGTH, FOOTPATH_DASH_LENGTH, FOOTPATH_WIDTH,
+        FORM_CONTOUR_DASH_INTERVAL_LENGTH, FORM_CONTOUR_DASH_LENGTH, FORM_CONTOUR_THICKNESS_MILLIMETTER,
+        IMPASSABLE_FENCE_TAG_LENGTH, IMPASSABLE_FENCE_TAG_SPACING, IMPASSABLE_FENCE_WIDTH,
+        IMPASSABLE_WALL_WIDTH, INCH, INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH, ISSPROM_BOULDER_DIAMETER,
+        ISSPROM_BUILDING_OUTLINE_WIDTH, ISSPROM_CLIFF_THICKNESS_2,
+        ISSPROM_INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH, MARSH_LINE_SPACING, MARSH_LINE_WIDTH,
+        MASTER_CONTOUR_THICKNESS_MILLIMETTER, MINOR_WATERCOURSE_DASH_INTERVAL_LENGTH,
+        MINOR_WATERCOURSE_DASH_LENGTH, MINOR_WATERCOURSE_WIDTH, PIT_HEIGHT, PIT_LINE_WIDTH, PIT_WIDTH,
+        POWERLINE_WIDTH, RAILWAY_DASH_INTERVAL_LENGTH, RAILWAY_DASH_LENGTH, RAILWAY_INNER_WIDTH,
+        RAILWAY_OUTER_WIDTH, ROAD_WIDTH, SCATTERED_TREES_DOT_DIAMETER, SCATTERED_TREES_DOT_SPACING,
+        SMALL_DEPRESSION_HEIGHT, SMALL_DEPRESSION_LINE_WIDTH, SMALL_DEPRESSION_WIDTH, SMALL_KNOLL_DIAMETER,
+        STEP_OR_EDGE_OF_PAVED_AREA_WIDTH, UNDERGROWTH_LINE_SPACING, UNDERGROWTH_LINE_WIDTH,
+        WIDE_ROAD_INNER_WIDTH, WIDE_ROAD_OUTER_WIDTH, XL_WIDE_ROAD_INNER_WIDTH, XL_WIDE_ROAD_OUTER_WIDTH,
+        XXL_WIDE_ROAD_INNER_WIDTH, XXL_WIDE_ROAD_OUTER_WIDTH,
+    },
+};
+
+/// The line widths, dash patterns, stripe spacings and point symbol sizes of a symbol set, in millimeters
+/// on the printed map. They are specified at the base scale of the symbol set, and enlarged at the other
+/// scale when the specification requires it (see get_symbol_enlargement).
+#[derive(Clone, Copy, Serialize)]
+pub struct SymbolDimensions {
+    pub contour_width: f32,
+    pub index_contour_width: f32,
+    pub form_line_width: f32,
+    pub form_line_dash_length: f32,
+    pub form_line_dash_interval_length: f32,
+    pub small_knoll_diameter: f32,
+    pub small_depression_width: f32,
+    pub small_depression_height: f32,
+    pub small_depression_line_width: f32,
+    pub pit_width: f32,
+    pub pit_height: f32,
+    pub pit_line_width: f32,
+    pub impassable_cliff_width: f32,
+    pub cliff_width: f32,
+    pub cliff_tag_width: f32,
+    pub cliff_tag_length: f32,
+    pub cliff_tag_spacing: f32,
+    pub boulder_diameter: f32,
+    pub uncrossable_body_of_water_outline_width: f32,
+    pub crossable_watercourse_width: f32,
+    pub minor_watercourse_width: f32,
+    pub minor_watercourse_dash_length: f32,
+    pub minor_watercourse_dash_interval_length: f32,
+    pub marsh_line_width: f32,
+    pub marsh_line_spacing: f32,
+    pub undergrowth_line_width: f32,
+    pub undergrowth_line_spacing: f32,
+    pub scattered_trees_dot_diameter: f32,
+    pub scattered_trees_dot_spacing: f32,
+    pub wide_road_inner_width: f32,
+    pub wide_road_outer_width: f32,
+    pub xl_wide_road_inner_width: f32,
+    pub xl_wide_road_outer_width: f32,
+    pub xxl_wide_road_inner_width: f32,
+    pub xxl_wide_road_outer_width: f32,
+    pub double_track_wide_road_inner_width: f32,
+    pub double_track_wide_road_outer_width: f32,
+    pub road_width: f32,
+    pub footpath_width: f32,
+    pub footpath_dash_length: f32,
+    pub footpath_dash_interval_length: f32,
+    pub railway_inner_width: f32,
+    pub railway_outer_width: f32,
+    pub railway_dash_length: f32,
+    pub railway_dash_interval_length: f32,
+    pub power_line_width: f32,
+    pub _major_power_line_inner_width: f32,
+    pub _major_power_line_outer_width: f32,
+    pub building_outline_width: f32,
+    pub impassable_wall_width: f32,
+    pub fence_width: f32,
+    pub fence_tag_length: f32,
+    pub fence_tag_spacing: f32,
+    pub impassable_fence_width: f32,
+    pub impassable_fence_tag_length: f32,
+    pub impassable_fence_tag_spacing: f32,
+    pub step_or_edge_of_paved_area_width: f32,
+}
+
+/// Returns the factor the symbol dimensions of a symbol set are enlarged by at a given map scale. The ISOM
+/// symbols are specified at 1:15000 and enlarged by 150% at 1:10000. The ISSprOM symbols are specified at
+/// 1:4000, and 1:3000 maps use the same dimensions.
+pub fn get_symbol_enlargement(symbol_set: SymbolSet, map_scale: u32) -> f32 {
+    match (symbol_set, map_scale) {
+        (SymbolSet::Isom2017_2, 10000) => 1.5,
+        _ => 1.0,
+    }
+}
+
+/// Returns the symbol dimensions of the given symbol set at the given map scale.
+pub fn get_symbol_dimensions(symbol_set: SymbolSet, map_scale: u32) -> SymbolDimensions {
+    let enlargement = get_symbol_enlargement(symbol_set, map_scale);
+    let size = |millimeters: f32| millimeters * enlargement;
+
+    let isom_dimensions = SymbolDimensions {
+        contour_width: size(CONTOUR_THICKNESS_MILLIMETTER),
+        index_contour_width: size(MASTER_CONTOUR_THICKNESS_MILLIMETTER),
+        form_line_width: size(FORM_CONTOUR_THICKNESS_MILLIMETTER),
+        form_line_dash_length: size(FORM_CONTOUR_DASH_LENGTH),
+        form_line_dash_interval_length: size(FORM_CONTOUR_DASH_INTERVAL_LENGTH),
+        small_knoll_diameter: size(SMALL_KNOLL_DIAMETER),
+        small_depression_width: size(SMALL_DEPRESSION_WIDTH),
+        small_depression_height: size(SMALL_DEPRESSION_HEIGHT),
+        small_depression_line_width: size(SMALL_DEPRESSION_LINE_WIDTH),
+        pit_width: size(PIT_WIDTH),
+        pit_height: size(PIT_HEIGHT),
+        pit_line_width: size(PIT_LINE_WIDTH),
+        impassable_cliff_width: size(CLIFF_THICKNESS_2),
+        cliff_width: size(CLIFF_THICKNESS_1),
+        cliff_tag_width: size(CLIFF_TAG_WIDTH),
+        cliff_tag_length: size(CLIFF_TAG_LENGTH),
+        cliff_tag_spacing: size(CLIFF_TAG_SPACING),
+        boulder_diameter: size(BOULDER_DIAMETER),
+        uncrossable_body_of_water_outline_width: size(INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH),
+        crossable_watercourse_width: size(CROSSABLE_WATERCOURSE_WIDTH),
+        minor_watercourse_width: size(MINOR_WATERCOURSE_WIDTH),
+        minor_watercourse_dash_length: size(MINOR_WATERCOURSE_DASH_LENGTH),
+        minor_watercourse_dash_interval_length: size(MINOR_WATERCOURSE_DASH_INTERVAL_LENGTH),
+        marsh_line_width: size(MARSH_LINE_WIDTH),
+        marsh_line_spacing: size(MARSH_LINE_SPACING),
+        undergrowth_line_width: size(UNDERGROWTH_LINE_WIDTH),
+        undergrowth_line_spacing: size(UNDERGROWTH_LINE_SPACING),
+        scattered_trees_dot_diameter: size(SCATTERED_TREES_DOT_DIAMETER),
+        scattered_trees_dot_spacing: size(SCATTERED_TREES_DOT_SPACING),
+        wide_road_inner_width: size(WIDE_ROAD_INNER_WIDTH),
+        wide_road_outer_width: size(WIDE_ROAD_OUTER_WIDTH),
+        xl_wide_road_inner_width: size(XL_WIDE_ROAD_INNER_WIDTH),
+        xl_wide_road_outer_width: size(XL_WIDE_ROAD_OUTER_WIDTH),
+        xxl_wide_road_inner_width: size(XXL_WIDE_ROAD_INNER_WIDTH),
+        xxl_wide_road_outer_width: size(XXL_WIDE_ROAD_OUTER_WIDTH),
+        double_track_wide_road_inner_width: size(DOUBLE_TRACK_WIDE_ROAD_INNER_WIDTH),
+        double_track_wide_road_outer_width: size(DOUBLE_TRACK_WIDE_ROAD_OUTER_WIDTH),
+        road_width: size(ROAD_WIDTH),
+        footpath_width: size(FOOTPATH_WIDTH),
+        footpath_dash_length: size(FOOTPATH_DASH_LENGTH),
+        footpath_dash_interval_length: size(FOOTPATH_DASH_INTERVAL_LENGTH),
+        railway_inner_width: size(RAILWAY_INNER_WIDTH),
+        railway_outer_width: size(RAILWAY_OUTER_WIDTH),
+        railway_dash_length: size(RAILWAY_DASH_LENGTH),
+        railway_dash_interval_length: size(RAILWAY_DASH_INTERVAL_LENGTH),
+        power_line_width: size(POWERLINE_WIDTH),
+        _major_power_line_inner_width: size(_MAJOR_POWERLINE_INNER_WIDTH),
+        _major_power_line_outer_width: size(_MAJOR_POWERLINE_OUTER_WIDTH),
+        building_outline_width: size(BUILDING_OUTLINE_WIDTH),
+        impassable_wall_width: size(IMPASSABLE_WALL_WIDTH),
+        fence_width: size(FENCE_WIDTH),
+        fence_tag_length: size(FENCE_TAG_LENGTH),
+        fence_tag_spacing: size(FENCE_TAG_SPACING),
+        impassable_fence_width: size(IMPASSABLE_FENCE_WIDTH),
+        impassable_fence_tag_length: size(IMPASSABLE_FENCE_TAG_LENGTH),
+        impassable_fence_tag_spacing: size(IMPASSABLE_FENCE_TAG_SPACING),
+        step_or_edge_of_paved_area_width: size(STEP_OR_EDGE_OF_PAVED_AREA_WIDTH),
+    };
+
+    match symbol_set {
+        SymbolSet::Isom2017_2 => isom_dimensions,
+        SymbolSet::Issprom2019 => SymbolDimensions {
+            impassable_cliff_width: size(ISSPROM_CLIFF_THICKNESS_2),
+            boulder_diameter: size(ISSPROM_BOULDER_DIAMETER),
+            uncrossable_body_of_water_outline_width: size(ISSPROM_INCROSSABLE_BODY_OF_WATER_OUTLINE_WIDTH),
+            building_outline_width: size(ISSPROM_BUILDING_OUTLINE_WIDTH),
+            ..isom_dimensions
+        },
+    }
+}
+
+/// Converts millimeters on the printed map to pixels of the rendered map.
+pub fn millimeters_to_pixels(millimeters: f32, dpi_resolution: f32) -> f32 {
+    return millimeters * dpi_resolution * 10.0 / INCH;
+}
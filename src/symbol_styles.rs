@@ -0,0 +1,402 @@
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    config::SymbolSet,
+    constants::{
+        BROWN, GREEN_1, GREEN_2, GREEN_3, PURPLE, ROUGH_OPEN_LAND_YELLOW, VECTOR_BLACK, VECTOR_BLUE,
+        VECTOR_BROWN, VECTOR_BUILDING_GRAY, VECTOR_BUILDING_PASSAGE_GRAY, VECTOR_GREEN, VECTOR_OLIVE_GREEN,
+        VECTOR_PAVED_AREA_BROWN, WHITE, YELLOW,
+    },
+    error::CassiniError,
+    symbol_set::{get_symbol_dimensions, get_symbol_enlargement, SymbolDimensions},
+};
+
+/// The code of the depression contours in the symbol styles file, as they have no ISOM code of their own
+const DEPRESSION_CONTOUR_SYMBOL: &str = "101-depression";
+
+const fn get_rgb(color: Rgba<u8>) -> (u8, u8, u8) {
+    (color.0[0], color.0[1], color.0[2])
+}
+
+/// The changes made by a style file to a symbol. Widths and lengths are in millimeters on the printed map at
+/// the base scale of the symbol set, they are enlarged like the default ones at the other scale.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SymbolStyle {
+    /// "#rrggbb"
+    color: Option<String>,
+    width: Option<f32>,
+    /// Dash length and gap length
+    dash: Option<[f32; 2]>,
+    /// Stripe width and stripe spacing, or dot diameter and dot spacing
+    hatch: Option<[f32; 2]>,
+}
+
+/// The colors of the symbols. The color of 402 is the one of the scattered trees dots, drawn over the open
+/// land of 401 or the rough open land of 403 (404).
+#[derive(Clone, Copy, Serialize, Debug)]
+pub struct SymbolColors {
+    pub contour: (u8, u8, u8),
+    pub index_contour: (u8, u8, u8),
+    pub form_line: (u8, u8, u8),
+    pub depression_contour: (u8, u8, u8),
+    pub small_knoll: (u8, u8, u8),
+    pub small_depression: (u8, u8, u8),
+    pub pit: (u8, u8, u8),
+    pub impassable_cliff: (u8, u8, u8),
+    pub cliff: (u8, u8, u8),
+    pub boulder: (u8, u8, u8),
+    pub uncrossable_body_of_water: (u8, u8, u8),
+    pub uncrossable_body_of_water_bank_line: (u8, u8, u8),
+    pub crossable_watercourse: (u8, u8, u8),
+    pub minor_seasonal_water_channel: (u8, u8, u8),
+    pub marsh: (u8, u8, u8),
+    pub open_land: (u8, u8, u8),
+    pub scattered_trees: (u8, u8, u8),
+    pub rough_open_land: (u8, u8, u8),
+    pub forest: (u8, u8, u8),
+    pub slow_running_vegetation: (u8, u8, u8),
+    pub walk_vegetation: (u8, u8, u8),
+    pub undergrowth: (u8, u8, u8),
+    pub fight_vegetation: (u8, u8, u8),
+    pub impassable_vegetation: (u8, u8, u8),
+    pub paved_area_with_bounding_line: (u8, u8, u8),
+    pub wide_road: (u8, u8, u8),
+    pub road: (u8, u8, u8),
+    pub footpath: (u8, u8, u8),
+    pub railway: (u8, u8, u8),
+    pub power_line: (u8, u8, u8),
+    pub impassable_wall: (u8, u8, u8),
+    pub fence: (u8, u8, u8),
+    pub impassable_fence: (u8, u8, u8),
+    pub area_that_shall_not_be_entered: (u8, u8, u8),
+    pub building: (u8, u8, u8),
+    pub building_passage: (u8, u8, u8),
+    pub paved_area: (u8, u8, u8),
+    pub step_or_edge_of_paved_area: (u8, u8, u8),
+}
+
+impl SymbolColors {
+    /// Returns the color of the symbol with the given ISOM or ISSprOM code, if the symbol has a color.
+    fn get_mut(&mut self, symbol: &str) -> Option<&mut (u8, u8, u8)> {
+        let color = match symbol {
+            "101" => &mut self.contour,
+            "102" => &mut self.index_contour,
+            "103" => &mut self.form_line,
+            DEPRESSION_CONTOUR_SYMBOL => &mut self.depression_contour,
+            "109" => &mut self.small_knoll,
+            "111" => &mut self.small_depression,
+            "112" => &mut self.pit,
+            "201" => &mut self.impassable_cliff,
+            "202" => &mut self.cliff,
+            "204" => &mut self.boulder,
+            "301.1" => &mut self.uncrossable_body_of_water,
+            "301.4" => &mut self.uncrossable_body_of_water_bank_line,
+            "304" => &mut self.crossable_watercourse,
+            "306" => &mut self.minor_seasonal_water_channel,
+            "308" => &mut self.marsh,
+            "401" => &mut self.open_land,
+            "402" => &mut self.scattered_trees,
+            "403" => &mut self.rough_open_land,
+            "405" => &mut self.forest,
+            "406" => &mut self.slow_running_vegetation,
+            "408" => &mut self.walk_vegetation,
+            "409" => &mut self.undergrowth,
+            "410" => &mut self.fight_vegetation,
+            "411" => &mut self.impassable_vegetation,
+            "501.1" => &mut self.paved_area_with_bounding_line,
+            "502" => &mut self.wide_road,
+            "503" => &mut self.road,
+            "505" => &mut self.footpath,
+            "509" => &mut self.railway,
+            "510" => &mut self.power_line,
+            "515" => &mut self.impassable_wall,
+            "516" => &mut self.fence,
+            "518" => &mut self.impassable_fence,
+            "520" => &mut self.area_that_shall_not_be_entered,
+            "521" => &mut self.building,
+            "521.1" => &mut self.building_passage,
+            "529" => &mut self.paved_area,
+            "529.1" => &mut self.step_or_edge_of_paved_area,
+            _ => return None,
+        };
+
+        return Some(color);
+    }
+}
+
+pub fn get_rgba((red, green, blue): (u8, u8, u8)) -> Rgba<u8> {
+    return Rgba([red, green, blue, 255]);
+}
+
+fn get_default_symbol_colors() -> SymbolColors {
+    SymbolColors {
+        contour: get_rgb(BROWN),
+        index_contour: get_rgb(BROWN),
+        form_line: get_rgb(BROWN),
+        depression_contour: get_rgb(PURPLE),
+        small_knoll: VECTOR_BROWN,
+        small_depression: VECTOR_BROWN,
+        pit: VECTOR_BROWN,
+        impassable_cliff: VECTOR_BLACK,
+        cliff: VECTOR_BLACK,
+        boulder: VECTOR_BLACK,
+        uncrossable_body_of_water: VECTOR_BLUE,
+        uncrossable_body_of_water_bank_line: VECTOR_BLACK,
+        crossable_watercourse: VECTOR_BLUE,
+        minor_seasonal_water_channel: VECTOR_BLUE,
+        marsh: VECTOR_BLUE,
+        open_land: get_rgb(YELLOW),
+        scattered_trees: get_rgb(WHITE),
+        rough_open_land: get_rgb(ROUGH_OPEN_LAND_YELLOW),
+        forest: get_rgb(WHITE),
+        slow_running_vegetation: get_rgb(GREEN_1),
+        walk_vegetation: get_rgb(GREEN_2),
+        undergrowth: get_rgb(GREEN_3),
+        fight_vegetation: get_rgb(GREEN_3),
+        impassable_vegetation: VECTOR_GREEN,
+        paved_area_with_bounding_line: VECTOR_PAVED_AREA_BROWN,
+        wide_road: VECTOR_PAVED_AREA_BROWN,
+        road: VECTOR_BLACK,
+        footpath: VECTOR_BLACK,
+        railway: VECTOR_BLACK,
+        power_line: VECTOR_BLACK,
+        impassable_wall: VECTOR_BLACK,
+        fence: VECTOR_BLACK,
+        impassable_fence: VECTOR_BLACK,
+        area_that_shall_not_be_entered: VECTOR_OLIVE_GREEN,
+        building: VECTOR_BUILDING_GRAY,
+        building_passage: VECTOR_BUILDING_PASSAGE_GRAY,
+        paved_area: VECTOR_PAVED_AREA_BROWN,
+        step_or_edge_of_paved_area: VECTOR_BLACK,
+    }
+}
+
+/// The colors and dimensions the symbols are drawn with: the defaults of the symbol set, with the changes
+/// of the style file if any.
+#[derive(Clone, Serialize)]
+pub struct SymbolStyles {
+    pub dimensions: SymbolDimensions,
+    pub colors: SymbolColors,
+}
+
+/// Reads the symbol styles of the given symbol set at the given map scale, with the changes of the given
+/// style file.
+pub fn get_symbol_styles(
+    path: &Option<PathBuf>,
+    symbol_set: SymbolSet,
+    map_scale: u32,
+) -> Result<SymbolStyles, CassiniError> {
+    let mut styles = SymbolStyles {
+        dimensions: get_symbol_dimensions(symbol_set, map_scale),
+        colors: get_default_symbol_colors(),
+    };
+
+    let Some(path) = path else {
+        return Ok(styles);
+    };
+
+    let name = path.to_string_lossy().to_string();
+    let style_by_symbol: BTreeMap<String, SymbolStyle> = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| {
+            CassiniError::InvalidData(format!("Invalid symbol styles file {}: {}", name, error))
+        })?;
+
+    let enlargement = get_symbol_enlargement(symbol_set, map_scale);
+
+    for (symbol, style) in style_by_symbol {
+        apply_symbol_style(&mut styles, &symbol, &style, enlargement).map_err(|error| {
+            CassiniError::InvalidData(format!("Invalid symbol styles file {}: {}", name, error))
+        })?;
+    }
+
+    return Ok(styles);
+}
+
+fn apply_symbol_style(
+    styles: &mut SymbolStyles,
+    symbol: &str,
+    style: &SymbolStyle,
+    enlargement: f32,
+) -> Result<(), String> {
+    let symbol_color = styles
+        .colors
+        .get_mut(symbol)
+        .ok_or(format!("unknown symbol {}", symbol))?;
+
+    if let Some(color) = &style.color {
+        *symbol_color =
+            parse_hex_color(color).ok_or(format!("invalid color {} of symbol {}", color, symbol))?;
+    }
+
+    let dimensions = &mut styles.dimensions;
+
+    if let Some(width) = style.width {
+        let symbol_width = match symbol {
+            "101" => &mut dimensions.contour_width,
+            "102" => &mut dimensions.index_contour_width,
+            "103" => &mut dimensions.form_line_width,
+            "111" => &mut dimensions.small_depression_line_width,
+            "112" => &mut dimensions.pit_line_width,
+            "201" => &mut dimensions.impassable_cliff_width,
+            "202" => &mut dimensions.cliff_width,
+            "301.4" => &mut dimensions.uncrossable_body_of_water_outline_width,
+            "304" => &mut dimensions.crossable_watercourse_width,
+            "306" => &mut dimensions.minor_watercourse_width,
+            "503" => &mut dimensions.road_width,
+            "505" => &mut dimensions.footpath_width,
+            "509" => &mut dimensions.railway_outer_width,
+            "510" => &mut dimensions.power_line_width,
+            "515" => &mut dimensions.impassable_wall_width,
+            "516" => &mut dimensions.fence_width,
+            "518" => &mut dimensions.impassable_fence_width,
+            "521" => &mut dimensions.building_outline_width,
+            "529.1" => &mut dimensions.step_or_edge_of_paved_area_width,
+            // The bounding line is exported to OpenOrienteering Mapper as a 529.1 line, so it keeps its width
+            "501.1" => {
+                return Err("symbol 501.1 has no width, its bounding line has the width of 529.1".to_owned())
+            }
+            _ => return Err(format!("symbol {} has no width", symbol)),
+        };
+
+        *symbol_width = width * enlargement;
+    }
+
+    if let Some([dash_length, gap_length]) = style.dash {
+        let (symbol_dash_length, symbol_gap_length) = match symbol {
+            "103" => (
+                &mut dimensions.form_line_dash_length,
+                &mut dimensions.form_line_dash_interval_length,
+            ),
+            "306" => (
+                &mut dimensions.minor_watercourse_dash_length,
+                &mut dimensions.minor_watercourse_dash_interval_length,
+            ),
+            "505" => (
+                &mut dimensions.footpath_dash_length,
+                &mut dimensions.footpath_dash_interval_length,
+            ),
+            "509" => (
+                &mut dimensions.railway_dash_length,
+                &mut dimensions.railway_dash_interval_length,
+            ),
+            _ => return Err(format!("symbol {} has no dashes", symbol)),
+        };
+
+        *symbol_dash_length = dash_length * enlargement;
+        *symbol_gap_length = gap_length * enlargement;
+    }
+
+    if let Some([hatch_width, hatch_spacing]) = style.hatch {
+        let (symbol_hatch_width, symbol_hatch_spacing) = match symbol {
+            "308" => (
+                &mut dimensions.marsh_line_width,
+                &mut dimensions.marsh_line_spacing,
+            ),
+            "409" => (
+                &mut dimensions.undergrowth_line_width,
+                &mut dimensions.undergrowth_line_spacing,
+            ),
+            "402" => (
+                &mut dimensions.scattered_trees_dot_diameter,
+                &mut dimensions.scattered_trees_dot_spacing,
+            ),
+            _ => return Err(format!("symbol {} has no hatch", symbol)),
+        };
+
+        *symbol_hatch_width = hatch_width * enlargement;
+        *symbol_hatch_spacing = hatch_spacing * enlargement;
+    }
+
+    Ok(())
+}
+
+/// Parses a "#rrggbb" color.
+fn parse_hex_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#')?;
+
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let red = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let green = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let blue = u8::from_str_radix(&hex[4..6], 16).ok()?;
+
+    return Some((red, green, blue));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_style(color: Option<&str>, width: Option<f32>, dash: Option<[f32; 2]>) -> SymbolStyle {
+        SymbolStyle {
+            color: color.map(|color| color.to_owned()),
+            width,
+            dash,
+            hatch: None,
+        }
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(parse_hex_color("#d15c00"), Some((209, 92, 0)));
+        assert_eq!(parse_hex_color("#FFFFFF"), Some((255, 255, 255)));
+        assert_eq!(parse_hex_color("d15c00"), None);
+        assert_eq!(parse_hex_color("#d15c0"), None);
+        assert_eq!(parse_hex_color("#d15c0g"), None);
+        assert_eq!(parse_hex_color("#d15c00ff"), None);
+        // Six bytes, but not six ASCII characters
+        assert_eq!(parse_hex_color("#d1\u{e9}00"), None);
+    }
+
+    #[test]
+    fn symbol_style_changes_color_and_enlarged_width() {
+        let mut styles = get_symbol_styles(&None, SymbolSet::Isom2017_2, 10000).unwrap();
+
+        apply_symbol_style(
+            &mut styles,
+            "503",
+            &get_style(Some("#ff0000"), Some(0.5), None),
+            1.5,
+        )
+        .unwrap();
+
+        assert_eq!(styles.colors.road, (255, 0, 0));
+        assert_eq!(styles.dimensions.road_width, 0.75);
+    }
+
+    #[test]
+    fn symbol_style_rejects_what_the_symbol_does_not_have() {
+        let mut styles = get_symbol_styles(&None, SymbolSet::Isom2017_2, 15000).unwrap();
+
+        let errors = [
+            ("999", get_style(Some("#000000"), None, None)),
+            ("503", get_style(Some("#00000"), None, None)),
+            ("401", get_style(None, Some(0.1), None)),
+            ("501.1", get_style(None, Some(0.1), None)),
+            ("503", get_style(None, None, Some([1., 0.5]))),
+            (
+                "405",
+                SymbolStyle {
+                    color: None,
+                    width: None,
+                    dash: None,
+                    hatch: Some([0.1, 0.5]),
+                },
+            ),
+        ];
+
+        for (symbol, style) in errors {
+            assert!(apply_symbol_style(&mut styles, symbol, &style, 1.).is_err());
+        }
+
+        assert_eq!(
+            apply_symbol_style(&mut styles, "401", &get_style(None, Some(0.1), None), 1.),
+            Err("symbol 401 has no width".to_owned())
+        );
+    }
+}
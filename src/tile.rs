@@ -2,6 +2,8 @@ use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
+use crate::error::CassiniError;
+
 #[derive(Debug, Clone)]
 pub struct TileWithNeighbors {
     pub laz_path: PathBuf,
@@ -17,26 +19,35 @@ pub struct Tile {
     pub min_y: i64,
     pub max_x: i64,
     pub max_y: i64,
+    pub crs: String,
 }
 
-pub fn get_extent_from_lidar_dir_path(lidar_dir_path: &PathBuf) -> (i64, i64, i64, i64) {
+pub fn get_extent_from_lidar_dir_path(
+    lidar_dir_path: &PathBuf,
+) -> Result<(i64, i64, i64, i64), CassiniError> {
     let extent_file_path = lidar_dir_path.join("extent.txt");
-    let mut file = File::open(extent_file_path).expect("Could not read the extent.txt file");
+    let mut file = File::open(extent_file_path)?;
 
     let mut extent_content = String::new();
-    file.read_to_string(&mut extent_content)
-        .expect("Could not read the extent.txt file");
+    file.read_to_string(&mut extent_content)?;
+
+    let corrupted_file_error = || {
+        CassiniError::InvalidData(format!(
+            "The extent.txt file in {:?} is corrupted",
+            lidar_dir_path
+        ))
+    };
 
     let parts: Vec<i64> = extent_content
         .trim()
         .split('|')
         .map(|s| s.parse::<i64>())
         .collect::<Result<Vec<_>, _>>()
-        .expect("The extent.txt file is corrupted");
+        .map_err(|_| corrupted_file_error())?;
 
     if parts.len() != 4 {
-        panic!("The extent.txt file is corrupted")
+        return Err(corrupted_file_error());
     }
 
-    return (parts[0], parts[1], parts[2], parts[3]);
+    return Ok((parts[0], parts[1], parts[2], parts[3]));
 }
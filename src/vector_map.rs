@@ -0,0 +1,725 @@
+use image::{codecs::png::PngEncoder, ExtendedColorType, ImageEncoder, RgbImage};
+use log::warn;
+use shapefile::{
+    dbase::{FieldValue, Record},
+    read_as, Point, Polyline,
+};
+use std::{fs::File, io::Write, path::PathBuf};
+
+use crate::{
+    error::CassiniError, helpers::escape_xml, projection::Projection, symbol_set::millimeters_to_pixels,
+    symbol_styles::SymbolStyles, tile::Tile,
+};
+
+const POINTS_PER_INCH: f32 = 72.;
+/// Control point distance of the cubic Bézier curves approximating a quarter of a circle
+const CIRCLE_KAPPA: f32 = 0.5523;
+const WGS84_WKT: &str = "GEOGCS[\"WGS 84\",DATUM[\"WGS_1984\",SPHEROID[\"WGS 84\",6378137,298.257223563]],PRIMEM[\"Greenwich\",0],UNIT[\"degree\",0.0174532925199433]]";
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum VectorMapFormat {
+    Svg,
+    Pdf,
+}
+
+/// A shape of a map layer, in pixels of the raster map.
+pub enum VectorShape {
+    Line {
+        points: Vec<(f32, f32)>,
+        color: (u8, u8, u8),
+        width: f32,
+        dash: Option<(f32, f32)>,
+    },
+    Area {
+        outer: Vec<(f32, f32)>,
+        holes: Vec<Vec<(f32, f32)>>,
+        color: (u8, u8, u8),
+    },
+    Dot {
+        center: (f32, f32),
+        radius: f32,
+        color: (u8, u8, u8),
+    },
+    /// An area cleared from the layer, like the contours crossing a lake
+    Erased {
+        outer: Vec<(f32, f32)>,
+        holes: Vec<Vec<(f32, f32)>>,
+    },
+}
+
+struct VectorLayer {
+    name: &'static str,
+    shapes: Vec<VectorShape>,
+}
+
+/// A map made of a vegetation raster background and of named layers of vector shapes,
+/// written as a georeferenced SVG or PDF file at the scale of the raster map.
+pub struct VectorMap {
+    format: VectorMapFormat,
+    min_x: i64,
+    min_y: i64,
+    max_x: i64,
+    max_y: i64,
+    crs: String,
+    image_width: u32,
+    image_height: u32,
+    scale_factor: f32,
+    dpi_resolution: f32,
+    background: Option<RgbImage>,
+    layers: Vec<VectorLayer>,
+    contours_shapes: Vec<VectorShape>,
+}
+
+impl VectorMap {
+    /// Loads the contours rendered by the previous stages of the pipeline.
+    pub fn new(
+        tile: &Tile,
+        image_width: u32,
+        image_height: u32,
+        scale_factor: f32,
+        dpi_resolution: f32,
+        symbol_styles: &SymbolStyles,
+        format: VectorMapFormat,
+    ) -> Result<VectorMap, CassiniError> {
+        let mut vector_map = VectorMap {
+            format,
+            min_x: tile.min_x,
+            min_y: tile.min_y,
+            max_x: tile.max_x,
+            max_y: tile.max_y,
+            crs: tile.crs.clone(),
+            image_width,
+            image_height,
+            scale_factor,
+            dpi_resolution,
+            background: None,
+            layers: vec![],
+            contours_shapes: vec![],
+        };
+
+        vector_map.contours_shapes = vector_map.read_contours_shapes(
+            &tile
+                .render_dir_path
+                .join("contours")
+                .join("rendered-contours.shp"),
+            symbol_styles,
+        )?;
+
+        return Ok(vector_map);
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self.format {
+            VectorMapFormat::Svg => "svg",
+            VectorMapFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Sets the raster background of the map from a PNG image of the raster map size.
+    pub fn set_background(&mut self, png_data: &[u8]) -> Result<(), CassiniError> {
+        self.background = Some(image::load_from_memory(png_data)?.to_rgb8());
+        Ok(())
+    }
+
+    pub fn add_layer(&mut self, name: &'static str, shapes: Vec<VectorShape>) {
+        if !shapes.is_empty() {
+            self.layers.push(VectorLayer { name, shapes });
+        }
+    }
+
+    /// Adds the contours, with the areas erased from the contours layer of the raster map.
+    pub fn add_contours_layer(&mut self, erased_shapes: Vec<VectorShape>) {
+        let mut shapes = std::mem::take(&mut self.contours_shapes);
+        shapes.extend(erased_shapes);
+        self.add_layer("Contours", shapes);
+    }
+
+    pub fn save_as(&self, path: &PathBuf) -> Result<(), CassiniError> {
+        let content = match self.format {
+            VectorMapFormat::Svg => self.get_svg_content()?.into_bytes(),
+            VectorMapFormat::Pdf => self.get_pdf_content()?,
+        };
+
+        let mut file = File::create(path)?;
+        file.write_all(&content)?;
+
+        Ok(())
+    }
+
+    fn to_pixel(&self, point: &Point) -> (f32, f32) {
+        (
+            (point.x - self.min_x as f64) as f32 * self.scale_factor,
+            self.image_height as f32 - (point.y - self.min_y as f64) as f32 * self.scale_factor,
+        )
+    }
+
+    fn read_contours_shapes(
+        &self,
+        path: &PathBuf,
+        symbol_styles: &SymbolStyles,
+    ) -> Result<Vec<VectorShape>, CassiniError> {
+        let mut shapes: Vec<VectorShape> = vec![];
+        let colors = &symbol_styles.colors;
+
+        for (line, record) in read_as::<_, Polyline, Record>(path)? {
+            let kind = match record.get("kind") {
+                Some(FieldValue::Character(Some(kind))) => kind.as_str(),
+                _ => "",
+            };
+
+            let (symbol_color, thickness) = match kind {
+                "index" => (colors.index_contour, symbol_styles.dimensions.index_contour_width),
+                "formline" => (colors.form_line, symbol_styles.dimensions.form_line_width),
+                _ => (colors.contour, symbol_styles.dimensions.contour_width),
+            };
+
+            let color = match record.get("depression") {
+                Some(FieldValue::Integer(1)) => colors.depression_contour,
+                _ => symbol_color,
+            };
+
+            for part in line.parts() {
+                shapes.push(VectorShape::Line {
+                    points: part.iter().map(|point| self.to_pixel(point)).collect(),
+                    color,
+                    width: millimeters_to_pixels(thickness, self.dpi_resolution),
+                    dash: None,
+                });
+            }
+        }
+
+        return Ok(shapes);
+    }
+
+    fn get_svg_content(&self) -> Result<String, CassiniError> {
+        let millimeters_per_pixel = 1.0 / millimeters_to_pixels(1.0, self.dpi_resolution);
+        let width = self.image_width as f32 * millimeters_per_pixel;
+        let height = self.image_height as f32 * millimeters_per_pixel;
+
+        let mut svg = String::new();
+        svg.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        svg.push_str(&format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+            xmlns:inkscape=\"http://www.inkscape.org/namespaces/inkscape\" \
+            width=\"{:.3}mm\" height=\"{:.3}mm\" viewBox=\"0 0 {} {}\">\n",
+            width, height, self.image_width, self.image_height
+        ));
+
+        // SVG has no standard georeferencing, the extent and the CRS are stored as metadata
+        svg.push_str(&format!(
+            "<metadata><cassini:georeferencing xmlns:cassini=\"https://cassini-map.com\" \
+            min_x=\"{}\" min_y=\"{}\" max_x=\"{}\" max_y=\"{}\" crs=\"{}\"/></metadata>\n",
+            self.min_x,
+            self.min_y,
+            self.max_x,
+            self.max_y,
+            escape_xml(&self.crs)
+        ));
+
+        if let Some(background) = &self.background {
+            svg.push_str(
+                "<g id=\"vegetation\" inkscape:groupmode=\"layer\" inkscape:label=\"Vegetation\">\n",
+            );
+            svg.push_str(&format!(
+                "<image x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" \
+                xlink:href=\"data:image/png;base64,{}\"/>\n",
+                self.image_width,
+                self.image_height,
+                encode_base64(&encode_png(background)?)
+            ));
+            svg.push_str("</g>\n");
+        }
+
+        for layer in &self.layers {
+            let id = layer.name.to_lowercase().replace(' ', "-");
+            let erased_path_data = get_erased_path_data(&layer.shapes);
+
+            if erased_path_data.is_empty() {
+                svg.push_str(&format!(
+                    "<g id=\"{}\" inkscape:groupmode=\"layer\" inkscape:label=\"{}\">\n",
+                    id, layer.name
+                ));
+            } else {
+                // Erased areas are cut out of the layer with an even-odd clip path around the map
+                svg.push_str(&format!(
+                    "<clipPath id=\"{}-clip\"><path clip-rule=\"evenodd\" d=\"M0 0H{}V{}H0Z{}\"/></clipPath>\n",
+                    id, self.image_width, self.image_height, erased_path_data
+                ));
+                svg.push_str(&format!(
+                    "<g id=\"{}\" inkscape:groupmode=\"layer\" inkscape:label=\"{}\" clip-path=\"url(#{}-clip)\">\n",
+                    id, layer.name, id
+                ));
+            }
+
+            for shape in &layer.shapes {
+                match shape {
+                    VectorShape::Line {
+                        points,
+                        color,
+                        width,
+                        dash,
+                    } => {
+                        let dash_attribute = match dash {
+                            Some((on, off)) => format!(" stroke-dasharray=\"{:.2} {:.2}\"", on, off),
+                            None => String::new(),
+                        };
+
+                        svg.push_str(&format!(
+                            "<path d=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"{:.2}\"{}/>\n",
+                            get_path_data(points, false),
+                            get_hex_color(color),
+                            width,
+                            dash_attribute
+                        ));
+                    }
+                    VectorShape::Area { outer, holes, color } => {
+                        let mut path_data = get_path_data(outer, true);
+
+                        for hole in holes {
+                            path_data.push_str(&get_path_data(hole, true));
+                        }
+
+                        svg.push_str(&format!(
+                            "<path d=\"{}\" fill=\"{}\" fill-rule=\"evenodd\"/>\n",
+                            path_data,
+                            get_hex_color(color)
+                        ));
+                    }
+                    VectorShape::Dot {
+                        center,
+                        radius,
+                        color,
+                    } => {
+                        svg.push_str(&format!(
+                            "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\" fill=\"{}\"/>\n",
+                            center.0,
+                            center.1,
+                            radius,
+                            get_hex_color(color)
+                        ));
+                    }
+                    VectorShape::Erased { .. } => (),
+                }
+            }
+
+            svg.push_str("</g>\n");
+        }
+
+        svg.push_str("</svg>\n");
+
+        return Ok(svg);
+    }
+
+    fn get_pdf_content(&self) -> Result<Vec<u8>, CassiniError> {
+        let points_per_pixel = POINTS_PER_INCH / self.dpi_resolution;
+        let width = self.image_width as f32 * points_per_pixel;
+        let height = self.image_height as f32 * points_per_pixel;
+
+        // Objects 1 to 4 are the catalog, the pages, the page and its content, then come the image,
+        // the optional content groups (the layers) and the georeferencing.
+        let image_object = 5;
+        let first_layer_object = 6;
+        let measure_object = first_layer_object + self.layers.len();
+
+        let mut content = String::new();
+
+        // Drawing in pixels of the raster map, with the y axis going down
+        content.push_str(&format!(
+            "{:.6} 0 0 {:.6} 0 {:.3} cm\n",
+            points_per_pixel, -points_per_pixel, height
+        ));
+
+        if self.background.is_some() {
+            content.push_str(&format!(
+                "q {} 0 0 {} 0 {} cm /Im0 Do Q\n",
+                self.image_width,
+                -(self.image_height as i64),
+                self.image_height
+            ));
+        }
+
+        for (layer_index, layer) in self.layers.iter().enumerate() {
+            content.push_str(&format!("/OC /L{} BDC\nq\n", layer_index));
+
+            let erased_path = get_pdf_erased_path(&layer.shapes);
+
+            if !erased_path.is_empty() {
+                content.push_str(&format!(
+                    "0 0 {} {} re\n{}W* n\n",
+                    self.image_width, self.image_height, erased_path
+                ));
+            }
+
+            for shape in &layer.shapes {
+                match shape {
+                    VectorShape::Line {
+                        points,
+                        color,
+                        width,
+                        dash,
+                    } => {
+                        let dash_pattern = match dash {
+                            Some((on, off)) => format!("[{:.2} {:.2}] 0 d", on, off),
+                            None => "[] 0 d".to_string(),
+                        };
+
+                        content.push_str(&format!(
+                            "{} RG {:.2} w {}\n{}S\n",
+                            get_pdf_color(color),
+                            width,
+                            dash_pattern,
+                            get_pdf_path(points, false)
+                        ));
+                    }
+                    VectorShape::Area { outer, holes, color } => {
+                        content.push_str(&format!(
+                            "{} rg\n{}",
+                            get_pdf_color(color),
+                            get_pdf_path(outer, true)
+                        ));
+
+                        for hole in holes {
+                            content.push_str(&get_pdf_path(hole, true));
+                        }
+
+                        content.push_str("f*\n");
+                    }
+                    VectorShape::Dot {
+                        center,
+                        radius,
+                        color,
+                    } => {
+                        content.push_str(&format!(
+                            "{} rg\n{}f\n",
+                            get_pdf_color(color),
+                            get_pdf_circle_path(*center, *radius)
+                        ));
+                    }
+                    VectorShape::Erased { .. } => (),
+                }
+            }
+
+            content.push_str("Q\nEMC\n");
+        }
+
+        let layer_references = (0..self.layers.len())
+            .map(|layer_index| format!("{} 0 R", first_layer_object + layer_index))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let layer_properties = (0..self.layers.len())
+            .map(|layer_index| format!("/L{} {} 0 R", layer_index, first_layer_object + layer_index))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        let measure = self.get_pdf_measure();
+
+        let viewport = match measure {
+            Some(_) => format!(
+                " /VP [<< /Type /Viewport /BBox [0 0 {:.3} {:.3}] /Measure {} 0 R >>]",
+                width, height, measure_object
+            ),
+            None => String::new(),
+        };
+
+        // The image object is a null one when there is no background, so it must not be referenced
+        let image_resources = match &self.background {
+            Some(_) => format!("/XObject << /Im0 {} 0 R >> ", image_object),
+            None => String::new(),
+        };
+
+        let mut objects: Vec<Vec<u8>> = vec![
+            format!(
+                "<< /Type /Catalog /Pages 2 0 R /OCProperties << /OCGs [{}] /D << /Order [{}] /ON [{}] >> >> >>",
+                layer_references, layer_references, layer_references
+            )
+            .into_bytes(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.3} {:.3}] /Contents 4 0 R \
+                /Resources << {}/Properties << {} >> >>{} >>",
+                width, height, image_resources, layer_properties, viewport
+            )
+            .into_bytes(),
+            get_pdf_stream(&format!("<< /Length {} >>", content.len()), content.as_bytes()),
+        ];
+
+        objects.push(match &self.background {
+            Some(background) => {
+                let deflated_data = get_png_deflated_data(&encode_png(background)?);
+
+                // The deflated data of a PNG file keeps its filter bytes, that PDF decodes as predictors
+                get_pdf_stream(
+                    &format!(
+                        "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+                        /BitsPerComponent 8 /Filter /FlateDecode \
+                        /DecodeParms << /Predictor 15 /Colors 3 /BitsPerComponent 8 /Columns {} >> /Length {} >>",
+                        background.width(),
+                        background.height(),
+                        background.width(),
+                        deflated_data.len()
+                    ),
+                    &deflated_data,
+                )
+            }
+            None => b"null".to_vec(),
+        });
+
+        for layer in &self.layers {
+            objects.push(format!("<< /Type /OCG /Name ({}) >>", layer.name).into_bytes());
+        }
+
+        if let Some(measure) = measure {
+            objects.push(measure.into_bytes());
+        }
+
+        return Ok(get_pdf_file(objects));
+    }
+
+    /// The geospatial measure dictionary of ISO 32000-2, giving the WGS84 coordinates of the map corners.
+    fn get_pdf_measure(&self) -> Option<String> {
+        let Some(projection) = Projection::from_crs(&self.crs) else {
+            warn!(
+                "Tile min_x={} min_y={} max_x={} max_y={}. Unsupported CRS {}, the PDF map is not georeferenced",
+                self.min_x, self.min_y, self.max_x, self.max_y, self.crs
+            );
+
+            return None;
+        };
+
+        // Bottom left, top left, top right and bottom right corners
+        let corners = [
+            (self.min_x, self.min_y),
+            (self.min_x, self.max_y),
+            (self.max_x, self.max_y),
+            (self.max_x, self.min_y),
+        ];
+
+        let geographic_points = corners
+            .iter()
+            .map(|(x, y)| {
+                let (longitude, latitude) = projection.to_wgs84(*x as f64, *y as f64);
+                format!("{:.9} {:.9}", latitude, longitude)
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        Some(format!(
+            "<< /Type /Measure /Subtype /GEO /Bounds [0 0 0 1 1 1 1 0] /LPTS [0 0 0 1 1 1 1 0] \
+            /GPTS [{}] /GCS << /Type /GEOGCS /EPSG 4326 /WKT ({}) >> /PDU [/M /SQM /DEG] >>",
+            geographic_points, WGS84_WKT
+        ))
+    }
+}
+
+fn get_path_data(points: &[(f32, f32)], is_closed: bool) -> String {
+    let mut path_data = String::new();
+
+    for (index, (x, y)) in points.iter().enumerate() {
+        let command = if index == 0 { "M" } else { "L" };
+        path_data.push_str(&format!("{}{:.2} {:.2}", command, x, y));
+    }
+
+    if is_closed {
+        path_data.push('Z');
+    }
+
+    return path_data;
+}
+
+fn get_erased_path_data(shapes: &Vec<VectorShape>) -> String {
+    let mut path_data = String::new();
+
+    for shape in shapes {
+        if let VectorShape::Erased { outer, holes } = shape {
+            path_data.push_str(&get_path_data(outer, true));
+
+            for hole in holes {
+                path_data.push_str(&get_path_data(hole, true));
+            }
+        }
+    }
+
+    return path_data;
+}
+
+fn get_pdf_path(points: &[(f32, f32)], is_closed: bool) -> String {
+    let mut path = String::new();
+
+    for (index, (x, y)) in points.iter().enumerate() {
+        let operator = if index == 0 { "m" } else { "l" };
+        path.push_str(&format!("{:.2} {:.2} {}\n", x, y, operator));
+    }
+
+    if is_closed {
+        path.push_str("h\n");
+    }
+
+    return path;
+}
+
+fn get_pdf_erased_path(shapes: &Vec<VectorShape>) -> String {
+    let mut path = String::new();
+
+    for shape in shapes {
+        if let VectorShape::Erased { outer, holes } = shape {
+            path.push_str(&get_pdf_path(outer, true));
+
+            for hole in holes {
+                path.push_str(&get_pdf_path(hole, true));
+            }
+        }
+    }
+
+    return path;
+}
+
+fn get_pdf_circle_path((x, y): (f32, f32), radius: f32) -> String {
+    let k = radius * CIRCLE_KAPPA;
+
+    format!(
+        "{:.2} {:.2} m\n\
+        {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c\n\
+        {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c\n\
+        {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c\n\
+        {:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c\n",
+        x + radius,
+        y,
+        x + radius,
+        y + k,
+        x + k,
+        y + radius,
+        x,
+        y + radius,
+        x - k,
+        y + radius,
+        x - radius,
+        y + k,
+        x - radius,
+        y,
+        x - radius,
+        y - k,
+        x - k,
+        y - radius,
+        x,
+        y - radius,
+        x + k,
+        y - radius,
+        x + radius,
+        y - k,
+        x + radius,
+        y
+    )
+}
+
+pub fn get_hex_color((red, green, blue): &(u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+fn get_pdf_color((red, green, blue): &(u8, u8, u8)) -> String {
+    format!(
+        "{:.3} {:.3} {:.3}",
+        *red as f32 / 255.,
+        *green as f32 / 255.,
+        *blue as f32 / 255.
+    )
+}
+
+fn get_pdf_stream(dictionary: &str, data: &[u8]) -> Vec<u8> {
+    let mut stream = format!("{}\nstream\n", dictionary).into_bytes();
+    stream.extend_from_slice(data);
+    stream.extend_from_slice(b"\nendstream");
+    return stream;
+}
+
+/// Numbers the objects from 1, and appends the cross-reference table and the trailer.
+fn get_pdf_file(objects: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.7\n".to_vec();
+    let mut offsets: Vec<usize> = vec![];
+
+    for (index, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", index + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+
+    return pdf;
+}
+
+fn encode_png(image: &RgbImage) -> Result<Vec<u8>, CassiniError> {
+    let mut png_data: Vec<u8> = vec![];
+
+    PngEncoder::new(&mut png_data).write_image(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        ExtendedColorType::Rgb8,
+    )?;
+
+    return Ok(png_data);
+}
+
+/// Concatenates the IDAT chunks of a PNG file, which form a single zlib stream.
+fn get_png_deflated_data(png_data: &[u8]) -> Vec<u8> {
+    let mut deflated_data: Vec<u8> = vec![];
+    // Skipping the PNG signature
+    let mut offset = 8;
+
+    while offset + 8 <= png_data.len() {
+        let chunk_length = u32::from_be_bytes([
+            png_data[offset],
+            png_data[offset + 1],
+            png_data[offset + 2],
+            png_data[offset + 3],
+        ]) as usize;
+
+        let chunk_type = &png_data[offset + 4..offset + 8];
+        let chunk_data_start = offset + 8;
+
+        if chunk_type == b"IDAT" {
+            deflated_data.extend_from_slice(&png_data[chunk_data_start..chunk_data_start + chunk_length]);
+        }
+
+        // Chunk length, type, data and CRC
+        offset = chunk_data_start + chunk_length + 4;
+    }
+
+    return deflated_data;
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let triple = ((bytes[0] as u32) << 16) | ((bytes[1] as u32) << 8) | bytes[2] as u32;
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[((triple >> (18 - 6 * index)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    return encoded;
+}
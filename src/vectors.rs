@@ -1,15 +1,22 @@
 use crate::{
     coastlines::get_polygon_with_holes_from_coastlines,
-    config::Config,
-    constants::{COASTLINE_EDGE_BUFFER, INCH},
+    config::{BuildingsMode, Config},
+    constants::COASTLINE_EDGE_BUFFER,
+    error::CassiniError,
     helpers::does_polyline_intersect_tile,
+    land_cover::is_area_covered,
     map_renderer::MapRenderer,
+    omap::OmapMap,
+    streams::{is_stream_along_waterways, snap_waterway_to_streams},
+    symbol_rules::{find_symbol_rule, RuleGeometry, SymbolRule, COASTLINE_SYMBOL},
+    symbol_styles::SymbolStyles,
     tile::Tile,
+    vector_map::{VectorMap, VectorMapFormat},
 };
 use log::info;
 use shapefile::{
     dbase::{FieldValue, Record},
-    read_as, Polygon, Polyline,
+    read_as, Point, Polygon, Polyline,
 };
 use std::{collections::HashMap, path::PathBuf, time::Instant};
 
@@ -21,12 +28,34 @@ pub fn render_map_with_osm_vector_shapes(
     vegetation_path: &PathBuf,
     undergrowth_path: &PathBuf,
     contours_path: &PathBuf,
-    cliffs_path: &PathBuf,
     skip_520: bool,
+    symbol_rules: &Vec<SymbolRule>,
+    symbol_styles: &SymbolStyles,
     shapes_path: Option<PathBuf>,
-) {
+    vector_map_format: Option<VectorMapFormat>,
+    omap: bool,
+) -> Result<(), CassiniError> {
     let start = Instant::now();
-    let scale_factor = config.dpi_resolution / INCH;
+    let scale_factor = config.get_pixels_per_meter();
+
+    let vector_map = match vector_map_format {
+        Some(format) => Some(VectorMap::new(
+            tile,
+            image_width,
+            image_height,
+            scale_factor,
+            config.dpi_resolution,
+            symbol_styles,
+            format,
+        )?),
+        None => None,
+    };
+
+    let omap_map = if omap {
+        Some(OmapMap::new(tile, config, symbol_styles)?)
+    } else {
+        None
+    };
 
     let mut map_renderer = MapRenderer::new(
         tile.min_x,
@@ -35,11 +64,61 @@ pub fn render_map_with_osm_vector_shapes(
         image_height,
         scale_factor,
         config.dpi_resolution,
+        symbol_styles,
         vegetation_path,
         undergrowth_path,
         contours_path,
-        cliffs_path,
-    );
+        vector_map.is_some(),
+        omap_map,
+    )?;
+
+    // Cliffs are drawn first so that water areas can erase them
+    let cliffs_path = tile.render_dir_path.join("cliffs").join("cliffs.shp");
+
+    for (line, record) in read_as::<_, Polyline, Record>(&cliffs_path)? {
+        let slope = match record.get("slope") {
+            Some(FieldValue::Numeric(Some(slope))) => *slope,
+            _ => 0.,
+        };
+
+        map_renderer = if slope > config.cliff_threshold_2 as f64 {
+            map_renderer.impassable_cliff_201(&line)
+        } else {
+            map_renderer.cliff_202(&line)
+        };
+    }
+
+    let micro_relief_path = tile.render_dir_path.join("micro-relief");
+
+    for (point, _) in read_as::<_, Point, Record>(&micro_relief_path.join("knolls.shp"))? {
+        map_renderer = map_renderer.small_knoll_109(&point);
+    }
+
+    for (point, _) in read_as::<_, Point, Record>(&micro_relief_path.join("depressions.shp"))? {
+        map_renderer = map_renderer.small_depression_111(&point);
+    }
+
+    for (point, _) in read_as::<_, Point, Record>(&micro_relief_path.join("pits.shp"))? {
+        map_renderer = map_renderer.pit_112(&point);
+    }
+
+    for (point, _) in read_as::<_, Point, Record>(&micro_relief_path.join("boulders.shp"))? {
+        map_renderer = map_renderer.boulder_204(&point);
+    }
+
+    let streams_path = tile.render_dir_path.join("streams").join("streams.shp");
+    let streams = read_as::<_, Polyline, Record>(&streams_path)?;
+
+    let stream_lines: Vec<Vec<(f64, f64)>> = streams
+        .iter()
+        .flat_map(|(line, _)| line.parts().iter())
+        .map(|part| part.iter().map(|point| (point.x, point.y)).collect())
+        .collect();
+
+    let mut waterways: Vec<Vec<(f64, f64)>> = vec![];
+    let mut drawn_areas: Vec<Polygon> = vec![];
+    let mut osm_buildings: Vec<Polygon> = vec![];
+    let mut building_passages: Vec<Polygon> = vec![];
 
     if let Some(shapes_path) = shapes_path {
         info!(
@@ -48,31 +127,27 @@ pub fn render_map_with_osm_vector_shapes(
         );
 
         let multipolygons_path = shapes_path.join("multipolygons.shp");
-        let multipolygons = read_as::<_, Polygon, Record>(&multipolygons_path)
-            .expect("Could not open multipolygons shapefile");
+        let multipolygons = read_as::<_, Polygon, Record>(&multipolygons_path)?;
 
         let mut islands: Vec<Vec<(f32, f32)>> = vec![];
 
         for (polygon, record) in multipolygons {
-            let natural = match record.get("natural") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
-            };
+            let other_tags = get_and_parse_other_tags(&record);
 
-            // 308 marsh
-            if natural == "wetland" {
-                map_renderer = map_renderer.marsh_308(&polygon);
-                continue;
-            }
+            let rule = match find_symbol_rule(
+                symbol_rules,
+                RuleGeometry::Area,
+                config.symbol_set,
+                &record,
+                &other_tags,
+            ) {
+                Some(rule) => rule,
+                None => continue,
+            };
 
-            // 301 uncrossable body of water
-            if natural == "water" {
-                map_renderer = map_renderer.uncrossable_body_of_water_301(&polygon);
-                continue;
-            }
+            let symbol = rule.symbol.as_str();
 
-            if natural == "coastline" {
+            if symbol == COASTLINE_SYMBOL {
                 let mut points: Vec<(f32, f32)> = vec![];
 
                 for point in polygon.rings()[0].points() {
@@ -83,191 +158,84 @@ pub fn render_map_with_osm_vector_shapes(
                 continue;
             }
 
-            let building = match record.get("building") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
-            };
-
-            // 521 building
-            if building != "" {
-                map_renderer = map_renderer.building_521(&polygon);
+            // 520 area that shall not be entered
+            if symbol == "520" && skip_520 {
                 continue;
             }
 
-            if !skip_520 {
-                let landuse = match record.get("landuse") {
-                    Some(FieldValue::Character(Some(x))) => x,
-                    Some(_) => "",
-                    None => "",
-                };
-
-                // 520 area that shall not be entered
-                if landuse == "residential" || landuse == "railway" || landuse == "industrial" {
-                    map_renderer = map_renderer.area_that_shall_not_be_entered_520(&polygon);
+            // 521 building and 521.1 building passage
+            if symbol == "521" || symbol == "521.1" {
+                if config.buildings.mode == BuildingsMode::Replace {
                     continue;
                 }
-            }
-        }
-
-        let lines_path = shapes_path.join("lines.shp");
-        let lines = read_as::<_, Polyline, Record>(lines_path).expect("Could not open lines shapefile");
-
-        let mut coastlines: Vec<Vec<(f32, f32)>> = vec![];
 
-        for (line, record) in lines {
-            let highway = match record.get("highway") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
-            };
-
-            // 502 wide road
-            if highway == "motorway" || highway == "motorway_link" {
-                map_renderer = map_renderer.double_track_wide_road_502(&line);
-                continue;
+                osm_buildings.push(polygon.clone());
             }
 
-            if highway == "trunk"
-                || highway == "trunk_link"
-                || highway == "primary"
-                || highway == "primary_link"
-            {
-                map_renderer = map_renderer.xxl_wide_road_502(&line);
+            // The passages are drawn once all the buildings are
+            if symbol == "521.1" {
+                building_passages.push(polygon.clone());
+                drawn_areas.push(polygon);
                 continue;
             }
 
-            if highway == "secondary"
-                || highway == "secondary_link"
-                || highway == "tertiary"
-                || highway == "tertiary_link"
-            {
-                map_renderer = map_renderer.xl_wide_road_502(&line);
-                continue;
+            if let Some(draw_symbol) = MapRenderer::get_area_symbol_function(symbol) {
+                map_renderer = draw_symbol(map_renderer, &polygon);
+                drawn_areas.push(polygon);
             }
+        }
 
-            if highway == "residential"
-                || highway == "unclassified"
-                || highway == "living_street"
-                || highway == "service"
-                || highway == "pedestrian"
-                || highway == "bus_guideway"
-                || highway == "escape"
-                || highway == "road"
-                || highway == "busway"
-            {
-                map_renderer = map_renderer.wide_road_502(&line);
-                continue;
-            }
+        let lines_path = shapes_path.join("lines.shp");
+        let lines = read_as::<_, Polyline, Record>(lines_path)?;
 
-            // 503 road
-            if highway == "track" || highway == "cycleway" {
-                map_renderer = map_renderer.road_503(&line);
-                continue;
-            }
+        let mut coastlines: Vec<Vec<(f32, f32)>> = vec![];
 
-            // 505 footpath
-            if highway == "footway"
-                || highway == "bridleway"
-                || highway == "steps"
-                || highway == "path"
-                || highway == "footpath"
-            {
-                map_renderer = map_renderer.footpath_505(&line);
-                continue;
-            }
+        for (line, record) in lines {
+            let other_tags = get_and_parse_other_tags(&record);
 
-            let waterway = match record.get("waterway") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
+            let rule = match find_symbol_rule(
+                symbol_rules,
+                RuleGeometry::Line,
+                config.symbol_set,
+                &record,
+                &other_tags,
+            ) {
+                Some(rule) => rule,
+                None => continue,
             };
 
-            let should_draw_water_course = waterway == "stream" || waterway == "drain" || waterway == "ditch";
-
-            // 304 crossable watercourse
-            if should_draw_water_course {
-                let itermittent = match record.get("itermittent") {
-                    Some(FieldValue::Character(Some(x))) => x,
-                    Some(_) => "",
-                    None => "",
-                };
+            let symbol = rule.symbol.as_str();
 
-                let seasonal = match record.get("seasonal") {
-                    Some(FieldValue::Character(Some(x))) => x,
-                    Some(_) => "",
-                    None => "",
-                };
+            if symbol == COASTLINE_SYMBOL {
+                let mut points: Vec<(f32, f32)> = vec![];
 
-                if itermittent != "" || seasonal != "" {
-                    map_renderer = map_renderer.minor_seasonal_water_channel_306(&line);
-                } else {
-                    map_renderer = map_renderer.crossable_watercourse_304(&line);
+                for point in line.parts()[0].clone() {
+                    points.push((point.x as f32, point.y as f32));
                 }
 
+                coastlines.push(points);
                 continue;
             }
 
-            let railway = match record.get("railway") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
-            };
-
-            // 509 railway
-            if railway == "rail" {
-                map_renderer = map_renderer.railway_509(&line);
-                continue;
-            }
-
-            let other_tags = get_and_parse_other_tags(&record);
-
-            let power = match other_tags.get("power") {
-                Some(p) => p,
-                None => "",
-            };
-
-            let aerialway = match record.get("aerialway") {
-                Some(FieldValue::Character(Some(x))) => x,
-                Some(_) => "",
-                None => "",
-            };
-
-            // 510 power line, cableway or skilift
-            if power == "minor_line"
-                || aerialway == "cable_car"
-                || aerialway == "gondola"
-                || aerialway == "mixed_lift"
-                || aerialway == "chair_lift"
-                || aerialway == "drag_lift"
-                || aerialway == "t-bar"
-                || aerialway == "j-bar"
-                || aerialway == "platter"
-            {
-                map_renderer = map_renderer.power_line_cableway_or_skilift_510(&line);
-                continue;
-            }
+            // 304 crossable watercourse and 306 minor/seasonal water channel
+            let line = if symbol == "304" || symbol == "306" {
+                let line = if config.streams.snap_distance > 0. {
+                    snap_waterway_to_streams(&line, &stream_lines, config.streams.snap_distance)
+                } else {
+                    line
+                };
 
-            // 511 major power line
-            if power == "line" {
-                map_renderer = map_renderer.power_line_cableway_or_skilift_510(&line);
-                continue;
-            }
+                for part in line.parts() {
+                    waterways.push(part.iter().map(|point| (point.x, point.y)).collect());
+                }
 
-            let natural = match other_tags.get("natural") {
-                Some(p) => p,
-                None => "",
+                line
+            } else {
+                line
             };
 
-            if natural == "coastline" {
-                let mut points: Vec<(f32, f32)> = vec![];
-
-                for point in line.parts()[0].clone() {
-                    points.push((point.x as f32, point.y as f32));
-                }
-
-                coastlines.push(points);
-                continue;
+            if let Some(draw_symbol) = MapRenderer::get_line_symbol_function(symbol) {
+                map_renderer = draw_symbol(map_renderer, &line);
             }
         }
 
@@ -298,6 +266,7 @@ pub fn render_map_with_osm_vector_shapes(
 
             for coastline_polygon in coastlines_polygons {
                 map_renderer = map_renderer.uncrossable_body_of_water_area_301_1(&coastline_polygon);
+                drawn_areas.push(coastline_polygon);
             }
 
             for coastlines_edge in coastlines_edges {
@@ -306,13 +275,69 @@ pub fn render_map_with_osm_vector_shapes(
         }
     }
 
-    map_renderer.save_as(tile.render_dir_path.join("full-map.png"));
+    // In merge mode, the buildings found in the LiDAR data are only drawn where no OSM building is
+    if config.buildings.mode != BuildingsMode::Osm {
+        let buildings_path = tile.render_dir_path.join("buildings").join("buildings.shp");
+
+        for (polygon, _) in read_as::<_, Polygon, Record>(&buildings_path)? {
+            if config.buildings.mode == BuildingsMode::Merge && is_area_covered(&polygon, &osm_buildings) {
+                continue;
+            }
+
+            map_renderer = map_renderer.building_521(&polygon);
+            drawn_areas.push(polygon);
+        }
+    }
+
+    // Passages are drawn over the buildings around them
+    for polygon in building_passages {
+        map_renderer = map_renderer.building_passage_521_1(&polygon);
+    }
+
+    // The water and paved areas found in the LiDAR data are only drawn where no other area is
+    let land_cover_path = tile.render_dir_path.join("land-cover");
+
+    for (polygon, _) in read_as::<_, Polygon, Record>(&land_cover_path.join("water.shp"))? {
+        if !is_area_covered(&polygon, &drawn_areas) {
+            map_renderer = map_renderer.uncrossable_body_of_water_301(&polygon);
+        }
+    }
+
+    for (polygon, _) in read_as::<_, Polygon, Record>(&land_cover_path.join("paved-areas.shp"))? {
+        if !is_area_covered(&polygon, &drawn_areas) {
+            map_renderer = map_renderer.paved_area_529(&polygon);
+        }
+    }
+
+    // The derived streams along an OSM waterway are left out, the waterway being drawn instead
+    for (line, record) in streams {
+        let points: Vec<(f64, f64)> = line.parts()[0].iter().map(|point| (point.x, point.y)).collect();
+
+        if is_stream_along_waterways(&points, &waterways, config.streams.snap_distance) {
+            continue;
+        }
+
+        let area = match record.get("area") {
+            Some(FieldValue::Numeric(Some(area))) => *area,
+            _ => 0.,
+        };
+
+        map_renderer = if area >= config.streams.watercourse_min_area {
+            map_renderer.crossable_watercourse_304(&line)
+        } else {
+            map_renderer.minor_seasonal_water_channel_306(&line)
+        };
+    }
+
+    map_renderer.save_as(tile.render_dir_path.join("full-map.png"), vector_map)?;
     let duration = start.elapsed();
 
     info!(
         "Tile min_x={} min_y={} max_x={} max_y={}. Vectors rendered in {:.1?}",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
     );
+
+    Ok(())
 }
 
 fn get_and_parse_other_tags(record: &Record) -> HashMap<String, String> {
@@ -1,17 +1,22 @@
 use crate::{
-    buffer::create_tif_with_buffer,
     config::Config,
-    constants::{BUFFER, GREEN_1, GREEN_2, GREEN_3, INCH, TRANSPARENT, VEGETATION_BLOCK_SIZE, WHITE, YELLOW},
+    constants::{BUFFER, TRANSPARENT, VEGETATION_BLOCK_SIZE},
+    error::CassiniError,
+    mosaic::create_raster_with_buffer,
+    print_layout::write_symbol_codes,
+    raster::Raster,
+    symbol_set::millimeters_to_pixels,
+    symbol_styles::{get_rgba, SymbolStyles},
     tile::Tile,
+    vegetation_model::{get_vegetation_model, GroundCover, Runnability, VegetationDensities},
 };
-use image::{imageops, Rgba, RgbaImage};
+use image::{imageops, RgbaImage};
 use imageproc::{
     drawing::{draw_filled_ellipse_mut, draw_filled_rect_mut},
     rect::Rect,
 };
 use log::info;
-use std::{f32::consts::E, fs::File, path::PathBuf, time::Instant, u8};
-use tiff::decoder::{Decoder, DecodingResult};
+use std::{collections::BTreeSet, f32::consts::E, path::PathBuf, time::Instant, u8};
 
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum UndergrowthMode {
@@ -29,8 +34,9 @@ pub fn render_vegetation(
     image_width: u32,
     image_height: u32,
     config: &Config,
+    symbol_styles: &SymbolStyles,
     undergrowth_mode: &UndergrowthMode,
-) {
+) -> Result<(), CassiniError> {
     info!(
         "Tile min_x={} min_y={} max_x={} max_y={}. Rendering vegetation",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y
@@ -38,49 +44,44 @@ pub fn render_vegetation(
 
     let start = Instant::now();
 
-    let vegetation_block_size_pixel = VEGETATION_BLOCK_SIZE as f32 * config.dpi_resolution / INCH;
+    let vegetation_block_size_pixel = VEGETATION_BLOCK_SIZE as f32 * config.get_pixels_per_meter();
     let casted_base_vegetation_block_size_pixel = (vegetation_block_size_pixel * 2.).ceil() as i32;
     let casted_green_block_size_pixel = (vegetation_block_size_pixel).ceil() as u32;
 
-    create_tif_with_buffer(tile, neighbor_tiles, BUFFER as i64, "high-vegetation", 1.0);
-    create_tif_with_buffer(tile, neighbor_tiles, BUFFER as i64, "medium-vegetation", 1.0);
-    create_tif_with_buffer(tile, neighbor_tiles, BUFFER as i64, "low-vegetation", 1.0);
-
     let high_vegetation =
-        get_image_data_from_tif(&tile.render_dir_path.join("high-vegetation-with-buffer.tif"));
+        create_raster_with_buffer(tile, neighbor_tiles, BUFFER as i64, "high-vegetation", 1.0, 0u8)?;
 
     let medium_vegetation =
-        get_image_data_from_tif(&tile.render_dir_path.join("medium-vegetation-with-buffer.tif"));
+        create_raster_with_buffer(tile, neighbor_tiles, BUFFER as i64, "medium-vegetation", 1.0, 0u8)?;
 
     let low_vegetation =
-        get_image_data_from_tif(&tile.render_dir_path.join("low-vegetation-with-buffer.tif"));
+        create_raster_with_buffer(tile, neighbor_tiles, BUFFER as i64, "low-vegetation", 1.0, 0u8)?;
 
-    let mut base_vegetation_img = RgbaImage::from_pixel(image_width, image_height, YELLOW);
+    let vegetation_model = get_vegetation_model(config);
+    let tile_width = (tile.max_x - tile.min_x) as usize;
+    let tile_height = (tile.max_y - tile.min_y) as usize;
+
+    let colors = &symbol_styles.colors;
+    let mut base_vegetation_img =
+        RgbaImage::from_pixel(image_width, image_height, get_rgba(colors.open_land));
+    let mut rough_open_land_img = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
+    let mut forest_img = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
     let mut green_vegetation_img = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
     let mut undergrowth_vegetation_img = RgbaImage::from_pixel(image_width, image_height, TRANSPARENT);
+    let mut has_scattered_trees = vec![false; tile_width * tile_height];
+    let mut used_symbol_codes: BTreeSet<&str> = BTreeSet::new();
 
     let medium_vegetation_kernel_radius = 2;
     let medium_vegetation_kernel = get_convolution_kernel_matrix(medium_vegetation_kernel_radius);
     let low_vegetation_kernel_radius = 4;
     let low_vegetation_kernel = get_convolution_kernel_matrix(low_vegetation_kernel_radius);
+    let canopy_cover_radius = 4;
 
-    for x_index in BUFFER..((tile.max_x + BUFFER as i64 - tile.min_x) as usize) {
-        for y_index in BUFFER..((tile.max_y + BUFFER as i64 - tile.min_y) as usize) {
+    for x_index in BUFFER..(tile_width + BUFFER) {
+        for y_index in BUFFER..(tile_height + BUFFER) {
             let x_pixel = ((x_index - BUFFER) as f32 * vegetation_block_size_pixel) as i32;
             let y_pixel = ((y_index - BUFFER) as f32 * vegetation_block_size_pixel) as i32;
 
-            let high_vegetation_density = get_min_value_in_circle(&high_vegetation, x_index, y_index);
-
-            if high_vegetation_density > config.yellow_threshold as u8 {
-                draw_filled_ellipse_mut(
-                    &mut base_vegetation_img,
-                    (x_pixel, y_pixel),
-                    casted_base_vegetation_block_size_pixel,
-                    casted_base_vegetation_block_size_pixel,
-                    WHITE,
-                );
-            }
-
             let mut medium_vegetation_density = get_average_pixel_value(
                 &medium_vegetation,
                 x_index,
@@ -89,6 +90,14 @@ pub fn render_vegetation(
                 medium_vegetation_kernel_radius,
             );
 
+            let low_vegetation_density = get_average_pixel_value(
+                &low_vegetation,
+                x_index,
+                y_index,
+                &low_vegetation_kernel,
+                low_vegetation_kernel_radius,
+            );
+
             match undergrowth_mode {
                 UndergrowthMode::Merge => {
                     medium_vegetation_density += get_average_pixel_value(
@@ -100,30 +109,24 @@ pub fn render_vegetation(
                     );
                 }
                 UndergrowthMode::Symbol406 | UndergrowthMode::Symbol409 => {
-                    let low_vegetation_density = get_average_pixel_value(
-                        &low_vegetation,
-                        x_index,
-                        y_index,
-                        &low_vegetation_kernel,
-                        low_vegetation_kernel_radius,
-                    );
-
-                    let undergrowth_color = match undergrowth_mode {
-                        UndergrowthMode::Symbol406 => Some(GREEN_1),
-                        UndergrowthMode::Symbol409 => Some(GREEN_3),
+                    let undergrowth_symbol = match undergrowth_mode {
+                        UndergrowthMode::Symbol406 => Some(("406", colors.slow_running_vegetation)),
+                        UndergrowthMode::Symbol409 => Some(("409", colors.undergrowth)),
                         _ => None,
                     };
 
                     if low_vegetation_density > config.low_vegetation_density_threshold {
-                        match undergrowth_color {
-                            Some(color) => {
+                        match undergrowth_symbol {
+                            Some((code, color)) => {
+                                used_symbol_codes.insert(code);
+
                                 draw_filled_rect_mut(
                                     &mut undergrowth_vegetation_img,
                                     Rect::at(x_pixel, y_pixel).of_size(
                                         casted_green_block_size_pixel,
                                         casted_green_block_size_pixel,
                                     ),
-                                    color,
+                                    get_rgba(color),
                                 );
                             }
                             _ => (),
@@ -133,16 +136,53 @@ pub fn render_vegetation(
                 UndergrowthMode::None => {}
             }
 
-            let mut green_color: Option<Rgba<u8>> = None;
+            let vegetation_class = vegetation_model.classify(&VegetationDensities {
+                high: get_min_value_in_circle(&high_vegetation, x_index, y_index),
+                medium: medium_vegetation_density,
+                low: low_vegetation_density,
+                canopy_cover: get_canopy_cover(&high_vegetation, x_index, y_index, canopy_cover_radius),
+            });
+
+            match vegetation_class.ground_cover {
+                GroundCover::Forest => {
+                    draw_filled_ellipse_mut(
+                        &mut forest_img,
+                        (x_pixel, y_pixel),
+                        casted_base_vegetation_block_size_pixel,
+                        casted_base_vegetation_block_size_pixel,
+                        get_rgba(colors.forest),
+                    );
+                }
+                GroundCover::RoughOpenLand | GroundCover::RoughOpenLandWithScatteredTrees => {
+                    draw_filled_rect_mut(
+                        &mut rough_open_land_img,
+                        Rect::at(x_pixel, y_pixel)
+                            .of_size(casted_green_block_size_pixel, casted_green_block_size_pixel),
+                        get_rgba(colors.rough_open_land),
+                    );
+                }
+                GroundCover::OpenLand | GroundCover::OpenLandWithScatteredTrees => {}
+            }
 
-            if medium_vegetation_density > config.green_threshold_3 {
-                green_color = Some(GREEN_3);
-            } else if medium_vegetation_density > config.green_threshold_2 {
-                green_color = Some(GREEN_2);
-            } else if medium_vegetation_density > config.green_threshold_1 {
-                green_color = Some(GREEN_1);
+            if vegetation_class.ground_cover == GroundCover::OpenLandWithScatteredTrees
+                || vegetation_class.ground_cover == GroundCover::RoughOpenLandWithScatteredTrees
+            {
+                has_scattered_trees[(y_index - BUFFER) * tile_width + x_index - BUFFER] = true;
+            }
+
+            used_symbol_codes.insert(vegetation_class.ground_cover.get_code());
+
+            if let Some(runnability) = vegetation_class.runnability {
+                used_symbol_codes.insert(runnability.get_code());
             }
 
+            let green_color = match vegetation_class.runnability {
+                Some(Runnability::SlowRunning) => Some(get_rgba(colors.slow_running_vegetation)),
+                Some(Runnability::Walk) => Some(get_rgba(colors.walk_vegetation)),
+                Some(Runnability::Fight) => Some(get_rgba(colors.fight_vegetation)),
+                None => None,
+            };
+
             match green_color {
                 Some(color) => {
                     draw_filled_rect_mut(
@@ -157,6 +197,20 @@ pub fn render_vegetation(
         }
     }
 
+    // Forest takes precedence over rough open land, as the white blocks are drawn larger than the cells
+    imageops::overlay(&mut base_vegetation_img, &rough_open_land_img, 0, 0);
+    imageops::overlay(&mut base_vegetation_img, &forest_img, 0, 0);
+
+    draw_scattered_trees(
+        &mut base_vegetation_img,
+        &has_scattered_trees,
+        tile_width,
+        tile_height,
+        vegetation_block_size_pixel,
+        config.dpi_resolution,
+        symbol_styles,
+    );
+
     match undergrowth_mode {
         UndergrowthMode::Symbol406 => {
             imageops::overlay(&mut base_vegetation_img, &undergrowth_vegetation_img, 0, 0);
@@ -164,9 +218,7 @@ pub fn render_vegetation(
         UndergrowthMode::Symbol409 => {
             let undergrowth_output_path = tile.render_dir_path.join("undergrowth.png");
 
-            undergrowth_vegetation_img
-                .save(undergrowth_output_path)
-                .expect("could not save undergrowth output png");
+            undergrowth_vegetation_img.save(undergrowth_output_path)?;
         }
         UndergrowthMode::None | UndergrowthMode::Merge => {}
     }
@@ -174,9 +226,13 @@ pub fn render_vegetation(
     imageops::overlay(&mut base_vegetation_img, &green_vegetation_img, 0, 0);
     let vegetation_output_path = tile.render_dir_path.join("vegetation.png");
 
-    base_vegetation_img
-        .save(vegetation_output_path)
-        .expect("could not save vegetation output png");
+    base_vegetation_img.save(vegetation_output_path)?;
+
+    let used_symbol_codes: Vec<&str> = used_symbol_codes.into_iter().collect();
+    write_symbol_codes(
+        &tile.render_dir_path.join("vegetation-symbols.json"),
+        &used_symbol_codes,
+    )?;
 
     let duration = start.elapsed();
 
@@ -184,14 +240,89 @@ pub fn render_vegetation(
         "Tile min_x={} min_y={} max_x={} max_y={}. Vegetation rendered in {:.1?}",
         tile.min_x, tile.min_y, tile.max_x, tile.max_y, duration
     );
+
+    Ok(())
+}
+
+/// Draws the white dots of the open land with scattered trees on a regular grid, on the cells flagged in
+/// has_scattered_trees.
+fn draw_scattered_trees(
+    img: &mut RgbaImage,
+    has_scattered_trees: &Vec<bool>,
+    tile_width: usize,
+    tile_height: usize,
+    vegetation_block_size_pixel: f32,
+    dpi_resolution: f32,
+    symbol_styles: &SymbolStyles,
+) {
+    let symbol_dimensions = &symbol_styles.dimensions;
+    let dot_spacing_pixel =
+        millimeters_to_pixels(symbol_dimensions.scattered_trees_dot_spacing, dpi_resolution);
+    let dot_radius_pixel =
+        (millimeters_to_pixels(symbol_dimensions.scattered_trees_dot_diameter, dpi_resolution) / 2.).round()
+            as i32;
+    let number_of_columns = (img.width() as f32 / dot_spacing_pixel).ceil() as usize;
+    let number_of_rows = (img.height() as f32 / dot_spacing_pixel).ceil() as usize;
+
+    for row in 0..number_of_rows {
+        for column in 0..number_of_columns {
+            let x_pixel = (column as f32 + 0.5) * dot_spacing_pixel;
+            let y_pixel = (row as f32 + 0.5) * dot_spacing_pixel;
+            let x_index = (x_pixel / vegetation_block_size_pixel) as usize;
+            let y_index = (y_pixel / vegetation_block_size_pixel) as usize;
+
+            if x_index >= tile_width
+                || y_index >= tile_height
+                || !has_scattered_trees[y_index * tile_width + x_index]
+            {
+                continue;
+            }
+
+            draw_filled_ellipse_mut(
+                img,
+                (x_pixel as i32, y_pixel as i32),
+                dot_radius_pixel.max(1),
+                dot_radius_pixel.max(1),
+                get_rgba(symbol_styles.colors.scattered_trees),
+            );
+        }
+    }
+}
+
+/// Returns the share of the cells within radius of a cell that have high vegetation points.
+fn get_canopy_cover(high_vegetation: &Raster<u8>, x_index: usize, y_index: usize, radius: usize) -> f32 {
+    let mut number_of_cells = 0;
+    let mut number_of_covered_cells = 0;
+
+    for y in y_index.saturating_sub(radius)..=(y_index + radius).min(high_vegetation.height - 1) {
+        for x in x_index.saturating_sub(radius)..=(x_index + radius).min(high_vegetation.width - 1) {
+            let (dx, dy) = (x as i64 - x_index as i64, y as i64 - y_index as i64);
+
+            if dx * dx + dy * dy > (radius * radius) as i64 {
+                continue;
+            }
+
+            number_of_cells += 1;
+
+            if high_vegetation.get(x, y) > 0 {
+                number_of_covered_cells += 1;
+            }
+        }
+    }
+
+    if number_of_cells == 0 {
+        return 0.;
+    }
+
+    return number_of_covered_cells as f32 / number_of_cells as f32;
 }
 
-fn get_min_value_in_circle(tif_image: &TifImage, x_index: usize, y_index: usize) -> u8 {
+fn get_min_value_in_circle(tif_image: &Raster<u8>, x_index: usize, y_index: usize) -> u8 {
     let mut min = u8::MAX;
-    let width = tif_image.width as usize;
-    let height = tif_image.height as usize;
+    let width = tif_image.width;
+    let height = tif_image.height;
 
-    if tif_image.pixels.len() == 0 {
+    if tif_image.data.len() == 0 {
         panic!("Image with no pixels")
     }
 
@@ -214,7 +345,7 @@ fn get_min_value_in_circle(tif_image: &TifImage, x_index: usize, y_index: usize)
                 continue;
             }
 
-            let pixel_value = tif_image.pixels[y * width + x];
+            let pixel_value = tif_image.data[y * width + x];
 
             if pixel_value < min {
                 min = pixel_value;
@@ -257,7 +388,7 @@ pub fn get_convolution_kernel_matrix(radius: usize) -> Vec<Vec<f32>> {
 }
 
 fn get_average_pixel_value(
-    tif_image: &TifImage,
+    tif_image: &Raster<u8>,
     x: usize,
     y: usize,
     kernel: &Vec<Vec<f32>>,
@@ -267,8 +398,8 @@ fn get_average_pixel_value(
         panic!("kernel should be a square matrix of size 2 at least")
     }
 
-    let width = tif_image.width as usize;
-    let height = tif_image.height as usize;
+    let width = tif_image.width;
+    let height = tif_image.height;
     let size = kernel.len();
     let radius_i = kernel_radius as isize;
     let mut weighted_sum = 0.0f32;
@@ -285,7 +416,7 @@ fn get_average_pixel_value(
 
             let nxi = nx as usize;
             let nyi = ny as usize;
-            let pixel = tif_image.pixels[nyi * width + nxi] as f32;
+            let pixel = tif_image.data[nyi * width + nxi] as f32;
             let weight = kernel[ky][kx];
 
             weighted_sum += pixel * weight;
@@ -299,26 +430,3 @@ fn get_average_pixel_value(
 
     return 0.;
 }
-
-struct TifImage {
-    pixels: Vec<u8>,
-    width: u32,
-    height: u32,
-}
-
-fn get_image_data_from_tif(path: &PathBuf) -> TifImage {
-    let tif_file = File::open(path).expect("Cannot find high vegetation tif image!");
-    let mut img_decoder = Decoder::new(tif_file).expect("Cannot create decoder");
-    img_decoder = img_decoder.with_limits(tiff::decoder::Limits::unlimited());
-    let (width, height) = img_decoder.dimensions().unwrap();
-
-    let DecodingResult::U8(image_data) = img_decoder.read_image().unwrap() else {
-        panic!("Cannot read band data")
-    };
-
-    return TifImage {
-        pixels: image_data,
-        width,
-        height,
-    };
-}
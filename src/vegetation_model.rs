@@ -0,0 +1,292 @@
+use crate::config::{Config, VegetationModelKind};
+
+/// The vegetation densities around a one meter by one meter cell, from the vegetation rasters of the LiDAR
+/// step
+pub struct VegetationDensities {
+    /// Minimum number of high vegetation points in the cells around
+    pub high: u8,
+    /// Weighted average number of medium vegetation points around, low vegetation ones included in the
+    /// merged undergrowth mode
+    pub medium: f32,
+    /// Weighted average number of low vegetation points around
+    pub low: f32,
+    /// Share of the cells around with high vegetation points, between 0 and 1
+    pub canopy_cover: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GroundCover {
+    /// ISOM 401 Open land
+    OpenLand,
+    /// ISOM 402 Open land with scattered trees
+    OpenLandWithScatteredTrees,
+    /// ISOM 403 Rough open land
+    RoughOpenLand,
+    /// ISOM 404 Rough open land with scattered trees
+    RoughOpenLandWithScatteredTrees,
+    /// ISOM 405 Forest
+    Forest,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Runnability {
+    /// ISOM 406 Vegetation, slow running
+    SlowRunning,
+    /// ISOM 408 Vegetation, walk
+    Walk,
+    /// ISOM 410 Vegetation, fight
+    Fight,
+}
+
+impl GroundCover {
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            GroundCover::OpenLand => "401",
+            GroundCover::OpenLandWithScatteredTrees => "402",
+            GroundCover::RoughOpenLand => "403",
+            GroundCover::RoughOpenLandWithScatteredTrees => "404",
+            GroundCover::Forest => "405",
+        }
+    }
+}
+
+impl Runnability {
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            Runnability::SlowRunning => "406",
+            Runnability::Walk => "408",
+            Runnability::Fight => "410",
+        }
+    }
+}
+
+/// The classification of a cell: its ground cover, and the green drawn over it if the vegetation slows down
+/// the runner
+pub struct VegetationClass {
+    pub ground_cover: GroundCover,
+    pub runnability: Option<Runnability>,
+}
+
+pub trait VegetationModel {
+    fn classify(&self, densities: &VegetationDensities) -> VegetationClass;
+}
+
+/// Returns the vegetation model selected in the config.
+pub fn get_vegetation_model(config: &Config) -> Box<dyn VegetationModel> {
+    let runnability_thresholds = RunnabilityThresholds {
+        slow_running: config.green_threshold_1,
+        walk: config.green_threshold_2,
+        fight: config.green_threshold_3,
+    };
+
+    match config.vegetation.model {
+        VegetationModelKind::Thresholds => Box::new(ThresholdsVegetationModel {
+            yellow_threshold: config.yellow_threshold as u8,
+            runnability_thresholds,
+        }),
+        VegetationModelKind::CanopyCover => Box::new(CanopyCoverVegetationModel {
+            forest_min_canopy_cover: config.vegetation.forest_min_canopy_cover,
+            scattered_trees_min_canopy_cover: config.vegetation.scattered_trees_min_canopy_cover,
+            rough_open_land_min_density: config.vegetation.rough_open_land_min_density,
+            runnability_thresholds,
+        }),
+    }
+}
+
+/// Medium vegetation densities above which the vegetation is drawn with the three greens
+struct RunnabilityThresholds {
+    slow_running: f32,
+    walk: f32,
+    fight: f32,
+}
+
+impl RunnabilityThresholds {
+    fn get_runnability(&self, medium_density: f32) -> Option<Runnability> {
+        if medium_density > self.fight {
+            return Some(Runnability::Fight);
+        }
+
+        if medium_density > self.walk {
+            return Some(Runnability::Walk);
+        }
+
+        if medium_density > self.slow_running {
+            return Some(Runnability::SlowRunning);
+        }
+
+        return None;
+    }
+}
+
+/// The default model: forest where there are high vegetation points all around, open land elsewhere
+struct ThresholdsVegetationModel {
+    yellow_threshold: u8,
+    runnability_thresholds: RunnabilityThresholds,
+}
+
+impl VegetationModel for ThresholdsVegetationModel {
+    fn classify(&self, densities: &VegetationDensities) -> VegetationClass {
+        let ground_cover = if densities.high > self.yellow_threshold {
+            GroundCover::Forest
+        } else {
+            GroundCover::OpenLand
+        };
+
+        return VegetationClass {
+            ground_cover,
+            runnability: self.runnability_thresholds.get_runnability(densities.medium),
+        };
+    }
+}
+
+/// Tells forest from open land with the canopy cover, with scattered trees in between. Open land with
+/// dense low vegetation is rough.
+struct CanopyCoverVegetationModel {
+    forest_min_canopy_cover: f32,
+    scattered_trees_min_canopy_cover: f32,
+    rough_open_land_min_density: f32,
+    runnability_thresholds: RunnabilityThresholds,
+}
+
+impl VegetationModel for CanopyCoverVegetationModel {
+    fn classify(&self, densities: &VegetationDensities) -> VegetationClass {
+        let is_rough = densities.low >= self.rough_open_land_min_density;
+        let has_scattered_trees = densities.canopy_cover >= self.scattered_trees_min_canopy_cover;
+
+        let ground_cover = match (is_rough, has_scattered_trees) {
+            _ if densities.canopy_cover >= self.forest_min_canopy_cover => GroundCover::Forest,
+            (false, false) => GroundCover::OpenLand,
+            (false, true) => GroundCover::OpenLandWithScatteredTrees,
+            (true, false) => GroundCover::RoughOpenLand,
+            (true, true) => GroundCover::RoughOpenLandWithScatteredTrees,
+        };
+
+        return VegetationClass {
+            ground_cover,
+            runnability: self.runnability_thresholds.get_runnability(densities.medium),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_default_config(model: VegetationModelKind) -> Config {
+        let mut config: Config = serde_json::from_str("{}").unwrap();
+        config.vegetation.model = model;
+
+        return config;
+    }
+
+    fn get_densities(high: u8, medium: f32, low: f32, canopy_cover: f32) -> VegetationDensities {
+        return VegetationDensities {
+            high,
+            medium,
+            low,
+            canopy_cover,
+        };
+    }
+
+    #[test]
+    fn canopy_cover_thresholds() {
+        let mut config = get_default_config(VegetationModelKind::CanopyCover);
+        config.vegetation.forest_min_canopy_cover = 0.6;
+        config.vegetation.scattered_trees_min_canopy_cover = 0.15;
+        config.vegetation.rough_open_land_min_density = 1.0;
+        let model = get_vegetation_model(&config);
+        let get_ground_cover = |low: f32, canopy_cover: f32| {
+            model
+                .classify(&get_densities(0, 0.0, low, canopy_cover))
+                .ground_cover
+        };
+
+        assert_eq!(get_ground_cover(0.0, 0.0), GroundCover::OpenLand);
+        assert_eq!(get_ground_cover(0.99, 0.14), GroundCover::OpenLand);
+        assert_eq!(
+            get_ground_cover(0.0, 0.15),
+            GroundCover::OpenLandWithScatteredTrees
+        );
+        assert_eq!(
+            get_ground_cover(0.0, 0.59),
+            GroundCover::OpenLandWithScatteredTrees
+        );
+        assert_eq!(get_ground_cover(1.0, 0.0), GroundCover::RoughOpenLand);
+        assert_eq!(
+            get_ground_cover(1.0, 0.15),
+            GroundCover::RoughOpenLandWithScatteredTrees
+        );
+        // The forest wins over the rough open land
+        assert_eq!(get_ground_cover(0.0, 0.6), GroundCover::Forest);
+        assert_eq!(get_ground_cover(5.0, 1.0), GroundCover::Forest);
+
+        let get_runnability = |medium: f32| model.classify(&get_densities(0, medium, 0.0, 1.0)).runnability;
+
+        assert_eq!(get_runnability(config.green_threshold_1), None);
+        assert_eq!(
+            get_runnability(config.green_threshold_1 + 0.01),
+            Some(Runnability::SlowRunning)
+        );
+        assert_eq!(
+            get_runnability(config.green_threshold_2 + 0.01),
+            Some(Runnability::Walk)
+        );
+        assert_eq!(
+            get_runnability(config.green_threshold_3 + 0.01),
+            Some(Runnability::Fight)
+        );
+    }
+
+    /// The classification of render_vegetation before the vegetation models were introduced: white where
+    /// the minimum high vegetation density is above the yellow threshold, then the greens from the medium
+    /// density.
+    fn classify_like_before_vegetation_models(config: &Config, high: u8, medium: f32) -> (bool, Option<u8>) {
+        let is_white = high > config.yellow_threshold as u8;
+
+        let green = if medium > config.green_threshold_3 {
+            Some(3)
+        } else if medium > config.green_threshold_2 {
+            Some(2)
+        } else if medium > config.green_threshold_1 {
+            Some(1)
+        } else {
+            None
+        };
+
+        return (is_white, green);
+    }
+
+    #[test]
+    fn thresholds_model_matches_previous_vegetation_rendering() {
+        let config = get_default_config(VegetationModelKind::Thresholds);
+        let model = get_vegetation_model(&config);
+
+        for high in 0..=10 {
+            for medium_step in 0..=100 {
+                let medium = medium_step as f32 * 0.1;
+                // The canopy cover and the low density are not used by this model
+                let class = model.classify(&get_densities(high, medium, 10.0, 1.0));
+
+                let green = class.runnability.map(|runnability| match runnability {
+                    Runnability::SlowRunning => 1,
+                    Runnability::Walk => 2,
+                    Runnability::Fight => 3,
+                });
+
+                assert!(
+                    matches!(class.ground_cover, GroundCover::Forest | GroundCover::OpenLand),
+                    "high={} medium={}",
+                    high,
+                    medium
+                );
+                assert_eq!(
+                    (class.ground_cover == GroundCover::Forest, green),
+                    classify_like_before_vegetation_models(&config, high, medium),
+                    "high={} medium={}",
+                    high,
+                    medium
+                );
+            }
+        }
+    }
+}